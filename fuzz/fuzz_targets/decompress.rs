@@ -0,0 +1,11 @@
+//! Decompresses arbitrary bytes as LZ4 blocks and frames. Malformed input has to fail to
+//! decompress rather than panic.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use mintdb::compression::Compression;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = Compression::Lz4.decompress(data);
+});
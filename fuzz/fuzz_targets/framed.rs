@@ -0,0 +1,49 @@
+//! Reads arbitrary bytes as each of the framed logs: a manifest, the WAL, and an export,
+//! along with a framed bloom filter. The first byte picks which. Malformed input has to
+//! fail to read rather than panic.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use mintdb::{
+    backup::ExportRecord, bloom::BloomFilter, config::Config, framed::read_framed,
+    recovery::OpenReport, sstable::manifest::Manifest, wal::Wal,
+};
+
+fuzz_target!(|data: &[u8]| {
+    let Some((kind, data)) = data.split_first() else {
+        return;
+    };
+
+    match kind % 4 {
+        0 => {
+            if let Ok((manifest, _)) = Manifest::load(data) {
+                let _ = manifest.validate();
+            }
+        }
+        1 => {
+            // The WAL is only read through an open log, which needs a file.
+            let dir = std::env::temp_dir().join(format!("mintdb-fuzz-{}", std::process::id()));
+            std::fs::create_dir_all(&dir).expect("failed to create input dir");
+
+            let path = dir.join("wal.log");
+            std::fs::write(&path, data).expect("failed to write input");
+
+            if let Ok(wal) = Wal::open(path, &Config::new(&dir), &mut OpenReport::default()) {
+                let _ = wal.replay();
+            }
+        }
+        2 => {
+            let mut reader = data;
+
+            while read_framed::<_, ExportRecord>(&mut reader).is_ok() {}
+        }
+        _ => {
+            let mut reader = data;
+
+            if let Ok(filter) = read_framed::<_, BloomFilter>(&mut reader) {
+                let _ = filter.may_contain(reader);
+            }
+        }
+    }
+});
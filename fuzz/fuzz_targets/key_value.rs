@@ -0,0 +1,26 @@
+//! Decodes block entries, keys followed by their values, from arbitrary bytes. Malformed
+//! input has to fail to decode rather than panic.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use mintdb::{sstable::sstable::KeyEncoding, value::Value};
+
+fuzz_target!(|data: &[u8]| {
+    for encoding in [KeyEncoding::Plain, KeyEncoding::DeltaU64] {
+        let mut buf = bytes::Bytes::copy_from_slice(data);
+        let mut prev = None;
+
+        while !buf.is_empty() {
+            let Ok(key) = encoding.decode_key(&mut buf, prev.as_ref()) else {
+                break;
+            };
+
+            if Value::decode_from(&mut buf).is_err() {
+                break;
+            }
+
+            prev = Some(key);
+        }
+    }
+});
@@ -0,0 +1,44 @@
+//! Opens arbitrary bytes as an SSTable, and reads every entry of it. Malformed input has to
+//! fail to open or read rather than panic.
+
+#![no_main]
+
+use std::ops::Bound;
+
+use libfuzzer_sys::fuzz_target;
+use mintdb::{
+    key::SeqNo,
+    sstable::sstable::{BlockReadOptions, SSTable, SSTableFooter},
+};
+
+fuzz_target!(|data: &[u8]| {
+    let _ = SSTableFooter::decode_from(data);
+    let _ = SSTable::read_index(std::io::Cursor::new(data));
+
+    // Blocks are only read through an open table, which maps a file.
+    let path = std::env::temp_dir().join(format!("mintdb-fuzz-{}.sstable", std::process::id()));
+    std::fs::write(&path, data).expect("failed to write input");
+
+    let Ok(table) = SSTable::open(path) else {
+        return;
+    };
+
+    let entries = table.range(
+        (Bound::Unbounded, Bound::Unbounded),
+        BlockReadOptions::default(),
+    );
+
+    for entry in entries {
+        if entry.is_err() {
+            break;
+        }
+    }
+
+    for meta in table.index() {
+        let _ = table.get(
+            meta.last_key().user_key(),
+            SeqNo(u64::MAX),
+            BlockReadOptions::default(),
+        );
+    }
+});
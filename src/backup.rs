@@ -0,0 +1,59 @@
+//! The export format written by [`Database::export`](crate::Database::export) and read by
+//! [`Database::restore`](crate::Database::restore).
+//!
+//! An export is a sequence of framed records: each column family's name followed by its
+//! live entries in key order, ending with an [`ExportRecord::End`] that makes truncation
+//! detectable. Since entries are sorted, a restore can write them straight into SSTables.
+//!
+//! Also the [`BackupState`] that lets
+//! [`Database::incremental_checkpoint`](crate::Database::incremental_checkpoint) copy only
+//! what changed since the last backup.
+
+use std::collections::BTreeSet;
+
+use anyhow::Context;
+
+use crate::value::Value;
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub enum ExportRecord {
+    /// Starts the entries of a column family.
+    ColumnFamily { name: String },
+    /// A live entry of the current column family. Keys are strictly increasing within a
+    /// column family.
+    Entry { key: bytes::Bytes, value: Value },
+    /// Marks the end of the export, along with the total number of entries in it.
+    End { entries: u64 },
+}
+
+/// Reads the next record, failing if the export ends before its [`ExportRecord::End`].
+pub(crate) fn read_record(reader: impl std::io::Read) -> anyhow::Result<ExportRecord> {
+    match crate::framed::read_framed(reader) {
+        Err(crate::framed::FramedError::UnexpectedEnd) => {
+            anyhow::bail!("Export is truncated")
+        }
+        record => record.context("Failed to decode export record"),
+    }
+}
+
+/// The SSTables held by a backup directory, as returned by
+/// [`Database::incremental_checkpoint`](crate::Database::incremental_checkpoint). Passing it
+/// back to the next call is what lets that call skip them.
+///
+/// SSTables are never modified once written, so a file number is enough to identify one.
+/// It's serializable, so it can be kept alongside the backup between runs.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct BackupState {
+    pub(crate) files: BTreeSet<u64>,
+}
+
+impl BackupState {
+    /// The file numbers of the SSTables in the backup, in ascending order.
+    pub fn files(&self) -> impl Iterator<Item = u64> + '_ {
+        self.files.iter().copied()
+    }
+
+    pub fn contains(&self, file_number: u64) -> bool {
+        self.files.contains(&file_number)
+    }
+}
@@ -0,0 +1,145 @@
+//! Atomic groups of writes.
+
+use std::time::Duration;
+
+use crate::column_family::{ColumnFamily, ColumnFamilyId};
+
+/// A group of writes that are applied atomically by
+/// [`Database::write`](crate::Database::write).
+///
+/// The writes may target any number of column families. They're logged as a single WAL
+/// record, so after a crash either all of them are recovered or none are.
+#[derive(Debug, Clone, Default)]
+pub struct WriteBatch {
+    ops: Vec<BatchOp>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum BatchOp {
+    Put {
+        cf: ColumnFamilyId,
+        key: bytes::Bytes,
+        val: bytes::Bytes,
+        /// How long after the write the value expires, if it does.
+        ttl: Option<Duration>,
+    },
+    Delete {
+        cf: ColumnFamilyId,
+        key: bytes::Bytes,
+    },
+    DeleteRange {
+        cf: ColumnFamilyId,
+        start: bytes::Bytes,
+        end: Option<bytes::Bytes>,
+    },
+}
+
+impl BatchOp {
+    pub(crate) fn cf(&self) -> ColumnFamilyId {
+        match self {
+            BatchOp::Put { cf, .. }
+            | BatchOp::Delete { cf, .. }
+            | BatchOp::DeleteRange { cf, .. } => *cf,
+        }
+    }
+}
+
+impl WriteBatch {
+    pub fn new() -> Self {
+        WriteBatch::default()
+    }
+
+    pub fn put(
+        &mut self,
+        cf: &ColumnFamily,
+        key: impl Into<bytes::Bytes>,
+        val: impl Into<bytes::Bytes>,
+    ) -> &mut Self {
+        self.ops.push(BatchOp::Put {
+            cf: cf.id(),
+            key: key.into(),
+            val: val.into(),
+            ttl: None,
+        });
+        self
+    }
+
+    /// Like [`WriteBatch::put`], but the value reads as deleted once `ttl` has passed since
+    /// the batch was written.
+    pub fn put_with_ttl(
+        &mut self,
+        cf: &ColumnFamily,
+        key: impl Into<bytes::Bytes>,
+        val: impl Into<bytes::Bytes>,
+        ttl: Duration,
+    ) -> &mut Self {
+        self.ops.push(BatchOp::Put {
+            cf: cf.id(),
+            key: key.into(),
+            val: val.into(),
+            ttl: Some(ttl),
+        });
+        self
+    }
+
+    pub fn delete(&mut self, cf: &ColumnFamily, key: impl Into<bytes::Bytes>) -> &mut Self {
+        self.ops.push(BatchOp::Delete {
+            cf: cf.id(),
+            key: key.into(),
+        });
+        self
+    }
+
+    /// Deletes every key in `[start, end)`, or every key from `start` onward if `end` is
+    /// `None`.
+    pub fn delete_range(
+        &mut self,
+        cf: &ColumnFamily,
+        start: impl Into<bytes::Bytes>,
+        end: Option<bytes::Bytes>,
+    ) -> &mut Self {
+        self.ops.push(BatchOp::DeleteRange {
+            cf: cf.id(),
+            start: start.into(),
+            end,
+        });
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// Splits the batch into one per shard, out of `shards`, each holding the writes to the
+    /// keys that shard owns in their original order; see
+    /// [`shard_for_key`](crate::shard::shard_for_key). A range deletion can cover keys of
+    /// any shard, so it goes to all of them.
+    pub fn split_by_shard(self, shards: usize) -> Vec<WriteBatch> {
+        let mut batches = vec![WriteBatch::new(); shards.max(1)];
+
+        for op in self.ops {
+            match &op {
+                BatchOp::Put { key, .. } | BatchOp::Delete { key, .. } => {
+                    batches[crate::shard::shard_for_key(key, shards)]
+                        .ops
+                        .push(op);
+                }
+                BatchOp::DeleteRange { .. } => {
+                    for batch in &mut batches {
+                        batch.ops.push(op.clone());
+                    }
+                }
+            }
+        }
+
+        batches
+    }
+
+    pub(crate) fn into_ops(self) -> Vec<BatchOp> {
+        self.ops
+    }
+}
@@ -0,0 +1,98 @@
+//! Per-file bloom filters over user keys, which let point lookups skip SSTables that
+//! cover a key by range but don't hold it.
+//!
+//! A file's filter is kept in its [`FileMeta`](crate::sstable::manifest::FileMeta), so it's
+//! held in memory for as long as the file is live.
+//! [`Config::bloom_filter_levels`](crate::config::Config::bloom_filter_levels) decides which
+//! levels' files get one.
+
+use std::hash::Hasher;
+
+use crate::sstable::Level;
+
+/// Filter bits per distinct user key, for a false positive rate of about 1%.
+pub const BITS_PER_KEY: usize = 10;
+
+/// Which levels' files are written with bloom filters.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BloomFilterLevels {
+    /// Every file gets a filter.
+    #[default]
+    All,
+    /// Only files in this level and the levels above it get a filter. The deepest levels
+    /// hold most of the data, so leaving them out saves most of the memory, at the cost of
+    /// reading a block for lookups of keys they don't hold.
+    UpTo(Level),
+    /// No file gets a filter.
+    None,
+}
+
+impl BloomFilterLevels {
+    /// Whether files written to `level` get a filter.
+    pub fn includes(&self, level: Level) -> bool {
+        match self {
+            BloomFilterLevels::All => true,
+            BloomFilterLevels::UpTo(deepest) => level <= *deepest,
+            BloomFilterLevels::None => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct BloomFilter {
+    bits: bytes::Bytes,
+    probes: u32,
+}
+
+impl BloomFilter {
+    /// Builds a filter over the keys with the given [`hash`]es.
+    pub fn from_hashes(hashes: &[u64]) -> Self {
+        let len = (hashes.len() * BITS_PER_KEY).div_ceil(8).max(8);
+        let mut bits = vec![0u8; len];
+
+        // k = ln(2) * bits per key minimizes the false positive rate.
+        let probes = ((BITS_PER_KEY as f64 * std::f64::consts::LN_2) as u32).clamp(1, 30);
+
+        for hash in hashes {
+            for bit in probe_bits(*hash, probes, len * 8) {
+                bits[bit / 8] |= 1 << (bit % 8);
+            }
+        }
+
+        BloomFilter {
+            bits: bits.into(),
+            probes,
+        }
+    }
+
+    /// Whether the filter may hold `user_key`. `false` means it definitely doesn't.
+    pub fn may_contain(&self, user_key: &[u8]) -> bool {
+        // Only a corrupt filter is empty, and it can't rule anything out.
+        if self.bits.is_empty() {
+            return true;
+        }
+
+        probe_bits(hash(user_key), self.probes, self.bits.len() * 8)
+            .all(|bit| self.bits[bit / 8] & (1 << (bit % 8)) != 0)
+    }
+
+    /// The size of the filter's bits, in bytes.
+    pub fn size(&self) -> usize {
+        self.bits.len()
+    }
+}
+
+/// The hash of `user_key` that filters are built from.
+pub fn hash(user_key: &[u8]) -> u64 {
+    let mut hasher = twox_hash::XxHash64::with_seed(0);
+    hasher.write(user_key);
+    hasher.finish()
+}
+
+/// The bits a key with `hash` sets, derived from its two halves by double hashing.
+fn probe_bits(hash: u64, probes: u32, bits: usize) -> impl Iterator<Item = usize> {
+    let h1 = hash & 0xffff_ffff;
+    let h2 = (hash >> 32) | 1;
+
+    (0..probes as u64).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % bits as u64) as usize)
+}
@@ -0,0 +1,165 @@
+//! A shared LRU cache of decoded SSTable blocks.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::sstable::manager::FileNo;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BlockId {
+    pub file_no: FileNo,
+    /// The [`FileMeta::file_checksum`](crate::sstable::manifest::FileMeta::file_checksum)
+    /// of the file, so that when a cache is shared through
+    /// [`Config::block_cache`](crate::config::Config::block_cache), a file number reused for
+    /// different contents, or the same number in another database, can't be served the
+    /// first file's blocks.
+    pub generation: u64,
+    pub offset: u64,
+}
+
+#[derive(Debug)]
+struct Entry {
+    block: bytes::Bytes,
+    /// Whether the block's checksum has been verified.
+    verified: bool,
+    tick: u64,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    entries: HashMap<BlockId, Entry>,
+    /// Entries ordered by last access, oldest first.
+    lru: BTreeMap<u64, BlockId>,
+    tick: u64,
+    size: usize,
+}
+
+/// A size-bounded LRU cache of SSTable blocks, keyed by file number, generation and block
+/// offset.
+#[derive(Debug)]
+pub struct BlockCache {
+    capacity: usize,
+    /// A lower bound on the capacity set by [`BlockCache::set_limit`], while other memory
+    /// users need the room.
+    limit: AtomicUsize,
+    inner: parking_lot::Mutex<Inner>,
+}
+
+impl BlockCache {
+    pub fn new(capacity: usize) -> Self {
+        BlockCache {
+            capacity,
+            limit: AtomicUsize::new(capacity),
+            inner: parking_lot::Mutex::new(Inner::default()),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The size the cache is currently held to: its capacity, or the limit if that's lower.
+    pub fn effective_capacity(&self) -> usize {
+        self.capacity.min(self.limit.load(Ordering::Relaxed))
+    }
+
+    /// Holds the cache to `limit` bytes, below its capacity, evicting the least recently
+    /// used blocks until it fits. Raising the limit again lets it grow back as blocks are
+    /// read.
+    pub fn set_limit(&self, limit: usize) {
+        self.limit.store(limit, Ordering::Relaxed);
+
+        let mut inner = self.inner.lock();
+        Self::evict_to(&mut inner, self.effective_capacity());
+    }
+
+    /// The total size of all cached blocks.
+    pub fn size(&self) -> usize {
+        self.inner.lock().size
+    }
+
+    /// Returns the cached block and whether it has been checksum-verified.
+    pub fn get(&self, id: BlockId) -> Option<(bytes::Bytes, bool)> {
+        let mut inner = self.inner.lock();
+        let inner = &mut *inner;
+
+        let entry = inner.entries.get_mut(&id)?;
+
+        inner.lru.remove(&entry.tick);
+        inner.tick += 1;
+        entry.tick = inner.tick;
+        inner.lru.insert(entry.tick, id);
+
+        Some((entry.block.clone(), entry.verified))
+    }
+
+    pub fn insert(&self, id: BlockId, block: bytes::Bytes, verified: bool) {
+        let capacity = self.effective_capacity();
+
+        if block.len() > capacity {
+            return;
+        }
+
+        let mut inner = self.inner.lock();
+        let inner = &mut *inner;
+
+        inner.tick += 1;
+
+        let entry = Entry {
+            block,
+            verified,
+            tick: inner.tick,
+        };
+
+        inner.size += entry.block.len();
+        inner.lru.insert(entry.tick, id);
+
+        if let Some(old) = inner.entries.insert(id, entry) {
+            inner.size -= old.block.len();
+            inner.lru.remove(&old.tick);
+        }
+
+        Self::evict_to(inner, capacity);
+    }
+
+    /// Evicts the least recently used blocks until the cache holds at most `capacity` bytes.
+    fn evict_to(inner: &mut Inner, capacity: usize) {
+        while inner.size > capacity {
+            let Some((_, oldest)) = inner.lru.pop_first() else {
+                break;
+            };
+
+            if let Some(evicted) = inner.entries.remove(&oldest) {
+                inner.size -= evicted.block.len();
+            }
+        }
+    }
+
+    pub fn mark_verified(&self, id: BlockId) {
+        if let Some(entry) = self.inner.lock().entries.get_mut(&id) {
+            entry.verified = true;
+        }
+    }
+
+    /// Drops every cached block belonging to `file_no` at `generation`.
+    pub fn remove_file(&self, file_no: FileNo, generation: u64) {
+        let mut inner = self.inner.lock();
+        let inner = &mut *inner;
+
+        let size = &mut inner.size;
+        let lru = &mut inner.lru;
+
+        inner.entries.retain(|id, entry| {
+            if id.file_no != file_no || id.generation != generation {
+                return true;
+            }
+
+            *size -= entry.block.len();
+            lru.remove(&entry.tick);
+
+            false
+        });
+    }
+}
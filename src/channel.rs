@@ -0,0 +1,190 @@
+#![allow(dead_code)]
+
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    rc::Rc,
+    task::{Context, Poll, Waker},
+};
+
+/// Error returned by [`Sender::send`] when the receiver is gone, with the unsent value.
+#[derive(Debug, PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+/// Error returned by [`Sender::try_send`], with the unsent value.
+#[derive(Debug, PartialEq, Eq)]
+pub enum TrySendError<T> {
+    /// The channel is at capacity.
+    Full(T),
+    /// The receiver is gone.
+    Closed(T),
+}
+
+struct Inner<T> {
+    queue: VecDeque<T>,
+    capacity: usize,
+    /// The number of live senders. Once it drops to zero the receiver drains the queue and
+    /// then sees the end of the channel.
+    senders: usize,
+    /// Set once the receiver is closed or dropped; sends fail from then on.
+    closed: bool,
+    recv_waker: Option<Waker>,
+    /// Senders waiting for room in the queue.
+    send_wakers: Vec<Waker>,
+}
+
+pub struct Sender<T> {
+    inner: Rc<RefCell<Inner<T>>>,
+}
+
+pub struct Receiver<T> {
+    inner: Rc<RefCell<Inner<T>>>,
+}
+
+/// Create a bounded multi-producer, single-consumer channel that holds at most `capacity`
+/// values.
+///
+/// Like the oneshot channel, this is `!Send` and meant for single-threaded
+/// runtimes like Glommio. Values are received in the order they were sent.
+pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(capacity > 0, "channel capacity must be at least 1");
+
+    let inner = Rc::new(RefCell::new(Inner {
+        queue: VecDeque::with_capacity(capacity),
+        capacity,
+        senders: 1,
+        closed: false,
+        recv_waker: None,
+        send_wakers: Vec::new(),
+    }));
+    let tx = Sender {
+        inner: inner.clone(),
+    };
+    let rx = Receiver { inner };
+    (tx, rx)
+}
+
+impl<T> Sender<T> {
+    /// Sends a value without waiting for room.
+    pub fn try_send(&self, val: T) -> Result<(), TrySendError<T>> {
+        let mut inner = self.inner.borrow_mut();
+
+        if inner.closed {
+            return Err(TrySendError::Closed(val));
+        }
+
+        if inner.queue.len() >= inner.capacity {
+            return Err(TrySendError::Full(val));
+        }
+
+        inner.queue.push_back(val);
+
+        if let Some(waker) = inner.recv_waker.take() {
+            waker.wake();
+        }
+
+        Ok(())
+    }
+
+    /// Sends a value, waiting for room if the channel is full.
+    ///
+    /// Returns `Err` with the value if the receiver is closed or dropped, including while
+    /// waiting.
+    pub async fn send(&self, val: T) -> Result<(), SendError<T>> {
+        let mut val = Some(val);
+
+        std::future::poll_fn(|cx| {
+            match self.try_send(val.take().expect("polled after completion")) {
+                Ok(()) => Poll::Ready(Ok(())),
+                Err(TrySendError::Closed(v)) => Poll::Ready(Err(SendError(v))),
+                Err(TrySendError::Full(v)) => {
+                    val = Some(v);
+                    self.inner.borrow_mut().send_wakers.push(cx.waker().clone());
+                    Poll::Pending
+                }
+            }
+        })
+        .await
+    }
+
+    /// Whether the receiver has been closed or dropped, so there's no point sending.
+    pub fn is_closed(&self) -> bool {
+        self.inner.borrow().closed
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.inner.borrow_mut().senders += 1;
+
+        Sender {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut inner = self.inner.borrow_mut();
+
+        inner.senders -= 1;
+
+        if inner.senders == 0
+            && let Some(waker) = inner.recv_waker.take()
+        {
+            // Notify the receiver that the channel has ended.
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Receiver<T> {
+    /// Receives the next value, waiting for one to be sent.
+    ///
+    /// Returns `None` once every sender has been dropped (or the receiver closed) and every
+    /// value already sent has been received.
+    pub async fn recv(&mut self) -> Option<T> {
+        std::future::poll_fn(|cx| self.poll_recv(cx)).await
+    }
+
+    /// Polls for the next value, registering the task to be woken when one is sent if
+    /// there isn't one yet.
+    pub fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let mut inner = self.inner.borrow_mut();
+
+        if let Some(val) = inner.queue.pop_front() {
+            // Wake every waiting sender; whichever runs first takes the free slot and
+            // the rest wait again.
+            for waker in inner.send_wakers.drain(..) {
+                waker.wake();
+            }
+
+            return Poll::Ready(Some(val));
+        }
+
+        if inner.senders == 0 || inner.closed {
+            return Poll::Ready(None);
+        }
+
+        inner.recv_waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+
+    /// Stops accepting values. Values already sent can still be received.
+    pub fn close(&mut self) {
+        let mut inner = self.inner.borrow_mut();
+
+        inner.closed = true;
+
+        // Waiting senders fail rather than waiting for room that will never be used.
+        for waker in inner.send_wakers.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
@@ -0,0 +1,131 @@
+//! Time sources, injectable through [`Config::clock`](crate::config::Config::clock) so that
+//! time-based behaviour can be driven deterministically.
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+pub trait Clock: std::fmt::Debug + Send + Sync {
+    fn now(&self) -> Instant;
+
+    /// The wall-clock time, for times that have to survive a restart.
+    fn wall_time(&self) -> SystemTime;
+
+    /// [`Clock::wall_time`] in milliseconds since the Unix epoch.
+    fn unix_millis(&self) -> u64 {
+        self.wall_time()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |since| since.as_millis() as u64)
+    }
+
+    /// Blocks the calling thread for `duration`. Only used where there's nothing to await,
+    /// like retrying a file lock while opening the database; see [`Clock::sleep_async`].
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+
+    /// Waits for `duration` without blocking the executor, so that other tasks run in the
+    /// meantime, like reads while a flush backs off before a retry. Has to be awaited on a
+    /// glommio executor.
+    fn sleep_async(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()>>> {
+        Box::pin(glommio::timer::sleep(duration))
+    }
+}
+
+/// The real monotonic clock.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn wall_time(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A clock that only moves when told to.
+#[derive(Debug)]
+pub struct ManualClock {
+    now: parking_lot::Mutex<(Instant, SystemTime)>,
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        ManualClock {
+            now: parking_lot::Mutex::new((Instant::now(), SystemTime::now())),
+        }
+    }
+
+    /// Moves both the monotonic and the wall-clock time forward by `by`.
+    pub fn advance(&self, by: Duration) {
+        let mut now = self.now.lock();
+
+        now.0 += by;
+        now.1 += by;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.now.lock().0
+    }
+
+    fn wall_time(&self) -> SystemTime {
+        self.now.lock().1
+    }
+
+    /// Returns immediately, having moved the clock forward by `duration`.
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+
+    /// Resolves immediately, having moved the clock forward by `duration`.
+    fn sleep_async(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()>>> {
+        self.advance(duration);
+        Box::pin(std::future::ready(()))
+    }
+}
+
+/// Decides when a long-running task should yield to the executor, based on how long it
+/// has run since it last yielded.
+#[derive(Debug)]
+pub struct YieldTimer {
+    clock: Arc<dyn Clock>,
+    interval: Duration,
+    last_yield: Instant,
+}
+
+impl YieldTimer {
+    pub fn new(clock: Arc<dyn Clock>, interval: Duration) -> Self {
+        let last_yield = clock.now();
+
+        YieldTimer {
+            clock,
+            interval,
+            last_yield,
+        }
+    }
+
+    /// Returns true if at least `interval` has elapsed since the last [`YieldTimer::reset`].
+    pub fn should_yield(&self) -> bool {
+        self.clock.now().duration_since(self.last_yield) >= self.interval
+    }
+
+    /// Restarts the interval. Call this after yielding, so that time spent suspended isn't
+    /// counted against the task.
+    pub fn reset(&mut self) {
+        self.last_yield = self.clock.now();
+    }
+}
@@ -0,0 +1,87 @@
+//! Column families: independent keyspaces that share a database's WAL and seqno space.
+
+use std::{collections::VecDeque, sync::Arc};
+
+use crate::memtable::{state, MemTable};
+
+/// Name of the column family every database starts with.
+pub const DEFAULT_COLUMN_FAMILY_NAME: &str = "default";
+
+#[derive(
+    Debug, Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize,
+)]
+pub struct ColumnFamilyId(pub u32);
+
+impl ColumnFamilyId {
+    /// The id of the [`DEFAULT_COLUMN_FAMILY_NAME`] column family.
+    pub const DEFAULT: ColumnFamilyId = ColumnFamilyId(0);
+}
+
+impl std::fmt::Display for ColumnFamilyId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// A handle to a column family, returned by
+/// [`Database::create_cf`](crate::Database::create_cf) and [`Database::cf`](crate::Database::cf).
+///
+/// Keys in different column families never interact: each family has its own memtables and
+/// SSTables. Writes to several families can still be made atomically with a
+/// [`WriteBatch`](crate::batch::WriteBatch), since all families share one WAL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnFamily {
+    id: ColumnFamilyId,
+    name: Arc<str>,
+}
+
+impl ColumnFamily {
+    pub(crate) fn new(id: ColumnFamilyId, name: impl Into<Arc<str>>) -> Self {
+        ColumnFamily {
+            id,
+            name: name.into(),
+        }
+    }
+
+    pub fn id(&self) -> ColumnFamilyId {
+        self.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// The in-memory state of a single column family.
+pub(crate) struct ColumnFamilyData {
+    pub(crate) handle: ColumnFamily,
+
+    /// The active MemTable
+    pub(crate) table: MemTable<state::Active>,
+
+    /// Frozen, immutable memtables waiting to be turned into SSTables.
+    pub(crate) imm_tables: glommio::sync::RwLock<VecDeque<MemTable<state::Frozen>>>,
+}
+
+impl ColumnFamilyData {
+    pub(crate) fn new(handle: ColumnFamily) -> Self {
+        ColumnFamilyData {
+            handle,
+            table: MemTable::new(),
+            imm_tables: glommio::sync::RwLock::new(VecDeque::new()),
+        }
+    }
+
+    /// The bytes held by the active and frozen memtables. Frozen memtables are only ever
+    /// write-locked without awaiting in between, so they can't be locked while this runs.
+    pub(crate) fn memtable_bytes(&self) -> usize {
+        self.table.size() + self.frozen_memtable_bytes()
+    }
+
+    /// The bytes held by the frozen memtables alone, waiting to be flushed.
+    pub(crate) fn frozen_memtable_bytes(&self) -> usize {
+        self.imm_tables
+            .try_read()
+            .map_or(0, |tables| tables.iter().map(MemTable::size).sum())
+    }
+}
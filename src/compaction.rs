@@ -0,0 +1,331 @@
+//! Compaction: merging SSTables into the level below, dropping data no reader can see.
+
+use std::sync::Arc;
+
+use crate::{
+    column_family::ColumnFamilyId,
+    iter::MergeIterator,
+    key::{Key, SeqNo},
+    sstable::{manifest::FileMeta, Level},
+    tombstone::RangeTombstone,
+    value::Value,
+};
+
+/// A compaction to run with [`Database::run_compaction`](crate::Database::run_compaction).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompactionJob {
+    pub cf: ColumnFamilyId,
+    /// Every file in this level is merged, along with the files it overlaps in the next
+    /// level, into the next level.
+    pub level: Level,
+}
+
+/// Default number of runs a level collects before size-tiered compaction merges it.
+pub const DEFAULT_SIZE_TIERED_MIN_RUNS: usize = 4;
+
+/// How [`Database::compact`](crate::Database::compact) picks what to merge, configured with
+/// [`Config::compaction_strategy`](crate::config::Config::compaction_strategy).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CompactionStrategy {
+    /// Merge L0 into L1, rewriting the L1 files it overlaps, so that every level below L0
+    /// is a single sorted run. Reads check one file per level, at the cost of rewriting
+    /// data each time it moves down a level.
+    #[default]
+    Leveled,
+    /// Let each level collect runs, and once one holds `min_runs` of them merge them all
+    /// into a single new run in the next level. Each entry is rewritten about once per
+    /// level, at the cost of reads checking a file per run.
+    SizeTiered { min_runs: usize },
+}
+
+/// What a [`CompactionFilter`] wants done with an entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FilterDecision {
+    Keep,
+    /// Delete the entry, as if it had been overwritten with a tombstone.
+    Remove,
+    /// Replace the entry's value.
+    ChangeValue(bytes::Bytes),
+}
+
+/// A hook consulted by compaction for each live entry it rewrites, configured with
+/// [`Config::compaction_filter`](crate::config::Config::compaction_filter).
+///
+/// Only the newest version of each key is filtered; older versions kept alive for snapshots
+/// are left as they are. Entries are only filtered when they're compacted, so a filter's
+/// effects show up gradually rather than all at once.
+pub trait CompactionFilter: std::fmt::Debug + Send + Sync {
+    /// Decides the fate of `key`, which is being compacted into `level`.
+    fn filter(&self, level: Level, key: &bytes::Bytes, value: &bytes::Bytes) -> FilterDecision;
+}
+
+/// Checks run on a compaction's output before it replaces the inputs, configured with
+/// [`Config::compaction_verification`](crate::config::Config::compaction_verification).
+///
+/// Every output file is read back in full, checking its checksums, that its keys are in
+/// order and match the range recorded for it, and that it holds as many entries as were
+/// written to it. The entries written are reconciled against those read from the inputs,
+/// and the output has to fall within the inputs' key range.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CompactionVerification {
+    /// The largest fraction of a compaction's input entries, from 0 to 1, that the
+    /// [`CompactionFilter`] may remove before the compaction is taken to have gone wrong.
+    pub max_filtered_fraction: f64,
+}
+
+impl Default for CompactionVerification {
+    fn default() -> Self {
+        CompactionVerification {
+            max_filtered_fraction: 1.0,
+        }
+    }
+}
+
+/// Returned by a compaction whose output failed its [`CompactionVerification`]. The output
+/// has been deleted, and the inputs are left as they were.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompactionVerificationFailed {
+    pub reason: String,
+}
+
+impl std::fmt::Display for CompactionVerificationFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Compaction aborted, its output failed verification: {}",
+            self.reason
+        )
+    }
+}
+
+impl std::error::Error for CompactionVerificationFailed {}
+
+/// The entries a [`CompactionIterator`] has read, and what became of them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct CompactionCounts {
+    pub(crate) read: u64,
+    pub(crate) written: u64,
+    /// Entries the [`CompactionFilter`] removed.
+    pub(crate) filtered: u64,
+    /// Tombstones dropped once there was nothing left for them to hide.
+    pub(crate) tombstones_dropped: u64,
+    /// Expired values dropped along with the tombstones they turned into.
+    pub(crate) expired_dropped: u64,
+}
+
+/// What a manual compaction did, returned by
+/// [`Database::compact_level`](crate::Database::compact_level) and
+/// [`Database::run_compaction`](crate::Database::run_compaction).
+///
+/// Files moved down a level without being rewritten are counted in `files_moved`, and
+/// nowhere else.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompactionResult {
+    /// The number of files merged, and their total size.
+    pub input_files: usize,
+    pub input_bytes: u64,
+    /// The number of files written in their place, and their total size.
+    pub output_files: usize,
+    pub output_bytes: u64,
+    pub files_moved: usize,
+
+    /// The number of entries read from the input files, and written to the output files.
+    /// The difference is made up of the dropped entries counted below, and of versions
+    /// that were overwritten or deleted by a range tombstone.
+    pub entries_read: u64,
+    pub entries_written: u64,
+    pub tombstones_dropped: u64,
+    pub expired_dropped: u64,
+    /// Entries the [`Config::compaction_filter`](crate::config::Config::compaction_filter)
+    /// removed.
+    pub entries_filtered: u64,
+}
+
+impl CompactionResult {
+    pub(crate) fn new(inputs: &[FileMeta], outputs: &[FileMeta], counts: CompactionCounts) -> Self {
+        CompactionResult {
+            input_files: inputs.len(),
+            input_bytes: inputs.iter().map(|file| file.file_size).sum(),
+            output_files: outputs.len(),
+            output_bytes: outputs.iter().map(|file| file.file_size).sum(),
+            files_moved: 0,
+            entries_read: counts.read,
+            entries_written: counts.written,
+            tombstones_dropped: counts.tombstones_dropped,
+            expired_dropped: counts.expired_dropped,
+            entries_filtered: counts.filtered,
+        }
+    }
+
+    /// The space freed on disk, once the input files are deleted.
+    pub fn bytes_reclaimed(&self) -> u64 {
+        self.input_bytes.saturating_sub(self.output_bytes)
+    }
+
+    pub(crate) fn add(&mut self, other: CompactionResult) {
+        self.input_files += other.input_files;
+        self.input_bytes += other.input_bytes;
+        self.output_files += other.output_files;
+        self.output_bytes += other.output_bytes;
+        self.files_moved += other.files_moved;
+        self.entries_read += other.entries_read;
+        self.entries_written += other.entries_written;
+        self.tombstones_dropped += other.tombstones_dropped;
+        self.expired_dropped += other.expired_dropped;
+        self.entries_filtered += other.entries_filtered;
+    }
+}
+
+/// Applies compaction's garbage collection rules to an all-versions merge of its inputs.
+///
+/// Versions newer than the oldest live snapshot are always kept. At or below it, only the
+/// newest version of each key is kept, and that too is dropped if it's deleted by a range
+/// tombstone. Expired values are turned into tombstones. Tombstones themselves are dropped
+/// once nothing older than them can remain, which is only true when compacting into the
+/// bottom level.
+pub(crate) struct CompactionIterator<'a> {
+    input: MergeIterator<'a>,
+    level: Level,
+    bottommost: bool,
+    oldest_snapshot: Option<SeqNo>,
+    range_tombstones: &'a [RangeTombstone],
+    filter: Option<Arc<dyn CompactionFilter>>,
+    /// The current time in milliseconds since the Unix epoch, for expiring values.
+    now: u64,
+
+    /// The user key of the previous entry.
+    current_user_key: Option<bytes::Bytes>,
+    /// Whether a version of `current_user_key` visible to every reader has been seen, which
+    /// hides every older version.
+    shadowed: bool,
+
+    counts: CompactionCounts,
+}
+
+impl<'a> CompactionIterator<'a> {
+    pub(crate) fn new(
+        input: MergeIterator<'a>,
+        level: Level,
+        bottommost: bool,
+        oldest_snapshot: Option<SeqNo>,
+        range_tombstones: &'a [RangeTombstone],
+        filter: Option<Arc<dyn CompactionFilter>>,
+        now: u64,
+    ) -> Self {
+        CompactionIterator {
+            input,
+            level,
+            bottommost,
+            oldest_snapshot,
+            range_tombstones,
+            filter,
+            now,
+            current_user_key: None,
+            shadowed: false,
+            counts: CompactionCounts::default(),
+        }
+    }
+
+    pub(crate) fn counts(&self) -> CompactionCounts {
+        self.counts
+    }
+
+    /// Whether a write at `seqno` is visible to every current and future reader.
+    fn visible_to_all(&self, seqno: SeqNo) -> bool {
+        self.oldest_snapshot.is_none_or(|oldest| seqno <= oldest)
+    }
+
+    /// The range tombstones that should be carried into the compaction's output.
+    pub(crate) fn surviving_range_tombstones(&self) -> Vec<RangeTombstone> {
+        self.range_tombstones
+            .iter()
+            .filter(|t| !(self.bottommost && self.visible_to_all(t.seqno)))
+            .cloned()
+            .collect()
+    }
+
+    /// Decides what, if anything, to write for `key`.
+    fn compact(&mut self, key: Key, value: Value) -> Option<(Key, Value)> {
+        let newest = self.current_user_key.as_ref() != Some(key.user_key());
+
+        if newest {
+            self.current_user_key = Some(key.user_key().clone());
+            self.shadowed = false;
+        }
+
+        let visible_to_all = self.visible_to_all(key.seqno());
+
+        if visible_to_all {
+            if self.shadowed {
+                return None;
+            }
+
+            self.shadowed = true;
+
+            let deleted = self
+                .range_tombstones
+                .iter()
+                .any(|t| self.visible_to_all(t.seqno) && t.deletes(&key, t.seqno));
+
+            if deleted {
+                return None;
+            }
+        }
+
+        // Nothing older than a tombstone can remain below the bottom level, so once every
+        // reader can see the tombstone it has nothing left to hide.
+        let droppable = self.bottommost && visible_to_all;
+
+        // An expired value reads the same as a tombstone, and still has to hide older
+        // versions until it can be dropped.
+        let expired = value.is_expired(self.now);
+        let value = if expired { Value::Tombstone } else { value };
+
+        if droppable && matches!(value, Value::Tombstone) {
+            if expired {
+                self.counts.expired_dropped += 1;
+            } else {
+                self.counts.tombstones_dropped += 1;
+            }
+
+            return None;
+        }
+
+        let decision = match (&self.filter, value.data()) {
+            (Some(filter), Some(data)) if newest => filter.filter(self.level, key.user_key(), data),
+            _ => FilterDecision::Keep,
+        };
+
+        if decision == FilterDecision::Remove {
+            self.counts.filtered += 1;
+        }
+
+        match decision {
+            FilterDecision::Keep => Some((key, value)),
+            FilterDecision::Remove if droppable => None,
+            FilterDecision::Remove => Some((key, Value::Tombstone)),
+            FilterDecision::ChangeValue(bytes) => Some((key, value.with_data(bytes))),
+        }
+    }
+}
+
+impl Iterator for CompactionIterator<'_> {
+    type Item = anyhow::Result<(Key, Value)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (key, value) = match self.input.next()? {
+                Ok(entry) => entry,
+                Err(e) => return Some(Err(e)),
+            };
+
+            self.counts.read += 1;
+
+            if let Some(entry) = self.compact(key, value) {
+                self.counts.written += 1;
+
+                return Some(Ok(entry));
+            }
+        }
+    }
+}
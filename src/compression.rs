@@ -0,0 +1,59 @@
+//! Block/record compression codecs.
+
+/// The most an LZ4 block can expand by when decompressed. Each extra byte of a match length
+/// adds at most 255 bytes of output, so no valid input decompresses to more than this many
+/// times its size.
+pub const LZ4_MAX_RATIO: usize = 255;
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize,
+)]
+#[repr(u8)]
+pub enum Compression {
+    #[default]
+    None = 0,
+    Lz4 = 1,
+}
+
+impl Compression {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            x if x == Compression::None as u8 => Some(Compression::None),
+            x if x == Compression::Lz4 as u8 => Some(Compression::Lz4),
+            _ => None,
+        }
+    }
+
+    pub fn compress(&self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Compression::None => data.to_vec(),
+            Compression::Lz4 => lz4_flex::compress_prepend_size(data),
+        }
+    }
+
+    pub fn decompress(&self, data: &[u8]) -> anyhow::Result<Vec<u8>> {
+        match self {
+            Compression::None => Ok(data.to_vec()),
+            Compression::Lz4 => {
+                let Some((size, compressed)) = data.split_first_chunk::<4>() else {
+                    anyhow::bail!("LZ4 data is too short to hold its size");
+                };
+
+                // The size is read from disk, and may be corrupt, so it's checked before it's
+                // allocated.
+                let size = u32::from_le_bytes(*size) as usize;
+
+                if size > compressed.len().saturating_mul(LZ4_MAX_RATIO) {
+                    anyhow::bail!(
+                        "LZ4 data claims to decompress to {size} bytes, more than {} bytes of it \
+                         could",
+                        compressed.len()
+                    );
+                }
+
+                lz4_flex::decompress(compressed, size)
+                    .map_err(|e| anyhow::anyhow!("Failed to decompress LZ4 data: {e}"))
+            }
+        }
+    }
+}
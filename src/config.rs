@@ -1,6 +1,352 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+use crate::{
+    bloom::BloomFilterLevels,
+    cache::BlockCache,
+    clock::{Clock, SystemClock},
+    compaction::{CompactionFilter, CompactionStrategy, CompactionVerification},
+    compression::Compression,
+    counter::CounterOverflow,
+    lock::LockStrategy,
+    memtable::MemtableSize,
+    retry::RetryPolicy,
+    scrub::CorruptionListener,
+    sstable::sstable::{KeyEncoding, BLOCK_SIZE},
+    stall::WriteStallListener,
+    wal::WritePolicy,
+};
+
+/// Default size memtables are frozen and flushed at (64KB).
+pub const DEFAULT_MEMTABLE_SIZE: usize = 1024 * 64;
+/// Default WAL preallocation chunk (1MB).
+pub const DEFAULT_WAL_PREALLOCATE_CHUNK: u64 = 1024 * 1024;
+/// Default block cache capacity (8MB).
+pub const DEFAULT_BLOCK_CACHE_CAPACITY: usize = 1024 * 1024 * 8;
+/// Default minimum serialized record size for WAL compression (512B).
+pub const DEFAULT_WAL_COMPRESSION_THRESHOLD: usize = 512;
+/// Default initial capacity of the buffer SSTable blocks are built in (20KB): a full block
+/// plus room for its last entry and restart points.
+pub const DEFAULT_BLOCK_BUFFER_CAPACITY: usize = BLOCK_SIZE + 4 * 1024;
+/// Default number of entries between SSTable block restart points.
+pub const DEFAULT_BLOCK_RESTART_INTERVAL: usize = 16;
+/// Default number of manifest records between manifest snapshots.
+pub const DEFAULT_MANIFEST_SNAPSHOT_INTERVAL: usize = 1024;
+/// Default memory [`Database::ingest_unsorted`](crate::Database::ingest_unsorted) sorts in
+/// before spilling to disk (64MB).
+pub const DEFAULT_INGEST_MEMORY_BUDGET: usize = 1024 * 1024 * 64;
+/// Default time a write's idempotency key is remembered (1 minute).
+pub const DEFAULT_IDEMPOTENCY_WINDOW: Duration = Duration::from_secs(60);
+/// Default number of idempotency keys remembered.
+pub const DEFAULT_IDEMPOTENCY_CAPACITY: usize = 4096;
+/// Default time a memtable flush runs before yielding to other tasks (500µs).
+pub const DEFAULT_FLUSH_YIELD_INTERVAL: Duration = Duration::from_micros(500);
 
 #[derive(Debug, Clone)]
 pub struct Config {
     pub data_dir: PathBuf,
+
+    /// Whether writes go to a write-ahead log before their memtable. Without one, writes
+    /// are only durable once [`Database::flush`](crate::Database::flush) (or
+    /// [`Database::close`](crate::Database::close)) has written them to SSTables: a crash,
+    /// or dropping the database without closing it, loses every write since the last flush.
+    /// Suits data that can be rebuilt, like caches and staging stores.
+    ///
+    /// Can be changed between opens, but the WAL can only be disabled once it's empty, as
+    /// it is after [`Database::close`](crate::Database::close).
+    pub wal_enabled: bool,
+
+    /// Whether writes are logged to the WAL before or after they're applied to their
+    /// memtable, trading durability for the latency of the WAL append. See [`WritePolicy`]
+    /// for what a crash loses under each.
+    pub write_policy: WritePolicy,
+
+    /// Size of the chunks the WAL file is grown by when an append would run past the
+    /// end of the file. Set to 0 to disable preallocation and grow the file per-record.
+    pub wal_preallocate_chunk: u64,
+
+    /// Compression applied to individual WAL records. Fixed when the database is created:
+    /// opening it with a different setting fails with
+    /// [`OptionsMismatch`](crate::sstable::manifest::OptionsMismatch).
+    pub wal_compression: Compression,
+    /// Records smaller than this (serialized) are written to the WAL uncompressed.
+    pub wal_compression_threshold: usize,
+
+    /// Whether SSTable block checksums are verified when blocks are read. Can be overridden
+    /// per read with [`ReadOptions::verify_checksums`](crate::options::ReadOptions).
+    ///
+    /// Disabling this saves a CRC pass over every block read, at the cost of returning
+    /// garbage (or failing to decode) if the storage has silently corrupted a block.
+    pub verify_checksums_on_read: bool,
+
+    /// How large a memtable grows before it's frozen and flushed to an L0 SSTable, either
+    /// fixed or adapting to the write rate.
+    pub memtable_size: MemtableSize,
+
+    /// Capacity of the SSTable block cache in bytes. Set to 0 to disable caching.
+    pub block_cache_capacity: usize,
+
+    /// A block cache to use in place of one of [`Config::block_cache_capacity`], which can
+    /// be shared with other handles, or kept by a parent across restarts, so they start
+    /// with warm blocks. Blocks are keyed by each file's checksum as well as its number,
+    /// so databases sharing it never see each other's blocks.
+    ///
+    /// A handle with a [`Config::total_memory_budget`] limits the cache to fit it, which
+    /// limits it for every handle sharing it.
+    pub block_cache: Option<Arc<BlockCache>>,
+
+    /// A cap on the memory held by the memtables and the block cache together, in bytes,
+    /// on top of their own limits. The block cache gives up whatever the memtables take, and
+    /// once the memtables hold more than half the budget they're flushed early, so that
+    /// the cache always keeps some of it. `None` leaves each to its own limit.
+    pub total_memory_budget: Option<usize>,
+
+    /// The bytes of keys and values
+    /// [`Database::ingest_unsorted`](crate::Database::ingest_unsorted) sorts in memory
+    /// before spilling them to a run file on disk.
+    pub ingest_memory_budget: usize,
+
+    /// The size, in bytes, at which an SSTable block being written is finished and a new
+    /// one started. Larger blocks make for a smaller index and compress better, smaller
+    /// ones cost less to read for a point lookup.
+    ///
+    /// Only writes use this. Readers take each block's bounds from its file's index, so
+    /// files written with any block size, by this database or another, read the same.
+    pub block_size: usize,
+
+    /// The initial capacity, in bytes, of the buffer SSTable blocks are built in while
+    /// flushing and compacting. A block is finished once it reaches
+    /// [`block_size`](Self::block_size), so its last entry and restart points run past
+    /// that: room for them up front saves growing the buffer when the first block is
+    /// finished.
+    pub block_buffer_capacity: usize,
+
+    /// The number of entries after which the next new user key in an SSTable block gets a
+    /// restart point, which seeks within the block binary search over.
+    pub block_restart_interval: usize,
+
+    /// Whether every new user key in an SSTable block gets a restart point, so that a seek
+    /// to a user key with many versions lands straight on its first version. Restart points
+    /// are still kept at least [`MIN_RESTART_SPACING`] bytes apart.
+    ///
+    /// [`MIN_RESTART_SPACING`]: crate::sstable::sstable::MIN_RESTART_SPACING
+    pub restart_at_every_user_key: bool,
+
+    /// How keys are stored in newly written SSTable blocks. Each file records its own
+    /// encoding, so this can be changed between opens.
+    pub key_encoding: KeyEncoding,
+
+    /// Compression applied to each block of newly written SSTables. Each file records its
+    /// own, so this can be changed between opens, and
+    /// [`Database::recompress`](crate::Database::recompress) brings existing files over.
+    /// Also applied to the snapshots the manifest is rotated into.
+    pub sstable_compression: Compression,
+
+    /// Which levels' newly written SSTables get a bloom filter, which point lookups check
+    /// before reading a block. Filters are held in memory along with the manifest, so
+    /// leaving them out of the deepest levels trades read cost on cold data for memory.
+    /// Files already written keep theirs until they're compacted.
+    pub bloom_filter_levels: BloomFilterLevels,
+
+    /// Whether L0 is organized into sub-levels of non-overlapping files, so that a point
+    /// lookup checks at most one file per sub-level. When disabled, every flushed memtable
+    /// is its own sub-level.
+    pub l0_sub_levels: bool,
+
+    /// The most bytes a flush can write and still be placed straight in L1, skipping L0,
+    /// when none of its files overlap a file in L0 or L1. Saves compacting the tiny L0
+    /// files that frequent explicit flushes or a low write rate make. Only applies under
+    /// [`CompactionStrategy::Leveled`]; `None` always flushes to L0.
+    pub l1_flush_max_bytes: Option<u64>,
+
+    /// The number of records appended to the manifest after which it's snapshotted into a
+    /// fresh file, which bounds how much of it opening the database has to replay. Set to 0
+    /// to never snapshot.
+    pub manifest_snapshot_interval: usize,
+    /// The number of old manifest files kept when the manifest is snapshotted into a fresh
+    /// one, newest first, so that the database can be inspected as of each of them with
+    /// [`Database::open_at_manifest`](crate::Database::open_at_manifest). The SSTables they
+    /// reference aren't kept, so that only works until compaction deletes one of them.
+    ///
+    /// Older ones are deleted as the manifest is rotated, other than one a handle is still
+    /// open at, which is deleted by a later rotation once it's closed.
+    pub manifest_retention: usize,
+    /// Whether the CURRENT file, which names the active manifest, is written with a CRC32
+    /// of the name. A CURRENT that fails its checksum, or doesn't hold a manifest name at
+    /// all, is rebuilt on open to point at the manifest with the highest committed seqno
+    /// rather than failing it. Both forms are read either way, but versions from before
+    /// the checksum can only read CURRENT without it.
+    pub current_checksum: bool,
+
+    /// Whether [`Database::open`](crate::Database::open) drops SSTables that the manifest
+    /// references but that are missing from disk, rather than failing with
+    /// [`MissingSstable`](crate::sstable::manager::MissingSstable). Their data is lost.
+    pub repair_missing_sstables: bool,
+
+    /// Whether [`Database::open`](crate::Database::open) double-checks its own recovery,
+    /// re-reading the WAL after replaying it and failing if any record that wasn't already
+    /// in an SSTable is missing from the rebuilt memtables. Costs a second pass over the WAL.
+    ///
+    /// It also makes open fail on a WAL holding two records for the same key at the same
+    /// seqno, and a flush fail on a memtable that was written such a pair, which only a bug
+    /// can do. Otherwise the last of them wins, as it would have when they were written,
+    /// and the flush logs the duplicate. Likewise a WAL record that fails its checksum
+    /// fails open, rather than the WAL being cut off before it.
+    ///
+    /// Memtables are also recounted before they're frozen by
+    /// [`Database::flush`](crate::Database::flush) or
+    /// [`Database::freeze_memtable`](crate::Database::freeze_memtable), failing the call if
+    /// the size they've kept track of as they were written doesn't match their contents.
+    pub paranoid_checks: bool,
+
+    /// The number of frozen memtables a column family can hold before a write to it stalls
+    /// until they've been flushed. Memtables are normally flushed as soon as they're frozen,
+    /// so this only comes into play when they're frozen without being flushed, with
+    /// [`Database::freeze_memtable`](crate::Database::freeze_memtable). `None` never stalls.
+    pub max_frozen_memtables: Option<usize>,
+
+    /// The bytes the frozen memtables of every column family together can hold before a
+    /// write stalls until they've been flushed. Unlike
+    /// [`max_frozen_memtables`](Self::max_frozen_memtables) this tracks the memory they
+    /// actually hold, however large each one grew, such as under an adaptive
+    /// [`memtable_size`](Self::memtable_size). `None` never stalls.
+    pub max_frozen_bytes: Option<usize>,
+
+    /// How long a write can sit in an active memtable, by [`Config::clock`], before the
+    /// memtables are flushed even though they're under their size limit. Bounds how far the
+    /// SSTables can fall behind when writes are slow. Writes check it as they come in; see
+    /// [`Database::flush_aged_memtables`](crate::Database::flush_aged_memtables) for when
+    /// they stop. `None` only flushes on size.
+    pub max_memtable_age: Option<Duration>,
+
+    /// The number of files L0 of a column family can hold before a write to it stalls until
+    /// L0 has been compacted into L1, which bounds how many files a read may have to check.
+    /// `None` never stalls, leaving compaction entirely to [`Database::compact`].
+    ///
+    /// [`Database::compact`]: crate::Database::compact
+    pub l0_stop_writes_trigger: Option<usize>,
+
+    /// Told when writes start and stop stalling on [`Config::max_frozen_memtables`],
+    /// [`Config::max_frozen_bytes`], [`Config::l0_stop_writes_trigger`], or
+    /// [`Config::max_total_bytes`].
+    pub on_write_stall: Option<Arc<dyn WriteStallListener>>,
+
+    /// Told about every corrupt block or unreadable SSTable
+    /// [`Database::scrub`](crate::Database::scrub) finds.
+    pub on_corruption: Option<Arc<dyn CorruptionListener>>,
+
+    /// A cap on the total size of the database's SSTables, in bytes. When a flush leaves
+    /// them over this, every level is compacted to reclaim space, and if that isn't enough
+    /// writes other than deletes fail with
+    /// [`DiskBudgetExceeded`](crate::db::DiskBudgetExceeded) until space is freed.
+    pub max_total_bytes: Option<u64>,
+
+    /// How long the [`WriteOptions::idempotency_key`] of a write is remembered.
+    ///
+    /// [`WriteOptions::idempotency_key`]: crate::options::WriteOptions::idempotency_key
+    pub idempotency_window: Duration,
+    /// The most idempotency keys remembered at once. The oldest are forgotten first.
+    pub idempotency_capacity: usize,
+
+    /// How long [`Database::open`](crate::Database::open) waits for another handle to the
+    /// database to let go of it before failing with [`AlreadyOpen`](crate::db::AlreadyOpen).
+    /// `None` waits forever.
+    pub open_lock_timeout: Option<Duration>,
+
+    /// How the database is locked against being opened by two handles at once.
+    pub lock_strategy: LockStrategy,
+
+    /// How flushes and compactions retry after a transient IO error, such as a full disk,
+    /// rather than failing. Off by default.
+    pub io_retry: RetryPolicy,
+
+    /// How long a memtable flush may run before yielding to foreground tasks.
+    pub flush_yield_interval: Duration,
+
+    /// The time source used for all time-based decisions.
+    pub clock: Arc<dyn Clock>,
+
+    /// How compaction picks the files it merges. Can be changed between opens: leveled
+    /// compaction merges any runs size-tiered compaction left behind as it reaches them.
+    pub compaction_strategy: CompactionStrategy,
+
+    /// Consulted for each entry rewritten by compaction, to drop or transform it.
+    pub compaction_filter: Option<Arc<dyn CompactionFilter>>,
+
+    /// Checks each compaction's output before it replaces the inputs, as a safety net for
+    /// compaction filters and strategies. A compaction that fails them is aborted with
+    /// [`CompactionVerificationFailed`](crate::compaction::CompactionVerificationFailed),
+    /// leaving the inputs in place. Costs reading everything a compaction writes back a
+    /// second time. `None` doesn't check.
+    pub compaction_verification: Option<CompactionVerification>,
+
+    /// What [`Database::increment`](crate::Database::increment) does when a counter would
+    /// overflow.
+    pub counter_overflow: CounterOverflow,
+
+    /// The number of shards a server splits the keyspace across, each a separate database
+    /// under `data_dir` opened with [`Config::for_shard`] and owned by its own executor. See
+    /// [`shard`](crate::shard). [`Database::open`](crate::Database::open) itself ignores it.
+    pub shards: usize,
+}
+
+impl Config {
+    pub fn new(data_dir: impl Into<PathBuf>) -> Self {
+        Config {
+            data_dir: data_dir.into(),
+            wal_enabled: true,
+            write_policy: WritePolicy::WriteAhead,
+            wal_preallocate_chunk: DEFAULT_WAL_PREALLOCATE_CHUNK,
+            wal_compression: Compression::None,
+            wal_compression_threshold: DEFAULT_WAL_COMPRESSION_THRESHOLD,
+            verify_checksums_on_read: true,
+            memtable_size: MemtableSize::Fixed(DEFAULT_MEMTABLE_SIZE),
+            block_cache_capacity: DEFAULT_BLOCK_CACHE_CAPACITY,
+            block_cache: None,
+            total_memory_budget: None,
+            ingest_memory_budget: DEFAULT_INGEST_MEMORY_BUDGET,
+            block_size: BLOCK_SIZE,
+            block_buffer_capacity: DEFAULT_BLOCK_BUFFER_CAPACITY,
+            block_restart_interval: DEFAULT_BLOCK_RESTART_INTERVAL,
+            restart_at_every_user_key: false,
+            key_encoding: KeyEncoding::Plain,
+            sstable_compression: Compression::None,
+            bloom_filter_levels: BloomFilterLevels::All,
+            l0_sub_levels: true,
+            l1_flush_max_bytes: None,
+            manifest_snapshot_interval: DEFAULT_MANIFEST_SNAPSHOT_INTERVAL,
+            manifest_retention: 0,
+            current_checksum: true,
+            repair_missing_sstables: false,
+            paranoid_checks: false,
+            max_frozen_memtables: None,
+            max_frozen_bytes: None,
+            max_memtable_age: None,
+            l0_stop_writes_trigger: None,
+            on_write_stall: None,
+            on_corruption: None,
+            max_total_bytes: None,
+            idempotency_window: DEFAULT_IDEMPOTENCY_WINDOW,
+            idempotency_capacity: DEFAULT_IDEMPOTENCY_CAPACITY,
+            open_lock_timeout: None,
+            lock_strategy: LockStrategy::default(),
+            io_retry: RetryPolicy::default(),
+            flush_yield_interval: DEFAULT_FLUSH_YIELD_INTERVAL,
+            clock: Arc::new(SystemClock),
+            compaction_strategy: CompactionStrategy::Leveled,
+            compaction_filter: None,
+            compaction_verification: None,
+            counter_overflow: CounterOverflow::Error,
+            shards: 1,
+        }
+    }
+
+    /// The config to open the database of `shard` with: this one, with `data_dir` set to
+    /// the shard's subdirectory of it.
+    pub fn for_shard(&self, shard: usize) -> Config {
+        Config {
+            data_dir: crate::shard::shard_dir(&self.data_dir, shard),
+            shards: 1,
+            ..self.clone()
+        }
+    }
 }
@@ -0,0 +1,75 @@
+//! The integer encoding of the counters maintained by
+//! [`Database::increment`](crate::Database::increment).
+//!
+//! A counter is stored as an ordinary value: its `i64` in 8 big-endian bytes. Reading a
+//! counter key returns those bytes, which [`decode`] turns back into the integer.
+//!
+//! There are no merge operands: every increment reads the counter and writes its new total
+//! back as a plain put, so the latest version always holds the whole sum and nothing needs
+//! folding on reads or compactions. The cost is a point lookup per increment.
+
+/// What [`Database::increment`](crate::Database::increment) does when adding the delta
+/// would take a counter past the range of an `i64`, configured with
+/// [`Config::counter_overflow`](crate::config::Config::counter_overflow).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CounterOverflow {
+    /// Fail with [`CounterOverflowed`], leaving the counter as it was.
+    #[default]
+    Error,
+    /// Clamp the counter to `i64::MIN` or `i64::MAX`.
+    Saturate,
+}
+
+/// Returned by [`Database::increment`](crate::Database::increment) when the increment
+/// would overflow the counter and [`CounterOverflow::Error`] is configured.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CounterOverflowed {
+    pub key: bytes::Bytes,
+    pub value: i64,
+    pub delta: i64,
+}
+
+impl std::fmt::Display for CounterOverflowed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Adding {} to counter {:?} at {} would overflow it",
+            self.delta, self.key, self.value
+        )
+    }
+}
+
+impl std::error::Error for CounterOverflowed {}
+
+/// Returned by [`Database::increment`](crate::Database::increment) when the key already
+/// holds a value that isn't a counter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NotACounter {
+    pub key: bytes::Bytes,
+    /// The length of the value found, which a counter's always [`ENCODED_LEN`] bytes.
+    pub len: usize,
+}
+
+impl std::fmt::Display for NotACounter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Key {:?} holds a {}-byte value, which isn't a counter",
+            self.key, self.len
+        )
+    }
+}
+
+impl std::error::Error for NotACounter {}
+
+/// The size of an encoded counter.
+pub const ENCODED_LEN: usize = std::mem::size_of::<i64>();
+
+pub fn encode(value: i64) -> bytes::Bytes {
+    bytes::Bytes::copy_from_slice(&value.to_be_bytes())
+}
+
+/// The counter encoded in `bytes`, or `None` if it isn't one.
+pub fn decode(bytes: &[u8]) -> Option<i64> {
+    bytes.try_into().ok().map(i64::from_be_bytes)
+}
@@ -1,191 +1,3297 @@
 use std::{
-    collections::VecDeque,
-    sync::{atomic::AtomicUsize, Arc},
+    cell::{Cell, RefCell},
+    cmp::Reverse,
+    collections::{BTreeMap, BinaryHeap, VecDeque},
+    ops::{Bound, RangeBounds, RangeInclusive},
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
 };
 
 use anyhow::Context;
 
 use crate::{
+    backup::{self, BackupState, ExportRecord},
+    batch::{BatchOp, WriteBatch},
+    channel,
+    column_family::{ColumnFamily, ColumnFamilyData, ColumnFamilyId, DEFAULT_COLUMN_FAMILY_NAME},
+    compaction::{CompactionJob, CompactionResult, CompactionStrategy},
     config::Config,
+    counter::{self, CounterOverflow, CounterOverflowed, NotACounter},
+    event_log::EventLog,
+    idempotency::RecentWrites,
+    ingest::{ExternalSorter, INGEST_DIR_NAME},
+    iter::{MergeIterator, NewestVersion, Source},
     key::{Key, SeqNo},
-    memtable::{state, MemTable},
-    sstable::manager::{FileNo, SSTableManager},
+    lock::LockFile,
+    memtable::{
+        state::{self, MemTableState},
+        FlushTarget, MemTable,
+    },
+    oneshot,
+    options::{ReadOptions, WriteOptions},
+    reader::DbReader,
+    recovery::{OpenReport, RecoveryAction},
+    scan::{Scan, SCAN_PAGE_SIZE},
+    scrub::ScrubReport,
+    snapshot::{Snapshot, SnapshotList},
+    sstable::{
+        manager::{FileNo, SSTableManager},
+        manifest::FileMeta,
+        sstable::BlockReadOptions,
+        Level,
+    },
+    stall::{WriteStall, WriteStallReason},
+    stats::{DbStats, DiskUsage, ReadStats},
+    tail::{self, Tail, TAIL_BUFFER_CAPACITY},
+    tombstone::{max_covering_seqno, RangeTombstone},
     value::Value,
-    wal::{Wal, WalRecord},
+    wal::{PreparedBatches, Wal, WalRecord, WritePolicy, WRITE_BEHIND_BATCH_SIZE},
 };
 
+/// The number of entries [`Database::export`] reads per scan.
+const EXPORT_PAGE_SIZE: usize = 1024;
+
+/// Decides whether a live key/value pair is returned by a filtered scan.
+type ScanPredicate<'a> = &'a dyn Fn(&bytes::Bytes, &bytes::Bytes) -> bool;
+
+/// A page of scanned pairs, and the key the next page resumes after, if there is one.
+type ScanPage<T> = (Vec<(bytes::Bytes, T)>, Option<bytes::Bytes>);
+
+/// Returned by writes while the database's SSTables are over
+/// [`Config::max_total_bytes`] and compaction couldn't reclaim enough space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiskBudgetExceeded {
+    pub total_bytes: u64,
+    pub max_total_bytes: u64,
+}
+
+impl std::fmt::Display for DiskBudgetExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "SSTables take up {} bytes, over the budget of {} bytes",
+            self.total_bytes, self.max_total_bytes
+        )
+    }
+}
+
+impl std::error::Error for DiskBudgetExceeded {}
+
+/// Returned by [`Database::open`] when the data directory can't be written to, such as when
+/// it's on a read-only filesystem or owned by another user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadOnlyDataDir {
+    pub path: std::path::PathBuf,
+    /// The underlying error.
+    pub reason: String,
+}
+
+impl std::fmt::Display for ReadOnlyDataDir {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Data directory {} isn't writable, but opening a database needs write access: {}",
+            self.path.display(),
+            self.reason
+        )
+    }
+}
+
+impl std::error::Error for ReadOnlyDataDir {}
+
+/// Returned by [`Database::open`] when another handle, in this process or another, still
+/// has the database open after [`Config::open_lock_timeout`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlreadyOpen {
+    /// The file whose lock couldn't be taken.
+    pub path: std::path::PathBuf,
+}
+
+impl std::fmt::Display for AlreadyOpen {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Database is already open: {} is locked by another handle",
+            self.path.display()
+        )
+    }
+}
+
+impl std::error::Error for AlreadyOpen {}
+
+/// Returned when writing through a handle opened with [`Database::open_secondary`], or
+/// doing anything else that would change the database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReadOnlyHandle;
+
+impl std::fmt::Display for ReadOnlyHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Database was opened as a secondary handle, which is read-only"
+        )
+    }
+}
+
+impl std::error::Error for ReadOnlyHandle {}
+
 pub struct Database {
     config: Arc<Config>,
 
-    /// The active MemTable
-    table: MemTable<state::Active>,
+    /// Every column family, keyed by id. Always contains the default column family.
+    families: BTreeMap<ColumnFamilyId, ColumnFamilyData>,
 
-    /// Frozen, immutable memtables waiting to be turned into SSTables.
-    imm_tables: glommio::sync::RwLock<VecDeque<MemTable<state::Frozen>>>,
+    /// The write-ahead log, shared by all column families. `None` for in-memory databases.
+    wal: Option<Wal>,
 
-    wal: Wal,
+    /// Writes applied to the memtables but not yet appended to the WAL, oldest first. Only
+    /// ever non-empty under [`WritePolicy::WriteBehind`].
+    unlogged: Vec<WalRecord>,
+
+    /// The records of each batch prepared with [`Database::prepare_batch`] and not yet
+    /// committed or aborted, by transaction id.
+    prepared: PreparedBatches,
 
     seqno: SeqNo,
 
-    sstables: SSTableManager,
+    /// The highest seqno known to survive a crash, either fsynced to the WAL or flushed to
+    /// SSTables.
+    durable_seqno: SeqNo,
+
+    /// The futures returned by [`Database::wait_durable`] still waiting, each told once
+    /// `durable_seqno` reaches its seqno.
+    durable_waiters: RefCell<Vec<(SeqNo, oneshot::Sender<()>)>>,
+
+    /// On-disk storage. `None` for in-memory databases, which never flush their memtable.
+    sstables: Option<SSTableManager>,
+
+    /// Every live snapshot, so compaction knows which old versions must be kept.
+    snapshots: Arc<SnapshotList>,
+
+    /// Known expiry times, in milliseconds since the Unix epoch: the earliest of each
+    /// SSTable, and every expiring value written to a memtable.
+    expiries: BinaryHeap<Reverse<u64>>,
+
+    /// The idempotency keys of recent writes, so retries aren't applied twice.
+    recent_writes: RecentWrites,
+
+    /// Where each committed write is sent for the live part of every [`Tail`].
+    tailers: RefCell<Vec<channel::Sender<WalRecord>>>,
+
+    /// Whether a write has been turned away for being over [`Config::max_total_bytes`]
+    /// since one last got through, so the end of the stall can be reported.
+    disk_budget_stalled: bool,
+
+    /// When the oldest write in the active memtables was applied, by [`Config::clock`], for
+    /// [`Config::max_memtable_age`]. Only meaningful while some active memtable isn't empty.
+    memtable_started: Instant,
+
+    /// The size the active memtables are frozen at, per [`Config::memtable_size`].
+    flush_target: FlushTarget,
+
+    /// The lockfile held under
+    /// [`LockStrategy::Lockfile`](crate::lock::LockStrategy::Lockfile). Last, so that it's
+    /// only removed once everything else has been let go of.
+    _lock_file: Option<LockFile>,
 }
 
 pub async fn coordinator_loop() {
-    loop {}
+    futures_lite::future::pending::<()>().await;
+}
+
+impl Drop for Database {
+    fn drop(&mut self) {
+        if let Err(e) = self.sync_wal() {
+            eprintln!("Failed to log unlogged writes on drop: {:?}", e);
+        }
+    }
 }
 
 impl Database {
+    /// Opens the database in [`Config::data_dir`], creating it if it doesn't exist.
+    ///
+    /// Fails with [`ReadOnlyDataDir`] if the data directory can't be written to, such as
+    /// when it's on a read-only mount, and with [`AlreadyOpen`] if another handle has it
+    /// open for longer than [`Config::open_lock_timeout`].
     pub fn open(config: Config) -> anyhow::Result<Self> {
+        Self::open_with_report(config).map(|(db, _)| db)
+    }
+
+    /// Opens the database like [`Database::open`], along with a report of everything it had
+    /// to recover, such as a torn WAL tail or the leftovers of an interrupted compaction,
+    /// so that an unclean open can be told apart from a clean one.
+    pub fn open_with_report(config: Config) -> anyhow::Result<(Self, OpenReport)> {
+        let data_dir = config.data_dir.clone();
+
+        Self::open_writable(config).map_err(|e| {
+            let kind = e
+                .chain()
+                .find_map(|cause| cause.downcast_ref::<std::io::Error>())
+                .map(std::io::Error::kind);
+
+            match kind {
+                Some(
+                    std::io::ErrorKind::ReadOnlyFilesystem | std::io::ErrorKind::PermissionDenied,
+                ) => ReadOnlyDataDir {
+                    path: data_dir,
+                    reason: e.root_cause().to_string(),
+                }
+                .into(),
+                _ => e,
+            }
+        })
+    }
+
+    fn open_writable(config: Config) -> anyhow::Result<(Self, OpenReport)> {
         let config = Arc::new(config);
+        let mut report = OpenReport::default();
 
         let manifests_dir = config.data_dir.join("manifests");
         let sstables_dir = config.data_dir.join("sstables");
 
         std::fs::create_dir_all(&config.data_dir).context("Failed to create data directory")?;
+        let lock_file = LockFile::acquire(&config, &mut report)?;
+
         std::fs::create_dir_all(&sstables_dir).context("Failed to create sstables directory")?;
         std::fs::create_dir_all(&manifests_dir).context("Failed to create manifests directory")?;
 
-        let mut wal = Wal::open(config.data_dir.join("wal.log"))?;
-
-        let replay = wal.replay()?;
+        let (wal, replay, prepared) = if config.wal_enabled {
+            let wal = Wal::open(config.data_dir.join("wal.log"), &config, &mut report)?;
+            let (replay, prepared) = wal.replay_with_prepared()?;
 
-        let mut table = MemTable::new();
-        let mut imm_tables = VecDeque::new();
+            (Some(wal), replay, prepared)
+        } else {
+            (None, Vec::new(), BTreeMap::new())
+        };
 
         // TODO: CURRENT should point to the latest manifest file, not be a manifest itself.
-        let sstables = SSTableManager::open(Arc::clone(&config))?;
+        let sstables = SSTableManager::open(Arc::clone(&config), &mut report)?;
+
+        // Checked once the manifest locks are held, so that it isn't another handle's WAL.
+        if wal.is_none() {
+            remove_empty_wal(&config.data_dir.join("wal.log"))?;
+        }
+
+        let mut families = BTreeMap::new();
+        let mut max_seqno = SeqNo::from(0u64);
+        let mut expiries = BinaryHeap::new();
+        let mut replayed = 0;
+        // Every version replayed so far, under `paranoid_checks`, since a memtable keeps one
+        // entry per version and a second record at the same seqno would replace the first.
+        // Otherwise that's left to happen, which keeps the last.
+        let mut replayed_versions = config.paranoid_checks.then(std::collections::HashSet::new);
+
+        for (id, name) in sstables.column_families() {
+            families.insert(id, ColumnFamilyData::new(ColumnFamily::new(id, name)));
 
-        let mut max_seqno = sstables.last_committed_sequence_number();
+            max_seqno = max_seqno.max(sstables.last_committed_sequence_number(id)?);
+            expiries.extend(sstables.earliest_expiries(id)?.into_iter().map(Reverse));
+        }
+
+        // Replayed writes don't count toward the write rate.
+        let flush_target = FlushTarget::new(config.memtable_size, config.clock.now());
 
-        for record in replay {
-            if record.key().seqno() < sstables.last_committed_sequence_number() {
+        for record in replay.into_iter().flat_map(WalRecord::into_records) {
+            let (Some(cf), Some(key)) = (record.cf(), record.key()) else {
+                unreachable!("batches are flattened");
+            };
+            let seqno = key.seqno();
+
+            // Each column family is flushed independently, so a record may be committed
+            // in one family while records around it are not.
+            if is_committed(&sstables, cf, seqno)? {
                 continue;
             }
 
-            match record {
-                WalRecord::Put { key, val } => {
-                    max_seqno = max_seqno.max(key.seqno());
+            let family = families
+                .get_mut(&cf)
+                .with_context(|| format!("WAL record for unknown column family {cf}"))?;
 
-                    table.put(key, val);
-                }
-                WalRecord::Delete { key } => {
-                    max_seqno = max_seqno.max(key.seqno());
+            // Seqnos are allocated once per write, so a repeat can only come from a bug.
+            if let Some(replayed_versions) = &mut replayed_versions
+                && !matches!(record, WalRecord::DeleteRange { .. })
+                && !replayed_versions.insert((cf, key.clone()))
+            {
+                anyhow::bail!(
+                    "WAL holds more than one record for key {:?} at seqno {} in column family \
+                     {cf}",
+                    key.user_key(),
+                    seqno.get()
+                );
+            }
 
-                    table.delete(key);
-                }
+            max_seqno = max_seqno.max(seqno);
+            replayed += 1;
+
+            if let WalRecord::PutExpiring { expires_at, .. } = record {
+                expiries.push(Reverse(expires_at));
             }
 
-            if table.should_freeze() {
-                imm_tables.push_back(table.freeze());
+            apply_record(&mut family.table, record);
+
+            if family.table.should_freeze(flush_target.size()) {
+                let frozen = family.table.freeze();
+
+                family
+                    .imm_tables
+                    .get_mut()
+                    .expect("lock closed")
+                    .push_back(frozen);
             }
         }
 
+        if config.paranoid_checks
+            && let Some(wal) = &wal
+        {
+            check_replay(&mut families, &sstables, wal.replay()?)?;
+        }
+
+        if replayed > 0 {
+            report
+                .actions
+                .push(RecoveryAction::WalReplayed { records: replayed });
+        }
+
         // TODO: truncate WAL to remove processed entries (seqno <= last_committed_sequence_number)
 
-        Ok(Self {
+        // Whatever was replayed into the memtables is aged from now.
+        let mut db = Self::from_parts(config, families, Some(sstables));
+
+        db.wal = wal;
+        db.seqno = max_seqno + 1;
+        db.expiries = expiries;
+        db.flush_target = flush_target;
+        db._lock_file = lock_file;
+        db.prepared = prepared;
+        // Everything replayed is already in the WAL or an SSTable.
+        db.durable_seqno = max_seqno;
+
+        // The memtables rebuilt from the WAL already take their share.
+        db.limit_block_cache();
+
+        Ok((db, report))
+    }
+
+    /// Opens the database in [`Config::data_dir`] as a secondary handle: a read-only view
+    /// of it that can be open at the same time as the primary handle opened with
+    /// [`Database::open`], in this process or another.
+    ///
+    /// A secondary handle only sees what the primary has flushed to SSTables, as of the
+    /// last [`Database::refresh`], so its reads lag the primary's writes by up to a flush and
+    /// a refresh. Writes fail with [`ReadOnlyHandle`]. The primary doesn't delete SSTables
+    /// the secondary is still reading, and deletes them once it has refreshed past them.
+    pub fn open_secondary(config: Config) -> anyhow::Result<Self> {
+        let config = Arc::new(config);
+        let sstables = SSTableManager::open_secondary(Arc::clone(&config))?;
+
+        Self::open_read_only(config, sstables)
+    }
+
+    /// Opens the database in [`Config::data_dir`] as it was as of the manifest file numbered
+    /// `manifest_file`, rather than the one CURRENT names, for looking into what it held at
+    /// an earlier point. [`manifest_files`] lists the ones there are, which are the current
+    /// one and the old ones kept by [`Config::manifest_retention`].
+    ///
+    /// The handle is a secondary one pinned to that manifest: it only reads, can be open
+    /// alongside the primary, and can't be refreshed. Fails with
+    /// [`MissingSstable`](crate::sstable::manager::MissingSstable) once compaction has deleted
+    /// an SSTable the manifest references.
+    ///
+    /// [`manifest_files`]: crate::sstable::manager::manifest_files
+    pub fn open_at_manifest(config: Config, manifest_file: FileNo) -> anyhow::Result<Self> {
+        let config = Arc::new(config);
+        let sstables = SSTableManager::open_at(Arc::clone(&config), manifest_file)?;
+
+        Self::open_read_only(config, sstables)
+    }
+
+    /// Wraps the SSTables of a secondary or pinned handle, with the column families its
+    /// manifest lists.
+    fn open_read_only(config: Arc<Config>, sstables: SSTableManager) -> anyhow::Result<Self> {
+        let mut db = Self::from_parts(config, BTreeMap::new(), Some(sstables));
+
+        db.sync_with_manifest()?;
+
+        Ok(db)
+    }
+
+    /// A handle over `families` and `sstables` with nothing else in it yet: no WAL, no
+    /// writes since the last seqno, and no snapshots, tails or prepared batches. Each way of
+    /// opening the database fills in the rest.
+    fn from_parts(
+        config: Arc<Config>,
+        families: BTreeMap<ColumnFamilyId, ColumnFamilyData>,
+        sstables: Option<SSTableManager>,
+    ) -> Self {
+        let recent_writes =
+            RecentWrites::new(config.idempotency_window, config.idempotency_capacity);
+
+        let now = config.clock.now();
+        let flush_target = FlushTarget::new(config.memtable_size, now);
+
+        Self {
             config,
 
-            table,
-            imm_tables: glommio::sync::RwLock::new(imm_tables),
-            wal,
-            seqno: max_seqno.max(sstables.last_committed_sequence_number()) + 1,
+            families,
+            wal: None,
+            seqno: SeqNo(1),
             sstables,
-        })
+            snapshots: Arc::new(SnapshotList::default()),
+            expiries: BinaryHeap::new(),
+            recent_writes,
+            tailers: RefCell::default(),
+            disk_budget_stalled: false,
+            memtable_started: now,
+            flush_target,
+            _lock_file: None,
+            unlogged: Vec::new(),
+            prepared: BTreeMap::new(),
+            durable_seqno: SeqNo(0),
+            durable_waiters: RefCell::default(),
+        }
+    }
+
+    /// Catches a secondary handle up with everything the primary has flushed since it was
+    /// opened or last refreshed, returning whether there was anything new. Call it
+    /// periodically, such as on a timer, to bound how far behind the primary reads are.
+    ///
+    /// Fails for a handle that isn't secondary.
+    pub fn refresh(&mut self) -> anyhow::Result<bool> {
+        let Some(sstables) = &mut self.sstables else {
+            anyhow::bail!("Only secondary handles can be refreshed");
+        };
+
+        if !sstables.refresh()? {
+            return Ok(false);
+        }
+
+        self.sync_with_manifest()?;
+
+        Ok(true)
+    }
+
+    /// Picks up the column families and seqnos of a secondary handle's newly loaded
+    /// manifest.
+    fn sync_with_manifest(&mut self) -> anyhow::Result<()> {
+        let sstables = self
+            .sstables
+            .as_ref()
+            .expect("secondary handles have SSTables");
+
+        for (id, name) in sstables.column_families() {
+            self.families
+                .entry(id)
+                .or_insert_with(|| ColumnFamilyData::new(ColumnFamily::new(id, name)));
+
+            self.seqno = self
+                .seqno
+                .max(sstables.last_committed_sequence_number(id)? + 1);
+        }
+
+        Ok(())
+    }
+
+    /// Whether this handle was opened with [`Database::open_secondary`].
+    pub fn is_secondary(&self) -> bool {
+        self.sstables
+            .as_ref()
+            .is_some_and(SSTableManager::is_secondary)
+    }
+
+    /// Opens a database that lives entirely in memory.
+    ///
+    /// No files are created: writes skip the WAL and the memtable is never frozen or
+    /// flushed, so the database behaves like a sorted in-memory map with the same
+    /// semantics as the on-disk one. Everything is lost when it's dropped.
+    pub fn open_in_memory() -> Self {
+        let default = ColumnFamily::new(ColumnFamilyId::DEFAULT, DEFAULT_COLUMN_FAMILY_NAME);
+
+        Self::from_parts(
+            Arc::new(Config::new(std::path::PathBuf::new())),
+            BTreeMap::from_iter([(ColumnFamilyId::DEFAULT, ColumnFamilyData::new(default))]),
+            None,
+        )
+    }
+
+    /// Returns the column family every database starts with, which is used by the methods
+    /// that don't take a column family.
+    pub fn default_cf(&self) -> ColumnFamily {
+        self.families[&ColumnFamilyId::DEFAULT].handle.clone()
+    }
+
+    /// Returns the column family called `name`, if it exists.
+    pub fn cf(&self, name: &str) -> Option<ColumnFamily> {
+        self.families
+            .values()
+            .find(|family| family.handle.name() == name)
+            .map(|family| family.handle.clone())
+    }
+
+    /// Creates a new, empty column family called `name`.
+    pub fn create_cf(&mut self, name: &str) -> anyhow::Result<ColumnFamily> {
+        if self.cf(name).is_some() {
+            anyhow::bail!("Column family {name:?} already exists");
+        }
+
+        let id = match &mut self.sstables {
+            Some(sstables) => sstables.create_column_family(name)?,
+            None => self
+                .families
+                .keys()
+                .next_back()
+                .map_or(ColumnFamilyId::DEFAULT, |id| ColumnFamilyId(id.0 + 1)),
+        };
+
+        let handle = ColumnFamily::new(id, name);
+
+        self.families
+            .insert(id, ColumnFamilyData::new(handle.clone()));
+
+        Ok(handle)
+    }
+
+    fn family(&self, cf: &ColumnFamily) -> anyhow::Result<&ColumnFamilyData> {
+        self.families
+            .get(&cf.id())
+            .filter(|family| family.handle == *cf)
+            .with_context(|| format!("Unknown column family {:?}", cf.name()))
+    }
+
+    pub fn is_in_memory(&self) -> bool {
+        self.sstables.is_none()
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
     }
 
     pub fn should_freeze_memtable(&self) -> bool {
-        self.table.should_freeze() || self.wal.should_compact()
+        if self.is_in_memory() {
+            return false;
+        }
+
+        self.families
+            .values()
+            .any(|family| family.table.should_freeze(self.flush_target.size()))
+            || self
+                .wal
+                .as_ref()
+                .is_some_and(|wal| wal.should_compact(self.flush_target.size()))
+            || self.memtable_too_old()
     }
 
-    pub async fn get(&self, key: &bytes::Bytes) -> Option<bytes::Bytes> {
-        if let Some(value) = self.table.get_latest(key) {
-            match value {
-                Value::Data(bytes) => return Some(bytes.clone()),
-                Value::Tombstone => return None,
-            }
+    /// Whether the active memtables hold a write older than [`Config::max_memtable_age`].
+    fn memtable_too_old(&self) -> bool {
+        self.config.max_memtable_age.is_some_and(|max_age| {
+            self.config
+                .clock
+                .now()
+                .duration_since(self.memtable_started)
+                >= max_age
+        }) && self
+            .families
+            .values()
+            .any(|family| !family.table.is_empty())
+    }
+
+    /// Flushes the memtables if they've held a write for longer than
+    /// [`Config::max_memtable_age`], returning whether they were flushed.
+    ///
+    /// Writes already check the age as they come in, so this is only needed when they may
+    /// stop for longer than the limit. Call it periodically, such as on a timer, to bound
+    /// how long the last writes stay out of the SSTables.
+    pub async fn flush_aged_memtables(&mut self) -> anyhow::Result<bool> {
+        if self.is_in_memory() || self.is_secondary() || !self.memtable_too_old() {
+            return Ok(false);
         }
 
-        for table in self
-            .imm_tables
-            .read()
-            .await
-            .expect("lock closed")
-            .iter()
-            .rev()
+        self.flush().await?;
+
+        Ok(true)
+    }
+
+    pub async fn get(&self, key: &bytes::Bytes) -> anyhow::Result<Option<bytes::Bytes>> {
+        self.get_opt(key, &ReadOptions::default()).await
+    }
+
+    pub async fn get_opt(
+        &self,
+        key: &bytes::Bytes,
+        options: &ReadOptions,
+    ) -> anyhow::Result<Option<bytes::Bytes>> {
+        self.get_cf_opt(&self.default_cf(), key, options).await
+    }
+
+    pub async fn get_cf(
+        &self,
+        cf: &ColumnFamily,
+        key: &bytes::Bytes,
+    ) -> anyhow::Result<Option<bytes::Bytes>> {
+        self.get_cf_opt(cf, key, &ReadOptions::default()).await
+    }
+
+    pub async fn get_cf_opt(
+        &self,
+        cf: &ColumnFamily,
+        key: &bytes::Bytes,
+        options: &ReadOptions,
+    ) -> anyhow::Result<Option<bytes::Bytes>> {
+        self.get_inner(cf, key, options, None).await
+    }
+
+    /// The length in bytes of `key`'s value, or `None` if it doesn't exist.
+    ///
+    /// This is the same lookup as [`Database::get`], reading the same blocks, so it only
+    /// saves the caller from holding the value. A value in a memtable or an uncompressed
+    /// block is a shared slice, but a compressed block is decompressed whole to find it, so
+    /// a large value's length costs as much to read as the value itself.
+    pub async fn value_len(&self, key: &bytes::Bytes) -> anyhow::Result<Option<usize>> {
+        self.value_len_cf(&self.default_cf(), key).await
+    }
+
+    pub async fn value_len_cf(
+        &self,
+        cf: &ColumnFamily,
+        key: &bytes::Bytes,
+    ) -> anyhow::Result<Option<usize>> {
+        let value = self
+            .get_inner(cf, key, &ReadOptions::default(), None)
+            .await?;
+
+        Ok(value.as_ref().map(bytes::Bytes::len))
+    }
+
+    /// Whether `key` might exist, checking only the memtables and the key ranges of the
+    /// SSTables, so no blocks are read.
+    ///
+    /// `false` means a [`Database::get`] would definitely return `None`. `true` means it
+    /// might not, but the key may still turn out to be missing or deleted.
+    pub async fn may_exist(&self, key: &bytes::Bytes) -> anyhow::Result<bool> {
+        self.may_exist_cf(&self.default_cf(), key).await
+    }
+
+    pub async fn may_exist_cf(
+        &self,
+        cf: &ColumnFamily,
+        key: &bytes::Bytes,
+    ) -> anyhow::Result<bool> {
+        let family = self.family(cf)?;
+
+        let imm_tables = family.imm_tables.read().await.expect("lock closed");
+
+        // The newest version in the memtables is newer than anything on disk.
+        let newest = family.table.get_latest(key).or_else(|| {
+            imm_tables
+                .iter()
+                .rev()
+                .find_map(|table| table.get_latest(key))
+        });
+
+        if let Some(value) = newest {
+            return Ok(!matches!(value, Value::Tombstone)
+                && !value.is_expired(self.config.clock.unix_millis()));
+        }
+
+        match &self.sstables {
+            Some(sstables) => sstables.may_contain(cf.id(), key),
+            None => Ok(false),
+        }
+    }
+
+    /// Like [`Database::get_cf_opt`], but also reports how many SSTables and blocks the
+    /// lookup touched.
+    pub async fn get_with_stats(
+        &self,
+        cf: &ColumnFamily,
+        key: &bytes::Bytes,
+        options: &ReadOptions,
+    ) -> anyhow::Result<(Option<bytes::Bytes>, ReadStats)> {
+        let stats = ReadStats::default();
+        let value = self.get_inner(cf, key, options, Some(&stats)).await?;
+
+        Ok((value, stats))
+    }
+
+    async fn get_inner(
+        &self,
+        cf: &ColumnFamily,
+        key: &bytes::Bytes,
+        options: &ReadOptions,
+        stats: Option<&ReadStats>,
+    ) -> anyhow::Result<Option<bytes::Bytes>> {
+        let family = self.family(cf)?;
+        let seqno = self.read_seqno(options);
+
+        let imm_tables = family.imm_tables.read().await.expect("lock closed");
+
+        // The newest version at or below `seqno`, searching newest data first.
+        let mut entry = family
+            .table
+            .get_at(key, seqno)
+            .map(|(k, v)| (k.seqno(), v.clone()));
+
+        if entry.is_none() {
+            entry = imm_tables
+                .iter()
+                .rev()
+                .find_map(|table| table.get_at(key, seqno))
+                .map(|(k, v)| (k.seqno(), v.clone()));
+        }
+
+        if entry.is_none()
+            && let Some(sstables) = &self.sstables
         {
-            if let Some(value) = table.get_latest(key) {
-                match value {
-                    Value::Data(bytes) => return Some(bytes.clone()),
-                    Value::Tombstone => return None,
-                }
-            }
+            entry = sstables
+                .get(cf.id(), key, seqno, self.block_read_options(options, stats))?
+                .map(|(k, v)| (k.seqno(), v));
         }
 
-        // // TODO: SSTables
-        // for level in 0..self.sstables.max_level().0 {
-        //     for table in self.sstables.iter_level(Level(level)).expect("level should exist") {
-        //
-        //
-        //     }
-        // }
+        let Some((version, value)) = entry else {
+            return Ok(None);
+        };
+
+        value.ensure_supported()?;
+
+        let Some(bytes) = value.live_data(self.config.clock.unix_millis()) else {
+            return Ok(None);
+        };
+
+        let sstable_tombstones = match &self.sstables {
+            Some(sstables) => Some(sstables.range_tombstones(cf.id())?),
+            None => None,
+        };
+
+        let tombstones = family
+            .table
+            .range_tombstones()
+            .iter()
+            .chain(imm_tables.iter().flat_map(|table| table.range_tombstones()))
+            .chain(sstable_tombstones.into_iter().flatten());
+
+        if max_covering_seqno(tombstones, key, seqno).is_some_and(|deleted| version < deleted) {
+            return Ok(None);
+        }
 
-        None
+        Ok(Some(bytes))
     }
 
-    pub async fn put(
-        &mut self,
-        key: impl Into<bytes::Bytes>,
-        val: impl Into<bytes::Bytes>,
-    ) -> anyhow::Result<()> {
-        let key = key.into();
-        let val = val.into();
-        let key = Key::new(key, self.seqno.next());
+    /// Returns a read-only handle with its own read options, which start out as the
+    /// defaults; see [`DbReader::with_options`].
+    pub fn reader(&self) -> DbReader<'_> {
+        DbReader::new(self, ReadOptions::default())
+    }
 
-        self.wal.append(WalRecord::Put {
-            key: key.clone(),
-            val: val.clone(),
-        })?;
+    /// Returns the append-only [`EventLog`] kept in the column family called `name`,
+    /// creating the column family if it doesn't exist.
+    pub async fn event_log(&mut self, name: &str) -> anyhow::Result<EventLog<'_>> {
+        EventLog::open(self, name).await
+    }
 
-        self.table.put(key, val);
+    /// Returns a snapshot of the database's current state.
+    ///
+    /// The snapshot is released when it's dropped or passed to [`release_snapshot`]. Until
+    /// then, compaction keeps every version it can see, so a long-lived snapshot holds onto
+    /// overwritten and deleted data.
+    ///
+    /// [`release_snapshot`]: Database::release_snapshot
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot::new(SeqNo(self.seqno.get() - 1), Arc::clone(&self.snapshots))
+    }
 
-        self.maybe_rotate_memtable().await;
+    /// Releases `snapshot`, so that the next compaction can drop the versions only it could
+    /// see. The same as dropping it, but explicit about where the snapshot ends.
+    pub fn release_snapshot(&self, snapshot: Snapshot) {
+        drop(snapshot);
+    }
 
-        Ok(())
+    /// The highest seqno visible to a read with `options`.
+    fn read_seqno(&self, options: &ReadOptions) -> SeqNo {
+        match &options.snapshot {
+            Some(snapshot) => snapshot.seqno(),
+            None => SeqNo(self.seqno.get() - 1),
+        }
     }
 
-    pub async fn delete(&mut self, key: impl Into<bytes::Bytes>) -> anyhow::Result<()> {
-        let key = key.into();
-        let key = Key::new(key, self.seqno.next());
+    fn block_read_options<'a>(
+        &'a self,
+        options: &ReadOptions,
+        stats: Option<&'a ReadStats>,
+    ) -> BlockReadOptions<'a> {
+        BlockReadOptions {
+            verify_checksums: options
+                .verify_checksums
+                .unwrap_or(self.config.verify_checksums_on_read),
+            fill_cache: options.fill_cache,
+            stats,
+            deadline: options
+                .deadline
+                .map(|deadline| (deadline, self.config.clock.as_ref())),
+        }
+    }
+
+    /// Iterates over every live key/value pair in `range`, in key order: the newest version
+    /// of each key across the memtables and SSTables, leaving out deleted keys and expired
+    /// values. An unbounded range walks the whole column family.
+    ///
+    /// The range is read lazily, [`SCAN_PAGE_SIZE`] pairs at a time, from a snapshot taken
+    /// when the scan starts. See [`Scan`].
+    pub fn scan(&self, range: impl RangeBounds<bytes::Bytes>) -> Scan<'_> {
+        self.scan_cf(&self.default_cf(), range)
+    }
 
-        self.wal.append(WalRecord::Delete { key: key.clone() })?;
+    pub fn scan_cf(&self, cf: &ColumnFamily, range: impl RangeBounds<bytes::Bytes>) -> Scan<'_> {
+        let options = ReadOptions {
+            snapshot: Some(self.snapshot()),
+            ..ReadOptions::default()
+        };
 
-        self.table.delete(key);
+        Scan::new(
+            self,
+            cf.clone(),
+            (range.start_bound().cloned(), range.end_bound().cloned()),
+            options,
+        )
+    }
 
-        self.maybe_rotate_memtable().await;
+    /// Reads the page of a [`Scan`] after `after`, without waiting on the frozen memtables'
+    /// lock, which nothing can hold while the scan borrows the database.
+    pub(crate) fn scan_page(
+        &self,
+        cf: &ColumnFamily,
+        range: (Bound<bytes::Bytes>, Bound<bytes::Bytes>),
+        after: Option<bytes::Bytes>,
+        options: &ReadOptions,
+    ) -> anyhow::Result<ScanPage<bytes::Bytes>> {
+        let imm_tables = self
+            .family(cf)?
+            .imm_tables
+            .try_read()
+            .map_err(|e| anyhow::anyhow!("Failed to lock frozen memtables: {e}"))?;
 
-        Ok(())
+        let (page, continuation) = self.scan_locked(
+            cf,
+            &imm_tables,
+            range,
+            SCAN_PAGE_SIZE,
+            after,
+            options,
+            None,
+            None,
+        )?;
+
+        Ok((live_pairs(page), continuation))
     }
 
-    async fn maybe_rotate_memtable(&mut self) {
-        if self.should_freeze_memtable() {
-            let frozen = self.table.freeze();
+    /// Returns up to `limit` live key/value pairs in `range`, along with a continuation key
+    /// to pass as `after` to fetch the next page (or `None` if the range is exhausted).
+    ///
+    /// Each page is read from a snapshot taken when the call starts, so a page is internally
+    /// consistent. Pages are *not* consistent with each other: writes made between calls
+    /// will be visible to later pages if they fall after the continuation key.
+    pub async fn scan_paginated(
+        &self,
+        range: impl RangeBounds<bytes::Bytes>,
+        limit: usize,
+        after: Option<bytes::Bytes>,
+    ) -> anyhow::Result<(Vec<(bytes::Bytes, bytes::Bytes)>, Option<bytes::Bytes>)> {
+        self.scan_paginated_opt(range, limit, after, &ReadOptions::default())
+            .await
+    }
 
-            self.imm_tables
-                .write()
-                .await
-                .expect("lock closed")
-                .push_back(frozen);
+    pub async fn scan_paginated_opt(
+        &self,
+        range: impl RangeBounds<bytes::Bytes>,
+        limit: usize,
+        after: Option<bytes::Bytes>,
+        options: &ReadOptions,
+    ) -> anyhow::Result<(Vec<(bytes::Bytes, bytes::Bytes)>, Option<bytes::Bytes>)> {
+        self.scan_paginated_cf_opt(&self.default_cf(), range, limit, after, options)
+            .await
+    }
+
+    pub async fn scan_paginated_cf(
+        &self,
+        cf: &ColumnFamily,
+        range: impl RangeBounds<bytes::Bytes>,
+        limit: usize,
+        after: Option<bytes::Bytes>,
+    ) -> anyhow::Result<(Vec<(bytes::Bytes, bytes::Bytes)>, Option<bytes::Bytes>)> {
+        self.scan_paginated_cf_opt(cf, range, limit, after, &ReadOptions::default())
+            .await
+    }
 
-            // self.sstables.flush_memtable(frozen)
+    pub async fn scan_paginated_cf_opt(
+        &self,
+        cf: &ColumnFamily,
+        range: impl RangeBounds<bytes::Bytes>,
+        limit: usize,
+        after: Option<bytes::Bytes>,
+        options: &ReadOptions,
+    ) -> anyhow::Result<(Vec<(bytes::Bytes, bytes::Bytes)>, Option<bytes::Bytes>)> {
+        let (page, continuation) = self
+            .scan_inner(cf, range, limit, after, options, None, None)
+            .await?;
 
-            // TODO: Handle WAL spanning multiple memtables so we can be crash-safe and
-            // not lose the frozen memtables.
-            // For now, we'll just never clear the WAL because we don't have SSTables.
-            // self.wal.clear();
-        }
+        Ok((live_pairs(page), continuation))
     }
 
-    pub fn debug_replay_wal(&mut self) -> anyhow::Result<Vec<WalRecord>> {
-        self.wal.replay()
+    /// Like [`Database::scan_paginated_cf_opt`], but also reports how many SSTables and
+    /// blocks the scan touched.
+    pub async fn scan_paginated_with_stats(
+        &self,
+        cf: &ColumnFamily,
+        range: impl RangeBounds<bytes::Bytes>,
+        limit: usize,
+        after: Option<bytes::Bytes>,
+        options: &ReadOptions,
+    ) -> anyhow::Result<(
+        Vec<(bytes::Bytes, bytes::Bytes)>,
+        Option<bytes::Bytes>,
+        ReadStats,
+    )> {
+        let stats = ReadStats::default();
+        let (page, continuation) = self
+            .scan_inner(cf, range, limit, after, options, Some(&stats), None)
+            .await?;
+
+        Ok((live_pairs(page), continuation, stats))
+    }
+
+    /// Returns every live key/value pair in `range` for which `predicate` returns true.
+    ///
+    /// The predicate runs inside the scan, after older versions, deleted keys, and expired
+    /// values have been dropped, so it only ever sees what a read would return and pairs it
+    /// rejects are never collected.
+    pub async fn scan_filter(
+        &self,
+        range: impl RangeBounds<bytes::Bytes>,
+        predicate: impl Fn(&bytes::Bytes, &bytes::Bytes) -> bool,
+    ) -> anyhow::Result<Vec<(bytes::Bytes, bytes::Bytes)>> {
+        self.scan_filter_cf(&self.default_cf(), range, predicate)
+            .await
+    }
+
+    pub async fn scan_filter_cf(
+        &self,
+        cf: &ColumnFamily,
+        range: impl RangeBounds<bytes::Bytes>,
+        predicate: impl Fn(&bytes::Bytes, &bytes::Bytes) -> bool,
+    ) -> anyhow::Result<Vec<(bytes::Bytes, bytes::Bytes)>> {
+        let (page, _) = self
+            .scan_inner(
+                cf,
+                range,
+                usize::MAX,
+                None,
+                &ReadOptions::default(),
+                None,
+                Some(&predicate),
+            )
+            .await?;
+
+        Ok(live_pairs(page))
+    }
+
+    /// Counts the live keys in `range` of the default column family: keys that haven't been
+    /// deleted and whose values haven't expired, each counted once however many versions
+    /// of it are stored.
+    ///
+    /// The count is exact, so it's a full merge scan of the range: O(keys in range), and an
+    /// unbounded range reads the whole column family. Use [`Database::stats`] for cheap
+    /// approximations.
+    pub async fn count(&self, range: impl RangeBounds<bytes::Bytes>) -> anyhow::Result<u64> {
+        self.count_cf(&self.default_cf(), range).await
     }
+
+    pub async fn count_cf(
+        &self,
+        cf: &ColumnFamily,
+        range: impl RangeBounds<bytes::Bytes>,
+    ) -> anyhow::Result<u64> {
+        let count = Cell::new(0u64);
+
+        // The predicate sees every live entry, so counting there and rejecting them all
+        // scans the range without collecting it.
+        let counter = |_: &bytes::Bytes, _: &bytes::Bytes| {
+            count.set(count.get() + 1);
+            false
+        };
+
+        self.scan_inner(
+            cf,
+            range,
+            usize::MAX,
+            None,
+            &ReadOptions::default(),
+            None,
+            Some(&counter),
+        )
+        .await?;
+
+        Ok(count.get())
+    }
+
+    /// Scans `range` from `after`, returning at most `limit` entries. With a `predicate`,
+    /// only live entries it accepts are returned; otherwise deleted entries may be too.
+    #[allow(clippy::too_many_arguments)]
+    async fn scan_inner(
+        &self,
+        cf: &ColumnFamily,
+        range: impl RangeBounds<bytes::Bytes>,
+        limit: usize,
+        after: Option<bytes::Bytes>,
+        options: &ReadOptions,
+        stats: Option<&ReadStats>,
+        predicate: Option<ScanPredicate<'_>>,
+    ) -> anyhow::Result<ScanPage<Value>> {
+        let imm_tables = self
+            .family(cf)?
+            .imm_tables
+            .read()
+            .await
+            .expect("lock closed");
+
+        self.scan_locked(
+            cf,
+            &imm_tables,
+            range,
+            limit,
+            after,
+            options,
+            stats,
+            predicate,
+        )
+    }
+
+    /// [`Database::scan_inner`], given the frozen memtables of `cf` already locked.
+    #[allow(clippy::too_many_arguments)]
+    fn scan_locked(
+        &self,
+        cf: &ColumnFamily,
+        imm_tables: &VecDeque<MemTable<state::Frozen>>,
+        range: impl RangeBounds<bytes::Bytes>,
+        limit: usize,
+        after: Option<bytes::Bytes>,
+        options: &ReadOptions,
+        stats: Option<&ReadStats>,
+        predicate: Option<ScanPredicate<'_>>,
+    ) -> anyhow::Result<ScanPage<Value>> {
+        let family = self.family(cf)?;
+
+        let (mut start, end) = Key::range_by_user_bounds(&range);
+
+        if let Some(after) = after {
+            let after = Key::user_key_upper_bound(after);
+
+            let resume = match &start {
+                Bound::Included(key) | Bound::Excluded(key) => *key <= after,
+                Bound::Unbounded => true,
+            };
+
+            if resume {
+                start = Bound::Excluded(after);
+            }
+        }
+
+        let bounds = (start, end);
+
+        if limit == 0 || Key::is_empty_range(&bounds) {
+            return Ok((Vec::new(), None));
+        }
+
+        let snapshot = self.read_seqno(options);
+        let block_options = self.block_read_options(options, stats);
+
+        let (sstables, sstable_tombstones) = match &self.sstables {
+            Some(sstables) => (
+                sstables.tables_in_range(cf.id(), &bounds)?,
+                Some(sstables.range_tombstones(cf.id())?),
+            ),
+            None => (Vec::new(), None),
+        };
+
+        let mut sources: Vec<Source<'_>> =
+            Vec::with_capacity(imm_tables.len() + sstables.len() + 1);
+
+        sources.push(Box::new(
+            family
+                .table
+                .range(bounds.clone())
+                .filter(move |(k, _)| k.seqno() <= snapshot)
+                .map(|(k, v)| Ok((k.clone(), v.clone()))),
+        ));
+
+        for table in imm_tables.iter().rev() {
+            sources.push(Box::new(
+                table
+                    .range(bounds.clone())
+                    .filter(move |(k, _)| k.seqno() <= snapshot)
+                    .map(|(k, v)| Ok((k.clone(), v.clone()))),
+            ));
+        }
+
+        for table in &sstables {
+            sources.push(Box::new(table.range(bounds.clone(), block_options).filter(
+                move |entry| entry.as_ref().map_or(true, |(k, _)| k.seqno() <= snapshot),
+            )));
+        }
+
+        let tombstones = family
+            .table
+            .range_tombstones()
+            .iter()
+            .chain(imm_tables.iter().flat_map(|table| table.range_tombstones()))
+            .chain(sstable_tombstones.into_iter().flatten())
+            .filter(|t| t.seqno <= snapshot)
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let now = self.config.clock.unix_millis();
+
+        let mut iter = NewestVersion::new(MergeIterator::new(sources))
+            .filter(|entry| {
+                entry.as_ref().map_or(true, |(key, _)| {
+                    !tombstones.iter().any(|t| t.deletes(key, snapshot))
+                })
+            })
+            .filter(|entry| {
+                entry
+                    .as_ref()
+                    .map_or(true, |(_, value)| !value.is_expired(now))
+            })
+            .map(|entry| {
+                let (key, value) = entry?;
+                value.ensure_supported()?;
+
+                Ok((key.user_key().clone(), value))
+            })
+            .filter(|entry| match (predicate, entry) {
+                (Some(predicate), Ok((key, value))) => {
+                    value.data().is_some_and(|data| predicate(key, data))
+                }
+                _ => true,
+            });
+
+        let page = iter
+            .by_ref()
+            .take(limit)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let continuation = match iter.next().transpose()? {
+            Some(_) => page.last().map(|(key, _)| key.clone()),
+            None => None,
+        };
+
+        Ok((page, continuation))
+    }
+
+    /// Returns every live key/value pair in `range` that's stored in SSTables within
+    /// `levels`, ignoring the memtables and every other level.
+    ///
+    /// This shows where data lives rather than what a read would return: a key overwritten
+    /// in a newer level still shows its old value here if only its old level is scanned.
+    /// Range tombstones only apply if they're stored in `levels` too.
+    pub async fn scan_levels(
+        &self,
+        range: impl RangeBounds<bytes::Bytes>,
+        levels: RangeInclusive<Level>,
+    ) -> anyhow::Result<Vec<(bytes::Bytes, bytes::Bytes)>> {
+        self.scan_levels_cf(&self.default_cf(), range, levels).await
+    }
+
+    pub async fn scan_levels_cf(
+        &self,
+        cf: &ColumnFamily,
+        range: impl RangeBounds<bytes::Bytes>,
+        levels: RangeInclusive<Level>,
+    ) -> anyhow::Result<Vec<(bytes::Bytes, bytes::Bytes)>> {
+        self.family(cf)?;
+
+        let bounds = Key::range_by_user_bounds(&range);
+
+        let Some(sstables) = &self.sstables else {
+            return Ok(Vec::new());
+        };
+
+        if Key::is_empty_range(&bounds) {
+            return Ok(Vec::new());
+        }
+
+        let tables = sstables.tables_in_levels(cf.id(), &bounds, levels.clone())?;
+        let tombstones = sstables
+            .range_tombstones_in_levels(cf.id(), levels)?
+            .collect::<Vec<_>>();
+
+        let read_seqno = self.read_seqno(&ReadOptions::default());
+        let block_options = self.block_read_options(&ReadOptions::default(), None);
+        let now = self.config.clock.unix_millis();
+
+        let sources = tables
+            .iter()
+            .map(|table| -> Source<'_> { Box::new(table.range(bounds.clone(), block_options)) })
+            .collect();
+
+        NewestVersion::new(MergeIterator::new(sources))
+            .filter(|entry| {
+                entry.as_ref().map_or(true, |(key, _)| {
+                    !tombstones.iter().any(|t| t.deletes(key, read_seqno))
+                })
+            })
+            .filter_map(|entry| match entry {
+                Ok((_, value)) if let Err(e) = value.ensure_supported() => Some(Err(e)),
+                // Tombstones are skipped, so only expired values are dropped here.
+                Ok((key, value)) => value
+                    .live_data(now)
+                    .map(|bytes| Ok((key.user_key().clone(), bytes))),
+                Err(e) => Some(Err(e)),
+            })
+            .collect()
+    }
+
+    /// Writes `val` under `key`. Any byte string is a valid key, including the empty one,
+    /// which sorts before every other key.
+    pub async fn put(
+        &mut self,
+        key: impl Into<bytes::Bytes>,
+        val: impl Into<bytes::Bytes>,
+    ) -> anyhow::Result<()> {
+        self.put_opt(key, val, &WriteOptions::default()).await
+    }
+
+    pub async fn put_opt(
+        &mut self,
+        key: impl Into<bytes::Bytes>,
+        val: impl Into<bytes::Bytes>,
+        options: &WriteOptions,
+    ) -> anyhow::Result<()> {
+        self.put_cf_opt(&self.default_cf(), key, val, options).await
+    }
+
+    pub async fn put_cf(
+        &mut self,
+        cf: &ColumnFamily,
+        key: impl Into<bytes::Bytes>,
+        val: impl Into<bytes::Bytes>,
+    ) -> anyhow::Result<()> {
+        self.put_cf_opt(cf, key, val, &WriteOptions::default())
+            .await
+    }
+
+    /// Adds `delta` to the counter at `key`, which starts at 0 if there's no value there,
+    /// and returns its new value. See [`counter`] for how counters are stored.
+    ///
+    /// This is a read followed by a put of the new total, not a merge: it costs a point
+    /// lookup, and the value written is the whole counter. The read and the write happen
+    /// without another write in between, so increments never lose each other's updates. Fails with [`NotACounter`] if `key` holds some other
+    /// value, and, depending on [`Config::counter_overflow`], with [`CounterOverflowed`] if
+    /// the counter would overflow.
+    pub async fn increment(
+        &mut self,
+        key: impl Into<bytes::Bytes>,
+        delta: i64,
+    ) -> anyhow::Result<i64> {
+        self.increment_cf(&self.default_cf(), key, delta).await
+    }
+
+    pub async fn increment_cf(
+        &mut self,
+        cf: &ColumnFamily,
+        key: impl Into<bytes::Bytes>,
+        delta: i64,
+    ) -> anyhow::Result<i64> {
+        let key = key.into();
+
+        let value = match self.get_cf(cf, &key).await? {
+            Some(bytes) => counter::decode(&bytes).ok_or(NotACounter {
+                key: key.clone(),
+                len: bytes.len(),
+            })?,
+            None => 0,
+        };
+
+        let new_value = match self.config.counter_overflow {
+            CounterOverflow::Error => value.checked_add(delta).ok_or(CounterOverflowed {
+                key: key.clone(),
+                value,
+                delta,
+            })?,
+            CounterOverflow::Saturate => value.saturating_add(delta),
+        };
+
+        self.put_cf(cf, key, counter::encode(new_value)).await?;
+
+        Ok(new_value)
+    }
+
+    pub async fn put_cf_opt(
+        &mut self,
+        cf: &ColumnFamily,
+        key: impl Into<bytes::Bytes>,
+        val: impl Into<bytes::Bytes>,
+        options: &WriteOptions,
+    ) -> anyhow::Result<()> {
+        let mut batch = WriteBatch::new();
+        batch.put(cf, key, val);
+
+        self.write_opt(batch, options).await
+    }
+
+    /// Writes `val` under `key` such that it reads as deleted once `ttl` has passed.
+    ///
+    /// Expiry is judged by [`Config::clock`]'s wall time. Expired values are hidden from
+    /// reads straight away, and their space is reclaimed by the next compaction that
+    /// rewrites them; see [`Database::next_expiry`].
+    pub async fn put_with_ttl(
+        &mut self,
+        key: impl Into<bytes::Bytes>,
+        val: impl Into<bytes::Bytes>,
+        ttl: Duration,
+    ) -> anyhow::Result<()> {
+        self.put_cf_with_ttl(&self.default_cf(), key, val, ttl)
+            .await
+    }
+
+    pub async fn put_cf_with_ttl(
+        &mut self,
+        cf: &ColumnFamily,
+        key: impl Into<bytes::Bytes>,
+        val: impl Into<bytes::Bytes>,
+        ttl: Duration,
+    ) -> anyhow::Result<()> {
+        let mut batch = WriteBatch::new();
+        batch.put_with_ttl(cf, key, val, ttl);
+
+        self.write(batch).await
+    }
+
+    pub async fn delete(&mut self, key: impl Into<bytes::Bytes>) -> anyhow::Result<()> {
+        self.delete_opt(key, &WriteOptions::default()).await
+    }
+
+    pub async fn delete_opt(
+        &mut self,
+        key: impl Into<bytes::Bytes>,
+        options: &WriteOptions,
+    ) -> anyhow::Result<()> {
+        self.delete_cf_opt(&self.default_cf(), key, options).await
+    }
+
+    pub async fn delete_cf(
+        &mut self,
+        cf: &ColumnFamily,
+        key: impl Into<bytes::Bytes>,
+    ) -> anyhow::Result<()> {
+        self.delete_cf_opt(cf, key, &WriteOptions::default()).await
+    }
+
+    pub async fn delete_cf_opt(
+        &mut self,
+        cf: &ColumnFamily,
+        key: impl Into<bytes::Bytes>,
+        options: &WriteOptions,
+    ) -> anyhow::Result<()> {
+        let key = key.into();
+
+        if options.delete_if_exists && self.get_cf(cf, &key).await?.is_none() {
+            return Ok(());
+        }
+
+        let mut batch = WriteBatch::new();
+        batch.delete(cf, key);
+
+        self.write_opt(batch, options).await
+    }
+
+    /// Deletes every key in `[start, end)`, or every key from `start` onward if `end` is
+    /// `None`.
+    pub async fn delete_range(
+        &mut self,
+        start: impl Into<bytes::Bytes>,
+        end: Option<bytes::Bytes>,
+    ) -> anyhow::Result<()> {
+        self.delete_range_opt(start, end, &WriteOptions::default())
+            .await
+    }
+
+    pub async fn delete_range_opt(
+        &mut self,
+        start: impl Into<bytes::Bytes>,
+        end: Option<bytes::Bytes>,
+        options: &WriteOptions,
+    ) -> anyhow::Result<()> {
+        self.delete_range_cf_opt(&self.default_cf(), start, end, options)
+            .await
+    }
+
+    pub async fn delete_range_cf(
+        &mut self,
+        cf: &ColumnFamily,
+        start: impl Into<bytes::Bytes>,
+        end: Option<bytes::Bytes>,
+    ) -> anyhow::Result<()> {
+        self.delete_range_cf_opt(cf, start, end, &WriteOptions::default())
+            .await
+    }
+
+    pub async fn delete_range_cf_opt(
+        &mut self,
+        cf: &ColumnFamily,
+        start: impl Into<bytes::Bytes>,
+        end: Option<bytes::Bytes>,
+        options: &WriteOptions,
+    ) -> anyhow::Result<()> {
+        let mut batch = WriteBatch::new();
+        batch.delete_range(cf, start, end);
+
+        self.write_opt(batch, options).await
+    }
+
+    /// Deletes every key that starts with `prefix`.
+    ///
+    /// This writes a single range tombstone rather than a tombstone per key, so it's cheap
+    /// regardless of how many keys share the prefix.
+    pub async fn drop_prefix(&mut self, prefix: impl Into<bytes::Bytes>) -> anyhow::Result<()> {
+        self.drop_prefix_cf(&self.default_cf(), prefix).await
+    }
+
+    pub async fn drop_prefix_cf(
+        &mut self,
+        cf: &ColumnFamily,
+        prefix: impl Into<bytes::Bytes>,
+    ) -> anyhow::Result<()> {
+        let prefix = prefix.into();
+        let end = prefix_upper_bound(&prefix);
+
+        self.delete_range_cf(cf, prefix, end).await
+    }
+
+    /// Deletes every key in `[start, end)`, or every key from `start` onward if `end` is
+    /// `None`, and only returns once the deleted data has been physically removed from
+    /// disk rather than leaving it for compaction to reclaim eventually.
+    ///
+    /// Every memtable is flushed and each level is compacted into the next, down to the
+    /// deepest level holding files, which removes the data from the WAL and from every
+    /// SSTable. Fails without deleting anything if a snapshot is live, since the snapshot
+    /// may still read the data.
+    pub async fn guarantee_deleted(
+        &mut self,
+        start: impl Into<bytes::Bytes>,
+        end: Option<bytes::Bytes>,
+    ) -> anyhow::Result<()> {
+        self.guarantee_deleted_cf(&self.default_cf(), start, end)
+            .await
+    }
+
+    pub async fn guarantee_deleted_cf(
+        &mut self,
+        cf: &ColumnFamily,
+        start: impl Into<bytes::Bytes>,
+        end: Option<bytes::Bytes>,
+    ) -> anyhow::Result<()> {
+        self.family(cf)?;
+
+        if self.snapshots.oldest().is_some() {
+            anyhow::bail!("Can't guarantee deletion while a snapshot is live");
+        }
+
+        self.delete_range_cf(cf, start, end).await?;
+        self.flush().await?;
+
+        if let Some(sstables) = &mut self.sstables {
+            // The last compaction is into the bottom level, which drops the range tombstone
+            // along with everything it covers.
+            let deepest = sstables.deepest_level(cf.id())?.unwrap_or(Level(0));
+
+            for level in 0..deepest.0.max(1) {
+                sstables.compact_level(cf.id(), Level(level), None).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Deletes every key below `key_upper_bound`, for retention of data whose keys are
+    /// ordered by time, and returns the number of SSTables dropped.
+    ///
+    /// SSTables whose keys all fall below the bound are dropped from the manifest as they
+    /// are, without reading or rewriting them, which is far cheaper than compacting a range
+    /// deletion through them. The rest of the range, in the memtables and in files that
+    /// reach the bound, is deleted with a range tombstone and left for compaction. Fails
+    /// without deleting anything if a snapshot is live, since the snapshot may still read
+    /// the dropped files.
+    pub async fn drop_files_before(
+        &mut self,
+        key_upper_bound: impl Into<bytes::Bytes>,
+    ) -> anyhow::Result<usize> {
+        self.drop_files_before_cf(&self.default_cf(), key_upper_bound)
+            .await
+    }
+
+    pub async fn drop_files_before_cf(
+        &mut self,
+        cf: &ColumnFamily,
+        key_upper_bound: impl Into<bytes::Bytes>,
+    ) -> anyhow::Result<usize> {
+        self.family(cf)?;
+
+        if self.snapshots.oldest().is_some() {
+            anyhow::bail!("Can't drop files while a snapshot is live");
+        }
+
+        let bound = key_upper_bound.into();
+
+        // Written first, so that dropping a file can't expose older versions of its keys
+        // from the files that are kept.
+        self.delete_range_cf(cf, bytes::Bytes::new(), Some(bound.clone()))
+            .await?;
+
+        let Some(sstables) = &mut self.sstables else {
+            return Ok(0);
+        };
+
+        Ok(sstables.drop_files_before(cf.id(), &bound)?.len())
+    }
+
+    /// Adds SSTables written by another database to `level`, without rewriting them, and
+    /// returns the number attached.
+    ///
+    /// Every file is read through to validate its footer and blocks, then hard-linked into
+    /// the data directory, or copied if it's on another filesystem; the originals are left
+    /// for the caller to remove. The memtables are flushed first, and the files mustn't
+    /// overlap each other or any data already in the column family, at any level, since
+    /// their seqnos can't be ordered against it. Range deletions made in the source
+    /// database aren't carried over. Fails without attaching anything if a snapshot is
+    /// live.
+    pub async fn attach_sstables(
+        &mut self,
+        files: &[std::path::PathBuf],
+        level: Level,
+    ) -> anyhow::Result<usize> {
+        self.attach_sstables_cf(&self.default_cf(), files, level)
+            .await
+    }
+
+    pub async fn attach_sstables_cf(
+        &mut self,
+        cf: &ColumnFamily,
+        files: &[std::path::PathBuf],
+        level: Level,
+    ) -> anyhow::Result<usize> {
+        self.family(cf)?;
+
+        if self.snapshots.oldest().is_some() {
+            anyhow::bail!("Can't attach SSTables while a snapshot is live");
+        }
+
+        if self.sstables.is_none() {
+            anyhow::bail!("Can't attach SSTables to an in-memory database");
+        }
+
+        // Flushing also clears the WAL, so raising the committed seqno past records it
+        // still holds can't make replay skip them.
+        self.flush().await?;
+
+        let sstables = self.sstables.as_mut().expect("on-disk database");
+        let max_seqno = sstables.attach(cf.id(), files, level)?;
+
+        self.seqno = self.seqno.max(max_seqno + 1);
+
+        Ok(files.len())
+    }
+
+    /// Fsyncs the WAL, making every write so far durable without flushing memtables to
+    /// SSTables.
+    ///
+    /// Writes made with [`WriteOptions::sync`] unset are only durable once this (or a
+    /// synced write) returns, so batching unsynced writes and calling this afterwards trades
+    /// an fsync per write for one per batch.
+    ///
+    /// Under [`WritePolicy::WriteBehind`] this first appends the writes that haven't been
+    /// logged yet. Call it periodically, such as on a timer, to bound how many acknowledged
+    /// writes a crash can lose.
+    pub fn sync_wal(&mut self) -> anyhow::Result<()> {
+        self.log_unlogged()?;
+
+        let Some(wal) = &mut self.wal else {
+            return Ok(());
+        };
+
+        wal.flush()?;
+        self.mark_durable();
+
+        Ok(())
+    }
+
+    /// Appends the writes held back under [`WritePolicy::WriteBehind`] to the WAL, oldest
+    /// first, without fsyncing them.
+    fn log_unlogged(&mut self) -> anyhow::Result<()> {
+        let Some(wal) = &mut self.wal else {
+            return Ok(());
+        };
+
+        // Logged in order, and only dropped from `unlogged` once appended, so that a failed
+        // append doesn't leave a gap in the log.
+        let mut logged = 0;
+        let appended = self.unlogged.iter().try_for_each(|record| {
+            wal.append(record.clone(), false)?;
+            logged += 1;
+
+            anyhow::Ok(())
+        });
+
+        self.unlogged.drain(..logged);
+        appended
+    }
+
+    /// Returns a future that resolves once the write with `seqno`, and every write before
+    /// it, would survive a crash: fsynced to the WAL, or flushed to SSTables.
+    ///
+    /// Writes made with [`WriteOptions::sync`] unset, under either [`WritePolicy`], only
+    /// become durable at the next [`sync_wal`], synced write, or full [`flush`], so this
+    /// lets a caller acknowledge a write once it's safe without forcing an fsync of its own.
+    /// With [`Config::wal_enabled`] unset, only a flush makes writes durable. The seqno of
+    /// the latest write is [`Database::last_seqno`].
+    ///
+    /// The future doesn't borrow the database, so it can be awaited from another task. It
+    /// fails if the database is dropped first, and straight away for in-memory databases
+    /// and secondary handles, which never make anything durable.
+    ///
+    /// [`sync_wal`]: Database::sync_wal
+    /// [`flush`]: Database::flush
+    pub fn wait_durable(&self, seqno: SeqNo) -> impl Future<Output = anyhow::Result<()>> + use<> {
+        let waiter = if self.is_in_memory() || self.is_secondary() {
+            Err(anyhow::anyhow!(
+                "Writes to an in-memory database or secondary handle are never durable"
+            ))
+        } else if seqno <= self.durable_seqno {
+            Ok(None)
+        } else {
+            let (tx, rx) = oneshot::channel();
+            self.durable_waiters.borrow_mut().push((seqno, tx));
+
+            Ok(Some(rx))
+        };
+
+        async move {
+            if let Some(rx) = waiter? {
+                rx.await.map_err(|_| {
+                    anyhow::anyhow!("Database closed before seqno {} was durable", seqno.get())
+                })?;
+            }
+
+            Ok(())
+        }
+    }
+
+    /// The seqno of the latest write, to pass to [`Database::wait_durable`].
+    pub fn last_seqno(&self) -> SeqNo {
+        SeqNo(self.seqno.get() - 1)
+    }
+
+    /// Records that every write so far is durable, resolving the waiters it satisfies.
+    fn mark_durable(&mut self) {
+        self.durable_seqno = self.last_seqno();
+
+        let waiters = self.durable_waiters.get_mut();
+        let (ready, waiting) = std::mem::take(waiters)
+            .into_iter()
+            .filter(|(_, tx)| !tx.is_closed())
+            .partition::<Vec<_>, _>(|(seqno, _)| *seqno <= self.durable_seqno);
+        *waiters = waiting;
+
+        for (_, tx) in ready {
+            // The future may have been dropped since.
+            let _ = tx.send(());
+        }
+    }
+
+    /// Shuts the database down cleanly: flushes every memtable to SSTables, syncs the WAL,
+    /// and then releases the database's file locks so it can be opened again.
+    ///
+    /// Taking `self` means no other operation can be in flight. Dropping the database
+    /// without closing it loses nothing that was synced, but leaves the unflushed writes to
+    /// be replayed from the WAL on the next open.
+    pub async fn close(mut self) -> anyhow::Result<()> {
+        self.flush().await?;
+        self.sync_wal()?;
+
+        Ok(())
+    }
+
+    /// Applies every write in `batch` atomically.
+    pub async fn write(&mut self, batch: WriteBatch) -> anyhow::Result<()> {
+        self.write_opt(batch, &WriteOptions::default()).await
+    }
+
+    pub async fn write_opt(
+        &mut self,
+        batch: WriteBatch,
+        options: &WriteOptions,
+    ) -> anyhow::Result<()> {
+        if self.is_secondary() {
+            return Err(ReadOnlyHandle.into());
+        }
+
+        let ops = batch.into_ops();
+
+        if let Some(key) = &options.idempotency_key
+            && self.recent_writes.contains(key, self.config.clock.now())
+        {
+            return Ok(());
+        }
+
+        // Validate every column family up front so a bad handle can't leave the batch
+        // half-applied.
+        if let Some(cf) = ops
+            .iter()
+            .map(BatchOp::cf)
+            .find(|cf| !self.families.contains_key(cf))
+        {
+            anyhow::bail!("Unknown column family {cf}");
+        }
+
+        self.wait_for_backpressure(&ops).await?;
+
+        let has_puts = ops.iter().any(|op| matches!(op, BatchOp::Put { .. }));
+
+        // Deletes are still allowed over budget, since they're how space gets freed.
+        if has_puts
+            && let DbStats {
+                total_bytes,
+                max_total_bytes: Some(max_total_bytes),
+                ..
+            } = self.stats()
+            && total_bytes > max_total_bytes
+        {
+            if !self.disk_budget_stalled {
+                self.disk_budget_stalled = true;
+                self.notify_write_stall(WriteStall::Started(WriteStallReason::DiskBudget));
+            }
+
+            return Err(DiskBudgetExceeded {
+                total_bytes,
+                max_total_bytes,
+            }
+            .into());
+        }
+
+        if has_puts && self.disk_budget_stalled {
+            self.disk_budget_stalled = false;
+            self.notify_write_stall(WriteStall::Ended(WriteStallReason::DiskBudget));
+        }
+
+        let now = self.config.clock.unix_millis();
+        let mut records = batch_records(ops, now, || self.seqno.next());
+
+        let record = match records.len() {
+            0 => return Ok(()),
+            1 => records.pop().expect("one record"),
+            _ => WalRecord::Batch(records),
+        };
+
+        // Under write-behind, the record is held back until it's been applied, unless it's
+        // synced. A synced write is never acknowledged before it's durable, so it's logged
+        // ahead, after the writes held back before it.
+        let log_ahead = match self.config.write_policy {
+            WritePolicy::WriteAhead => true,
+            WritePolicy::WriteBehind => options.sync,
+        };
+
+        if log_ahead {
+            self.log_unlogged()?;
+        }
+
+        let mut unlogged = None;
+
+        if let Some(wal) = &mut self.wal {
+            if log_ahead {
+                wal.append(record.clone(), options.sync)?;
+            } else {
+                unlogged = Some(record.clone());
+            }
+
+            if options.sync {
+                self.mark_durable();
+            }
+        }
+
+        self.apply_write(record);
+
+        if let Some(record) = unlogged {
+            self.unlogged.push(record);
+
+            // The write has been applied, so it's not failed by the batch failing to log.
+            // The batch stays held back, and the next `sync_wal` reports the error.
+            if self.unlogged.len() >= WRITE_BEHIND_BATCH_SIZE
+                && let Err(e) = self.sync_wal()
+            {
+                eprintln!("Failed to log write-behind batch: {e:?}");
+            }
+        }
+
+        if let Some(key) = &options.idempotency_key {
+            self.recent_writes
+                .insert(key.clone(), self.config.clock.now());
+        }
+
+        self.maybe_rotate_memtable().await?;
+        self.enforce_memory_budget().await?;
+
+        Ok(())
+    }
+
+    /// Applies a logged write, a single record or a batch, to the memtables and sends it
+    /// to the tails. Its column families must have been validated.
+    fn apply_write(&mut self, record: WalRecord) {
+        // Tails that have fallen too far behind, or been dropped, are let go.
+        self.tailers
+            .get_mut()
+            .retain(|tx| tx.try_send(record.clone()).is_ok());
+
+        if self.families.values().all(|family| family.table.is_empty()) {
+            self.memtable_started = self.config.clock.now();
+        }
+
+        let active_bytes = self.active_memtable_bytes();
+
+        for record in record.into_records() {
+            let cf = record.cf().expect("batches are flattened");
+            let family = self.families.get_mut(&cf).expect("validated above");
+
+            if let WalRecord::PutExpiring { expires_at, .. } = record {
+                self.expiries.push(Reverse(expires_at));
+            }
+
+            apply_record(&mut family.table, record);
+        }
+
+        self.flush_target.record(
+            self.active_memtable_bytes().saturating_sub(active_bytes),
+            self.config.clock.now(),
+        );
+    }
+
+    /// Prepares `batch` under the transaction id `txn`, the first phase of a two-phase
+    /// commit across several databases, such as the shards written by
+    /// [`shard::write_sharded`](crate::shard::write_sharded). The batch is logged and fsynced but not
+    /// applied, so it isn't visible to reads, until [`Database::commit_prepared`] is called
+    /// with the same `txn`, or dropped by [`Database::abort_prepared`].
+    ///
+    /// A prepared batch survives a crash: it's listed by [`Database::prepared_batches`] once
+    /// the database is reopened, still waiting to be committed or aborted. Without a WAL it
+    /// only lasts as long as the handle.
+    ///
+    /// Fails if `txn` is already prepared, or the batch names an unknown column family.
+    pub fn prepare_batch(&mut self, txn: u64, batch: WriteBatch) -> anyhow::Result<()> {
+        if self.is_secondary() {
+            return Err(ReadOnlyHandle.into());
+        }
+
+        if self.prepared.contains_key(&txn) {
+            anyhow::bail!("Transaction {txn} is already prepared");
+        }
+
+        let ops = batch.into_ops();
+
+        if let Some(cf) = ops
+            .iter()
+            .map(BatchOp::cf)
+            .find(|cf| !self.families.contains_key(cf))
+        {
+            anyhow::bail!("Unknown column family {cf}");
+        }
+
+        // Seqnos are only allocated on commit, so that the batch orders after every write
+        // made while it was prepared.
+        let now = self.config.clock.unix_millis();
+        let records = batch_records(ops, now, || SeqNo::from(0u64));
+
+        // Logged after the writes held back under write-behind, so that those commit first.
+        self.sync_wal()?;
+
+        if let Some(wal) = &mut self.wal {
+            wal.append(
+                WalRecord::Prepare {
+                    txn,
+                    records: records.clone(),
+                },
+                true,
+            )?;
+        }
+
+        self.prepared.insert(txn, records);
+
+        Ok(())
+    }
+
+    /// Commits the batch prepared under `txn`, applying it as one atomic write. The commit
+    /// is fsynced before this returns, under either [`WritePolicy`].
+    pub async fn commit_prepared(&mut self, txn: u64) -> anyhow::Result<()> {
+        if self.is_secondary() {
+            return Err(ReadOnlyHandle.into());
+        }
+
+        let Some(records) = self.prepared.remove(&txn) else {
+            anyhow::bail!("Transaction {txn} isn't prepared");
+        };
+
+        let seqno = self.seqno;
+        self.seqno.skip(records.len() as u64);
+
+        self.sync_wal()?;
+
+        if let Some(wal) = &mut self.wal {
+            wal.append(WalRecord::CommitPrepared { txn, seqno }, true)?;
+            self.mark_durable();
+        }
+
+        self.apply_write(crate::wal::commit_prepared(records, seqno));
+
+        self.maybe_rotate_memtable().await?;
+        self.enforce_memory_budget().await?;
+
+        Ok(())
+    }
+
+    /// Drops the batch prepared under `txn` without applying any of it.
+    pub fn abort_prepared(&mut self, txn: u64) -> anyhow::Result<()> {
+        if self.is_secondary() {
+            return Err(ReadOnlyHandle.into());
+        }
+
+        if self.prepared.remove(&txn).is_none() {
+            anyhow::bail!("Transaction {txn} isn't prepared");
+        }
+
+        if let Some(wal) = &mut self.wal {
+            wal.append(WalRecord::AbortPrepared { txn }, true)?;
+        }
+
+        Ok(())
+    }
+
+    /// The ids of the batches prepared but not yet committed or aborted, including those
+    /// recovered from the WAL, in ascending order.
+    pub fn prepared_batches(&self) -> Vec<u64> {
+        self.prepared.keys().copied().collect()
+    }
+
+    /// The bytes held by the memtables of every column family.
+    fn memtable_bytes(&self) -> usize {
+        self.families
+            .values()
+            .map(ColumnFamilyData::memtable_bytes)
+            .sum()
+    }
+
+    /// The bytes held by the frozen memtables of every column family.
+    fn frozen_memtable_bytes(&self) -> usize {
+        self.families
+            .values()
+            .map(ColumnFamilyData::frozen_memtable_bytes)
+            .sum()
+    }
+
+    /// The bytes held by the active memtables of every column family.
+    fn active_memtable_bytes(&self) -> usize {
+        self.families
+            .values()
+            .map(|family| family.table.size())
+            .sum()
+    }
+
+    /// Keeps the memtables and block cache within [`Config::total_memory_budget`], flushing
+    /// the memtables once they hold more than half of it.
+    async fn enforce_memory_budget(&mut self) -> anyhow::Result<()> {
+        let Some(budget) = self.config.total_memory_budget else {
+            return Ok(());
+        };
+
+        // Flushing frees the memtables, and updates the cache's share.
+        if self.sstables.is_some() && self.memtable_bytes() > budget / 2 {
+            return self.flush().await;
+        }
+
+        self.limit_block_cache();
+
+        Ok(())
+    }
+
+    /// Holds the block cache to whatever [`Config::total_memory_budget`] the memtables leave.
+    fn limit_block_cache(&self) {
+        if let Some(budget) = self.config.total_memory_budget
+            && let Some(cache) = self.sstables.as_ref().and_then(SSTableManager::block_cache)
+        {
+            cache.set_limit(budget.saturating_sub(self.memtable_bytes()));
+        }
+    }
+
+    /// Holds up a write to the column families in `ops` until none of them is at
+    /// [`Config::max_frozen_memtables`] or [`Config::l0_stop_writes_trigger`], and the
+    /// database is within [`Config::max_frozen_bytes`], flushing or compacting as needed.
+    /// Reports each stall to [`Config::on_write_stall`].
+    async fn wait_for_backpressure(&mut self, ops: &[BatchOp]) -> anyhow::Result<()> {
+        let frozen_bytes = self.frozen_memtable_bytes();
+
+        let Some(sstables) = &mut self.sstables else {
+            return Ok(());
+        };
+
+        let notify = |stall| {
+            if let Some(listener) = &self.config.on_write_stall {
+                listener.on_write_stall(stall);
+            }
+        };
+
+        if let Some(limit) = self.config.max_frozen_bytes
+            && frozen_bytes > limit
+        {
+            notify(WriteStall::Started(WriteStallReason::TooManyFrozenBytes));
+            let mut flushed = Ok(());
+
+            for (id, family) in &self.families {
+                flushed = flush_frozen_memtables(sstables, *id, family).await;
+
+                if flushed.is_err() {
+                    break;
+                }
+            }
+
+            notify(WriteStall::Ended(WriteStallReason::TooManyFrozenBytes));
+
+            flushed?;
+        }
+
+        let cfs = ops
+            .iter()
+            .map(BatchOp::cf)
+            .collect::<std::collections::BTreeSet<_>>();
+
+        for cf in cfs {
+            let family = self.families.get(&cf).expect("validated above");
+
+            if let Some(limit) = self.config.max_frozen_memtables
+                && family.imm_tables.read().await.expect("lock closed").len() >= limit
+            {
+                notify(WriteStall::Started(WriteStallReason::TooManyMemtables));
+                let flushed = flush_frozen_memtables(sstables, cf, family).await;
+                notify(WriteStall::Ended(WriteStallReason::TooManyMemtables));
+
+                flushed?;
+            }
+
+            if let Some(limit) = self.config.l0_stop_writes_trigger
+                && sstables.level_file_count(cf, Level(0))? >= limit
+            {
+                notify(WriteStall::Started(WriteStallReason::TooManyL0Files));
+                let compacted = sstables
+                    .compact_level(cf, Level(0), self.snapshots.oldest())
+                    .await;
+                notify(WriteStall::Ended(WriteStallReason::TooManyL0Files));
+
+                compacted?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn notify_write_stall(&self, stall: WriteStall) {
+        if let Some(listener) = &self.config.on_write_stall {
+            listener.on_write_stall(stall);
+        }
+    }
+
+    async fn maybe_rotate_memtable(&mut self) -> anyhow::Result<()> {
+        if self.should_freeze_memtable() {
+            self.flush().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Freezes the active memtable and writes it, along with any other frozen memtables,
+    /// out to L0 SSTables.
+    ///
+    /// Once everything has been flushed the WAL is cleared, since all of its records are
+    /// then committed to SSTables.
+    pub async fn flush(&mut self) -> anyhow::Result<()> {
+        // A secondary handle's memtables are always empty.
+        let Some(sstables) = self.sstables.as_mut().filter(|s| !s.is_secondary()) else {
+            return Ok(());
+        };
+
+        for (id, family) in &mut self.families {
+            if !family.table.is_empty() {
+                if self.config.paranoid_checks {
+                    check_memtable_size(*id, &family.table)?;
+                }
+
+                let frozen = family.table.freeze();
+
+                family
+                    .imm_tables
+                    .write()
+                    .await
+                    .expect("lock closed")
+                    .push_back(frozen);
+            }
+
+            flush_frozen_memtables(sstables, *id, family).await?;
+        }
+
+        let flushed_all = self.families.values().all(|family| family.table.is_empty());
+
+        if let Some(wal) = &mut self.wal
+            && flushed_all
+        {
+            wal.clear()?;
+            // Flushed along with everything else, so they no longer need logging.
+            self.unlogged.clear();
+
+            // Still waiting on a commit, so they have to outlive the records cleared.
+            for (txn, records) in &self.prepared {
+                wal.append(
+                    WalRecord::Prepare {
+                        txn: *txn,
+                        records: records.clone(),
+                    },
+                    false,
+                )?;
+            }
+
+            if !self.prepared.is_empty() {
+                wal.flush()?;
+            }
+        }
+
+        // Durable with or without a WAL, now that it's all in SSTables.
+        if flushed_all {
+            self.mark_durable();
+        }
+
+        if self.stats().over_budget() {
+            self.compact_all_levels().await?;
+        }
+
+        self.limit_block_cache();
+
+        Ok(())
+    }
+
+    /// Compacts every level of every column family down into its deepest level, dropping as
+    /// much overwritten and deleted data as the live snapshots allow.
+    async fn compact_all_levels(&mut self) -> anyhow::Result<()> {
+        let Some(sstables) = &mut self.sstables else {
+            return Ok(());
+        };
+
+        let oldest_snapshot = self.snapshots.oldest();
+
+        for id in self.families.keys() {
+            let deepest = sstables.deepest_level(*id)?.unwrap_or(Level(0));
+
+            for level in 0..deepest.0.max(1) {
+                sstables
+                    .compact_level(*id, Level(level), oldest_snapshot)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The database's current size and limits.
+    pub fn stats(&self) -> DbStats {
+        DbStats {
+            total_bytes: self
+                .sstables
+                .as_ref()
+                .map_or(0, SSTableManager::total_file_size),
+            max_total_bytes: self.config.max_total_bytes,
+            value_counts: self
+                .sstables
+                .as_ref()
+                .map(SSTableManager::value_counts)
+                .unwrap_or_default(),
+            bloom_filter_bytes: self
+                .sstables
+                .as_ref()
+                .map_or(0, SSTableManager::total_bloom_filter_size),
+            memory_bytes: (self.memtable_bytes()
+                + self
+                    .sstables
+                    .as_ref()
+                    .and_then(SSTableManager::block_cache)
+                    .map_or(0, |cache| cache.size())) as u64,
+            frozen_memtable_bytes: self.frozen_memtable_bytes() as u64,
+            memory_budget: self.config.total_memory_budget.map(|budget| budget as u64),
+            oldest_snapshot: self.snapshots.oldest(),
+            memtable_flush_target: self.flush_target.size() as u64,
+        }
+    }
+
+    /// The database's footprint on disk, broken down by the kind of file, along with an
+    /// estimate of how much of it is live data. See [`DiskUsage`]. In-memory databases use
+    /// no disk, and report zero for everything.
+    pub fn disk_usage(&self) -> anyhow::Result<DiskUsage> {
+        let Some(sstables) = &self.sstables else {
+            return Ok(DiskUsage::default());
+        };
+
+        let wal_bytes = match std::fs::metadata(self.config.data_dir.join("wal.log")) {
+            Ok(metadata) => metadata.len(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
+            Err(e) => return Err(e).context("Failed to read WAL size"),
+        };
+
+        let mut manifest_bytes = 0;
+
+        for entry in self
+            .config
+            .data_dir
+            .join("manifests")
+            .read_dir()
+            .context("Failed to read manifests dir")?
+        {
+            let entry = entry.context("Failed to read manifests dir entry")?;
+
+            // Deleted since the directory was listed.
+            if let Ok(metadata) = entry.metadata() {
+                manifest_bytes += metadata.len();
+            }
+        }
+
+        Ok(DiskUsage {
+            sstable_bytes_by_level: sstables.level_sizes(),
+            obsolete_sstable_bytes: sstables.obsolete_file_size()?,
+            wal_bytes,
+            manifest_bytes,
+            live_bytes: sstables.live_size_estimate()?,
+        })
+    }
+
+    /// Every SSTable in `cf`, along with the level it's in. The metadata includes each
+    /// file's size, key range, and [`ValueCounts`](crate::stats::ValueCounts).
+    pub fn live_files(&self, cf: &ColumnFamily) -> anyhow::Result<Vec<(Level, FileMeta)>> {
+        self.family(cf)?;
+
+        match &self.sstables {
+            Some(sstables) => sstables.files(cf.id()),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Creates a consistent copy of the database in `dir` that can be opened with
+    /// [`Database::open`], without blocking writes for longer than the copy takes.
+    ///
+    /// SSTables are hard-linked rather than copied where possible, so a checkpoint is cheap
+    /// in both time and space. Compaction needs `&mut self`, so it can't delete any of the
+    /// files being linked while this runs.
+    pub fn create_checkpoint(&self, dir: impl AsRef<std::path::Path>) -> anyhow::Result<()> {
+        let dir = dir.as_ref();
+
+        let Some(sstables) = &self.sstables else {
+            anyhow::bail!("In-memory databases can't be checkpointed");
+        };
+
+        if dir
+            .read_dir()
+            .is_ok_and(|mut entries| entries.next().is_some())
+        {
+            anyhow::bail!("Checkpoint directory {} is not empty", dir.display());
+        }
+
+        std::fs::create_dir_all(dir).context("Failed to create checkpoint directory")?;
+
+        // Records still in memtables are only durable in the WAL, so it comes along too,
+        // along with the writes that haven't been logged yet, so that the copy holds exactly
+        // the writes made so far. Without a WAL, only what's been flushed is checkpointed.
+        if let Some(wal) = &self.wal {
+            wal.copy_to(&dir.join("wal.log"), &self.unlogged)?;
+        }
+
+        sstables.checkpoint(dir)?;
+
+        Ok(())
+    }
+
+    /// Updates the checkpoint in `dir` to the current state of the database, copying only
+    /// the SSTables that `since` says it doesn't already have. Returns the state to pass to
+    /// the next call.
+    ///
+    /// Start with an empty `dir` and [`BackupState::default`], which makes a full
+    /// checkpoint. After that, each call links or copies in the new SSTables, replaces the
+    /// WAL copy and the manifest, and deletes the SSTables that compaction has since
+    /// dropped. The directory can be opened with [`Database::open`] after every call.
+    pub fn incremental_checkpoint(
+        &self,
+        dir: impl AsRef<std::path::Path>,
+        since: &BackupState,
+    ) -> anyhow::Result<BackupState> {
+        let dir = dir.as_ref();
+
+        let Some(sstables) = &self.sstables else {
+            anyhow::bail!("In-memory databases can't be checkpointed");
+        };
+
+        if since.files.is_empty()
+            && dir
+                .read_dir()
+                .is_ok_and(|mut entries| entries.next().is_some())
+        {
+            anyhow::bail!("Checkpoint directory {} is not empty", dir.display());
+        }
+
+        std::fs::create_dir_all(dir).context("Failed to create checkpoint directory")?;
+
+        let state = sstables.incremental_checkpoint(dir, since)?;
+
+        let Some(wal) = &self.wal else {
+            // A copy left by an earlier checkpoint, made while the WAL was enabled, would
+            // keep the checkpoint from opening with it disabled.
+            match std::fs::remove_file(dir.join("wal.log")) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    return Err(e).context("Failed to remove old WAL copy");
+                }
+                _ => return Ok(state),
+            }
+        };
+
+        // Replaced after the manifest, by renaming over the old copy. If this is
+        // interrupted, replaying the old copy on top of the new manifest is harmless, but
+        // the writes made since the last flush are only in the new one.
+        let wal_temp = dir.join("wal.log.tmp");
+
+        match std::fs::remove_file(&wal_temp) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                return Err(e).context("Failed to remove stale WAL copy");
+            }
+            _ => {}
+        }
+
+        wal.copy_to(&wal_temp, &self.unlogged)?;
+
+        std::fs::rename(&wal_temp, dir.join("wal.log")).context("Failed to replace WAL copy")?;
+
+        Ok(state)
+    }
+
+    /// Copies the user keys `[start, end)`, or `start` onward if `end` is `None`, of every
+    /// column family into a new database in [`Config::data_dir`], which is opened with
+    /// `config`. This database is left as it is.
+    ///
+    /// The memtables are flushed first, and the copy starts as a checkpoint, so SSTables
+    /// entirely inside the range are hard-linked rather than rewritten. Only the files that
+    /// straddle an end of the range are, and the rest are dropped. Every version of every
+    /// key in range comes along, along with the range deletions that cover it.
+    pub async fn extract_range(
+        &mut self,
+        start: impl Into<bytes::Bytes>,
+        end: Option<bytes::Bytes>,
+        config: Config,
+    ) -> anyhow::Result<Database> {
+        let start = start.into();
+
+        if end.as_ref().is_some_and(|end| *end <= start) {
+            anyhow::bail!("Can't extract an empty range");
+        }
+
+        self.flush().await?;
+        self.create_checkpoint(&config.data_dir)?;
+
+        let mut db = Database::open(config)?;
+
+        let Some(sstables) = &mut db.sstables else {
+            unreachable!("opened on disk");
+        };
+
+        for id in db.families.keys() {
+            sstables.retain_range(*id, &start, end.as_ref()).await?;
+        }
+
+        Ok(db)
+    }
+
+    /// Splits the keyspace at `key` into two new databases: one holding every key before
+    /// it, opened with `left`, and one holding `key` and every key after it, opened with
+    /// `right`. See [`Database::extract_range`].
+    pub async fn split_at(
+        &mut self,
+        key: impl Into<bytes::Bytes>,
+        left: Config,
+        right: Config,
+    ) -> anyhow::Result<(Database, Database)> {
+        let key = key.into();
+
+        let left = self
+            .extract_range(bytes::Bytes::new(), Some(key.clone()), left)
+            .await?;
+        let right = self.extract_range(key, None, right).await?;
+
+        Ok((left, right))
+    }
+
+    /// Recomputes the whole-file checksum of every SSTable and compares it with the one
+    /// recorded in the manifest when the file was written, failing with a
+    /// [`FileChecksumMismatch`](crate::sstable::manager::FileChecksumMismatch) for the first
+    /// file that differs.
+    ///
+    /// Unlike block checksums, which are checked as blocks are read, this covers every byte
+    /// of every file, so it's a cheap end-to-end check after copying the data directory.
+    pub fn verify_file_checksums(&self) -> anyhow::Result<()> {
+        match &self.sstables {
+            Some(sstables) => sstables.verify_file_checksums(),
+            None => Ok(()),
+        }
+    }
+
+    /// Reads and checksums up to `max_blocks` more SSTable blocks from disk, continuing
+    /// from where the last call stopped, even across restarts, and wrapping around to the
+    /// oldest file once the newest is done. Reports each corruption found to
+    /// [`Config::on_corruption`] as well as returning it.
+    ///
+    /// Blocks are otherwise only checked as they're read, so damage to data that isn't
+    /// read goes unnoticed until it's too late to restore it. The coordinator should call
+    /// this periodically, with `max_blocks` and the period picked to keep the scrub's IO
+    /// well below what foreground reads need. It yields to other tasks as it goes.
+    pub async fn scrub(&self, max_blocks: usize) -> anyhow::Result<ScrubReport> {
+        let Some(sstables) = &self.sstables else {
+            return Ok(ScrubReport {
+                pass_completed: true,
+                ..Default::default()
+            });
+        };
+
+        let report = sstables.scrub(max_blocks).await?;
+
+        if let Some(listener) = &self.config.on_corruption {
+            for corruption in &report.corruptions {
+                listener.on_corruption(corruption);
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Writes every live entry, as of a snapshot taken when this is called, to `writer`.
+    /// Returns the number of entries written.
+    ///
+    /// Entries are written in key order, so [`Database::restore`] can load the export
+    /// without replaying it as writes. See [`backup`] for the format.
+    pub async fn export(&self, mut writer: impl std::io::Write) -> anyhow::Result<u64> {
+        let options = ReadOptions {
+            snapshot: Some(self.snapshot()),
+            fill_cache: false,
+            ..Default::default()
+        };
+
+        let mut entries = 0;
+
+        for family in self.families.values() {
+            crate::framed::write_framed(
+                &mut writer,
+                &ExportRecord::ColumnFamily {
+                    name: family.handle.name().to_owned(),
+                },
+            )?;
+
+            let mut after = None;
+
+            loop {
+                let (page, continuation) = self
+                    .scan_inner(
+                        &family.handle,
+                        ..,
+                        EXPORT_PAGE_SIZE,
+                        after,
+                        &options,
+                        None,
+                        None,
+                    )
+                    .await?;
+
+                for (key, value) in page {
+                    crate::framed::write_framed(&mut writer, &ExportRecord::Entry { key, value })?;
+                    entries += 1;
+                }
+
+                match continuation {
+                    Some(continuation) => after = Some(continuation),
+                    None => break,
+                }
+            }
+        }
+
+        crate::framed::write_framed(&mut writer, &ExportRecord::End { entries })?;
+        writer.flush().context("Failed to flush export")?;
+
+        Ok(entries)
+    }
+
+    /// Creates a new database in `config.data_dir`, which must be empty, from an export
+    /// written by [`Database::export`].
+    ///
+    /// Entries are streamed straight into L1 SSTables rather than written through the WAL
+    /// and memtable, so a restore costs about as much as writing the files.
+    pub async fn restore(reader: impl std::io::Read, config: Config) -> anyhow::Result<Self> {
+        if config
+            .data_dir
+            .read_dir()
+            .is_ok_and(|mut entries| entries.next().is_some())
+        {
+            anyhow::bail!(
+                "Restore directory {} is not empty",
+                config.data_dir.display()
+            );
+        }
+
+        let mut db = Database::open(config)?;
+        let mut reader = std::io::BufReader::new(reader);
+
+        // Restored entries don't overwrite each other, so they can all share a seqno.
+        let seqno = db.seqno.next();
+        let mut restored = 0;
+
+        let mut record = backup::read_record(&mut reader)?;
+
+        loop {
+            let name = match record {
+                ExportRecord::ColumnFamily { name } => name,
+                ExportRecord::Entry { .. } => {
+                    anyhow::bail!("Export entry doesn't belong to a column family")
+                }
+                ExportRecord::End { entries } if entries == restored => break,
+                ExportRecord::End { entries } => {
+                    anyhow::bail!("Export should hold {entries} entries, but holds {restored}")
+                }
+            };
+
+            let cf = match db.cf(&name) {
+                Some(cf) => cf,
+                None => db.create_cf(&name)?,
+            };
+
+            let sstables = db.sstables.as_mut().expect("on-disk database");
+
+            let mut next_record = None;
+            let mut previous: Option<bytes::Bytes> = None;
+
+            let entries = std::iter::from_fn(|| {
+                if next_record.is_some() {
+                    return None;
+                }
+
+                match backup::read_record(&mut reader) {
+                    Ok(ExportRecord::Entry { key, value }) => {
+                        if previous.as_ref().is_some_and(|previous| *previous >= key) {
+                            return Some(Err(anyhow::anyhow!(
+                                "Export entries of column family {name:?} are out of order"
+                            )));
+                        }
+
+                        previous = Some(key.clone());
+                        restored += 1;
+
+                        Some(Ok((Key::new(key, seqno), value)))
+                    }
+                    Ok(record) => {
+                        next_record = Some(record);
+                        None
+                    }
+                    Err(e) => Some(Err(e)),
+                }
+            });
+
+            sstables.ingest(cf.id(), entries, Level(1), seqno).await?;
+
+            record = next_record.expect("entries end at the next record");
+        }
+
+        Ok(db)
+    }
+
+    /// Runs the compactions [`Config::compaction_strategy`] calls for in every column family.
+    ///
+    /// Leveled compaction merges L0 into L1. Size-tiered compaction merges each level that
+    /// has collected enough runs into the next, starting from L0, so a merge can cascade
+    /// down the levels.
+    pub async fn compact(&mut self) -> anyhow::Result<()> {
+        let Some(sstables) = &mut self.sstables else {
+            return Ok(());
+        };
+
+        let oldest_snapshot = self.snapshots.oldest();
+
+        for id in self.families.keys() {
+            match self.config.compaction_strategy {
+                CompactionStrategy::Leveled => {
+                    sstables
+                        .compact_level(*id, Level(0), oldest_snapshot)
+                        .await?;
+                }
+                CompactionStrategy::SizeTiered { min_runs } => {
+                    let mut level = Level(0);
+
+                    while Some(level) <= sstables.deepest_level(*id)? {
+                        let runs = sstables.runs_per_level(*id)?;
+
+                        if runs
+                            .get(&level)
+                            .is_some_and(|runs| *runs >= min_runs.max(1))
+                        {
+                            sstables.compact_tier(*id, level, oldest_snapshot).await?;
+                        }
+
+                        level = Level(level.0 + 1);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The earliest time at which a known value expires, if any haven't been reclaimed yet.
+    ///
+    /// The coordinator should wake up at this time and call [`Database::reclaim_expired`],
+    /// so that expired data is reclaimed close to when it expires rather than whenever
+    /// compaction is next triggered by size.
+    pub fn next_expiry(&self) -> Option<SystemTime> {
+        self.expiries
+            .peek()
+            .map(|Reverse(expires_at)| SystemTime::UNIX_EPOCH + Duration::from_millis(*expires_at))
+    }
+
+    /// If any known value has expired, flushes, compacts, and rewrites every SSTable
+    /// holding expired values so that their space is reclaimed. Returns whether anything
+    /// had expired.
+    ///
+    /// Expired values that are older versions of a key still visible to a live snapshot
+    /// are kept as tombstones until a later compaction.
+    pub async fn reclaim_expired(&mut self) -> anyhow::Result<bool> {
+        let now = self.config.clock.unix_millis();
+
+        if self
+            .expiries
+            .peek()
+            .is_none_or(|Reverse(expires_at)| *expires_at > now)
+        {
+            return Ok(false);
+        }
+
+        self.flush().await?;
+        self.compact().await?;
+
+        let Some(sstables) = &mut self.sstables else {
+            // In-memory databases never flush, so expired values stay hidden but in memory.
+            self.expiries
+                .retain(|Reverse(expires_at)| *expires_at > now);
+
+            return Ok(true);
+        };
+
+        let oldest_snapshot = self.snapshots.oldest();
+
+        self.expiries.clear();
+
+        for id in self.families.keys() {
+            sstables.reclaim_expired(*id, oldest_snapshot).await?;
+
+            // Memtables are empty after the flush, so the SSTables hold every expiry left.
+            self.expiries
+                .extend(sstables.earliest_expiries(*id)?.into_iter().map(Reverse));
+        }
+
+        Ok(true)
+    }
+
+    /// Writes `pairs`, in any order, straight into new L0 SSTables, bypassing the memtable
+    /// and WAL, and returns the number of keys written. A key given more than once keeps
+    /// the value given last. They're written as one write, newer than everything already
+    /// in the database, which they overwrite.
+    ///
+    /// The pairs are sorted in memory up to [`Config::ingest_memory_budget`] bytes at a
+    /// time, spilling each sorted run to disk, and the runs are then merged as the
+    /// SSTables are written, so the input can be far larger than memory. The memtables are
+    /// flushed first. The L0 files written can be large, and are left for compaction, or
+    /// [`Database::finalize_bulk_load`], to merge down.
+    pub async fn ingest_unsorted(
+        &mut self,
+        pairs: impl Iterator<Item = (bytes::Bytes, bytes::Bytes)>,
+    ) -> anyhow::Result<usize> {
+        self.ingest_unsorted_cf(&self.default_cf(), pairs).await
+    }
+
+    pub async fn ingest_unsorted_cf(
+        &mut self,
+        cf: &ColumnFamily,
+        pairs: impl Iterator<Item = (bytes::Bytes, bytes::Bytes)>,
+    ) -> anyhow::Result<usize> {
+        self.family(cf)?;
+
+        if self.is_secondary() {
+            return Err(ReadOnlyHandle.into());
+        }
+
+        if self.sstables.is_none() {
+            anyhow::bail!("Can't ingest SSTables into an in-memory database");
+        }
+
+        // Flushed first, so that the memtables can't hold versions newer than the files.
+        self.flush().await?;
+
+        let mut sorter = ExternalSorter::new(
+            self.config.data_dir.join(INGEST_DIR_NAME),
+            self.config.ingest_memory_budget,
+        )?;
+
+        for (key, value) in pairs {
+            sorter.push(key, value)?;
+        }
+
+        let seqno = self.seqno.next();
+        let mut ingested = 0;
+
+        let entries = sorter.finish()?.map(|pair| {
+            let (key, value) = pair?;
+            ingested += 1;
+
+            Ok((Key::new(key, seqno), Value::Data(value)))
+        });
+
+        let sstables = self.sstables.as_mut().expect("on-disk database");
+        sstables.ingest(cf.id(), entries, Level(0), seqno).await?;
+
+        Ok(ingested)
+    }
+
+    /// Flushes the memtables and compacts every column family down into its deepest level,
+    /// leaving no L0 files and a single sorted run in each level, so that a read checks at
+    /// most one file per level. Meant to be called once, after loading a lot of data and
+    /// before serving reads from it.
+    ///
+    /// Everything is rewritten, so this takes about as long as the load did. Versions a
+    /// live snapshot can see are kept.
+    pub async fn finalize_bulk_load(&mut self) -> anyhow::Result<()> {
+        self.flush().await?;
+        self.compact_all_levels().await?;
+
+        let Some(sstables) = &mut self.sstables else {
+            return Ok(());
+        };
+
+        let oldest_snapshot = self.snapshots.oldest();
+
+        // Size-tiered compaction can leave several runs in the deepest level, which only a
+        // merge into the level below collapses.
+        for id in self.families.keys() {
+            if let Some(deepest) = sstables.deepest_level(*id)?
+                && sstables.runs_per_level(*id)?.get(&deepest) > Some(&1)
+            {
+                sstables
+                    .compact_level(*id, deepest, oldest_snapshot)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Compacts every file in `level` of the default column family into the next level,
+    /// and reports what that reclaimed.
+    pub async fn compact_level(&mut self, level: Level) -> anyhow::Result<CompactionResult> {
+        self.run_compaction(CompactionJob {
+            cf: ColumnFamilyId::DEFAULT,
+            level,
+        })
+        .await
+    }
+
+    /// Runs `job`, returning once its output has been committed to the manifest and its
+    /// inputs deleted, with what it reclaimed.
+    pub async fn run_compaction(&mut self, job: CompactionJob) -> anyhow::Result<CompactionResult> {
+        if !self.families.contains_key(&job.cf) {
+            anyhow::bail!("Unknown column family {}", job.cf);
+        }
+
+        let Some(sstables) = &mut self.sstables else {
+            return Ok(CompactionResult::default());
+        };
+
+        sstables
+            .compact_level(job.cf, job.level, self.snapshots.oldest())
+            .await
+    }
+
+    /// Rewrites every SSTable written by an older version of the format in the current one,
+    /// so that existing data picks up format changes without a dump and reload.
+    ///
+    /// Safe to interrupt: files are swapped in one at a time, and already-upgraded files are
+    /// skipped when it's run again.
+    pub async fn upgrade_format(&mut self) -> anyhow::Result<()> {
+        let Some(sstables) = &mut self.sstables else {
+            return Ok(());
+        };
+
+        for id in self.families.keys() {
+            sstables.upgrade_format(*id).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Rewrites every SSTable whose blocks aren't compressed with
+    /// [`Config::sstable_compression`], so that existing data picks up a change to it.
+    /// Returns the number of files rewritten.
+    ///
+    /// Each file is rewritten on its own, at the same level and with the same key range,
+    /// which is much cheaper than compacting everything to the same end. Files written by an
+    /// older format version are brought up to date along the way, as with
+    /// [`Database::upgrade_format`], and it's just as safe to interrupt.
+    pub async fn recompress(&mut self) -> anyhow::Result<usize> {
+        let Some(sstables) = &mut self.sstables else {
+            return Ok(0);
+        };
+
+        let mut rewritten = 0;
+
+        for id in self.families.keys() {
+            rewritten += sstables.recompress(*id).await?;
+        }
+
+        Ok(rewritten)
+    }
+
+    /// Freezes the active memtable of `cf` without flushing it, so it can later be flushed
+    /// with [`Database::flush_frozen`]. Does nothing if the memtable is empty.
+    pub async fn freeze_memtable(&mut self, cf: &ColumnFamily) -> anyhow::Result<()> {
+        self.family(cf)?;
+
+        let family = self.families.get_mut(&cf.id()).expect("checked above");
+
+        if !family.table.is_empty() {
+            if self.config.paranoid_checks {
+                check_memtable_size(cf.id(), &family.table)?;
+            }
+
+            let frozen = family.table.freeze();
+
+            family
+                .imm_tables
+                .write()
+                .await
+                .expect("lock closed")
+                .push_back(frozen);
+        }
+
+        Ok(())
+    }
+
+    /// Flushes the frozen memtable of `cf` at `index` (0 being the oldest), regardless of
+    /// whether older frozen memtables have been flushed yet.
+    ///
+    /// Regular flushes always go oldest first; this exists so tools and tests can exercise
+    /// other orders. Fails if the memtable has a key in common with an older frozen one,
+    /// since flushing the newer version first would leave reads finding the older.
+    pub async fn flush_frozen(&mut self, cf: &ColumnFamily, index: usize) -> anyhow::Result<()> {
+        self.family(cf)?;
+
+        let Some(sstables) = &mut self.sstables else {
+            return Ok(());
+        };
+
+        let family = &self.families[&cf.id()];
+
+        sstables
+            .flush_specific(cf.id(), &family.imm_tables, index)
+            .await?;
+
+        family
+            .imm_tables
+            .write()
+            .await
+            .expect("lock closed")
+            .remove(index);
+
+        Ok(())
+    }
+
+    /// The highest seqno of `cf` at or below which every write has been flushed to an
+    /// SSTable.
+    pub fn last_committed_seqno(&self, cf: &ColumnFamily) -> anyhow::Result<SeqNo> {
+        self.family(cf)?;
+
+        match &self.sstables {
+            Some(sstables) => sstables.last_committed_sequence_number(cf.id()),
+            None => Ok(SeqNo::from(0u64)),
+        }
+    }
+
+    /// Streams every committed write with a seqno at or above `from_seqno`, in seqno order,
+    /// followed by each new write as it's committed. See [`Tail`].
+    ///
+    /// Writes still in the WAL are replayed as they were logged, batches included. Older
+    /// writes have been flushed out of the WAL, so they're read back from the SSTables one
+    /// entry at a time, and only the versions compaction has kept are left to replay.
+    ///
+    /// SSTables holding nothing at or after `from_seqno` are skipped, but the writes from
+    /// the rest are read into memory before this returns, so tailing from far back in a
+    /// large database is expensive.
+    pub async fn tail(&self, from_seqno: SeqNo) -> anyhow::Result<Tail> {
+        let mut logged = Vec::new();
+
+        if let Some(wal) = &self.wal {
+            for record in wal
+                .replay()?
+                .into_iter()
+                .chain(self.unlogged.iter().cloned())
+            {
+                if record.seqno().is_some_and(|seqno| seqno >= from_seqno) {
+                    logged.push(record);
+                } else if let WalRecord::Batch(records) = record {
+                    // A batch that straddles `from_seqno` is cut short.
+                    logged.extend(
+                        records.into_iter().filter(|record| {
+                            record.seqno().is_some_and(|seqno| seqno >= from_seqno)
+                        }),
+                    );
+                }
+            }
+        }
+
+        // Everything from the first logged write on is in the WAL. Without one, the
+        // memtables hold everything that hasn't been flushed.
+        let unlogged_seqnos = (
+            Bound::Included(from_seqno),
+            logged
+                .first()
+                .and_then(WalRecord::seqno)
+                .map_or(Bound::Unbounded, Bound::Excluded),
+        );
+
+        // Keyed by seqno, which also drops the copies of a range tombstone kept by the
+        // several files it was written to.
+        let mut unlogged = BTreeMap::new();
+        let mut tombstones = Vec::new();
+
+        // Entries are filtered as they're read, so only those being replayed are kept.
+        let mut add = |cf: ColumnFamilyId, key: Key, value: Value| {
+            let seqno = key.seqno();
+
+            if unlogged_seqnos.contains(&seqno)
+                && let Some(record) = tail::entry_record(cf, key, value)
+            {
+                unlogged.insert(seqno, record);
+            }
+        };
+
+        let all = (Bound::Unbounded, Bound::Unbounded);
+        let block_options = self.block_read_options(
+            &ReadOptions {
+                fill_cache: false,
+                ..Default::default()
+            },
+            None,
+        );
+
+        for (id, family) in &self.families {
+            if let Some(sstables) = &self.sstables {
+                for table in sstables.tables_since(*id, from_seqno)? {
+                    for entry in table.range(all.clone(), block_options) {
+                        let (key, value) = entry?;
+                        add(*id, key, value);
+                    }
+                }
+
+                tombstones.extend(sstables.range_tombstones(*id)?.map(|t| (*id, t.clone())));
+            }
+
+            if self.wal.is_none() {
+                let frozen = family.imm_tables.read().await.expect("lock closed");
+
+                for table in frozen.iter() {
+                    for (key, value) in table.range(all.clone()) {
+                        add(*id, key.clone(), value.clone());
+                    }
+                    tombstones.extend(table.range_tombstones().iter().map(|t| (*id, t.clone())));
+                }
+
+                for (key, value) in family.table.range(all.clone()) {
+                    add(*id, key.clone(), value.clone());
+                }
+                tombstones.extend(
+                    family
+                        .table
+                        .range_tombstones()
+                        .iter()
+                        .map(|t| (*id, t.clone())),
+                );
+            }
+        }
+
+        for (cf, tombstone) in tombstones {
+            if unlogged_seqnos.contains(&tombstone.seqno) {
+                unlogged.insert(
+                    tombstone.seqno,
+                    WalRecord::DeleteRange {
+                        cf,
+                        key: Key::new(tombstone.start, tombstone.seqno),
+                        end: tombstone.end,
+                    },
+                );
+            }
+        }
+
+        let (tx, rx) = channel::channel(TAIL_BUFFER_CAPACITY);
+
+        self.tailers.borrow_mut().push(tx);
+
+        Ok(Tail::new(
+            unlogged.into_values().chain(logged).collect(),
+            rx,
+        ))
+    }
+
+    pub fn debug_replay_wal(&mut self) -> anyhow::Result<Vec<WalRecord>> {
+        match &mut self.wal {
+            Some(wal) => wal.replay(),
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+/// The WAL records of a batch's writes, each keyed at the seqno `seqno` returns. Empty
+/// range deletions are left out.
+fn batch_records(ops: Vec<BatchOp>, now: u64, mut seqno: impl FnMut() -> SeqNo) -> Vec<WalRecord> {
+    let mut records = Vec::with_capacity(ops.len());
+
+    for op in ops {
+        let record = match op {
+            BatchOp::Put {
+                cf,
+                key,
+                val,
+                ttl: None,
+            } => WalRecord::Put {
+                cf,
+                key: Key::new(key, seqno()),
+                val,
+            },
+            BatchOp::Put {
+                cf,
+                key,
+                val,
+                ttl: Some(ttl),
+            } => WalRecord::PutExpiring {
+                cf,
+                key: Key::new(key, seqno()),
+                val,
+                expires_at: now.saturating_add(ttl.as_millis() as u64),
+            },
+            BatchOp::Delete { cf, key } => WalRecord::Delete {
+                cf,
+                key: Key::new(key, seqno()),
+            },
+            BatchOp::DeleteRange { cf, start, end } => {
+                if end.as_ref().is_some_and(|end| *end <= start) {
+                    continue;
+                }
+
+                WalRecord::DeleteRange {
+                    cf,
+                    key: Key::new(start, seqno()),
+                    end,
+                }
+            }
+        };
+
+        records.push(record);
+    }
+
+    records
+}
+
+/// Removes the WAL at `path`, left from when the database was last opened with
+/// [`Config::wal_enabled`] set, or fails if it holds writes that haven't been flushed, rather
+/// than dropping them.
+fn remove_empty_wal(path: &std::path::Path) -> anyhow::Result<()> {
+    let len = match std::fs::metadata(path) {
+        Ok(metadata) => metadata.len(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e).context("Failed to check for a WAL"),
+    };
+
+    if len > 0 {
+        anyhow::bail!(
+            "WAL {} still holds unflushed writes. Open the database with the WAL enabled and \
+             close it to flush them before disabling it",
+            path.display()
+        );
+    }
+
+    std::fs::remove_file(path).context("Failed to remove empty WAL")
+}
+
+/// Flushes every frozen memtable of `family`, oldest first.
+async fn flush_frozen_memtables(
+    sstables: &mut SSTableManager,
+    id: ColumnFamilyId,
+    family: &ColumnFamilyData,
+) -> anyhow::Result<()> {
+    while !family
+        .imm_tables
+        .read()
+        .await
+        .expect("lock closed")
+        .is_empty()
+    {
+        sstables.flush_memtable(id, &family.imm_tables).await?;
+
+        family
+            .imm_tables
+            .write()
+            .await
+            .expect("lock closed")
+            .pop_front();
+    }
+
+    Ok(())
+}
+
+/// Whether the write at `seqno` in `cf` is already in an SSTable, so WAL replay skips it.
+fn is_committed(
+    sstables: &SSTableManager,
+    cf: ColumnFamilyId,
+    seqno: SeqNo,
+) -> anyhow::Result<bool> {
+    Ok(seqno <= sstables.last_committed_sequence_number(cf)?)
+}
+
+/// Re-reads `replay` and checks that every record not yet committed to an SSTable is in
+/// the memtables rebuilt from it, failing with the first record that isn't.
+fn check_replay(
+    families: &mut BTreeMap<ColumnFamilyId, ColumnFamilyData>,
+    sstables: &SSTableManager,
+    replay: Vec<WalRecord>,
+) -> anyhow::Result<()> {
+    for record in replay.into_iter().flat_map(WalRecord::into_records) {
+        let (Some(cf), Some(key)) = (record.cf(), record.key()) else {
+            unreachable!("batches are flattened");
+        };
+
+        if is_committed(sstables, cf, key.seqno())? {
+            continue;
+        }
+
+        let family = families
+            .get_mut(&cf)
+            .with_context(|| format!("WAL record for unknown column family {cf}"))?;
+
+        let replayed = holds_record(&family.table, &record)
+            || family
+                .imm_tables
+                .get_mut()
+                .expect("lock closed")
+                .iter()
+                .any(|table| holds_record(table, &record));
+
+        if !replayed {
+            anyhow::bail!(
+                "WAL record for key {:?} at seqno {} in column family {cf} is missing from the \
+                 memtables after replay",
+                key.user_key(),
+                key.seqno().get()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that the size `table` has kept count of matches its contents, failing if it's
+/// drifted, which would have it frozen too early or too late.
+fn check_memtable_size(cf: ColumnFamilyId, table: &MemTable<state::Active>) -> anyhow::Result<()> {
+    let recomputed = table.recompute_size();
+
+    if table.size() != recomputed {
+        anyhow::bail!(
+            "Memtable of column family {cf} counts {} bytes but holds {recomputed}",
+            table.size()
+        );
+    }
+
+    Ok(())
+}
+
+/// Whether `record` (not a batch) has been applied to `table`.
+fn holds_record<S: MemTableState>(table: &MemTable<S>, record: &WalRecord) -> bool {
+    match record {
+        WalRecord::Put { key, val, .. } => {
+            matches!(table.get(key), Some(Value::Data(data)) if data == val)
+        }
+        WalRecord::PutExpiring {
+            key,
+            val,
+            expires_at,
+            ..
+        } => matches!(
+            table.get(key),
+            Some(Value::Expiring { data, expires_at: at }) if data == val && at == *expires_at
+        ),
+        WalRecord::Delete { key, .. } => matches!(table.get(key), Some(Value::Tombstone)),
+        WalRecord::DeleteRange { key, end, .. } => table
+            .range_tombstones()
+            .iter()
+            .any(|t| t.start == key.user_key() && t.end == *end && t.seqno == key.seqno()),
+        WalRecord::Batch(_)
+        | WalRecord::Prepare { .. }
+        | WalRecord::CommitPrepared { .. }
+        | WalRecord::AbortPrepared { .. } => {
+            unreachable!("batches are flattened, and prepared batches resolved")
+        }
+    }
+}
+
+/// Applies a single (non-batch) WAL record to a memtable.
+fn apply_record(table: &mut MemTable<state::Active>, record: WalRecord) {
+    match record {
+        WalRecord::Put { key, val, .. } => table.put(key, val),
+        WalRecord::PutExpiring {
+            key,
+            val,
+            expires_at,
+            ..
+        } => table.put_expiring(key, val, expires_at),
+        WalRecord::Delete { key, .. } => table.delete(key),
+        WalRecord::DeleteRange { key, end, .. } => table.delete_range(RangeTombstone {
+            start: key.user_key().clone(),
+            end,
+            seqno: key.seqno(),
+        }),
+        WalRecord::Batch(_)
+        | WalRecord::Prepare { .. }
+        | WalRecord::CommitPrepared { .. }
+        | WalRecord::AbortPrepared { .. } => {
+            unreachable!("batches are flattened, and prepared batches resolved")
+        }
+    }
+}
+
+/// Strips the values returned by [`Database::scan_inner`] down to their data.
+fn live_pairs(page: Vec<(bytes::Bytes, Value)>) -> Vec<(bytes::Bytes, bytes::Bytes)> {
+    page.into_iter()
+        .map(|(key, value)| (key, value.data().cloned().expect("scans skip tombstones")))
+        .collect()
+}
+
+/// Returns the smallest key greater than every key starting with `prefix`, or `None` if
+/// there isn't one (the prefix is empty or all `0xFF`).
+fn prefix_upper_bound(prefix: &[u8]) -> Option<bytes::Bytes> {
+    let last = prefix.iter().rposition(|&b| b != 0xFF)?;
+
+    let mut end = prefix[..=last].to_vec();
+    end[last] += 1;
+
+    Some(end.into())
 }
@@ -0,0 +1,117 @@
+//! An append-only log of events, each at a sequential offset, kept in a column family of
+//! its own. Like a topic in a message log, it's read forward from an offset and truncated
+//! from the front.
+//!
+//! Events are stored under their offset, as 8 big-endian bytes after [`EVENT_PREFIX`], so
+//! that they sort in the order they were appended. The next offset to assign is kept under
+//! [`NEXT_OFFSET_KEY`], which sorts after every event, and is written in the same batch as
+//! each event, so offsets are never reused, even once the events holding them have been
+//! truncated.
+
+use crate::{batch::WriteBatch, column_family::ColumnFamily, Database};
+
+/// The byte every event's key starts with.
+pub const EVENT_PREFIX: u8 = b'e';
+/// The key the next offset is stored under.
+pub const NEXT_OFFSET_KEY: &[u8] = b"next";
+
+fn event_key(offset: u64) -> bytes::Bytes {
+    let mut key = Vec::with_capacity(9);
+    key.push(EVENT_PREFIX);
+    key.extend_from_slice(&offset.to_be_bytes());
+    key.into()
+}
+
+fn decode_offset(bytes: &[u8]) -> anyhow::Result<u64> {
+    Ok(u64::from_be_bytes(bytes.try_into().map_err(|_| {
+        anyhow::anyhow!("Event log offset is {} bytes, not 8", bytes.len())
+    })?))
+}
+
+/// An append-only log of events in a column family, created with [`Database::event_log`].
+pub struct EventLog<'a> {
+    db: &'a mut Database,
+    cf: ColumnFamily,
+    next_offset: u64,
+}
+
+impl<'a> EventLog<'a> {
+    pub(crate) async fn open(db: &'a mut Database, name: &str) -> anyhow::Result<Self> {
+        let cf = match db.cf(name) {
+            Some(cf) => cf,
+            None => db.create_cf(name)?,
+        };
+
+        let next_offset = match db
+            .get_cf(&cf, &bytes::Bytes::from_static(NEXT_OFFSET_KEY))
+            .await?
+        {
+            Some(next) => decode_offset(&next)?,
+            None => 0,
+        };
+
+        Ok(EventLog {
+            db,
+            cf,
+            next_offset,
+        })
+    }
+
+    /// The column family the log is kept in.
+    pub fn cf(&self) -> &ColumnFamily {
+        &self.cf
+    }
+
+    /// The offset the next event appended will be given.
+    pub fn next_offset(&self) -> u64 {
+        self.next_offset
+    }
+
+    /// Appends `event` and returns the offset it was given, one past the last event's.
+    pub async fn append(&mut self, event: impl Into<bytes::Bytes>) -> anyhow::Result<u64> {
+        let offset = self.next_offset;
+
+        let mut batch = WriteBatch::new();
+        batch.put(&self.cf, event_key(offset), event);
+        batch.put(
+            &self.cf,
+            NEXT_OFFSET_KEY,
+            (offset + 1).to_be_bytes().to_vec(),
+        );
+
+        self.db.write(batch).await?;
+        self.next_offset = offset + 1;
+
+        Ok(offset)
+    }
+
+    /// Returns up to `limit` events in offset order, starting from the first at or after
+    /// `offset`, along with their offsets. Pass one past the last offset returned to read
+    /// on from there.
+    pub async fn read_from(
+        &self,
+        offset: u64,
+        limit: usize,
+    ) -> anyhow::Result<Vec<(u64, bytes::Bytes)>> {
+        let end = bytes::Bytes::from_static(&[EVENT_PREFIX + 1]);
+
+        let (events, _) = self
+            .db
+            .scan_paginated_cf(&self.cf, event_key(offset)..end, limit, None)
+            .await?;
+
+        events
+            .into_iter()
+            .map(|(key, event)| Ok((decode_offset(&key[1..])?, event)))
+            .collect()
+    }
+
+    /// Drops every event before `offset`, returning the number of SSTables dropped
+    /// outright; see [`Database::drop_files_before`]. Offsets aren't reused, so appends
+    /// carry on from where they were.
+    pub async fn truncate_before(&mut self, offset: u64) -> anyhow::Result<usize> {
+        self.db
+            .drop_files_before_cf(&self.cf, event_key(offset))
+            .await
+    }
+}
@@ -1,29 +1,161 @@
 //! This modle implements a generic on-disk log structure with framing around postcard.
 
-use std::io::Write;
+use std::io::{Read, Write};
 
 use anyhow::Context;
 
-pub fn write_framed<W, T>(mut writer: W, data: &T) -> anyhow::Result<usize>
+use crate::compression::Compression;
+
+/// Set in the length prefix of frames whose payload is `[compression u8][compressed data]`
+/// rather than plain postcard.
+const COMPRESSED_FLAG: u32 = 1 << 31;
+
+/// Set in the length prefix of frames whose prefix is followed by a CRC32 of the payload,
+/// which is every frame written since checksums were added. Frames written before then
+/// don't have it, and are read unchecked, except after a checksummed frame in a log read
+/// with a [`FrameReader`], or at all with [`FrameReader::checksummed_only`].
+const CHECKSUM_FLAG: u32 = 1 << 30;
+
+const FLAGS: u32 = COMPRESSED_FLAG | CHECKSUM_FLAG;
+
+/// Why a frame couldn't be read.
+#[derive(Debug)]
+pub enum FramedError {
+    /// The reader ended before a whole frame could be read, or where the pre-allocated
+    /// zeros after the last frame start.
+    UnexpectedEnd,
+    /// The payload doesn't match the checksum it was written with, so the frame was torn or
+    /// corrupted on disk.
+    ChecksumMismatch { expected: u32, actual: u32 },
+    /// The frame has no checksum, but follows frames in the same log that do. Frames are
+    /// only ever appended, so its checksum flag was corrupted on disk. See [`FrameReader`].
+    MissingChecksum,
+    /// The first frame of a log read with [`FrameReader::checksummed_only`] has no checksum,
+    /// so the log was written before checksums were added, in a record format that has
+    /// changed since.
+    Legacy,
+    /// The payload passed its checksum, or had none, but couldn't be decoded.
+    Decode(postcard::Error),
+}
+
+impl std::fmt::Display for FramedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FramedError::UnexpectedEnd => write!(f, "Frame is cut short"),
+            FramedError::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "Frame checksum mismatch: expected {expected:#010x}, got {actual:#010x}"
+            ),
+            FramedError::MissingChecksum => {
+                write!(f, "Frame has no checksum, but follows frames that do")
+            }
+            FramedError::Legacy => write!(
+                f,
+                "Log has no checksums, so it was written by an older version in a format \
+                 this one can't read"
+            ),
+            FramedError::Decode(e) => write!(f, "Failed to decode frame: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for FramedError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FramedError::Decode(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<postcard::Error> for FramedError {
+    fn from(e: postcard::Error) -> Self {
+        FramedError::Decode(e)
+    }
+}
+
+/// Writes `data` as one frame: `[len u32][crc32 u32][payload]`, with the length's top bits
+/// holding its flags.
+pub fn write_framed<W, T>(writer: W, data: &T) -> anyhow::Result<usize>
+where
+    W: Write,
+    T: serde::Serialize,
+{
+    let bytes = postcard::to_stdvec(&data)?;
+
+    write_framed_bytes(writer, &bytes, false)
+}
+
+/// Like [`write_framed`], but compresses the payload with `compression` if the serialized
+/// data is at least `threshold` bytes and compressing actually makes it smaller.
+pub fn write_framed_compressed<W, T>(
+    mut writer: W,
+    data: &T,
+    compression: Compression,
+    threshold: usize,
+) -> anyhow::Result<usize>
 where
     W: Write,
     T: serde::Serialize,
 {
     let bytes = postcard::to_stdvec(&data)?;
 
-    let len: u32 = bytes.len().try_into().context("Length exceeds u32::MAX")?;
+    if compression == Compression::None || bytes.len() < threshold {
+        return write_framed_bytes(writer, &bytes, false);
+    }
+
+    let mut compressed = Vec::with_capacity(bytes.len());
+    compressed.push(compression as u8);
+    compressed.extend(compression.compress(&bytes));
+
+    if compressed.len() >= bytes.len() {
+        return write_framed_bytes(writer, &bytes, false);
+    }
+
+    write_framed_bytes(&mut writer, &compressed, true)
+}
+
+fn write_framed_bytes<W: Write>(
+    mut writer: W,
+    bytes: &[u8],
+    compressed: bool,
+) -> anyhow::Result<usize> {
+    let mut len: u32 = bytes.len().try_into().context("Length exceeds u32::MAX")?;
+
+    if len & FLAGS != 0 {
+        anyhow::bail!("Length exceeds maximum frame size");
+    }
+
+    len |= CHECKSUM_FLAG;
+
+    if compressed {
+        len |= COMPRESSED_FLAG;
+    }
 
     writer
         .write_all(&len.to_le_bytes())
         .context("Failed to write framed length")?;
     writer
-        .write_all(&bytes)
+        .write_all(&crc32fast::hash(bytes).to_le_bytes())
+        .context("Failed to write framed checksum")?;
+    writer
+        .write_all(bytes)
         .context("Failed to write framed data")?;
 
-    Ok(bytes.len() + 4)
+    Ok(bytes.len() + 8)
 }
 
-pub fn read_framed<R, T>(mut reader: R) -> postcard::Result<T>
+pub fn read_framed<R, T>(reader: R) -> Result<T, FramedError>
+where
+    R: std::io::Read,
+    T: serde::de::DeserializeOwned,
+{
+    read_frame(reader, false).map(|(data, _)| data)
+}
+
+/// Reads one frame, along with whether it had a checksum, failing with
+/// [`FramedError::MissingChecksum`] if it didn't and `require_checksum` is set.
+fn read_frame<R, T>(mut reader: R, require_checksum: bool) -> Result<(T, bool), FramedError>
 where
     R: std::io::Read,
     T: serde::de::DeserializeOwned,
@@ -31,24 +163,173 @@ where
     let mut len_buf = [0u8; 4];
     reader
         .read_exact(&mut len_buf)
-        .map_err(|_| postcard::Error::DeserializeUnexpectedEnd)?;
+        .map_err(|_| FramedError::UnexpectedEnd)?;
 
     let len = u32::from_le_bytes(len_buf);
+    let compressed = len & COMPRESSED_FLAG != 0;
+    let checksummed = len & CHECKSUM_FLAG != 0;
+    let len = len & !FLAGS;
 
     if len == 0 {
-        return Err(postcard::Error::DeserializeUnexpectedEnd);
+        return Err(FramedError::UnexpectedEnd);
+    }
+
+    if require_checksum && !checksummed {
+        return Err(FramedError::MissingChecksum);
     }
 
-    let mut buf = vec![0u8; len as usize];
+    let expected = if checksummed {
+        let mut crc_buf = [0u8; 4];
+        reader
+            .read_exact(&mut crc_buf)
+            .map_err(|_| FramedError::UnexpectedEnd)?;
+
+        Some(u32::from_le_bytes(crc_buf))
+    } else {
+        None
+    };
+
+    // Read rather than allocated up front, so that a corrupt length doesn't allocate more
+    // than is actually there.
+    let mut buf = Vec::new();
 
     reader
-        .read_exact(&mut buf)
-        .map_err(|_| postcard::Error::DeserializeUnexpectedEnd)?;
+        .take(len.into())
+        .read_to_end(&mut buf)
+        .map_err(|_| FramedError::UnexpectedEnd)?;
 
-    postcard::from_bytes(&buf)
+    if buf.len() != len as usize {
+        return Err(FramedError::UnexpectedEnd);
+    }
+
+    if let Some(expected) = expected {
+        let actual = crc32fast::hash(&buf);
+
+        if actual != expected {
+            return Err(FramedError::ChecksumMismatch { expected, actual });
+        }
+    }
+
+    if compressed {
+        let compression =
+            Compression::from_u8(buf[0]).ok_or(postcard::Error::DeserializeBadEncoding)?;
+
+        let decompressed = compression
+            .decompress(&buf[1..])
+            .map_err(|_| postcard::Error::DeserializeBadEncoding)?;
+
+        return Ok((postcard::from_bytes(&decompressed)?, checksummed));
+    }
+
+    Ok((postcard::from_bytes(&buf)?, checksummed))
+}
+
+/// Reads the frames of a log, such as the WAL or a manifest, one after another.
+///
+/// Whether a frame has a checksum is a flag in its length prefix, which the checksum itself
+/// can't cover. Logs are only ever appended to, and every frame written since checksums
+/// were added has one, so once a frame with a checksum has been read, a frame without one
+/// must have had its flag corrupted, and fails with [`FramedError::MissingChecksum`] rather
+/// than being read unchecked.
+pub struct FrameReader<R> {
+    reader: R,
+    checksummed: bool,
+    /// Whether the first frame has to have a checksum too.
+    checksummed_only: bool,
+}
+
+impl<R: Read> FrameReader<R> {
+    pub fn new(reader: R) -> Self {
+        FrameReader {
+            reader,
+            checksummed: false,
+            checksummed_only: false,
+        }
+    }
+
+    /// Like [`FrameReader::new`], for logs whose records changed format after they were
+    /// first written without checksums, like the WAL and the manifest. A first frame
+    /// without a checksum fails with [`FramedError::Legacy`] rather than being decoded, or
+    /// worse misdecoded, as the current format.
+    pub fn checksummed_only(reader: R) -> Self {
+        FrameReader {
+            checksummed_only: true,
+            ..FrameReader::new(reader)
+        }
+    }
+
+    /// Reads the next frame. See [`read_framed`].
+    pub fn read<T: serde::de::DeserializeOwned>(&mut self) -> Result<T, FramedError> {
+        let require_checksum = self.checksummed || self.checksummed_only;
+
+        let (data, checksummed) = match read_frame(&mut self.reader, require_checksum) {
+            Err(FramedError::MissingChecksum) if !self.checksummed => {
+                return Err(FramedError::Legacy);
+            }
+            result => result?,
+        };
+        self.checksummed |= checksummed;
+
+        Ok(data)
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+}
+
+/// The number of frames in `buf`, found by following each one's length prefix without
+/// reading its payload, so frames that were cut short still count. Stops at a zero length,
+/// which is where pre-allocated space starts.
+pub fn count_frames(buf: &[u8]) -> usize {
+    let mut count = 0;
+    let mut offset = 0usize;
+
+    while offset < buf.len() {
+        let Some(prefix) = buf.get(offset..offset + 4) else {
+            // Cut off partway through its length.
+            return count + 1;
+        };
+
+        let prefix = u32::from_le_bytes(prefix.try_into().expect("4 bytes"));
+        let len = prefix & !FLAGS;
+
+        if len == 0 {
+            break;
+        }
+
+        let header = match prefix & CHECKSUM_FLAG {
+            0 => 4,
+            _ => 8,
+        };
+
+        count += 1;
+        offset = offset.saturating_add(header + len as usize);
+    }
+
+    count
+}
+
+/// Reads frames until the reader ends, failing at the first one that's corrupt. The frames
+/// are read as a log, with a [`FrameReader`].
+pub fn read_all_framed<R, T>(reader: R) -> Result<Vec<T>, FramedError>
+where
+    R: std::io::Read,
+    T: serde::de::DeserializeOwned,
+{
+    read_all_from(FrameReader::new(reader))
+}
+
+/// Like [`read_all_framed`], but with [`FrameReader::checksummed_only`].
+pub fn read_all_checksummed<R, T>(reader: R) -> Result<Vec<T>, FramedError>
+where
+    R: std::io::Read,
+    T: serde::de::DeserializeOwned,
+{
+    read_all_from(FrameReader::checksummed_only(reader))
 }
 
-pub fn read_all_framed<R, T>(mut reader: R) -> postcard::Result<Vec<T>>
+fn read_all_from<R, T>(mut reader: FrameReader<R>) -> Result<Vec<T>, FramedError>
 where
     R: std::io::Read,
     T: serde::de::DeserializeOwned,
@@ -56,14 +337,10 @@ where
     let mut res = Vec::new();
 
     loop {
-        match read_framed::<_, T>(&mut reader) {
+        match reader.read::<T>() {
             Ok(record) => res.push(record),
-            Err(e) => match e {
-                postcard::Error::DeserializeUnexpectedEnd => {
-                    break;
-                }
-                e => return Err(e),
-            },
+            Err(FramedError::UnexpectedEnd) => break,
+            Err(e) => return Err(e),
         };
     }
 
@@ -0,0 +1,58 @@
+//! Deduplication of retried writes by idempotency key.
+
+use std::{
+    collections::{HashSet, VecDeque},
+    time::{Duration, Instant},
+};
+
+/// The idempotency keys of recent writes, bounded both in age and in number.
+#[derive(Debug)]
+pub(crate) struct RecentWrites {
+    window: Duration,
+    capacity: usize,
+    /// Keys in the order they were recorded, with when.
+    order: VecDeque<(bytes::Bytes, Instant)>,
+    keys: HashSet<bytes::Bytes>,
+}
+
+impl RecentWrites {
+    pub(crate) fn new(window: Duration, capacity: usize) -> Self {
+        RecentWrites {
+            window,
+            capacity,
+            order: VecDeque::new(),
+            keys: HashSet::new(),
+        }
+    }
+
+    /// Forgets keys recorded longer than the window before `now`.
+    fn expire(&mut self, now: Instant) {
+        while let Some((key, at)) = self.order.front()
+            && now.saturating_duration_since(*at) >= self.window
+        {
+            self.keys.remove(key);
+            self.order.pop_front();
+        }
+    }
+
+    /// Whether a write with `key` was recorded within the window before `now`.
+    pub(crate) fn contains(&mut self, key: &bytes::Bytes, now: Instant) -> bool {
+        self.expire(now);
+        self.keys.contains(key)
+    }
+
+    /// Records a write with `key` at `now`, forgetting the oldest key if at capacity.
+    pub(crate) fn insert(&mut self, key: bytes::Bytes, now: Instant) {
+        if self.capacity == 0 || !self.keys.insert(key.clone()) {
+            return;
+        }
+
+        if self.order.len() >= self.capacity
+            && let Some((oldest, _)) = self.order.pop_front()
+        {
+            self.keys.remove(&oldest);
+        }
+
+        self.order.push_back((key, now));
+    }
+}
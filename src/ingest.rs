@@ -0,0 +1,282 @@
+//! The external sort behind [`Database::ingest_unsorted`](crate::Database::ingest_unsorted),
+//! which puts more pairs in key order than fit in memory.
+//!
+//! Pairs are buffered up to [`Config::ingest_memory_budget`] bytes, then sorted and spilled
+//! to a run file in [`INGEST_DIR_NAME`]. Once the input is exhausted the runs, and what's
+//! left in the buffer, are merged into one sorted stream. A key given more than once keeps
+//! the value it was given last.
+//!
+//! [`Config::ingest_memory_budget`]: crate::config::Config::ingest_memory_budget
+
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+
+use crate::framed::{read_framed, write_framed};
+
+/// The directory in [`Config::data_dir`](crate::config::Config::data_dir) that run files
+/// are spilled to. It's removed once the ingest is done, and cleared at the start of the
+/// next if an ingest was interrupted.
+pub const INGEST_DIR_NAME: &str = "ingest";
+
+/// The most runs merged at once, to bound the open files and read buffers of a merge. With
+/// more, the oldest are merged into one run first, as many times as it takes.
+pub const MAX_MERGE_WIDTH: usize = 64;
+
+type Pair = (bytes::Bytes, bytes::Bytes);
+
+/// A sorted run spilled to disk.
+#[derive(Debug)]
+struct Run {
+    path: PathBuf,
+    len: usize,
+}
+
+/// Collects pairs in any order, spilling sorted runs to disk as the buffer fills, and
+/// gives them back in key order with [`ExternalSorter::finish`].
+#[derive(Debug)]
+pub(crate) struct ExternalSorter {
+    dir: PathBuf,
+    memory_budget: usize,
+    buffer: Vec<Pair>,
+    buffered_bytes: usize,
+    /// Spilled runs, in the order they were spilled, so later runs hold later input.
+    runs: Vec<Run>,
+    next_run: u64,
+}
+
+impl ExternalSorter {
+    pub(crate) fn new(dir: PathBuf, memory_budget: usize) -> anyhow::Result<Self> {
+        match std::fs::remove_dir_all(&dir) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to clear {}", dir.display()));
+            }
+        }
+
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+
+        Ok(ExternalSorter {
+            dir,
+            memory_budget,
+            buffer: Vec::new(),
+            buffered_bytes: 0,
+            runs: Vec::new(),
+            next_run: 0,
+        })
+    }
+
+    pub(crate) fn push(&mut self, key: bytes::Bytes, value: bytes::Bytes) -> anyhow::Result<()> {
+        self.buffered_bytes += key.len() + value.len();
+        self.buffer.push((key, value));
+
+        if self.buffered_bytes >= self.memory_budget {
+            let pairs = sort_buffer(std::mem::take(&mut self.buffer));
+            self.buffered_bytes = 0;
+
+            let run = self.write_run(pairs.into_iter().map(Ok))?;
+            self.runs.push(run);
+        }
+
+        Ok(())
+    }
+
+    /// Merges everything pushed into one stream in key order.
+    pub(crate) fn finish(mut self) -> anyhow::Result<SortedPairs> {
+        while self.runs.len() > MAX_MERGE_WIDTH {
+            let oldest = self.runs.drain(..MAX_MERGE_WIDTH).collect::<Vec<_>>();
+            let merged = self.write_run(SortedPairs::merge(&oldest, Vec::new())?)?;
+
+            for run in &oldest {
+                remove_run(run)?;
+            }
+
+            self.runs.insert(0, merged);
+        }
+
+        let buffer = sort_buffer(std::mem::take(&mut self.buffer));
+        let pairs = SortedPairs::merge(&self.runs, buffer)?;
+
+        Ok(SortedPairs {
+            _sorter: Some(self),
+            ..pairs
+        })
+    }
+
+    fn write_run(
+        &mut self,
+        pairs: impl Iterator<Item = anyhow::Result<Pair>>,
+    ) -> anyhow::Result<Run> {
+        let path = self.dir.join(format!("{:06}.run", self.next_run));
+        self.next_run += 1;
+
+        let file = File::create_new(&path)
+            .with_context(|| format!("Failed to create run file {}", path.display()))?;
+        let mut writer = BufWriter::new(file);
+        let mut len = 0;
+
+        for pair in pairs {
+            write_framed(&mut writer, &pair?)?;
+            len += 1;
+        }
+
+        writer
+            .flush()
+            .with_context(|| format!("Failed to write run file {}", path.display()))?;
+
+        Ok(Run { path, len })
+    }
+}
+
+impl Drop for ExternalSorter {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_dir_all(&self.dir) {
+            eprintln!("Failed to remove {}: {e}", self.dir.display());
+        }
+    }
+}
+
+fn remove_run(run: &Run) -> anyhow::Result<()> {
+    std::fs::remove_file(&run.path)
+        .with_context(|| format!("Failed to remove run file {}", run.path.display()))
+}
+
+/// Sorts `pairs` by key, keeping only the last pair pushed for each key.
+fn sort_buffer(mut pairs: Vec<Pair>) -> Vec<Pair> {
+    // Reversed so that the stable sort puts the last of each key first, which is the one
+    // `dedup_by` keeps.
+    pairs.reverse();
+    pairs.sort_by(|a, b| a.0.cmp(&b.0));
+    pairs.dedup_by(|a, b| a.0 == b.0);
+    pairs
+}
+
+/// One of the sources merged by [`SortedPairs`]: a run file, or the buffer that was never
+/// spilled.
+#[derive(Debug)]
+enum Source {
+    Run {
+        reader: BufReader<File>,
+        path: PathBuf,
+        remaining: usize,
+    },
+    Buffer(std::vec::IntoIter<Pair>),
+}
+
+impl Source {
+    fn next(&mut self) -> anyhow::Result<Option<Pair>> {
+        match self {
+            Source::Run {
+                reader,
+                path,
+                remaining,
+            } => {
+                if *remaining == 0 {
+                    return Ok(None);
+                }
+
+                *remaining -= 1;
+
+                read_framed(reader)
+                    .map(Some)
+                    .with_context(|| format!("Failed to read run file {}", path.display()))
+            }
+            Source::Buffer(pairs) => Ok(pairs.next()),
+        }
+    }
+}
+
+/// Pairs in key order, merged from sorted runs. Of the pairs with the same key, only the
+/// one from the latest source is yielded.
+#[derive(Debug)]
+pub(crate) struct SortedPairs {
+    sources: Vec<Source>,
+    /// The next key of each source that has one, ordered so the smallest key comes first,
+    /// and of equal keys the one from the latest source.
+    heads: BinaryHeap<Reverse<(bytes::Bytes, Reverse<usize>)>>,
+    /// The value that goes with each source's key in `heads`.
+    values: Vec<Option<bytes::Bytes>>,
+    /// Keeps the run files around until the merge is done.
+    _sorter: Option<ExternalSorter>,
+}
+
+impl SortedPairs {
+    fn merge(runs: &[Run], buffer: Vec<Pair>) -> anyhow::Result<Self> {
+        let mut sources = runs
+            .iter()
+            .map(|run| open_run(&run.path, run.len))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        sources.push(Source::Buffer(buffer.into_iter()));
+
+        let mut pairs = SortedPairs {
+            values: vec![None; sources.len()],
+            sources,
+            heads: BinaryHeap::new(),
+            _sorter: None,
+        };
+
+        for i in 0..pairs.sources.len() {
+            pairs.advance(i)?;
+        }
+
+        Ok(pairs)
+    }
+
+    /// Reads the next pair of source `i` into the heap.
+    fn advance(&mut self, i: usize) -> anyhow::Result<()> {
+        if let Some((key, value)) = self.sources[i].next()? {
+            self.heads.push(Reverse((key, Reverse(i))));
+            self.values[i] = Some(value);
+        }
+
+        Ok(())
+    }
+
+    fn next_pair(&mut self) -> anyhow::Result<Option<Pair>> {
+        let Some(Reverse((key, Reverse(i)))) = self.heads.pop() else {
+            return Ok(None);
+        };
+
+        let value = self.values[i].take().expect("head has a value");
+        self.advance(i)?;
+
+        // Older values of the same key, from earlier sources.
+        while let Some(Reverse((next, Reverse(j)))) = self.heads.peek()
+            && *next == key
+        {
+            let j = *j;
+            self.heads.pop();
+            self.values[j] = None;
+            self.advance(j)?;
+        }
+
+        Ok(Some((key, value)))
+    }
+}
+
+impl Iterator for SortedPairs {
+    type Item = anyhow::Result<Pair>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_pair().transpose()
+    }
+}
+
+fn open_run(path: &Path, len: usize) -> anyhow::Result<Source> {
+    let file =
+        File::open(path).with_context(|| format!("Failed to open run file {}", path.display()))?;
+
+    Ok(Source::Run {
+        reader: BufReader::new(file),
+        path: path.to_owned(),
+        remaining: len,
+    })
+}
@@ -0,0 +1,176 @@
+//! Iterators for merging sorted key/value sources.
+
+use std::collections::BinaryHeap;
+
+use crate::{key::Key, value::Value};
+
+pub type Source<'a> = Box<dyn Iterator<Item = anyhow::Result<(Key, Value)>> + 'a>;
+
+/// An N-way merge over sorted `(Key, Value)` sources.
+///
+/// Entries are yielded in global [`Key`] order (user key ascending, newest version first),
+/// every version of every key included. Wrap it in [`NewestVersion`] to read only what's
+/// live.
+///
+/// If any source yields an error, the error is returned and the merge ends.
+pub struct MergeIterator<'a> {
+    sources: Vec<Source<'a>>,
+    heap: BinaryHeap<HeapEntry>,
+    error: Option<anyhow::Error>,
+    done: bool,
+}
+
+struct HeapEntry {
+    key: Key,
+    value: Value,
+    source: usize,
+}
+
+impl PartialEq for HeapEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == std::cmp::Ordering::Equal
+    }
+}
+
+impl Eq for HeapEntry {}
+
+impl PartialOrd for HeapEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for HeapEntry {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        // BinaryHeap is a max-heap, so reverse the key ordering to pop the smallest key first.
+        // Ties (which should only happen if two sources contain the exact same key) go to the
+        // source listed first.
+        other
+            .key
+            .cmp(&self.key)
+            .then_with(|| other.source.cmp(&self.source))
+    }
+}
+
+impl<'a> MergeIterator<'a> {
+    /// Creates a merge over `sources`, each of which must already be sorted by [`Key`].
+    pub fn new(sources: Vec<Source<'a>>) -> Self {
+        let mut iter = MergeIterator {
+            heap: BinaryHeap::with_capacity(sources.len()),
+            sources,
+            error: None,
+            done: false,
+        };
+
+        for source in 0..iter.sources.len() {
+            iter.refill(source);
+        }
+
+        iter
+    }
+
+    fn refill(&mut self, source: usize) {
+        match self.sources[source].next() {
+            Some(Ok((key, value))) => self.heap.push(HeapEntry { key, value, source }),
+            Some(Err(e)) => {
+                self.error.get_or_insert(e);
+            }
+            None => {}
+        }
+    }
+
+    fn pop(&mut self) -> Option<HeapEntry> {
+        let entry = self.heap.pop()?;
+
+        self.refill(entry.source);
+
+        Some(entry)
+    }
+}
+
+impl Iterator for MergeIterator<'_> {
+    type Item = anyhow::Result<(Key, Value)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        // A source that errored may have been hiding a newer version of a key, so stop
+        // before yielding anything else.
+        if let Some(e) = self.error.take() {
+            self.done = true;
+            return Some(Err(e));
+        }
+
+        let Some(entry) = self.pop() else {
+            self.done = true;
+            return None;
+        };
+
+        // The source it was refilled from may have failed in its place.
+        if let Some(e) = self.error.take() {
+            self.done = true;
+            return Some(Err(e));
+        }
+
+        Some(Ok((entry.key, entry.value)))
+    }
+}
+
+/// Narrows `(Key, Value)` entries sorted by [`Key`], as [`MergeIterator`] yields them, to
+/// what a read sees: the newest version of each user key, and nothing for a user key whose
+/// newest version is a tombstone.
+///
+/// Versions of a user key sort newest first, so the first one seen is the one kept. Range
+/// tombstones and expiry aren't applied, since they depend on the read.
+///
+/// An error from the entries is returned and ends the iteration.
+pub struct NewestVersion<I> {
+    entries: I,
+    /// The user key of the last entry taken, whose remaining versions are older.
+    last_user_key: Option<bytes::Bytes>,
+    done: bool,
+}
+
+impl<I> NewestVersion<I> {
+    pub fn new(entries: I) -> Self {
+        NewestVersion {
+            entries,
+            last_user_key: None,
+            done: false,
+        }
+    }
+}
+
+impl<I: Iterator<Item = anyhow::Result<(Key, Value)>>> Iterator for NewestVersion<I> {
+    type Item = anyhow::Result<(Key, Value)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        loop {
+            let (key, value) = match self.entries.next()? {
+                Ok(entry) => entry,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            };
+
+            if self.last_user_key.as_ref() == Some(key.user_key()) {
+                continue;
+            }
+
+            self.last_user_key = Some(key.user_key().clone());
+
+            if matches!(value, Value::Tombstone) {
+                continue;
+            }
+
+            return Some(Ok((key, value)));
+        }
+    }
+}
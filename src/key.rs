@@ -1,3 +1,5 @@
+use std::ops::Bound;
+
 use bytes::{Buf, BufMut};
 
 #[derive(
@@ -20,13 +22,14 @@ impl From<u64> for SeqNo {
     }
 }
 
-impl Into<u64> for SeqNo {
-    fn into(self) -> u64 {
-        self.0
+impl From<SeqNo> for u64 {
+    fn from(value: SeqNo) -> Self {
+        value.0
     }
 }
 
 impl SeqNo {
+    #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> SeqNo {
         let cur = SeqNo(self.0);
         self.0 += 1;
@@ -42,6 +45,10 @@ impl SeqNo {
     }
 }
 
+/// A version of a user key: the user key along with the seqno it was written at.
+///
+/// Any byte string is a valid user key, including the empty one, which sorts before every
+/// other key. Keys are ordered by user key bytewise, then newest seqno first.
 #[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
 pub struct Key(bytes::Bytes, SeqNo);
 
@@ -50,16 +57,64 @@ impl Key {
         Key(user_key, seqno)
     }
 
-    pub fn min_seqno(user_key: bytes::Bytes) -> Self {
+    /// The first possible version of `user_key` in [`Key`] order, which every version of
+    /// it sorts at or after. Newer versions sort first, so it has the highest seqno.
+    ///
+    /// This and [`Key::user_key_upper_bound`] are the only places user keys are turned into
+    /// bounds over [`Key`]s, so that the inverted seqno order is dealt with once.
+    pub fn user_key_lower_bound(user_key: bytes::Bytes) -> Self {
         Key(user_key, SeqNo(u64::MAX))
     }
 
-    pub fn max_seqno(user_key: bytes::Bytes) -> Self {
+    /// The last possible version of `user_key` in [`Key`] order, which every version of it
+    /// sorts at or before. Older versions sort last, so it has the lowest seqno.
+    pub fn user_key_upper_bound(user_key: bytes::Bytes) -> Self {
         Key(user_key, SeqNo(0))
     }
 
+    /// Every version of `user_key`, newest first.
     pub fn range_by_user_key(user_key: bytes::Bytes) -> std::ops::RangeInclusive<Self> {
-        Key::min_seqno(user_key.clone())..=Key::max_seqno(user_key)
+        Key::user_key_lower_bound(user_key.clone())..=Key::user_key_upper_bound(user_key)
+    }
+
+    /// The versions of `user_key` with a seqno at or below `seqno`, newest first.
+    pub fn range_at_seqno(user_key: bytes::Bytes, seqno: SeqNo) -> std::ops::RangeInclusive<Self> {
+        Key(user_key.clone(), seqno)..=Key::user_key_upper_bound(user_key)
+    }
+
+    /// Translates bounds over user keys into bounds over [`Key`]s covering every version
+    /// of each user key in range.
+    ///
+    /// An included bound takes in every version of its user key, and an excluded one leaves
+    /// out every version of it, so the start is placed before the first version for one and
+    /// after the last for the other, and the end the other way around.
+    pub fn range_by_user_bounds(
+        range: &impl std::ops::RangeBounds<bytes::Bytes>,
+    ) -> (Bound<Key>, Bound<Key>) {
+        let start = match range.start_bound() {
+            Bound::Included(k) => Bound::Included(Key::user_key_lower_bound(k.clone())),
+            Bound::Excluded(k) => Bound::Excluded(Key::user_key_upper_bound(k.clone())),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+
+        let end = match range.end_bound() {
+            Bound::Included(k) => Bound::Included(Key::user_key_upper_bound(k.clone())),
+            Bound::Excluded(k) => Bound::Excluded(Key::user_key_lower_bound(k.clone())),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+
+        (start, end)
+    }
+
+    /// Returns true if `range` can't contain any key. `BTreeMap::range` panics on such ranges.
+    pub fn is_empty_range(range: &(Bound<Key>, Bound<Key>)) -> bool {
+        match range {
+            (Bound::Included(start), Bound::Included(end)) => start > end,
+            (Bound::Included(start), Bound::Excluded(end))
+            | (Bound::Excluded(start), Bound::Included(end))
+            | (Bound::Excluded(start), Bound::Excluded(end)) => start >= end,
+            _ => false,
+        }
     }
 
     pub fn with_seqno(&self, seqno: SeqNo) -> Self {
@@ -109,24 +164,21 @@ impl Key {
 
 impl PartialOrd for Key {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Key {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
         match self.0.cmp(&other.0) {
             std::cmp::Ordering::Equal => {}
-            ord => return Some(ord),
+            ord => return ord,
         }
 
-        Some(match self.1.cmp(&other.1) {
+        match self.1.cmp(&other.1) {
             std::cmp::Ordering::Less => std::cmp::Ordering::Greater,
             std::cmp::Ordering::Greater => std::cmp::Ordering::Less,
             std::cmp::Ordering::Equal => std::cmp::Ordering::Equal,
-        })
-    }
-}
-
-impl Ord for Key {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        match self.partial_cmp(other) {
-            Some(ord) => ord,
-            None => unreachable!("PartialOrd impl never returns None"),
         }
     }
 }
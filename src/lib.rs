@@ -1,13 +1,40 @@
+pub mod backup;
+pub mod batch;
+pub mod bloom;
+pub mod cache;
+pub mod channel;
+pub mod clock;
+pub mod column_family;
+pub mod compaction;
+pub mod compression;
 pub mod config;
+pub mod counter;
 pub mod db;
+pub mod event_log;
 pub mod framed;
+pub mod iter;
 pub mod key;
+pub mod lock;
 pub mod memtable;
+pub mod oneshot;
+pub mod options;
+pub mod reader;
+pub mod recovery;
+pub mod retry;
+pub mod scan;
+pub mod scrub;
+pub mod shard;
+pub mod snapshot;
 pub mod sstable;
+pub mod stall;
+pub mod stats;
+pub mod tail;
+pub mod tombstone;
 pub mod value;
 pub mod wal;
 
-mod oneshot;
+mod idempotency;
+mod ingest;
 
 pub use db::Database;
 pub use value::Value;
@@ -0,0 +1,248 @@
+//! Taking the file locks that keep two handles from opening the same database.
+
+use std::{
+    fs::File,
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::Context;
+
+use crate::{
+    config::Config,
+    db::AlreadyOpen,
+    recovery::{OpenReport, RecoveryAction},
+};
+
+/// How long to wait between attempts to take a lock held by another handle.
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The name of the file in [`Config::data_dir`] that [`LockStrategy::Lockfile`] creates.
+pub const LOCK_FILE_NAME: &str = "LOCK";
+
+/// How a handle keeps others from opening the database while it has it open, configured
+/// with [`Config::lock_strategy`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LockStrategy {
+    /// Advisory locks on the WAL and CURRENT files, which the OS lets go of when the
+    /// process exits, however it exits. Some network filesystems don't implement them, or
+    /// don't enforce them between hosts.
+    #[default]
+    Advisory,
+    /// A [`LOCK_FILE_NAME`] file in the data directory, created exclusively and holding the
+    /// PID and hostname of the process that has the database open, for filesystems where
+    /// advisory locks can't be relied on. It's removed when the handle is dropped, so it's
+    /// left behind if the process dies.
+    ///
+    /// A lockfile left by a process on this host that's no longer running is stale. Open
+    /// fails on it with [`StaleLock`], unless `break_stale` is set, in which case it's
+    /// removed and taken over, and reported as [`RecoveryAction::StaleLockBroken`]. One
+    /// left by another host can't be checked, so it's waited on like a live one.
+    ///
+    /// Handles opened with
+    /// [`Database::open_secondary`](crate::Database::open_secondary) don't take it, and
+    /// still use shared advisory locks to keep their files from being deleted.
+    Lockfile { break_stale: bool },
+}
+
+/// Returned when opening a database whose [`LockStrategy::Lockfile`] was left behind by a
+/// process that's no longer running, and `break_stale` isn't set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StaleLock {
+    pub path: PathBuf,
+    /// The PID of the process that created it.
+    pub pid: u32,
+}
+
+impl std::fmt::Display for StaleLock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} was left by process {}, which is no longer running; remove it or set \
+             break_stale to open the database",
+            self.path.display(),
+            self.pid
+        )
+    }
+}
+
+impl std::error::Error for StaleLock {}
+
+/// A lockfile taken under [`LockStrategy::Lockfile`], which is removed when it's dropped.
+#[derive(Debug)]
+pub(crate) struct LockFile {
+    path: PathBuf,
+}
+
+impl LockFile {
+    /// Takes the lockfile in [`Config::data_dir`] under [`LockStrategy::Lockfile`], waiting
+    /// for as long as [`Config::open_lock_timeout`] allows if another handle holds it and
+    /// then failing with [`AlreadyOpen`]. Returns `None` under [`LockStrategy::Advisory`].
+    pub(crate) fn acquire(
+        config: &Config,
+        report: &mut OpenReport,
+    ) -> anyhow::Result<Option<Self>> {
+        let LockStrategy::Lockfile { break_stale } = config.lock_strategy else {
+            return Ok(None);
+        };
+
+        let path = config.data_dir.join(LOCK_FILE_NAME);
+        let owner = format!("{} {}\n", std::process::id(), hostname());
+        let deadline = config
+            .open_lock_timeout
+            .map(|timeout| config.clock.now() + timeout);
+
+        loop {
+            match File::create_new(&path) {
+                Ok(mut file) => {
+                    file.write_all(owner.as_bytes())
+                        .and_then(|()| file.sync_all())
+                        .with_context(|| format!("Failed to write {}", path.display()))?;
+
+                    return Ok(Some(LockFile { path }));
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+                Err(e) => {
+                    return Err(e).with_context(|| format!("Failed to create {}", path.display()));
+                }
+            }
+
+            if let Some(pid) = stale_owner(&path)? {
+                if !break_stale {
+                    return Err(StaleLock { path, pid }.into());
+                }
+
+                eprintln!(
+                    "Breaking {} left by process {pid}, which is no longer running",
+                    path.display()
+                );
+
+                match std::fs::remove_file(&path) {
+                    Ok(()) => {}
+                    // Broken by another handle first, which the next attempt will find.
+                    Err(e) if e.kind() == ErrorKind::NotFound => {}
+                    Err(e) => {
+                        return Err(e)
+                            .with_context(|| format!("Failed to remove {}", path.display()));
+                    }
+                }
+
+                report.actions.push(RecoveryAction::StaleLockBroken { pid });
+                continue;
+            }
+
+            let now = config.clock.now();
+
+            if let Some(deadline) = deadline
+                && now >= deadline
+            {
+                return Err(AlreadyOpen { path }.into());
+            }
+
+            let wait = deadline.map_or(LOCK_RETRY_INTERVAL, |deadline| {
+                LOCK_RETRY_INTERVAL.min(deadline - now)
+            });
+            config.clock.sleep(wait);
+        }
+    }
+}
+
+impl Drop for LockFile {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            eprintln!("Failed to remove {}: {e}", self.path.display());
+        }
+    }
+}
+
+/// The PID in the lockfile at `path`, if it was written on this host by a process that's
+/// no longer running. A lockfile that's empty or unreadable may still be being written,
+/// so it isn't stale.
+fn stale_owner(path: &Path) -> anyhow::Result<Option<u32>> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+
+    let Some((pid, host)) = contents.trim_end().split_once(' ') else {
+        return Ok(None);
+    };
+
+    let Ok(pid) = pid.parse::<u32>() else {
+        return Ok(None);
+    };
+
+    if host != hostname() || is_running(pid) {
+        return Ok(None);
+    }
+
+    Ok(Some(pid))
+}
+
+/// Whether a process with `pid` exists on this host.
+fn is_running(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+
+    // SAFETY: signal 0 only checks whether the process exists and can be signalled.
+    let res = unsafe { libc::kill(pid, 0) };
+
+    // EPERM means it exists, but belongs to another user.
+    res == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+fn hostname() -> String {
+    let mut buf = [0u8; 256];
+
+    // SAFETY: `buf` is valid for writes of its whole length.
+    let res = unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) };
+
+    if res != 0 {
+        return String::new();
+    }
+
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    String::from_utf8_lossy(&buf[..len]).into_owned()
+}
+
+/// Takes an exclusive lock on `file`, found at `path`, waiting for as long as
+/// [`Config::open_lock_timeout`] allows if another handle holds it and then failing with
+/// [`AlreadyOpen`]. Does nothing under [`LockStrategy::Lockfile`], where the lockfile
+/// does the job instead.
+pub(crate) fn lock_for_open(file: &File, path: &Path, config: &Config) -> anyhow::Result<()> {
+    if let LockStrategy::Lockfile { .. } = config.lock_strategy {
+        return Ok(());
+    }
+
+    let Some(timeout) = config.open_lock_timeout else {
+        return file
+            .lock()
+            .with_context(|| format!("Failed to lock {}", path.display()));
+    };
+
+    let deadline = config.clock.now() + timeout;
+
+    loop {
+        match file.try_lock() {
+            Ok(()) => return Ok(()),
+            Err(std::fs::TryLockError::WouldBlock) => {}
+            Err(std::fs::TryLockError::Error(e)) => {
+                return Err(e).with_context(|| format!("Failed to lock {}", path.display()));
+            }
+        }
+
+        let now = config.clock.now();
+
+        if now >= deadline {
+            return Err(AlreadyOpen {
+                path: path.to_owned(),
+            }
+            .into());
+        }
+
+        config.clock.sleep(LOCK_RETRY_INTERVAL.min(deadline - now));
+    }
+}
@@ -1,8 +1,14 @@
+use std::path::PathBuf;
+
+use anyhow::Context;
 use clap::Parser;
-use mintdb::config::Config;
+use mintdb::{config::Config, sstable::manager};
 
 #[derive(Debug, Clone, clap::Parser)]
 pub struct Cli {
+    #[arg(long, default_value = "example_wal")]
+    data_dir: PathBuf,
+
     #[command(subcommand)]
     command: CliCommand,
 }
@@ -26,18 +32,70 @@ pub enum CliCommand {
     Delete {
         key: String,
     },
+
+    /// Reads or replaces the manifest directly, without opening the database.
+    Manifest {
+        #[command(subcommand)]
+        command: ManifestCommand,
+    },
 }
 
-fn main() -> anyhow::Result<()> {
-    let args = Cli::parse();
+#[derive(Debug, Clone, clap::Subcommand)]
+pub enum ManifestCommand {
+    /// Prints the current manifest.
+    Export {
+        /// Print it as pretty JSON, the only format there is so far.
+        #[arg(long, required = true)]
+        json: bool,
+    },
+
+    /// Replaces the manifest with one read from `file`, or stdin, after checking that it's
+    /// consistent. The database mustn't be open.
+    Import {
+        /// Read it as JSON, as written by `manifest export --json`.
+        #[arg(long, required = true)]
+        json: bool,
+        file: Option<PathBuf>,
+    },
+}
+
+fn run_manifest(data_dir: PathBuf, command: ManifestCommand) -> anyhow::Result<()> {
+    match command {
+        ManifestCommand::Export { json: _ } => {
+            let manifest = manager::read_manifest(&data_dir)?;
+
+            println!("{}", serde_json::to_string_pretty(&manifest)?);
+        }
+        ManifestCommand::Import { json: _, file } => {
+            let manifest = match file {
+                Some(file) => {
+                    let reader = std::fs::File::open(&file)
+                        .with_context(|| format!("Failed to open {}", file.display()))?;
 
-    let mut db = mintdb::Database::open(Config {
-        data_dir: "example_wal".into(),
-    })?;
+                    serde_json::from_reader(std::io::BufReader::new(reader))
+                }
+                None => serde_json::from_reader(std::io::stdin().lock()),
+            }
+            .context("Failed to parse manifest JSON")?;
 
-    match args.command {
+            manager::write_manifest(&Config::new(data_dir), manifest)?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn run(args: Cli) -> anyhow::Result<()> {
+    let command = match args.command {
+        CliCommand::Manifest { command } => return run_manifest(args.data_dir, command),
+        command => command,
+    };
+
+    let mut db = mintdb::Database::open(Config::new(args.data_dir))?;
+
+    match command {
         CliCommand::Get { key } => {
-            println!("{:?}", db.get(&key.into()));
+            println!("{:?}", db.get(&key.into()).await?);
         }
         CliCommand::Put { key, value, stdin } => {
             db.put(
@@ -51,11 +109,13 @@ fn main() -> anyhow::Result<()> {
                         panic!("Value must be provided either as an argument or via stdin");
                     }
                 }),
-            )?;
+            )
+            .await?;
         }
         CliCommand::Delete { key } => {
-            db.delete(key)?;
+            db.delete(key).await?;
         }
+        CliCommand::Manifest { .. } => unreachable!("handled without opening the database"),
     }
 
     let r = db.debug_replay_wal()?;
@@ -63,3 +123,14 @@ fn main() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+fn main() -> anyhow::Result<()> {
+    let args = Cli::parse();
+
+    glommio::LocalExecutorBuilder::default()
+        .name("mintdb-cli")
+        .spawn(move || run(args))
+        .map_err(|e| anyhow::anyhow!("Failed to spawn executor: {e}"))?
+        .join()
+        .map_err(|e| anyhow::anyhow!("Executor failed: {e}"))?
+}
@@ -1,6 +1,10 @@
-use std::collections::BTreeMap;
+use std::{
+    collections::BTreeMap,
+    hash::{Hash, Hasher},
+    time::{Duration, Instant},
+};
 
-use crate::{key::Key, value::Value};
+use crate::{key::Key, tombstone::RangeTombstone, value::Value};
 
 pub mod state {
     #[derive(Debug, Clone)]
@@ -23,11 +27,116 @@ use state::MemTableState;
 #[derive(Debug, Clone)]
 pub struct MemTable<State: MemTableState> {
     data: BTreeMap<Key, Value>,
+    /// Range deletions, in the order they were written.
+    range_tombstones: Vec<RangeTombstone>,
     size: usize,
+    /// A [`digest`] of the contents taken when the table was frozen, in debug builds, for
+    /// [`MemTable::debug_verify_unchanged`].
+    frozen_digest: Option<u64>,
+    /// The first key written to the table twice. Each write gets its own seqno, so that
+    /// only happens through a bug, and the later write has replaced the earlier one.
+    duplicate: Option<Key>,
     phantom: std::marker::PhantomData<State>,
 }
 
-const MEMTABLE_MAX_SIZE: usize = 1024 * 64 /* 64KB */;
+/// A hash of every entry and range tombstone in a memtable.
+fn digest(data: &BTreeMap<Key, Value>, range_tombstones: &[RangeTombstone]) -> u64 {
+    let mut hasher = twox_hash::XxHash64::with_seed(0);
+    let mut buf = bytes::BytesMut::new();
+
+    for (key, value) in data {
+        key.hash(&mut hasher);
+
+        buf.clear();
+        value.encode_into(&mut buf);
+        hasher.write(&buf);
+    }
+
+    for tombstone in range_tombstones {
+        tombstone.start.hash(&mut hasher);
+        tombstone.end.hash(&mut hasher);
+        tombstone.seqno.hash(&mut hasher);
+    }
+
+    hasher.finish()
+}
+
+/// How far back [`MemtableSize::Adaptive`] looks for the recent write rate.
+pub const BURST_WINDOW: Duration = Duration::from_secs(1);
+/// How far back [`MemtableSize::Adaptive`] looks for the usual write rate.
+pub const STEADY_WINDOW: Duration = Duration::from_secs(60);
+
+/// How large an active memtable grows before it's frozen and flushed, which is also about
+/// the size of the L0 SSTables it's flushed to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemtableSize {
+    /// Always frozen at this many bytes.
+    Fixed(usize),
+    /// Frozen at between `min` and `max` bytes, depending on how the write rate over the
+    /// last [`BURST_WINDOW`] compares to the rate over the last [`STEADY_WINDOW`], by
+    /// [`Config::clock`](crate::config::Config::clock).
+    ///
+    /// Steady writes fill memtables up to `max`, for fewer, larger files with less metadata.
+    /// A burst shrinks the target in proportion to how far it's above the usual rate, down
+    /// to `min`, so that each flush is quicker and writes stall for less time behind it.
+    Adaptive { min: usize, max: usize },
+}
+
+/// The size memtables are frozen at under a [`MemtableSize`], along with the write rate
+/// [`MemtableSize::Adaptive`] picks it from.
+#[derive(Debug, Clone)]
+pub(crate) struct FlushTarget {
+    size: MemtableSize,
+    /// The bytes written, decayed exponentially over [`BURST_WINDOW`] and [`STEADY_WINDOW`],
+    /// so that divided by its window each is the write rate over about that long.
+    burst_bytes: f64,
+    steady_bytes: f64,
+    updated: Instant,
+}
+
+impl FlushTarget {
+    pub fn new(size: MemtableSize, now: Instant) -> Self {
+        FlushTarget {
+            size,
+            burst_bytes: 0.0,
+            steady_bytes: 0.0,
+            updated: now,
+        }
+    }
+
+    /// Counts `bytes` written to the memtables at `now`.
+    pub fn record(&mut self, bytes: usize, now: Instant) {
+        if let MemtableSize::Fixed(_) = self.size {
+            return;
+        }
+
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        let decay = |window: Duration| (-elapsed / window.as_secs_f64()).exp();
+
+        self.burst_bytes = self.burst_bytes * decay(BURST_WINDOW) + bytes as f64;
+        self.steady_bytes = self.steady_bytes * decay(STEADY_WINDOW) + bytes as f64;
+        self.updated = now;
+    }
+
+    /// The size the active memtables should be frozen at, in bytes.
+    pub fn size(&self) -> usize {
+        let (min, max) = match self.size {
+            MemtableSize::Fixed(size) => return size,
+            MemtableSize::Adaptive { min, max } => (min, max),
+        };
+
+        let burst_rate = self.burst_bytes / BURST_WINDOW.as_secs_f64();
+        let steady_rate = self.steady_bytes / STEADY_WINDOW.as_secs_f64();
+
+        if burst_rate <= steady_rate {
+            return max;
+        }
+
+        ((max as f64 * steady_rate / burst_rate) as usize)
+            .max(min)
+            .min(max)
+    }
+}
 
 impl<S: MemTableState> MemTable<S> {
     pub fn get(&self, k: &Key) -> Option<Value> {
@@ -38,6 +147,108 @@ impl<S: MemTableState> MemTable<S> {
         self.iter_by_user_key(k).next().map(|(_, v)| v)
     }
 
+    /// Returns the newest version of `k` with a seqno at or below `seqno`.
+    pub fn get_at(&self, k: &bytes::Bytes, seqno: crate::key::SeqNo) -> Option<(&Key, &Value)> {
+        self.data
+            .range(Key::range_at_seqno(k.clone(), seqno))
+            .next()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty() && self.range_tombstones.is_empty()
+    }
+
+    /// The bytes of keys and values held, which the table is frozen at.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Counts [`MemTable::size`] again from scratch, rather than trusting the total kept up
+    /// as entries are inserted, to catch a bug in that bookkeeping. Takes a pass over the
+    /// whole table.
+    pub fn recompute_size(&self) -> usize {
+        let entries = self
+            .data
+            .iter()
+            .map(|(key, value)| key.user_key().len() + value.data().map_or(0, |data| data.len()))
+            .sum::<usize>();
+
+        let tombstones = self
+            .range_tombstones
+            .iter()
+            .map(|t| t.start.len() + t.end.as_ref().map_or(0, |end| end.len()))
+            .sum::<usize>();
+
+        entries + tombstones
+    }
+
+    /// The highest seqno of any entry in the table.
+    pub fn max_seqno(&self) -> Option<crate::key::SeqNo> {
+        self.data
+            .keys()
+            .map(|key| key.seqno())
+            .chain(self.range_tombstones.iter().map(|t| t.seqno))
+            .max()
+    }
+
+    /// The lowest seqno of any entry in the table.
+    pub fn min_seqno(&self) -> Option<crate::key::SeqNo> {
+        self.data
+            .keys()
+            .map(|key| key.seqno())
+            .chain(self.range_tombstones.iter().map(|t| t.seqno))
+            .min()
+    }
+
+    pub fn range_tombstones(&self) -> &[RangeTombstone] {
+        &self.range_tombstones
+    }
+
+    /// The smallest and largest user keys the table covers, range tombstones included, or
+    /// `None` if it's empty. A largest key of `None` means the table reaches the end of the
+    /// keyspace. A tombstone's exclusive end is counted as covered, so the bounds can be a
+    /// little wider than they need to be.
+    fn user_key_bounds(&self) -> Option<(bytes::Bytes, Option<bytes::Bytes>)> {
+        let points = self
+            .data
+            .first_key_value()
+            .zip(self.data.last_key_value())
+            .map(|((first, _), (last, _))| {
+                (first.user_key().clone(), Some(last.user_key().clone()))
+            });
+
+        points
+            .into_iter()
+            .chain(
+                self.range_tombstones
+                    .iter()
+                    .map(|t| (t.start.clone(), t.end.clone())),
+            )
+            .reduce(|(start, end), (other_start, other_end)| {
+                let end = end.zip(other_end).map(|(end, other)| end.max(other));
+
+                (start.min(other_start), end)
+            })
+    }
+
+    /// Whether any user key could be in both this table and `other`.
+    pub fn overlaps<T: MemTableState>(&self, other: &MemTable<T>) -> bool {
+        let (Some((start, end)), Some((other_start, other_end))) =
+            (self.user_key_bounds(), other.user_key_bounds())
+        else {
+            return false;
+        };
+
+        end.is_none_or(|end| other_start <= end) && other_end.is_none_or(|other| start <= other)
+    }
+
+    pub fn range(
+        &self,
+        range: (std::ops::Bound<Key>, std::ops::Bound<Key>),
+    ) -> std::collections::btree_map::Range<'_, Key, Value> {
+        self.data.range(range)
+    }
+
     pub fn iter_by_user_key(
         &self,
         k: &bytes::Bytes,
@@ -50,67 +261,119 @@ impl MemTable<state::Frozen> {
     pub fn data(&self) -> &BTreeMap<Key, Value> {
         &self.data
     }
+
+    /// The first key, user key and seqno both, that was written to the table more than
+    /// once before it was frozen. Only the last write of it is kept.
+    pub fn duplicate(&self) -> Option<&Key> {
+        self.duplicate.as_ref()
+    }
+
+    /// Panics in debug builds if the table's contents have changed since it was frozen.
+    ///
+    /// Nothing can mutate a frozen table through its type, so this only catches a change
+    /// that sneaks in some other way. It matters because a flush that's retried after a
+    /// crash has to write the same SSTables as the first attempt.
+    pub fn debug_verify_unchanged(&self) {
+        if let Some(expected) = self.frozen_digest {
+            debug_assert_eq!(
+                digest(&self.data, &self.range_tombstones),
+                expected,
+                "Frozen memtable was modified after it was frozen"
+            );
+        }
+    }
+}
+
+impl Default for MemTable<state::Active> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl MemTable<state::Active> {
     pub fn new() -> Self {
         MemTable {
             data: BTreeMap::new(),
+            range_tombstones: Vec::new(),
             size: 0,
+            frozen_digest: None,
+            duplicate: None,
             phantom: std::marker::PhantomData,
         }
     }
 
-    pub fn should_freeze(&self) -> bool {
-        self.size >= MEMTABLE_MAX_SIZE
+    /// Whether the table has reached `target` bytes, as picked for
+    /// [`Config::memtable_size`](crate::config::Config::memtable_size).
+    pub fn should_freeze(&self, target: usize) -> bool {
+        self.size >= target
     }
 
     pub fn freeze(&mut self) -> MemTable<state::Frozen> {
+        debug_assert_eq!(
+            self.size,
+            self.recompute_size(),
+            "Memtable size drifted from its contents"
+        );
+
         let data = std::mem::take(&mut self.data);
+        let range_tombstones = std::mem::take(&mut self.range_tombstones);
         let size = std::mem::replace(&mut self.size, 0);
+        let frozen_digest = cfg!(debug_assertions).then(|| digest(&data, &range_tombstones));
 
         MemTable {
             data,
+            range_tombstones,
             size,
+            frozen_digest,
+            duplicate: self.duplicate.take(),
             phantom: std::marker::PhantomData,
         }
     }
 
     pub fn put(&mut self, k: Key, v: bytes::Bytes) {
-        let l_new = v.len();
-        let l_key = k.user_key().len();
+        self.insert(k, Value::Data(v));
+    }
 
-        if let Some(old) = self.data.insert(k, Value::Data(v)) {
-            match old {
-                Value::Data(old_bytes) => {
-                    let l_old = old_bytes.len();
-                    if l_old > l_new {
-                        self.size -= l_old - l_new;
-                    } else {
-                        self.size += l_new - l_old;
-                    }
-                }
-                Value::Tombstone => {
-                    self.size += l_new;
-                }
-            }
-        } else {
-            self.size += l_new + l_key;
-        }
+    /// Inserts `v` under `k`, which reads as deleted once `expires_at` (in milliseconds
+    /// since the Unix epoch) has passed.
+    pub fn put_expiring(&mut self, k: Key, v: bytes::Bytes, expires_at: u64) {
+        self.insert(
+            k,
+            Value::Expiring {
+                data: v,
+                expires_at,
+            },
+        );
     }
 
     pub fn delete(&mut self, k: Key) {
+        self.insert(k, Value::Tombstone);
+    }
+
+    fn insert(&mut self, k: Key, v: Value) {
         let l_key = k.user_key().len();
+        let l_new = v.data().map_or(0, |data| data.len());
+
+        if self.duplicate.is_none() && self.data.contains_key(&k) {
+            self.duplicate = Some(k.clone());
+        }
 
-        if let Some(old) = self.data.insert(k, Value::Tombstone) {
-            match old {
-                Value::Data(old) => {
-                    self.size -= old.len();
-                }
-                Value::Tombstone => {}
+        if let Some(old) = self.data.insert(k, v) {
+            let l_old = old.data().map_or(0, |data| data.len());
+
+            if l_old > l_new {
+                self.size -= l_old - l_new;
+            } else {
+                self.size += l_new - l_old;
             }
         } else {
-            self.size += l_key;
+            self.size += l_new + l_key;
         }
     }
+
+    pub fn delete_range(&mut self, tombstone: RangeTombstone) {
+        self.size += tombstone.start.len() + tombstone.end.as_ref().map_or(0, |end| end.len());
+
+        self.range_tombstones.push(tombstone);
+    }
 }
@@ -1,3 +1,5 @@
+#![allow(dead_code)]
+
 use std::{
     cell::RefCell,
     future::Future,
@@ -6,9 +8,15 @@ use std::{
     task::{Context, Poll, Waker},
 };
 
-/// Error returned when the sender is dropped before sending.
-#[derive(Debug)]
-pub struct Canceled;
+/// Why a [`Receiver`] completed without a value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecvError {
+    /// The sender gave up on purpose with [`Sender::cancel`].
+    Canceled,
+    /// The sender was dropped without sending or canceling, which means the task holding
+    /// it failed or panicked.
+    Dropped,
+}
 
 enum State<T> {
     /// No value yet, and no receiver waker stored.
@@ -17,7 +25,11 @@ enum State<T> {
     Waiting(Waker),
     /// Value is ready to be taken.
     Ready(T),
-    /// Channel is closed (sender dropped without sending).
+    /// The value has been taken by the receiver.
+    Taken,
+    /// The sender finished without sending.
+    Failed(RecvError),
+    /// The receiver was dropped.
     Closed,
 }
 
@@ -55,64 +67,80 @@ impl<T> Sender<T> {
     pub fn send(self, val: T) -> Result<(), T> {
         let mut inner = self.inner.borrow_mut();
 
-        match std::mem::replace(&mut inner.state, State::Closed) {
-            State::Empty => {
-                inner.state = State::Ready(val);
-                Ok(())
-            }
+        match std::mem::replace(&mut inner.state, State::Ready(val)) {
+            State::Empty => Ok(()),
             State::Waiting(waker) => {
-                inner.state = State::Ready(val);
                 waker.wake();
                 Ok(())
             }
-            State::Ready(_) => {
-                panic!("Sender sent value twice - this should be impossible");
-            }
             State::Closed => {
-                // Receiver dropped.
+                let State::Ready(val) = std::mem::replace(&mut inner.state, State::Closed) else {
+                    unreachable!("just stored");
+                };
+
                 Err(val)
             }
+            State::Ready(_) | State::Taken | State::Failed(_) => {
+                panic!("Sender finished twice - this should be impossible");
+            }
+        }
+    }
+
+    /// Completes the channel without a value, so that the receiver sees
+    /// [`RecvError::Canceled`] rather than [`RecvError::Dropped`].
+    pub fn cancel(self) {
+        self.fail(RecvError::Canceled);
+    }
+
+    /// Whether the receiver has been dropped, so there's no point sending.
+    pub fn is_closed(&self) -> bool {
+        matches!(self.inner.borrow().state, State::Closed)
+    }
+
+    fn fail(&self, error: RecvError) {
+        let mut inner = self.inner.borrow_mut();
+
+        match std::mem::replace(&mut inner.state, State::Failed(error)) {
+            State::Waiting(waker) => waker.wake(),
+            State::Empty => {}
+            // Already finished, or nobody is listening.
+            state => inner.state = state,
         }
     }
 }
 
 impl<T> Drop for Sender<T> {
     fn drop(&mut self) {
-        let mut inner = self.inner.borrow_mut();
+        // Does nothing if the value was sent or the sender canceled.
+        self.fail(RecvError::Dropped);
+    }
+}
 
-        match std::mem::replace(&mut inner.state, State::Closed) {
-            State::Waiting(waker) => {
-                // Notify the receiver that the channel is closed.
-                waker.wake();
-            }
-            // If it was Ready/Closed/Empty we just leave it as Closed.
-            _ => {
-                inner.state = State::Closed;
-            }
-        }
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.inner.borrow_mut().state = State::Closed;
     }
 }
 
 impl<T> Future for Receiver<T> {
-    type Output = Result<T, Canceled>;
+    type Output = Result<T, RecvError>;
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let mut inner = self.inner.borrow_mut();
 
-        match std::mem::replace(&mut inner.state, State::Empty) {
-            State::Ready(value) => {
-                // Value is ready, complete the future.
-                Poll::Ready(Ok(value))
-            }
-            State::Closed => {
-                // Sender dropped without sending.
-                Poll::Ready(Err(Canceled))
+        match std::mem::replace(&mut inner.state, State::Taken) {
+            State::Ready(value) => Poll::Ready(Ok(value)),
+            State::Failed(error) => {
+                inner.state = State::Failed(error);
+                Poll::Ready(Err(error))
             }
             State::Empty | State::Waiting(_) => {
                 // Not ready yet; store/replace the waker and return Pending.
                 inner.state = State::Waiting(cx.waker().clone());
                 Poll::Pending
             }
+            State::Taken => panic!("Receiver polled after completion"),
+            State::Closed => unreachable!("the receiver is alive"),
         }
     }
 }
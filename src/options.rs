@@ -0,0 +1,80 @@
+//! Per-operation options for reads and writes.
+
+use std::time::Instant;
+
+use crate::snapshot::Snapshot;
+
+/// Options for a single read.
+#[derive(Debug, Clone)]
+pub struct ReadOptions {
+    /// Read as of this snapshot rather than the latest state.
+    pub snapshot: Option<Snapshot>,
+    /// Whether to verify block checksums for this read. `None` falls back to
+    /// [`Config::verify_checksums_on_read`](crate::config::Config::verify_checksums_on_read).
+    pub verify_checksums: Option<bool>,
+    /// Whether blocks read from disk should be inserted into the block cache. Large scans
+    /// can disable this to avoid evicting the working set.
+    pub fill_cache: bool,
+    /// When, by [`Config::clock`](crate::config::Config::clock), to give up on the read
+    /// with a [`Timeout`]. Checked before each SSTable block is fetched, so a read that
+    /// only needs the memtables always finishes.
+    pub deadline: Option<Instant>,
+}
+
+impl Default for ReadOptions {
+    fn default() -> Self {
+        ReadOptions {
+            snapshot: None,
+            verify_checksums: None,
+            fill_cache: true,
+            deadline: None,
+        }
+    }
+}
+
+/// Returned by a read that passed its [`ReadOptions::deadline`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timeout;
+
+impl std::fmt::Display for Timeout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Read timed out")
+    }
+}
+
+impl std::error::Error for Timeout {}
+
+/// Options for a single write.
+#[derive(Debug, Clone)]
+pub struct WriteOptions {
+    /// Whether the WAL is fsynced before the write returns. Unsynced writes are still
+    /// written to the WAL, but may be lost if the machine crashes before the next sync.
+    pub sync: bool,
+    /// Whether single-key deletes like [`Database::delete_opt`](crate::Database::delete_opt)
+    /// skip writing a tombstone when the key isn't present, which keeps speculative deletes
+    /// from piling up tombstones.
+    ///
+    /// Checking costs a read of the key first, which may go to disk. Deletes in a
+    /// [`WriteBatch`](crate::batch::WriteBatch) always write a tombstone.
+    pub delete_if_exists: bool,
+    /// A key identifying the write, so that a retry of a write that already succeeded is
+    /// acknowledged without being applied again.
+    ///
+    /// Keys are remembered for [`Config::idempotency_window`], up to
+    /// [`Config::idempotency_capacity`] of them, and only in memory: a retry after the
+    /// database is reopened is applied again.
+    ///
+    /// [`Config::idempotency_window`]: crate::config::Config::idempotency_window
+    /// [`Config::idempotency_capacity`]: crate::config::Config::idempotency_capacity
+    pub idempotency_key: Option<bytes::Bytes>,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        WriteOptions {
+            sync: true,
+            delete_if_exists: false,
+            idempotency_key: None,
+        }
+    }
+}
@@ -0,0 +1,70 @@
+//! Read-only handles with their own default read options.
+
+use std::ops::RangeBounds;
+
+use crate::{column_family::ColumnFamily, options::ReadOptions, Database};
+
+/// A read-only view of a [`Database`] that applies its own [`ReadOptions`] to every read,
+/// created with [`Database::reader`].
+///
+/// Readers share the database's memtables, SSTables, and block cache, and are cheap to
+/// create and clone, so each component can keep its own snapshot and cache settings
+/// without passing options to every call.
+#[derive(Clone)]
+pub struct DbReader<'a> {
+    db: &'a Database,
+    options: ReadOptions,
+}
+
+impl<'a> DbReader<'a> {
+    pub(crate) fn new(db: &'a Database, options: ReadOptions) -> Self {
+        DbReader { db, options }
+    }
+
+    /// The options applied to every read through this reader.
+    pub fn options(&self) -> &ReadOptions {
+        &self.options
+    }
+
+    /// Replaces the options applied to every read through this reader.
+    pub fn with_options(mut self, options: ReadOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub async fn get(&self, key: &bytes::Bytes) -> anyhow::Result<Option<bytes::Bytes>> {
+        self.db.get_opt(key, &self.options).await
+    }
+
+    pub async fn get_cf(
+        &self,
+        cf: &ColumnFamily,
+        key: &bytes::Bytes,
+    ) -> anyhow::Result<Option<bytes::Bytes>> {
+        self.db.get_cf_opt(cf, key, &self.options).await
+    }
+
+    /// See [`Database::scan_paginated`].
+    pub async fn scan_paginated(
+        &self,
+        range: impl RangeBounds<bytes::Bytes>,
+        limit: usize,
+        after: Option<bytes::Bytes>,
+    ) -> anyhow::Result<(Vec<(bytes::Bytes, bytes::Bytes)>, Option<bytes::Bytes>)> {
+        self.db
+            .scan_paginated_opt(range, limit, after, &self.options)
+            .await
+    }
+
+    pub async fn scan_paginated_cf(
+        &self,
+        cf: &ColumnFamily,
+        range: impl RangeBounds<bytes::Bytes>,
+        limit: usize,
+        after: Option<bytes::Bytes>,
+    ) -> anyhow::Result<(Vec<(bytes::Bytes, bytes::Bytes)>, Option<bytes::Bytes>)> {
+        self.db
+            .scan_paginated_cf_opt(cf, range, limit, after, &self.options)
+            .await
+    }
+}
@@ -0,0 +1,53 @@
+//! What [`Database::open_with_report`](crate::Database::open_with_report) had to repair
+//! to open a database that wasn't closed cleanly, or that was damaged since.
+//!
+//! Every repair is also logged as it's made, but the report lets an operator or a test
+//! tell a clean open from a recovered one without scraping logs.
+
+use crate::sstable::manager::{FileNo, MissingSstable};
+
+/// The recovery actions taken while opening a database, in the order they were taken.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OpenReport {
+    pub actions: Vec<RecoveryAction>,
+}
+
+impl OpenReport {
+    /// Whether the database opened without anything to recover, as it does after
+    /// [`Database::close`](crate::Database::close).
+    pub fn is_clean(&self) -> bool {
+        self.actions.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecoveryAction {
+    /// The WAL ended in records that were only partly written when the database last went
+    /// down, which were cut off. `bytes` is how much was cut, and `records` how many
+    /// records it held the start of. None of them had been acknowledged as synced.
+    WalTailTruncated { bytes: u64, records: usize },
+    /// Writes that hadn't been flushed to SSTables were replayed from the WAL into the
+    /// memtables, since the database wasn't closed before it was last let go of.
+    WalReplayed { records: usize },
+    /// CURRENT named `named`, but a rotation was interrupted after writing the newer
+    /// manifest `chosen`, so CURRENT was switched to it.
+    CurrentRepaired { named: String, chosen: String },
+    /// CURRENT held `contents`, which isn't a valid manifest name or doesn't match its
+    /// checksum, so it was pointed at `chosen`, the manifest furthest ahead.
+    CurrentRebuilt { contents: String, chosen: String },
+    /// Manifests that were superseded by the one opened, or failed to load, were deleted.
+    StaleManifestsRemoved { names: Vec<String> },
+    /// SSTables the manifest referenced were missing from disk, and were dropped from it
+    /// along with their data, under
+    /// [`Config::repair_missing_sstables`](crate::config::Config::repair_missing_sstables).
+    MissingSstablesDropped { files: Vec<MissingSstable> },
+    /// SSTables that the manifest doesn't reference were deleted, or left to a later
+    /// retry if a secondary handle has them open. They're the output of a flush or
+    /// compaction that was interrupted before it was committed, which rolls it back, or
+    /// files whose deletion was still deferred when the database was last open.
+    OrphanedSstablesRemoved { files: Vec<FileNo> },
+    /// The lockfile taken under
+    /// [`LockStrategy::Lockfile`](crate::lock::LockStrategy::Lockfile) was left by process
+    /// `pid`, which is no longer running, and was broken.
+    StaleLockBroken { pid: u32 },
+}
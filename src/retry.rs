@@ -0,0 +1,98 @@
+//! Retrying flushes and compactions that fail with an IO error that may clear up on its
+//! own, such as a disk that's briefly full, rather than stalling the writes waiting on
+//! them.
+
+use std::{io::ErrorKind, sync::Arc, time::Duration};
+
+use crate::{clock::Clock, config::Config};
+
+/// How flushes and compactions retry after a transient IO error, configured with
+/// [`Config::io_retry`]. Errors that won't go away by waiting, like a corrupt file or a
+/// missing permission, always fail straight away.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// The most times an operation is retried. Zero, the default, never retries.
+    pub max_retries: u32,
+    /// The wait before the first retry. Each later retry waits twice as long as the last.
+    pub initial_backoff: Duration,
+    /// The longest wait between retries.
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 0,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The wait before retry number `attempt`, counting from zero.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_backoff)
+    }
+}
+
+/// Whether `error` was caused by an IO error that can succeed if the operation is tried
+/// again: an interrupted or timed out call, a busy resource, or a full disk or quota,
+/// which compaction and the deletion of obsolete files may free up.
+pub fn is_transient(error: &anyhow::Error) -> bool {
+    error
+        .chain()
+        .filter_map(|cause| cause.downcast_ref::<std::io::Error>())
+        .any(|error| {
+            matches!(
+                error.kind(),
+                ErrorKind::Interrupted
+                    | ErrorKind::WouldBlock
+                    | ErrorKind::TimedOut
+                    | ErrorKind::ResourceBusy
+                    | ErrorKind::StorageFull
+                    | ErrorKind::QuotaExceeded
+            )
+        })
+}
+
+/// The retries of one operation under a [`RetryPolicy`].
+pub(crate) struct Retry {
+    policy: RetryPolicy,
+    clock: Arc<dyn Clock>,
+    attempt: u32,
+}
+
+impl Retry {
+    pub(crate) fn new(config: &Config) -> Self {
+        Retry {
+            policy: config.io_retry,
+            clock: Arc::clone(&config.clock),
+            attempt: 0,
+        }
+    }
+
+    /// Whether the operation should be tried again after failing with `error`. If so,
+    /// waits out the backoff first, with [`Clock::sleep_async`], so the executor carries on
+    /// with other tasks in the meantime.
+    pub(crate) async fn should_retry(&mut self, error: &anyhow::Error, operation: &str) -> bool {
+        if self.attempt >= self.policy.max_retries || !is_transient(error) {
+            return false;
+        }
+
+        let backoff = self.policy.backoff(self.attempt);
+        self.attempt += 1;
+
+        eprintln!(
+            "{operation} failed with a transient error, retrying in {backoff:?} (attempt {} of \
+             {}): {error:?}",
+            self.attempt, self.policy.max_retries
+        );
+
+        self.clock.sleep_async(backoff).await;
+
+        true
+    }
+}
@@ -0,0 +1,70 @@
+//! Lazy iteration over a key range, returned by [`Database::scan`].
+
+use std::ops::Bound;
+
+use crate::{column_family::ColumnFamily, options::ReadOptions, Database};
+
+/// The number of pairs a [`Scan`] reads at once.
+pub const SCAN_PAGE_SIZE: usize = 1024;
+
+/// The live key/value pairs in a range, in key order, returned by [`Database::scan`].
+///
+/// Pairs are read [`SCAN_PAGE_SIZE`] at a time, each page resuming after the last key of the
+/// one before, so a scan of a whole column family only ever holds one page in memory. Every
+/// page reads the snapshot the scan was started from, which stays pinned until the scan is
+/// dropped, so the scan sees one consistent state of the range.
+///
+/// Yields an error, and then ends, if a page can't be read.
+pub struct Scan<'a> {
+    db: &'a Database,
+    cf: ColumnFamily,
+    range: (Bound<bytes::Bytes>, Bound<bytes::Bytes>),
+    /// Holds the snapshot every page is read from.
+    options: ReadOptions,
+    page: std::vec::IntoIter<(bytes::Bytes, bytes::Bytes)>,
+    /// Where the next page resumes, or `None` once the range is exhausted.
+    next: Option<Option<bytes::Bytes>>,
+}
+
+impl<'a> Scan<'a> {
+    pub(crate) fn new(
+        db: &'a Database,
+        cf: ColumnFamily,
+        range: (Bound<bytes::Bytes>, Bound<bytes::Bytes>),
+        options: ReadOptions,
+    ) -> Self {
+        Scan {
+            db,
+            cf,
+            range,
+            options,
+            page: Vec::new().into_iter(),
+            next: Some(None),
+        }
+    }
+}
+
+impl Iterator for Scan<'_> {
+    type Item = anyhow::Result<(bytes::Bytes, bytes::Bytes)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(pair) = self.page.next() {
+                return Some(Ok(pair));
+            }
+
+            let after = self.next.take()?;
+
+            match self
+                .db
+                .scan_page(&self.cf, self.range.clone(), after, &self.options)
+            {
+                Ok((page, continuation)) => {
+                    self.page = page.into_iter();
+                    self.next = continuation.map(Some);
+                }
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
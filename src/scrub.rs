@@ -0,0 +1,94 @@
+//! Background verification of SSTable blocks, run a slice at a time with
+//! [`Database::scrub`](crate::Database::scrub), so that bit rot in data nobody reads is
+//! found while there's still a backup to restore it from.
+
+use crate::{
+    column_family::ColumnFamilyId,
+    sstable::{manager::FileNo, Level},
+};
+
+/// The name of the file in [`Config::data_dir`](crate::config::Config::data_dir) that the
+/// scrub's position is kept in between calls, and across restarts.
+pub const SCRUB_CURSOR_FILE_NAME: &str = "SCRUB";
+
+/// Where the next scrub picks up: the block at index `block` of the SSTable numbered
+/// `file_number`, or the first block of the next file after it if that one's gone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScrubCursor {
+    pub file_number: FileNo,
+    pub block: usize,
+}
+
+impl Default for ScrubCursor {
+    fn default() -> Self {
+        ScrubCursor {
+            file_number: FileNo(0),
+            block: 0,
+        }
+    }
+}
+
+impl ScrubCursor {
+    pub(crate) fn encode(&self) -> String {
+        format!("{} {}", self.file_number, self.block)
+    }
+
+    /// Parses what [`ScrubCursor::encode`] wrote, or `None` if it's unreadable, in which
+    /// case the scrub starts over.
+    pub(crate) fn decode(s: &str) -> Option<Self> {
+        let (file_number, block) = s.trim().split_once(' ')?;
+
+        Some(ScrubCursor {
+            file_number: FileNo(file_number.parse().ok()?),
+            block: block.parse().ok()?,
+        })
+    }
+}
+
+/// A block, or a whole file, that failed verification.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Corruption {
+    pub cf: ColumnFamilyId,
+    pub level: Level,
+    pub file_number: FileNo,
+    /// The offset of the corrupt block in the file, or `None` if the file's footer or
+    /// index couldn't be read, so none of its blocks could be found.
+    pub offset: Option<u64>,
+    pub error: String,
+}
+
+impl std::fmt::Display for Corruption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "SSTable {} in level {} of column family {}",
+            self.file_number, self.level.0, self.cf
+        )?;
+
+        if let Some(offset) = self.offset {
+            write!(f, " has a corrupt block at offset {offset}")?;
+        } else {
+            write!(f, " can't be opened")?;
+        }
+
+        write!(f, ": {}", self.error)
+    }
+}
+
+/// A hook told about every corruption a scrub finds, so an operator can be alerted without
+/// each caller of [`Database::scrub`](crate::Database::scrub) having to. It's called from
+/// the scrub, so it should return quickly.
+pub trait CorruptionListener: std::fmt::Debug + Send + Sync {
+    fn on_corruption(&self, corruption: &Corruption);
+}
+
+/// What one call to [`Database::scrub`](crate::Database::scrub) did.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScrubReport {
+    /// The number of blocks read and verified.
+    pub blocks_verified: usize,
+    pub corruptions: Vec<Corruption>,
+    /// Whether the scrub reached the end of the newest file, so the next call starts
+    /// another pass from the oldest.
+    pub pass_completed: bool,
+}
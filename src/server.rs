@@ -1,7 +1,14 @@
-// use std::{io::Write, path::PathBuf, str::FromStr, time::Duration};
+// use std::{
+//     io::Write,
+//     os::fd::{AsRawFd, FromRawFd},
+//     path::PathBuf,
+//     str::FromStr,
+//     time::Duration,
+// };
 //
 // use anyhow::Context;
 // use clap::Parser;
+// use glommio::channels::shared_channel::{self, SharedReceiver, SharedSender};
 // use gmf::server::gmf_server::GmfServer;
 // use tonic::client::GrpcService;
 //
@@ -48,50 +55,201 @@
 // #[derive(Debug, Clone, clap::Parser)]
 // struct Cli {
 //     data_dir: PathBuf,
+//     /// The number of shards, each its own database on its own core. See
+//     /// `mintdb::shard`.
+//     #[arg(long, default_value_t = 1)]
+//     shards: usize,
 // }
 //
-// pub fn main() -> anyhow::Result<()> {
-//     let args = Cli::parse();
+// const SOCKET_PATH: &str = "/tmp/mintdb.sock";
 //
-//     std::fs::create_dir_all(&args.data_dir)?;
+// /// How many requests can be queued for a shard before the connections sending to it wait.
+// const SHARD_QUEUE_DEPTH: usize = 1024;
+//
+// /// How often the shutdown signal is polled for.
+// const SIGNAL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+//
+// /// The PID file, removed when the server exits, however it exits.
+// struct PidFile {
+//     path: PathBuf,
+// }
+//
+// impl PidFile {
+//     fn create(path: PathBuf) -> anyhow::Result<Self> {
+//         if path.try_exists().is_ok_and(|readable| readable) {
+//             return Err(anyhow::anyhow!(
+//                 "PID file already exists at {:?}. Is another instance running?",
+//                 path
+//             ));
+//         }
+//
+//         let me = procfs::process::Process::myself()?;
 //
-//     let pidfile_path = args.data_dir.join("mintdb.pid");
+//         let mut pidfile = std::fs::File::create(&path)?;
 //
-//     if pidfile_path.try_exists().is_ok_and(|readable| readable) {
-//         return Err(anyhow::anyhow!(
-//             "PID file already exists at {:?}. Is another instance running?",
-//             pidfile_path
-//         ));
+//         pidfile.write_all(format!("{}", me.pid).as_bytes())?;
+//         pidfile.flush()?;
+//
+//         Ok(PidFile { path })
 //     }
+// }
 //
-//     let me = procfs::process::Process::myself()?;
+// impl Drop for PidFile {
+//     fn drop(&mut self) {
+//         if let Err(e) = std::fs::remove_file(&self.path) {
+//             eprintln!("Failed to remove PID file {:?}: {}", self.path, e);
+//         }
+//     }
+// }
 //
-//     let mut pidfile = std::fs::File::create(&pidfile_path)?;
+// /// A non-blocking signalfd for SIGINT and SIGTERM.
+// ///
+// /// The signals are blocked in the calling thread, so this has to be created before any
+// /// executor is spawned for them to inherit the mask. Otherwise the default handler would
+// /// kill the process mid-write instead of the signal being queued for the fd.
+// struct ShutdownSignal {
+//     fd: std::os::fd::OwnedFd,
+// }
 //
-//     pidfile.write_all(format!("{}", me.pid).as_bytes())?;
-//     pidfile.flush()?;
+// impl ShutdownSignal {
+//     fn new() -> anyhow::Result<Self> {
+//         // SAFETY: `mask` is initialized by sigemptyset before it's used, and the fd returned
+//         // by signalfd is owned by nothing else.
+//         unsafe {
+//             let mut mask = std::mem::zeroed::<libc::sigset_t>();
+//             libc::sigemptyset(&mut mask);
+//             libc::sigaddset(&mut mask, libc::SIGINT);
+//             libc::sigaddset(&mut mask, libc::SIGTERM);
 //
-//     let database = glommio::LocalExecutorBuilder::new(glommio::Placement::Unbound)
-//         .name("server-executor")
-//         .spawn(|| async move {
-//             let executor = glommio::executor();
+//             if libc::pthread_sigmask(libc::SIG_BLOCK, &mask, std::ptr::null_mut()) != 0 {
+//                 return Err(std::io::Error::last_os_error()).context("Failed to block signals");
+//             }
 //
-//             let request_queue = executor.create_task_queue(
-//                 glommio::Shares::Static(10),
-//                 glommio::Latency::Matters(Duration::from_millis(10)),
-//                 "api-requests",
-//             );
+//             let fd = libc::signalfd(-1, &mask, libc::SFD_NONBLOCK | libc::SFD_CLOEXEC);
 //
-//             let background_queue = executor.create_task_queue(
-//                 glommio::Shares::Static(1),
-//                 glommio::Latency::NotImportant,
-//                 "background-tasks",
-//             );
+//             if fd < 0 {
+//                 return Err(std::io::Error::last_os_error()).context("Failed to create signalfd");
+//             }
 //
-//             // executor.create_task_queue()
-//             //
-//         })
-//         .expect("failed to spawn glommio executor");
+//             Ok(ShutdownSignal {
+//                 fd: std::os::fd::OwnedFd::from_raw_fd(fd),
+//             })
+//         }
+//     }
+//
+//     /// Waits for SIGINT or SIGTERM. Glommio can't wait on arbitrary fds, so the signalfd is
+//     /// polled on a timer instead, which never blocks the executor.
+//     async fn recv(&self) {
+//         let mut info = std::mem::MaybeUninit::<libc::signalfd_siginfo>::uninit();
+//
+//         loop {
+//             // SAFETY: `info` is large enough for one signalfd_siginfo, which is all that's
+//             // read.
+//             let read = unsafe {
+//                 libc::read(
+//                     self.fd.as_raw_fd(),
+//                     info.as_mut_ptr().cast(),
+//                     std::mem::size_of::<libc::signalfd_siginfo>(),
+//                 )
+//             };
+//
+//             if read > 0 {
+//                 return;
+//             }
+//
+//             glommio::timer::sleep(SIGNAL_POLL_INTERVAL).await;
+//         }
+//     }
+// }
+//
+// /// A request routed to the shard that owns its key, which answers on `reply`.
+// enum ShardRequest {
+//     Get {
+//         key: bytes::Bytes,
+//         reply: SharedSender<anyhow::Result<Option<bytes::Bytes>>>,
+//     },
+//     Put {
+//         key: bytes::Bytes,
+//         value: bytes::Bytes,
+//         reply: SharedSender<anyhow::Result<()>>,
+//     },
+//     Delete {
+//         key: bytes::Bytes,
+//         reply: SharedSender<anyhow::Result<()>>,
+//     },
+// }
+//
+// /// Runs one shard: owns its database, and serves the requests routed to it until every
+// /// sender is dropped, then closes the database.
+// async fn run_shard(config: mintdb::config::Config, requests: SharedReceiver<ShardRequest>) {
+//     let mut db = match mintdb::Database::open(config) {
+//         Ok(db) => db,
+//         Err(e) => {
+//             eprintln!("Failed to open shard database: {}", e);
+//
+//             return;
+//         }
+//     };
+//
+//     let requests = requests.connect().await;
+//
+//     // One at a time, so that each request sees every write routed to the shard before it.
+//     while let Some(request) = requests.recv().await {
+//         match request {
+//             ShardRequest::Get { key, reply } => {
+//                 let result = db.get(&key).await;
+//                 reply.connect().await.send(result).await.ok();
+//             }
+//             ShardRequest::Put { key, value, reply } => {
+//                 let result = db.put(key, value).await;
+//                 reply.connect().await.send(result).await.ok();
+//             }
+//             ShardRequest::Delete { key, reply } => {
+//                 let result = db.delete(key).await;
+//                 reply.connect().await.send(result).await.ok();
+//             }
+//         }
+//     }
+//
+//     if let Err(e) = db.close().await {
+//         eprintln!("Failed to close shard database: {}", e);
+//     }
+// }
+//
+// pub fn main() -> anyhow::Result<()> {
+//     let args = Cli::parse();
+//
+//     std::fs::create_dir_all(&args.data_dir)?;
+//
+//     let pidfile = PidFile::create(args.data_dir.join("mintdb.pid"))?;
+//
+//     // Before any executor is spawned, so that every thread has the signals blocked.
+//     let shutdown = ShutdownSignal::new()?;
+//
+//     let config = mintdb::config::Config {
+//         shards: args.shards.max(1),
+//         ..mintdb::config::Config::new(&args.data_dir)
+//     };
+//
+//     // Thread-per-core: each shard's database lives on its own executor, pinned to its own
+//     // CPU, and only ever sees the keys routed to it by `mintdb::shard::shard_for_key`.
+//     let cpus = std::thread::available_parallelism().map_or(1, |cpus| cpus.get());
+//     let mut shard_senders = Vec::new();
+//     let mut shard_executors = Vec::new();
+//
+//     for shard in 0..config.shards {
+//         let (sender, receiver) = shared_channel::new_bounded(SHARD_QUEUE_DEPTH);
+//         let shard_config = config.for_shard(shard);
+//
+//         // More shards than CPUs share them, rather than failing to start.
+//         shard_executors.push(
+//             glommio::LocalExecutorBuilder::new(glommio::Placement::Fixed(shard % cpus))
+//                 .name(&format!("shard-{shard}"))
+//                 .spawn(move || run_shard(shard_config, receiver))
+//                 .expect("failed to spawn shard executor"),
+//         );
+//         shard_senders.push(sender);
+//     }
 //
 //     let tonic = rpc::DatabaseServer::new(DbServer {});
 //     GmfServer::new(
@@ -109,16 +267,26 @@
 //
 //     let server = glommio::LocalExecutorBuilder::new(glommio::Placement::Unbound)
 //         .name("server-executor")
-//         .spawn(|| async move {
+//         .spawn(move || async move {
 //             let executor = glommio::executor();
 //
+//             let mut connected = Vec::new();
+//
+//             for sender in shard_senders {
+//                 connected.push(sender.connect().await);
+//             }
+//
+//             // Shared by every connection task, which routes each request to
+//             // `shards[mintdb::shard::shard_for_key(&key, shards.len())]`.
+//             let shards = std::rc::Rc::new(connected);
+//
 //             let queue = executor.create_task_queue(
 //                 glommio::Shares::Static(10),
 //                 glommio::Latency::Matters(Duration::from_millis(10)),
 //                 "incoming-connections",
 //             );
 //
-//             let listener = match glommio::net::UnixListener::bind("/tmp/mintdb.sock") {
+//             let listener = match glommio::net::UnixListener::bind(SOCKET_PATH) {
 //                 Ok(l) => l,
 //                 Err(e) => {
 //                     eprintln!("Failed to bind to socket: {}", e);
@@ -127,19 +295,36 @@
 //                 }
 //             };
 //
+//             // Every connection task runs behind the gate, so closing it waits for the
+//             // requests in flight to finish.
+//             let in_flight = glommio::sync::Gate::new();
+//
 //             loop {
-//                 let stream = match listener.accept().await {
-//                     Ok(s) => s,
-//                     Err(e) => {
+//                 let accepted = futures_lite::future::or(
+//                     async { Some(listener.accept().await) },
+//                     async {
+//                         shutdown.recv().await;
+//                         None
+//                     },
+//                 )
+//                 .await;
+//
+//                 let stream = match accepted {
+//                     Some(Ok(s)) => s,
+//                     Some(Err(e)) => {
 //                         eprintln!("Failed to accept connection: {}", e);
 //                         continue;
 //                     }
+//                     None => break,
 //                 };
 //
-//                 match executor.spawn_local_into(
+//                 let shards = shards.clone();
+//
+//                 match in_flight.spawn_into(
 //                     async move {
 //                         // TODO: Handle the connection
 //                         let _stream = stream;
+//                         let _shards = shards;
 //                     },
 //                     queue,
 //                 ) {
@@ -151,15 +336,35 @@
 //                     }
 //                 }
 //             }
+//
+//             // Shutdown: stop accepting and drain the requests in flight. Dropping the
+//             // senders then lets each shard flush and close its database, so nothing is left
+//             // for WAL replay.
+//             drop(listener);
+//
+//             if let Err(e) = in_flight.close().await {
+//                 eprintln!("Failed to drain in-flight requests: {}", e);
+//             }
+//
+//             drop(shards);
+//
+//             if let Err(e) = std::fs::remove_file(SOCKET_PATH) {
+//                 eprintln!("Failed to remove socket {}: {}", SOCKET_PATH, e);
+//             }
 //         })
 //         .expect("failed to spawn glommio executor");
 //
 //     server
 //         .join()
 //         .map_err(|e| anyhow::anyhow!("Failed to run server: {e}"))?;
-//     database
-//         .join()
-//         .map_err(|e| anyhow::anyhow!("Failed to run database: {e}"))?;
+//     for shard in shard_executors {
+//         shard
+//             .join()
+//             .map_err(|e| anyhow::anyhow!("Failed to run shard: {e}"))?;
+//     }
+//
+//     // Last, once everything else has shut down.
+//     drop(pidfile);
 //
 //     Ok(())
 // }
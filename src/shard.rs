@@ -0,0 +1,211 @@
+//! Splitting the keyspace across several databases, so that a server can run one per core.
+//!
+//! A [`Database`](crate::Database) is single-threaded, so a server scales across cores by
+//! giving each of [`Config::shards`](crate::config::Config::shards) executors its own
+//! database, opened with [`Config::for_shard`](crate::config::Config::for_shard), and
+//! sending each request to the executor that owns its key, as picked by
+//! [`shard_for_key`].
+//!
+//! Each shard has its own WAL, so shards recover independently, and in parallel if
+//! they're opened on their executors at once. A [`WriteBatch`] that spans shards is kept
+//! atomic by [`write_sharded`], with a [`CommitLog`] deciding the fate of its parts.
+
+use std::{
+    collections::BTreeSet,
+    fs::{File, OpenOptions},
+    hash::Hasher,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+
+use crate::{batch::WriteBatch, Database};
+
+/// Seeds the routing hash, so that it's independent of the hash bloom filters are built
+/// from. Otherwise every key in a shard would share the low bits the filters probe with.
+const SHARD_HASH_SEED: u64 = 0x6d69_6e74_7368_6172;
+
+/// The shard out of `shards` that owns `key`. The same key always maps to the same shard
+/// for a given number of shards, across processes and builds, so changing the number of
+/// shards moves most keys to another shard.
+pub fn shard_for_key(key: &[u8], shards: usize) -> usize {
+    let mut hasher = twox_hash::XxHash64::with_seed(SHARD_HASH_SEED);
+    hasher.write(key);
+
+    (hasher.finish() % shards.max(1) as u64) as usize
+}
+
+/// The directory the database of `shard` lives in under `data_dir`.
+pub fn shard_dir(data_dir: &Path, shard: usize) -> PathBuf {
+    data_dir.join(format!("shard-{shard:03}"))
+}
+
+/// The name of the file in the root `data_dir`, above the shards' directories, that
+/// [`CommitLog`] keeps its commits in.
+pub const COMMIT_LOG_FILE_NAME: &str = "COMMITS";
+
+/// The coordinator's record of which cross-shard batches committed, which decides what
+/// each shard does with the batches it finds still prepared when it's reopened.
+///
+/// A batch that spans shards is prepared in each of them with
+/// [`Database::prepare_batch`], then recorded here, then committed in each with
+/// [`Database::commit_prepared`]. Recording it is the commit point: after a crash, a
+/// prepared batch whose transaction is in the log is committed by [`CommitLog::resolve`],
+/// and one that isn't is aborted, so the batch is applied in every shard or in none.
+///
+/// Each commit is 8 little-endian bytes, fsynced before [`CommitLog::record_commit`]
+/// returns. A torn commit at the end of the file was never acknowledged, so it's ignored.
+#[derive(Debug)]
+pub struct CommitLog {
+    file: File,
+    committed: BTreeSet<u64>,
+    next_txn: u64,
+}
+
+impl CommitLog {
+    /// Opens the commit log in `data_dir`, the directory the shards live under, creating it
+    /// if it doesn't exist.
+    pub fn open(data_dir: &Path) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(data_dir).context("Failed to create data directory")?;
+
+        let path = data_dir.join(COMMIT_LOG_FILE_NAME);
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("Failed to open commit log {}", path.display()))?;
+
+        let mut buf = Vec::new();
+        file.read_to_end(&mut buf)
+            .with_context(|| format!("Failed to read commit log {}", path.display()))?;
+
+        let committed = buf
+            .chunks_exact(8)
+            .map(|txn| u64::from_le_bytes(txn.try_into().expect("chunk is 8 bytes")))
+            .collect::<BTreeSet<_>>();
+
+        // Cut a torn commit off, so the next one starts on a boundary.
+        let whole = buf.len() - buf.len() % 8;
+        if whole < buf.len() {
+            file.set_len(whole as u64)
+                .context("Failed to truncate torn commit")?;
+        }
+
+        let next_txn = committed.last().map_or(0, |txn| txn + 1);
+
+        Ok(CommitLog {
+            file,
+            committed,
+            next_txn,
+        })
+    }
+
+    /// Allocates the id of a new transaction, past every one committed.
+    ///
+    /// Ids of transactions that were never committed are handed out again after a restart,
+    /// so call [`CommitLog::resolve`] on every shard before beginning new ones.
+    pub fn begin(&mut self) -> u64 {
+        let txn = self.next_txn;
+        self.next_txn += 1;
+        txn
+    }
+
+    /// Durably records `txn` as committed. Once this returns, the batch prepared under it
+    /// has to be committed in every shard it was prepared in.
+    pub fn record_commit(&mut self, txn: u64) -> anyhow::Result<()> {
+        self.file
+            .write_all(&txn.to_le_bytes())
+            .context("Failed to write commit")?;
+        self.file.sync_data().context("Failed to sync commit log")?;
+
+        self.committed.insert(txn);
+        self.next_txn = self.next_txn.max(txn + 1);
+
+        Ok(())
+    }
+
+    pub fn is_committed(&self, txn: u64) -> bool {
+        self.committed.contains(&txn)
+    }
+
+    /// Settles every batch `db` recovered still prepared: those whose transaction was
+    /// committed are committed, and the rest are aborted. Returns how many of each.
+    pub async fn resolve(&self, db: &mut Database) -> anyhow::Result<(usize, usize)> {
+        let mut committed = 0;
+        let mut aborted = 0;
+
+        for txn in db.prepared_batches() {
+            if self.is_committed(txn) {
+                db.commit_prepared(txn).await?;
+                committed += 1;
+            } else {
+                db.abort_prepared(txn)?;
+                aborted += 1;
+            }
+        }
+
+        Ok((committed, aborted))
+    }
+}
+
+/// Writes `batch` across `shards`, the databases of every shard in order, so that each
+/// shard gets the writes to the keys it owns and the batch stays atomic: after a crash,
+/// either every shard has its part or none does.
+///
+/// A batch that only touches one shard is written to it directly. Otherwise it goes
+/// through a two-phase commit coordinated by `log`; see [`CommitLog`]. Every shard must
+/// have the batch's column families, with the same ids.
+pub async fn write_sharded(
+    log: &mut CommitLog,
+    shards: &mut [Database],
+    batch: WriteBatch,
+) -> anyhow::Result<()> {
+    let mut parts = batch
+        .split_by_shard(shards.len())
+        .into_iter()
+        .enumerate()
+        .filter(|(_, part)| !part.is_empty())
+        .collect::<Vec<_>>();
+
+    match parts.len() {
+        0 => return Ok(()),
+        1 => {
+            let (shard, part) = parts.pop().expect("one part");
+            return shards[shard].write(part).await;
+        }
+        _ => {}
+    }
+
+    let txn = log.begin();
+    let mut prepared: Vec<usize> = Vec::with_capacity(parts.len());
+
+    for (shard, part) in parts {
+        if let Err(e) = shards[shard].prepare_batch(txn, part) {
+            for shard in prepared {
+                if let Err(e) = shards[shard].abort_prepared(txn) {
+                    eprintln!("Failed to abort transaction {txn} in shard {shard}: {e:?}");
+                }
+            }
+
+            return Err(e.context(format!(
+                "Failed to prepare transaction {txn} in shard {shard}"
+            )));
+        }
+
+        prepared.push(shard);
+    }
+
+    log.record_commit(txn)?;
+
+    // Committed from here on: a shard that fails commits it when it's resolved on reopen.
+    for shard in prepared {
+        shards[shard]
+            .commit_prepared(txn)
+            .await
+            .with_context(|| format!("Failed to commit transaction {txn} in shard {shard}"))?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,66 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use crate::key::SeqNo;
+
+/// A consistent point-in-time view of the database.
+///
+/// Reads through a snapshot only observe writes with a seqno at or below the snapshot's.
+/// While a snapshot is alive, compaction keeps the versions it can see.
+#[derive(Debug)]
+pub struct Snapshot {
+    seqno: SeqNo,
+    list: Arc<SnapshotList>,
+}
+
+impl Snapshot {
+    pub(crate) fn new(seqno: SeqNo, list: Arc<SnapshotList>) -> Self {
+        list.acquire(seqno);
+
+        Snapshot { seqno, list }
+    }
+
+    pub fn seqno(&self) -> SeqNo {
+        self.seqno
+    }
+}
+
+impl Clone for Snapshot {
+    fn clone(&self) -> Self {
+        Snapshot::new(self.seqno, Arc::clone(&self.list))
+    }
+}
+
+impl Drop for Snapshot {
+    fn drop(&mut self) {
+        self.list.release(self.seqno);
+    }
+}
+
+/// The seqnos of every live [`Snapshot`], with a count of the snapshots at each.
+#[derive(Debug, Default)]
+pub(crate) struct SnapshotList {
+    live: parking_lot::Mutex<BTreeMap<SeqNo, usize>>,
+}
+
+impl SnapshotList {
+    fn acquire(&self, seqno: SeqNo) {
+        *self.live.lock().entry(seqno).or_default() += 1;
+    }
+
+    fn release(&self, seqno: SeqNo) {
+        let mut live = self.live.lock();
+
+        if let Some(count) = live.get_mut(&seqno) {
+            *count -= 1;
+
+            if *count == 0 {
+                live.remove(&seqno);
+            }
+        }
+    }
+
+    /// The seqno of the oldest live snapshot, if there are any.
+    pub(crate) fn oldest(&self) -> Option<SeqNo> {
+        self.live.lock().keys().next().copied()
+    }
+}
@@ -1,6 +1,9 @@
 use std::{
-    collections::VecDeque,
+    cell::RefCell,
+    collections::{BTreeMap, HashMap, VecDeque},
     io::{Read, Seek, Write},
+    ops::{Bound, RangeInclusive},
+    rc::Rc,
     sync::Arc,
 };
 
@@ -8,17 +11,37 @@ use anyhow::Context;
 use bytes::BufMut;
 
 use crate::{
+    backup::BackupState,
+    bloom::{self, BloomFilter},
+    cache::BlockCache,
+    clock::YieldTimer,
+    column_family::ColumnFamilyId,
+    compaction::{
+        CompactionCounts, CompactionIterator, CompactionResult, CompactionStrategy,
+        CompactionVerificationFailed,
+    },
     config::Config,
+    db::ReadOnlyHandle,
+    iter::{MergeIterator, Source},
     key::{Key, SeqNo},
-    memtable::{
-        state::{self, Frozen},
-        MemTable,
-    },
+    lock::{lock_for_open, LockFile},
+    memtable::{state::Frozen, MemTable},
+    recovery::{OpenReport, RecoveryAction},
+    retry::Retry,
+    scrub::{Corruption, ScrubCursor, ScrubReport, SCRUB_CURSOR_FILE_NAME},
     sstable::{
-        manifest::{FileMeta, LevelMeta, Manifest, ManifestRecord},
-        sstable::{index_block_size, BlockMeta, SSTableFooter, BLOCK_SIZE},
+        manifest::{
+            ColumnFamilyMeta, DatabaseOptions, FileMeta, LevelMeta, Manifest, ManifestRecord,
+        },
+        sstable::{
+            compress_block, finish_block, index_block_size, BlockMeta, BlockReadOptions, SSTable,
+            SSTableFooter, MIN_RESTART_SPACING,
+        },
         Level,
     },
+    stats::ValueCounts,
+    tombstone::RangeTombstone,
+    value::Value,
 };
 
 #[derive(
@@ -42,6 +65,17 @@ impl std::fmt::Display for FileNo {
 
 pub const SSTABLE_MAGIC: u32 = 0xDEAD_BEEF;
 
+/// How many times [`SSTableManager::refresh`] rereads the manifest when the primary changes
+/// it out from under the read, before giving up.
+const SECONDARY_REFRESH_ATTEMPTS: usize = 16;
+
+/// The SSTable format version written by this build, stored in each file's footer.
+///
+/// Files from before the footer carried a version read as version 0. Version 1 added each
+/// block's checksum to the index, version 2 restart points to the end of each block, version 3
+/// the footer's key encoding, and version 4 its block compression.
+pub const SSTABLE_FORMAT_VERSION: u32 = 4;
+
 /// L0 (base) SSTable file size (64MB).
 pub const BASE_LEVEL_SIZE: usize = 1024 * 1024 * 64;
 /// SSTable size ratio. Each level's file size is determined by [`BASE_LEVEL_SIZE`] * [`SIZE_RATIO`]^level.
@@ -59,6 +93,463 @@ pub fn format_file_name(id: FileNo, ext: &str) -> String {
     format!("{id:06}.{ext}")
 }
 
+/// The file number of a file named by [`format_file_name`] with extension `ext`.
+fn parse_file_name(name: &str, ext: &str) -> Option<FileNo> {
+    let (stem, name_ext) = name.split_once('.')?;
+
+    (name_ext == ext).then_some(())?;
+
+    stem.parse().ok().map(FileNo)
+}
+
+/// The numbers of the manifest files in the database in `data_dir`, oldest first: the one
+/// CURRENT names, and any older ones kept by [`Config::manifest_retention`].
+pub fn manifest_files(data_dir: &std::path::Path) -> anyhow::Result<Vec<FileNo>> {
+    let mut manifests = Vec::new();
+
+    for entry in data_dir
+        .join("manifests")
+        .read_dir()
+        .context("Failed to read manifest dir")?
+    {
+        let name = entry
+            .context("Failed to read manifest dir entry")?
+            .file_name();
+
+        if let Some(no) = parse_file_name(&name.to_string_lossy(), MANIFEST_FILE_EXT) {
+            manifests.push(no);
+        }
+    }
+
+    manifests.sort();
+
+    Ok(manifests)
+}
+
+/// The cache shared through [`Config::block_cache`], or a new one of
+/// [`Config::block_cache_capacity`] if there isn't one.
+fn block_cache_for(config: &Config) -> Option<Arc<BlockCache>> {
+    config.block_cache.clone().or_else(|| {
+        (config.block_cache_capacity > 0)
+            .then(|| Arc::new(BlockCache::new(config.block_cache_capacity)))
+    })
+}
+
+/// Whether `e` was caused by a file not existing.
+fn is_not_found(e: &anyhow::Error) -> bool {
+    e.chain()
+        .find_map(|cause| cause.downcast_ref::<std::io::Error>())
+        .is_some_and(|e| e.kind() == std::io::ErrorKind::NotFound)
+}
+
+/// Deletes the `kind` of file at `path`, an SSTable or manifest, unless a secondary handle
+/// has it open with a shared lock, as [`SSTable::open_shared`] and
+/// [`SSTableManager::open_at`] take. Returns whether it's gone, which it also is if it
+/// didn't exist.
+fn remove_unless_shared(path: &std::path::Path, kind: &str) -> anyhow::Result<bool> {
+    let file = match std::fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(true),
+        Err(e) => {
+            return Err(e).with_context(|| format!("Failed to open {kind} {}", path.display()));
+        }
+    };
+
+    match file.try_lock() {
+        Ok(()) => {}
+        Err(std::fs::TryLockError::WouldBlock) => return Ok(false),
+        Err(std::fs::TryLockError::Error(e)) => {
+            return Err(e).with_context(|| format!("Failed to lock {kind} {}", path.display()));
+        }
+    }
+
+    std::fs::remove_file(path)
+        .with_context(|| format!("Failed to remove {kind} {}", path.display()))?;
+
+    Ok(true)
+}
+
+/// Returned when the CURRENT file doesn't hold a valid manifest name, or holds one that
+/// doesn't match its checksum, as a torn or corrupted write can leave it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorruptCurrent {
+    pub contents: String,
+}
+
+impl std::fmt::Display for CorruptCurrent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "CURRENT file holds {:?}, which doesn't name a manifest or fails its checksum",
+            self.contents
+        )
+    }
+}
+
+impl std::error::Error for CorruptCurrent {}
+
+/// What a CURRENT file naming `manifest_name` holds: the name followed by a CRC32 of it
+/// in hex if `checksum` is set, or just the name otherwise.
+fn current_contents(manifest_name: &str, checksum: bool) -> String {
+    if checksum {
+        format!(
+            "{manifest_name} {:08x}",
+            crc32fast::hash(manifest_name.as_bytes())
+        )
+    } else {
+        manifest_name.to_owned()
+    }
+}
+
+/// Parses the manifest name out of the contents of a CURRENT file, in either form
+/// [`current_contents`] writes.
+fn parse_current(contents: &[u8]) -> Result<String, CorruptCurrent> {
+    let contents = String::from_utf8_lossy(contents);
+
+    let name = match contents.split_once(' ') {
+        Some((name, crc)) => u32::from_str_radix(crc, 16)
+            .is_ok_and(|crc| crc == crc32fast::hash(name.as_bytes()))
+            .then_some(name),
+        None => Some(&*contents),
+    };
+
+    // The name is checked either way, which catches most torn writes of the bare form.
+    name.filter(|name| parse_file_name(name, MANIFEST_FILE_EXT).is_some())
+        .map(str::to_owned)
+        .ok_or_else(|| CorruptCurrent {
+            contents: contents.clone().into_owned(),
+        })
+}
+
+/// Reads the manifest name from the CURRENT file at `path`.
+fn read_current(path: &std::path::Path) -> anyhow::Result<String> {
+    Ok(parse_current(&std::fs::read(path)?)?)
+}
+
+/// Points [`CURRENT_FILE_NAME`] at `manifest_name` by renaming a temporary file over it, so
+/// that a crash leaves it pointing at either the old manifest or the new one. Returns the
+/// new CURRENT file, locked.
+fn replace_current(
+    manifests_dir: &std::path::Path,
+    manifest_name: &str,
+    config: &Config,
+) -> anyhow::Result<std::fs::File> {
+    let current_path = manifests_dir.join(CURRENT_FILE_NAME);
+    let temp_path = manifests_dir.join(format!("{CURRENT_FILE_NAME}.tmp"));
+
+    let mut temp_file =
+        std::fs::File::create(&temp_path).context("Failed to create temporary CURRENT file")?;
+
+    temp_file
+        .write_all(current_contents(manifest_name, config.current_checksum).as_bytes())
+        .context("Failed to write temporary CURRENT file")?;
+    temp_file
+        .sync_all()
+        .context("Failed to sync temporary CURRENT file")?;
+
+    std::fs::rename(&temp_path, &current_path).context("Failed to replace CURRENT file")?;
+
+    std::fs::File::open(manifests_dir)
+        .and_then(|dir| dir.sync_all())
+        .context("Failed to sync manifests directory")?;
+
+    temp_file.lock().context("Failed to lock CURRENT file")?;
+
+    Ok(temp_file)
+}
+
+/// Writes `manifest` to `writer` as a [`ManifestRecord::Snapshot`], compressed with
+/// [`Config::sstable_compression`] if that makes it smaller. A snapshot holds the metadata
+/// of every file, so it's by far the largest record, and it's written on every rotation.
+/// Readers tell compressed records apart by their frame, so the setting can change freely.
+fn write_manifest_snapshot(
+    writer: impl Write,
+    manifest: Manifest,
+    config: &Config,
+) -> anyhow::Result<usize> {
+    crate::framed::write_framed_compressed(
+        writer,
+        &ManifestRecord::Snapshot(manifest),
+        config.sstable_compression,
+        0,
+    )
+}
+
+/// Reads the manifest CURRENT names in the database in `data_dir`, without opening the
+/// database, so it works while another handle has it open.
+pub fn read_manifest(data_dir: &std::path::Path) -> anyhow::Result<Manifest> {
+    let manifests_dir = data_dir.join("manifests");
+
+    let name = read_current(&manifests_dir.join(CURRENT_FILE_NAME)).with_context(|| {
+        format!(
+            "Failed to read CURRENT file in {}, is there a database there?",
+            manifests_dir.display()
+        )
+    })?;
+
+    let file = std::fs::File::open(manifests_dir.join(&name))
+        .with_context(|| format!("Failed to open manifest {name}"))?;
+
+    Manifest::load_from_file(&file)
+        .map(|(manifest, _)| manifest)
+        .with_context(|| format!("Failed to load manifest {name}"))
+}
+
+/// Replaces the manifest of the database in [`Config::data_dir`] with `manifest`, which
+/// has to pass [`Manifest::validate`]. The database can't be open while this runs.
+///
+/// `manifest` is written as the snapshot of a fresh manifest file, which then replaces
+/// every other manifest, so its next file number ends up one past the new file's.
+pub fn write_manifest(config: &Config, mut manifest: Manifest) -> anyhow::Result<()> {
+    manifest.validate()?;
+
+    let manifests_dir = config.data_dir.join("manifests");
+    let current_path = manifests_dir.join(CURRENT_FILE_NAME);
+
+    let current_file = std::fs::File::open(&current_path).with_context(|| {
+        format!(
+            "Failed to open CURRENT file {}, is there a database there?",
+            current_path.display()
+        )
+    })?;
+
+    let _lock_file = LockFile::acquire(config, &mut OpenReport::default())?;
+    lock_for_open(&current_file, &current_path, config).context("Failed to lock CURRENT file")?;
+
+    let mut old_manifests = Vec::new();
+
+    for entry in manifests_dir
+        .read_dir()
+        .context("Failed to read manifest dir")?
+    {
+        let name = entry
+            .context("Failed to read manifest dir entry")?
+            .file_name()
+            .to_string_lossy()
+            .into_owned();
+
+        if let Some(no) = parse_file_name(&name, MANIFEST_FILE_EXT) {
+            old_manifests.push((no, name));
+        }
+    }
+
+    // Numbered past every manifest there, so a leftover can't be mistaken for a newer one.
+    if let Some((highest, _)) = old_manifests.iter().max() {
+        manifest.next_file_number = manifest.next_file_number.max(*highest + 1);
+    }
+
+    let (manifest_no, _) = manifest.alloc_file_number();
+    let manifest_name = format_file_name(manifest_no, MANIFEST_FILE_EXT);
+
+    let mut manifest_file = std::fs::OpenOptions::new()
+        .create_new(true)
+        .append(true)
+        .open(manifests_dir.join(&manifest_name))
+        .context("Failed to create manifest")?;
+
+    write_manifest_snapshot(&mut manifest_file, manifest, config)
+        .context("Failed to write manifest snapshot")?;
+
+    manifest_file
+        .sync_all()
+        .context("Failed to sync new manifest file")?;
+
+    let new_current = replace_current(&manifests_dir, &manifest_name, config)?;
+
+    for (_, name) in old_manifests {
+        std::fs::remove_file(manifests_dir.join(&name))
+            .with_context(|| format!("Failed to remove old manifest {name}"))?;
+    }
+
+    new_current.unlock().ok();
+    current_file.unlock().ok();
+
+    Ok(())
+}
+
+/// The manifest [`SSTableManager::open`] should use, as picked by [`choose_manifest`].
+struct ChosenManifest {
+    name: String,
+    manifest: Manifest,
+    since_snapshot: usize,
+    /// Manifests to delete once CURRENT names the chosen one.
+    stale: Vec<String>,
+}
+
+/// Picks the manifest to open.
+///
+/// That's normally the one CURRENT names, but a rotation interrupted after the new manifest
+/// was written and before CURRENT was renamed over leaves a newer, complete manifest
+/// behind. Of the named manifest and any higher-numbered ones that load, the one with the
+/// highest committed seqno wins, the highest-numbered on a tie. The others are stale, and
+/// are deleted as rotation would have, since a leftover's name could be allocated again.
+///
+/// If CURRENT is corrupt, `named` is `None` and every manifest is a candidate. Only those
+/// numbered above the chosen one are stale then, since the ones below it may be kept by
+/// [`Config::manifest_retention`].
+fn choose_manifest(
+    manifests_dir: &std::path::Path,
+    named: Option<&str>,
+) -> anyhow::Result<ChosenManifest> {
+    let load = |name: &str| -> anyhow::Result<(Manifest, usize)> {
+        let file = std::fs::File::open(manifests_dir.join(name))
+            .with_context(|| format!("Failed to open manifest {name}"))?;
+
+        Manifest::load_from_file(&file).with_context(|| format!("Failed to load manifest {name}"))
+    };
+
+    let named_no = named.and_then(|named| parse_file_name(named, MANIFEST_FILE_EXT));
+    let mut newer = Vec::new();
+
+    if named.is_none() || named_no.is_some() {
+        for entry in manifests_dir
+            .read_dir()
+            .context("Failed to read manifest dir")?
+        {
+            let name = entry
+                .context("Failed to read manifest dir entry")?
+                .file_name()
+                .to_string_lossy()
+                .into_owned();
+
+            if let Some(no) = parse_file_name(&name, MANIFEST_FILE_EXT)
+                && named_no.is_none_or(|named_no| no > named_no)
+            {
+                newer.push((no, name));
+            }
+        }
+    }
+
+    let named_result = named.map(|named| (named, load(named)));
+
+    if newer.is_empty()
+        && let Some((named, named_result)) = named_result
+    {
+        let (manifest, since_snapshot) = named_result?;
+
+        return Ok(ChosenManifest {
+            name: named.to_owned(),
+            manifest,
+            since_snapshot,
+            stale: Vec::new(),
+        });
+    }
+
+    let mut candidates = Vec::new();
+    let mut invalid = Vec::new();
+
+    let named_error = match named_result {
+        Some((named, Ok(loaded))) => {
+            // Ranked below every newer manifest on a tie.
+            candidates.push((FileNo(0), named.to_owned(), loaded));
+            None
+        }
+        Some((named, Err(e))) => Some((named, e)),
+        None => None,
+    };
+
+    for (no, name) in newer {
+        match load(&name) {
+            Ok(loaded) => candidates.push((no, name, loaded)),
+            Err(e) => {
+                eprintln!("Ignoring invalid manifest {name}: {e:#}");
+                invalid.push((no, name));
+            }
+        }
+    }
+
+    let Some(chosen) = candidates
+        .iter()
+        .map(|(no, _, (manifest, _))| (manifest.last_committed_sequence_number(), *no))
+        .max()
+        .map(|(_, no)| no)
+    else {
+        return Err(match named_error {
+            Some((_, e)) => e,
+            None => anyhow::anyhow!("CURRENT is corrupt, and no manifest could be loaded"),
+        });
+    };
+
+    let mut chosen_manifest = None;
+    let mut stale = Vec::new();
+
+    for (no, name, (manifest, since_snapshot)) in candidates {
+        if no == chosen {
+            chosen_manifest = Some((name, manifest, since_snapshot));
+        } else if named.is_some() || no > chosen {
+            stale.push(name);
+        }
+    }
+
+    stale.extend(
+        invalid
+            .into_iter()
+            .filter(|(no, _)| named.is_some() || *no > chosen)
+            .map(|(_, name)| name),
+    );
+
+    let (name, manifest, since_snapshot) = chosen_manifest.expect("chosen from the candidates");
+
+    // The named manifest can only be stale if it failed to load or lost to a newer one.
+    if let Some((named, _)) = named_error {
+        stale.push(named.to_owned());
+    }
+
+    Ok(ChosenManifest {
+        name,
+        manifest,
+        since_snapshot,
+        stale,
+    })
+}
+
+/// Returned by [`Database::open`](crate::Database::open) when the manifest references an
+/// SSTable that doesn't exist on disk, unless
+/// [`Config::repair_missing_sstables`](crate::config::Config::repair_missing_sstables) is set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MissingSstable {
+    pub cf: ColumnFamilyId,
+    pub level: Level,
+    pub file_number: FileNo,
+}
+
+impl std::fmt::Display for MissingSstable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "SSTable {} in level {} of column family {} is missing",
+            format_file_name(self.file_number, SSTABLE_FILE_EXT),
+            self.level.0,
+            self.cf
+        )
+    }
+}
+
+impl std::error::Error for MissingSstable {}
+
+/// Returned when an SSTable's contents don't match the whole-file checksum recorded for it
+/// in the manifest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileChecksumMismatch {
+    pub file_number: FileNo,
+    pub expected: u64,
+    pub actual: u64,
+}
+
+impl std::fmt::Display for FileChecksumMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "SSTable {} has checksum {:#018x}, but the manifest recorded {:#018x}",
+            format_file_name(self.file_number, SSTABLE_FILE_EXT),
+            self.actual,
+            self.expected
+        )
+    }
+}
+
+impl std::error::Error for FileChecksumMismatch {}
+
 #[derive(Debug)]
 pub struct SSTableManager {
     config: Arc<crate::config::Config>,
@@ -69,6 +560,37 @@ pub struct SSTableManager {
     active_file: std::fs::File,
 
     active_manifest: Manifest,
+
+    /// The number of records appended to the active manifest since its snapshot.
+    records_since_snapshot: usize,
+
+    /// SSTables that have been opened for reading, keyed by file number.
+    open_tables: RefCell<HashMap<FileNo, Rc<SSTable>>>,
+
+    block_cache: Option<Arc<BlockCache>>,
+
+    /// The highest seqno flushed per column family that couldn't be committed yet, because
+    /// an older frozen memtable was still unflushed.
+    pending_commit: HashMap<ColumnFamilyId, SeqNo>,
+
+    /// SSTables dropped from the manifest whose files are still there, because a secondary
+    /// handle had them open when they were dropped.
+    deferred_removals: Vec<FileNo>,
+
+    /// For a secondary handle, the manifest it last loaded. `None` for the primary.
+    secondary: Option<ManifestView>,
+}
+
+/// The manifest file a secondary handle last loaded, and its length when it was loaded.
+/// Manifests are only ever appended to until they're replaced, so if neither has changed,
+/// neither has the manifest.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct ManifestView {
+    name: String,
+    len: u64,
+    /// Whether the handle was opened at this manifest with [`SSTableManager::open_at`], and
+    /// stays on it rather than following CURRENT.
+    pinned: bool,
 }
 
 impl Drop for SSTableManager {
@@ -79,11 +601,13 @@ impl Drop for SSTableManager {
 }
 
 impl SSTableManager {
-    pub fn open(config: Arc<Config>) -> anyhow::Result<Self> {
+    /// Opens the manifest of the database in [`Config::data_dir`], creating the database if
+    /// it doesn't exist, and adds whatever it had to repair to `report`.
+    pub fn open(config: Arc<Config>, report: &mut OpenReport) -> anyhow::Result<Self> {
         let manifests_dir = config.data_dir.join("manifests");
         let current_file_path = manifests_dir.join(CURRENT_FILE_NAME);
 
-        let (current_file, active_file, active_manifest) = if !current_file_path
+        let (current_file, active_file, active_manifest, since_snapshot) = if !current_file_path
             .try_exists()
             .is_ok_and(|readable| readable)
         {
@@ -96,14 +620,17 @@ impl SSTableManager {
                 // This is probably a new DB, create a new manifest and CURRENT file
                 let mut current_file = std::fs::OpenOptions::new()
                     .create(true)
+                    .truncate(false)
                     .write(true)
                     .read(true)
                     .open(&current_file_path)
                     .context("Failed to create CURRENT file")?;
 
-                current_file.lock().context("Failed to lock CURRENT file")?;
+                lock_for_open(&current_file, &current_file_path, &config)
+                    .context("Failed to lock CURRENT file")?;
 
                 let mut manifest = Manifest::new();
+                manifest.options = DatabaseOptions::from_config(&config);
 
                 // We don't need the alloc record since we're writing a snapshot immediately
                 let (initial_manifest_id, _) = manifest.alloc_file_number();
@@ -114,7 +641,10 @@ impl SSTableManager {
                     .set_len(0)
                     .context("Failed to truncate CURRENT file")?;
                 current_file
-                    .write_all(initial_manifest_name.as_bytes())
+                    .write_all(
+                        current_contents(&initial_manifest_name, config.current_checksum)
+                            .as_bytes(),
+                    )
                     .context("Failed to write initial manifest id to CURRENT file")?;
 
                 current_file
@@ -131,15 +661,15 @@ impl SSTableManager {
                     .open(manifests_dir.join(&initial_manifest_name))
                     .context("Failed to create first manifest")?;
 
-                active_file
-                    .lock()
-                    .context("Failed to lock active manifest file")?;
-
-                crate::framed::write_framed(
-                    &mut active_file,
-                    &ManifestRecord::Snapshot(manifest.clone()),
+                lock_for_open(
+                    &active_file,
+                    &manifests_dir.join(&initial_manifest_name),
+                    &config,
                 )
-                .context("Failed to write initial manifest snapshot")?;
+                .context("Failed to lock active manifest file")?;
+
+                write_manifest_snapshot(&mut active_file, manifest.clone(), &config)
+                    .context("Failed to write initial manifest snapshot")?;
 
                 active_file
                     .flush()
@@ -153,7 +683,7 @@ impl SSTableManager {
                 panic!("CURRENT file not detected but manifests were found");
             };
 
-            (current_file, active_file, active_manifest)
+            (current_file, active_file, active_manifest, 0)
         } else {
             let mut current_file = std::fs::OpenOptions::new()
                 .create(false)
@@ -162,327 +692,3057 @@ impl SSTableManager {
                 .open(&current_file_path)
                 .context("Failed to open CURRENT file")?;
 
-            current_file.lock().context("Failed to lock CURRENT file")?;
+            lock_for_open(&current_file, &current_file_path, &config)
+                .context("Failed to lock CURRENT file")?;
 
-            let mut current_manifest = String::new();
+            let mut current_contents = Vec::new();
             current_file
-                .read_to_string(&mut current_manifest)
+                .read_to_end(&mut current_contents)
                 .context("Failed to read current manifest name from CURRENT file")?;
 
+            let current_manifest = parse_current(&current_contents);
+
+            if let Err(e) = &current_manifest {
+                eprintln!("{e}, falling back to the manifest furthest ahead");
+            }
+
+            let ChosenManifest {
+                name: chosen_manifest,
+                manifest,
+                since_snapshot,
+                stale,
+            } = choose_manifest(&manifests_dir, current_manifest.as_deref().ok())?;
+
+            if current_manifest.as_ref() != Ok(&chosen_manifest) {
+                if let Ok(current_manifest) = &current_manifest {
+                    eprintln!(
+                        "CURRENT names manifest {current_manifest}, but {chosen_manifest} is \
+                         newer and no further behind, switching CURRENT to {chosen_manifest}"
+                    );
+                }
+
+                let new_current_file = replace_current(&manifests_dir, &chosen_manifest, &config)?;
+
+                current_file.unlock().ok();
+                current_file = new_current_file;
+
+                report.actions.push(match current_manifest {
+                    Ok(named) => RecoveryAction::CurrentRepaired {
+                        named,
+                        chosen: chosen_manifest.clone(),
+                    },
+                    Err(CorruptCurrent { contents }) => RecoveryAction::CurrentRebuilt {
+                        contents,
+                        chosen: chosen_manifest.clone(),
+                    },
+                });
+            }
+
+            for name in &stale {
+                eprintln!("Removing stale manifest {name}");
+
+                std::fs::remove_file(manifests_dir.join(name))
+                    .with_context(|| format!("Failed to remove stale manifest {name}"))?;
+            }
+
+            if !stale.is_empty() {
+                report
+                    .actions
+                    .push(RecoveryAction::StaleManifestsRemoved { names: stale });
+            }
+
             let current_manifest_file = std::fs::OpenOptions::new()
                 .create(false)
                 .read(true)
                 .append(true)
-                .open(manifests_dir.join(&current_manifest))
+                .open(manifests_dir.join(&chosen_manifest))
                 .context("Failed to open current manifest file")?;
 
-            current_manifest_file
-                .lock()
-                .context("Failed to lock current manifest file")?;
-
-            let manifest = Manifest::load_from_file(&current_manifest_file)?;
-
-            (current_file, current_manifest_file, manifest)
+            lock_for_open(
+                &current_manifest_file,
+                &manifests_dir.join(&chosen_manifest),
+                &config,
+            )
+            .context("Failed to lock current manifest file")?;
+
+            manifest.options.check(&config)?;
+
+            (
+                current_file,
+                current_manifest_file,
+                manifest,
+                since_snapshot,
+            )
         };
 
-        Ok(SSTableManager {
+        let block_cache = block_cache_for(&config);
+
+        let mut manager = SSTableManager {
             config,
 
             current: current_file,
 
             active_file,
             active_manifest,
-        })
-    }
-
-    fn append_record(&mut self, record: ManifestRecord) -> anyhow::Result<()> {
-        crate::framed::write_framed(&mut self.active_file, &record)
-            .context("Failed to append record")?;
+            records_since_snapshot: since_snapshot,
 
-        match record {
-            ManifestRecord::Snapshot(manifest) => {
-                self.active_manifest = manifest;
-            }
-            ManifestRecord::CreateFile { level, file_meta } => {
-                self.active_manifest
-                    .levels
-                    .entry(level)
-                    .or_insert_with(|| LevelMeta {
-                        level,
-                        files: Default::default(),
-                    })
-                    .files
-                    .insert(FileNo(file_meta.file_number), file_meta);
-            }
-            ManifestRecord::DeleteFile { level, file_number } => {
-                self.active_manifest
-                    .levels
-                    .entry(level)
-                    .or_insert_with(|| LevelMeta {
-                        level,
-                        files: Default::default(),
-                    })
-                    .files
-                    .remove(&FileNo(file_number));
-            }
-            ManifestRecord::SetLastSeqNo(seq_no) => {
-                let manifest = &mut self.active_manifest;
-                manifest.last_committed_sequence_number =
-                    seq_no.max(manifest.last_committed_sequence_number);
-            }
-            ManifestRecord::AllocFileNumber(file_no) => {
-                let manifest = &mut self.active_manifest;
+            open_tables: RefCell::new(HashMap::new()),
+            block_cache,
+            pending_commit: HashMap::new(),
+            deferred_removals: Vec::new(),
+            secondary: None,
+        };
 
-                manifest.next_file_number = manifest.next_file_number.max(file_no + 1);
-            }
-        }
+        manager.check_missing_sstables(report)?;
+        manager.remove_orphaned_sstables(report)?;
 
-        Ok(())
+        Ok(manager)
     }
 
-    fn sync(&mut self) -> anyhow::Result<()> {
-        self.active_file
-            .flush()
-            .context("Failed to flush active manifest file")?;
+    /// Opens the manifest of the database in [`Config::data_dir`] as a secondary handle,
+    /// which only reads, and can do so while the primary handle has the database open.
+    ///
+    /// None of the primary's locks are taken. Instead every SSTable the manifest references
+    /// is opened up front with [`SSTable::open_shared`], so that the primary can't delete
+    /// any of them until [`Self::refresh`] moves past them.
+    pub fn open_secondary(config: Arc<Config>) -> anyhow::Result<Self> {
+        let current_path = config.data_dir.join("manifests").join(CURRENT_FILE_NAME);
+
+        let current_file = std::fs::File::open(&current_path).with_context(|| {
+            format!(
+                "Failed to open CURRENT file {}, is there a database there?",
+                current_path.display()
+            )
+        })?;
 
-        self.active_file
-            .sync_all()
-            .context("Failed to fsync active manifest file")?;
+        let block_cache = block_cache_for(&config);
 
-        Ok(())
-    }
+        let mut manager = SSTableManager {
+            config,
 
-    pub fn alloc_file_number(&mut self) -> anyhow::Result<FileNo> {
-        let (fileno, record) = self.active_manifest.alloc_file_number();
+            // Replaced by the manifest the first refresh loads, which the empty view
+            // guarantees it does.
+            active_file: current_file
+                .try_clone()
+                .context("Failed to open CURRENT file")?,
+            current: current_file,
 
-        self.append_record(record)?;
+            active_manifest: Manifest::new(),
+            records_since_snapshot: 0,
 
-        self.sync()?;
+            open_tables: RefCell::new(HashMap::new()),
+            block_cache,
+            pending_commit: HashMap::new(),
+            deferred_removals: Vec::new(),
+            secondary: Some(ManifestView::default()),
+        };
 
-        Ok(fileno)
-    }
+        manager.refresh()?;
+        manager.active_manifest.options.check(&manager.config)?;
 
-    pub fn last_committed_sequence_number(&self) -> SeqNo {
-        self.active_manifest.last_committed_sequence_number
+        Ok(manager)
     }
 
-    fn finalize_sstable(
-        &mut self,
-        file: &mut std::fs::File,
-        file_no: FileNo,
-        sstable_size: u64,
-        first_key: &Key,
-        last_key: &Key,
-        block_meta: &[BlockMeta],
-    ) -> anyhow::Result<()> {
-        let mut index_buf = bytes::BytesMut::with_capacity(index_block_size(&block_meta));
-        let index_start = file.stream_position()?;
+    /// Opens the manifest file numbered `manifest_no` as a secondary handle that stays on it,
+    /// for inspecting the database as of an older manifest kept by
+    /// [`Config::manifest_retention`].
+    ///
+    /// Like [`Self::open_secondary`], every SSTable the manifest references is opened up
+    /// front, so the primary can't delete them while the handle is open. Fails with
+    /// [`MissingSstable`] if one has already been compacted away.
+    pub fn open_at(config: Arc<Config>, manifest_no: FileNo) -> anyhow::Result<Self> {
+        let name = format_file_name(manifest_no, MANIFEST_FILE_EXT);
+        let path = config.data_dir.join("manifests").join(&name);
+
+        let manifest_file = std::fs::File::open(&path)
+            .with_context(|| format!("Failed to open manifest {}", path.display()))?;
+
+        let len = manifest_file
+            .metadata()
+            .with_context(|| format!("Failed to read length of manifest {name}"))?
+            .len();
+
+        // Keeps rotation from deleting the manifest while it's open. The one CURRENT names
+        // is locked by the primary, but isn't deleted until it's been rotated away.
+        match manifest_file.try_lock_shared() {
+            Ok(()) | Err(std::fs::TryLockError::WouldBlock) => {}
+            Err(std::fs::TryLockError::Error(e)) => {
+                return Err(e).with_context(|| format!("Failed to lock manifest {name}"));
+            }
+        }
 
-        index_buf.put_u32_le(block_meta.len() as u32);
+        let (manifest, _) = Manifest::load_from_file(&manifest_file)
+            .with_context(|| format!("Failed to load manifest {name}"))?;
 
-        for meta in block_meta {
-            meta.last_key.encode_into(&mut index_buf);
-            index_buf.put_u64_le(meta.offset);
-            index_buf.put_u32_le(meta.size);
-        }
+        manifest.options.check(&config)?;
 
-        file.write_all(&index_buf)?;
+        let block_cache = block_cache_for(&config);
 
-        let index_size = index_buf.len();
+        let manager = SSTableManager {
+            config,
 
-        let footer = SSTableFooter {
-            index_offset: index_start,
-            index_size: index_size as u64,
-            _reserved1: 0,
-            _reserved2: 0,
-            magic: SSTABLE_MAGIC,
+            current: manifest_file
+                .try_clone()
+                .with_context(|| format!("Failed to open manifest {name}"))?,
+            active_file: manifest_file,
+
+            active_manifest: manifest,
+            records_since_snapshot: 0,
+
+            open_tables: RefCell::new(HashMap::new()),
+            block_cache,
+            pending_commit: HashMap::new(),
+            deferred_removals: Vec::new(),
+            secondary: Some(ManifestView {
+                name,
+                len,
+                pinned: true,
+            }),
         };
 
-        index_buf.clear();
+        for (cf, cf_meta) in &manager.active_manifest.column_families {
+            for (level, level_meta) in &cf_meta.levels {
+                for file_number in level_meta.files.keys() {
+                    match manager.table(*file_number) {
+                        Ok(_) => {}
+                        Err(e) if is_not_found(&e) => {
+                            return Err(MissingSstable {
+                                cf: *cf,
+                                level: *level,
+                                file_number: *file_number,
+                            }
+                            .into());
+                        }
+                        Err(e) => return Err(e),
+                    }
+                }
+            }
+        }
 
-        footer.encode_into(&mut index_buf);
+        Ok(manager)
+    }
 
-        file.write_all(&index_buf)?;
+    pub fn is_secondary(&self) -> bool {
+        self.secondary.is_some()
+    }
 
-        file.flush()?;
-        file.sync_all()?;
+    /// Catches a secondary handle up with the primary's manifest, picking up the files it
+    /// has flushed and compacted since the last refresh. Returns whether anything changed.
+    ///
+    /// The files of the new manifest are opened before it replaces the old one, and the
+    /// files only the old one referenced are let go of afterwards, so reads never find a
+    /// file missing. A file that's already gone was compacted away after the manifest was
+    /// read, in which case it's read again.
+    pub fn refresh(&mut self) -> anyhow::Result<bool> {
+        let Some(view) = &self.secondary else {
+            anyhow::bail!("Only secondary handles can be refreshed");
+        };
 
-        self.append_record(ManifestRecord::CreateFile {
-            level: Level(0),
-            file_meta: FileMeta {
-                file_number: file_no.0,
-                file_size: sstable_size
-                    + index_size as u64
-                    + std::mem::size_of::<SSTableFooter>() as u64,
-                smallest_key: first_key.encode_to_bytes(),
-                largest_key: last_key.encode_to_bytes(),
-            },
-        })?;
+        if view.pinned {
+            anyhow::bail!("Handles opened at a specific manifest can't be refreshed");
+        }
 
-        self.sync()?;
+        let manifests_dir = self.config.data_dir.join("manifests");
 
-        Ok(())
-    }
+        for _ in 0..SECONDARY_REFRESH_ATTEMPTS {
+            let name = read_current(&manifests_dir.join(CURRENT_FILE_NAME))
+                .context("Failed to read current manifest name from CURRENT file")?;
 
-    async fn flush_memtable_internal(
-        &mut self,
-        frozen: &glommio::sync::RwLock<VecDeque<MemTable<Frozen>>>,
-    ) -> anyhow::Result<()> {
-        let mut file_no = self.alloc_file_number()?;
-        let mut file = {
-            let file_name = format_file_name(file_no, SSTABLE_FILE_EXT);
+            let manifest_file = match std::fs::File::open(manifests_dir.join(&name)) {
+                Ok(file) => file,
+                // Rotated away since CURRENT was read.
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e).context("Failed to open current manifest file"),
+            };
 
-            std::fs::OpenOptions::new()
-                .create(true)
-                .write(true)
-                .read(true)
-                .open(&self.config.data_dir.join("sstables").join(file_name))
-                .context("Failed to create SSTable file")?
-        };
+            // Taken before reading, so that anything appended while the manifest is read
+            // shows up as a change on the next refresh.
+            let new_view = ManifestView {
+                len: manifest_file
+                    .metadata()
+                    .context("Failed to read current manifest file length")?
+                    .len(),
+                name,
+                pinned: false,
+            };
 
-        let mut block_meta = Vec::new();
-        let mut current_block = bytes::BytesMut::with_capacity(BLOCK_SIZE);
-        let mut sstable_size = 0u64;
+            if new_view == *view {
+                return Ok(false);
+            }
 
-        file.seek(std::io::SeekFrom::Start(0))?;
+            let (manifest, _) = Manifest::load_from_file(&manifest_file)?;
 
-        let mut first_key = None;
-        let mut last_key = None;
+            let file_checksums = manifest
+                .column_families
+                .values()
+                .flat_map(|cf_meta| cf_meta.levels.values())
+                .flat_map(|level_meta| level_meta.files.values())
+                .map(|file| (FileNo(file.file_number), file.file_checksum))
+                .collect::<HashMap<_, _>>();
 
-        let memtable = {
-            let read_guard = frozen.read().await.expect("lock closed");
+            let mut missing = false;
 
-            read_guard
-                .front()
-                .context("No frozen memtable available to flush")?
-                .clone()
-        };
+            for (file_no, checksum) in &file_checksums {
+                match self.open_table(*file_no, Some(*checksum)) {
+                    Ok(_) => {}
+                    Err(e) if is_not_found(&e) => {
+                        missing = true;
+                        break;
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
 
-        const BUDGET: usize = 25;
-        let mut consumed = 0;
+            if missing {
+                continue;
+            }
 
-        let memtable_data = memtable.data();
+            self.open_tables.borrow_mut().retain(|file_no, table| {
+                let keep = file_checksums.contains_key(file_no);
 
-        for (idx, (key, val)) in memtable_data.iter().enumerate() {
-            consumed += 1;
+                if !keep {
+                    table.evict_cached_blocks();
+                }
 
-            if consumed > BUDGET {
-                glommio::executor().yield_now().await;
-                consumed = 0;
-            }
+                keep
+            });
 
-            first_key = Some(first_key.unwrap_or_else(|| key.clone()));
-            last_key = Some(key.clone());
+            self.active_manifest = manifest;
+            self.active_file = manifest_file;
+            self.secondary = Some(new_view);
 
-            key.encode_into(&mut current_block);
-            val.encode_into(&mut current_block);
+            return Ok(true);
+        }
 
-            if current_block.len() >= BLOCK_SIZE {
-                block_meta.push(BlockMeta {
-                    last_key: last_key.clone().expect(
-                        "There should be at least one key in the block if we're writing it",
-                    ),
-                    offset: file.stream_position()?,
-                    size: current_block.len() as u32,
-                });
+        anyhow::bail!(
+            "The manifest changed on each of {SECONDARY_REFRESH_ATTEMPTS} attempts to read it"
+        )
+    }
 
-                file.write_all(&current_block)?;
+    /// Queues the SSTable files that aren't in the manifest for removal. They're left behind
+    /// by a removal that was still deferred when the database was closed, or by a flush or
+    /// compaction that was interrupted before its output was added to the manifest.
+    fn remove_orphaned_sstables(&mut self, report: &mut OpenReport) -> anyhow::Result<()> {
+        let live = self
+            .active_manifest
+            .column_families
+            .values()
+            .flat_map(|cf_meta| cf_meta.levels.values())
+            .flat_map(|level_meta| level_meta.files.keys().copied())
+            .collect::<std::collections::HashSet<_>>();
+
+        for entry in self
+            .config
+            .data_dir
+            .join("sstables")
+            .read_dir()
+            .context("Failed to read sstables dir")?
+        {
+            let name = entry
+                .context("Failed to read sstables dir entry")?
+                .file_name();
 
-                sstable_size += current_block.len() as u64;
+            if let Some(file_no) = parse_file_name(&name.to_string_lossy(), SSTABLE_FILE_EXT)
+                && !live.contains(&file_no)
+            {
+                self.deferred_removals.push(file_no);
+            }
+        }
+
+        if !self.deferred_removals.is_empty() {
+            self.deferred_removals.sort();
+
+            report
+                .actions
+                .push(RecoveryAction::OrphanedSstablesRemoved {
+                    files: self.deferred_removals.clone(),
+                });
+        }
+
+        self.retry_deferred_removals()
+    }
+
+    /// Deletes the SSTable files numbered from `first` on that aren't in the manifest: the
+    /// partly written outputs of a flush or compaction that failed after allocating them,
+    /// which would otherwise take up space until the next open. A file that can't be
+    /// removed is logged and left for then, so the error that failed the operation is the
+    /// one returned.
+    fn remove_unused_files_from(&self, first: FileNo) {
+        let sstables_dir = self.config.data_dir.join("sstables");
+
+        for file_no in (first.0..self.active_manifest.next_file_number.0).map(FileNo) {
+            if self.file_checksum(file_no).is_some() {
+                continue;
+            }
+
+            let path = sstables_dir.join(format_file_name(file_no, SSTABLE_FILE_EXT));
+
+            match std::fs::remove_file(&path) {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => eprintln!("Failed to remove SSTable {}: {e}", path.display()),
+            }
+        }
+    }
+
+    /// Deletes the files of the SSTables dropped from the manifest that haven't been yet,
+    /// other than those a secondary handle still has open, which are left for the next try.
+    fn retry_deferred_removals(&mut self) -> anyhow::Result<()> {
+        let sstables_dir = self.config.data_dir.join("sstables");
+        let mut result = Ok(());
+
+        self.deferred_removals.retain(|file_no| {
+            let path = sstables_dir.join(format_file_name(*file_no, SSTABLE_FILE_EXT));
+
+            match remove_unless_shared(&path, "SSTable") {
+                Ok(removed) => !removed,
+                Err(e) => {
+                    if result.is_ok() {
+                        result = Err(e);
+                    }
+
+                    true
+                }
+            }
+        });
+
+        result
+    }
+
+    /// Checks that every SSTable the manifest references exists, so that a deleted file is
+    /// reported at open rather than by whichever read first needs it.
+    ///
+    /// Fails with [`MissingSstable`] for the first missing file, or if
+    /// [`Config::repair_missing_sstables`] is set, removes every missing file from the
+    /// manifest. The data they held is lost.
+    fn check_missing_sstables(&mut self, report: &mut OpenReport) -> anyhow::Result<()> {
+        let sstables_dir = self.config.data_dir.join("sstables");
+        let mut missing = Vec::new();
+
+        for (cf, cf_meta) in &self.active_manifest.column_families {
+            for (level, level_meta) in &cf_meta.levels {
+                for file_number in level_meta.files.keys() {
+                    let path = sstables_dir.join(format_file_name(*file_number, SSTABLE_FILE_EXT));
+
+                    let exists = path.try_exists().with_context(|| {
+                        format!("Failed to check for SSTable {}", path.display())
+                    })?;
+
+                    if !exists {
+                        missing.push(MissingSstable {
+                            cf: *cf,
+                            level: *level,
+                            file_number: *file_number,
+                        });
+                    }
+                }
+            }
+        }
+
+        if missing.is_empty() {
+            return Ok(());
+        }
+
+        if !self.config.repair_missing_sstables {
+            return Err(missing[0].into());
+        }
+
+        for missing in &missing {
+            eprintln!("{missing}, removing it from the manifest");
+
+            self.append_record(ManifestRecord::DeleteFile {
+                cf: missing.cf,
+                level: missing.level,
+                file_number: missing.file_number.0,
+            })?;
+        }
+
+        self.sync()?;
+
+        report
+            .actions
+            .push(RecoveryAction::MissingSstablesDropped { files: missing });
+
+        Ok(())
+    }
+
+    fn append_record(&mut self, record: ManifestRecord) -> anyhow::Result<()> {
+        if self.is_secondary() {
+            return Err(ReadOnlyHandle.into());
+        }
+
+        crate::framed::write_framed(&mut self.active_file, &record)
+            .context("Failed to append record")?;
+
+        self.records_since_snapshot += 1;
+
+        self.active_manifest.apply(record)
+    }
+
+    /// Syncs the active manifest, then moves to a fresh one if enough records have been
+    /// appended since the last snapshot.
+    fn sync(&mut self) -> anyhow::Result<()> {
+        self.active_file
+            .flush()
+            .context("Failed to flush active manifest file")?;
+
+        self.active_file
+            .sync_all()
+            .context("Failed to fsync active manifest file")?;
+
+        // Secondary handles may have moved past the files whose removal was deferred since.
+        self.retry_deferred_removals()?;
+
+        let interval = self.config.manifest_snapshot_interval;
+
+        if interval > 0 && self.records_since_snapshot >= interval {
+            self.rotate_manifest()?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes a snapshot of the manifest to a new manifest file, points
+    /// [`CURRENT_FILE_NAME`] at it, and deletes the old one, so that opening the database
+    /// only has to replay records logged after the snapshot. The newest
+    /// [`Config::manifest_retention`] old ones are kept instead.
+    fn rotate_manifest(&mut self) -> anyhow::Result<()> {
+        let manifests_dir = self.config.data_dir.join("manifests");
+
+        // The snapshot records the allocation, so it doesn't need logging separately.
+        let (manifest_no, _) = self.active_manifest.alloc_file_number();
+        let manifest_name = format_file_name(manifest_no, MANIFEST_FILE_EXT);
+
+        let mut manifest_file = std::fs::OpenOptions::new()
+            .create_new(true)
+            .read(true)
+            .append(true)
+            .open(manifests_dir.join(&manifest_name))
+            .context("Failed to create manifest")?;
+
+        manifest_file
+            .lock()
+            .context("Failed to lock new manifest file")?;
+
+        write_manifest_snapshot(
+            &mut manifest_file,
+            self.active_manifest.clone(),
+            &self.config,
+        )
+        .context("Failed to write manifest snapshot")?;
+
+        manifest_file
+            .sync_all()
+            .context("Failed to sync new manifest file")?;
+
+        // Until CURRENT is replaced, the old manifest is still complete.
+        let old_name = read_current(&manifests_dir.join(CURRENT_FILE_NAME))
+            .context("Failed to read old manifest name from CURRENT file")?;
+
+        let temp_file = replace_current(&manifests_dir, &manifest_name, &self.config)?;
+
+        let old_file = std::mem::replace(&mut self.active_file, manifest_file);
+        let old_current = std::mem::replace(&mut self.current, temp_file);
+
+        old_file.unlock().ok();
+        old_current.unlock().ok();
+
+        self.records_since_snapshot = 0;
+
+        let Some(old_no) = parse_file_name(&old_name, MANIFEST_FILE_EXT) else {
+            return std::fs::remove_file(manifests_dir.join(&old_name))
+                .with_context(|| format!("Failed to remove old manifest {old_name}"));
+        };
+
+        let old_manifests = manifest_files(&self.config.data_dir)?
+            .into_iter()
+            .filter(|no| *no <= old_no)
+            .collect::<Vec<_>>();
+        let expired = old_manifests
+            .len()
+            .saturating_sub(self.config.manifest_retention);
+
+        // One a handle was opened at is left for a later rotation to collect.
+        for no in &old_manifests[..expired] {
+            let name = format_file_name(*no, MANIFEST_FILE_EXT);
+
+            remove_unless_shared(&manifests_dir.join(&name), "manifest")?;
+        }
+
+        Ok(())
+    }
+
+    pub fn alloc_file_number(&mut self) -> anyhow::Result<FileNo> {
+        let (fileno, record) = self.active_manifest.alloc_file_number();
+
+        self.append_record(record)?;
+
+        self.sync()?;
+
+        Ok(fileno)
+    }
+
+    fn column_family(&self, cf: ColumnFamilyId) -> anyhow::Result<&ColumnFamilyMeta> {
+        self.active_manifest
+            .column_families
+            .get(&cf)
+            .with_context(|| format!("Unknown column family {cf}"))
+    }
+
+    /// Returns the id and name of every column family.
+    pub fn column_families(&self) -> impl Iterator<Item = (ColumnFamilyId, &str)> {
+        self.active_manifest
+            .column_families
+            .iter()
+            .map(|(id, meta)| (*id, meta.name.as_str()))
+    }
+
+    /// Durably records a new, empty column family and returns its id.
+    pub fn create_column_family(&mut self, name: &str) -> anyhow::Result<ColumnFamilyId> {
+        let id = self.active_manifest.next_column_family_id();
+
+        self.append_record(ManifestRecord::CreateColumnFamily {
+            id,
+            name: name.to_owned(),
+        })?;
+
+        self.sync()?;
+
+        Ok(id)
+    }
+
+    pub fn last_committed_sequence_number(&self, cf: ColumnFamilyId) -> anyhow::Result<SeqNo> {
+        Ok(self.column_family(cf)?.last_committed_sequence_number)
+    }
+
+    fn create_sstable_file(&mut self) -> anyhow::Result<(FileNo, std::fs::File)> {
+        let file_no = self.alloc_file_number()?;
+        let file_name = format_file_name(file_no, SSTABLE_FILE_EXT);
+
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(true)
+            .write(true)
+            .read(true)
+            .open(self.config.data_dir.join("sstables").join(file_name))
+            .context("Failed to create SSTable file")?;
+
+        Ok((file_no, file))
+    }
+
+    /// Writes the index and footer, syncs the file, and returns its metadata. The file is
+    /// not added to the manifest.
+    fn finalize_sstable(
+        &mut self,
+        file: &mut std::fs::File,
+        file_no: FileNo,
+        first_key: &Key,
+        last_key: &Key,
+        block_meta: &[BlockMeta],
+        range_tombstones: Vec<RangeTombstone>,
+    ) -> anyhow::Result<FileMeta> {
+        let mut index_buf = bytes::BytesMut::with_capacity(index_block_size(block_meta));
+        let index_start = file.stream_position()?;
+
+        index_buf.put_u32_le(block_meta.len() as u32);
+
+        for meta in block_meta {
+            meta.encode_into(&mut index_buf);
+        }
+
+        file.write_all(&index_buf)?;
+
+        let index_size = index_buf.len();
+
+        let footer = SSTableFooter {
+            index_offset: index_start,
+            index_size: index_size as u64,
+            key_encoding: self.config.key_encoding as u32,
+            compression: self.config.sstable_compression as u32,
+            version: SSTABLE_FORMAT_VERSION,
+            magic: SSTABLE_MAGIC,
+        };
+
+        index_buf.clear();
+
+        footer.encode_into(&mut index_buf);
+
+        file.write_all(&index_buf)?;
+
+        file.flush()?;
+        file.sync_all()?;
+
+        file.seek(std::io::SeekFrom::Start(0))?;
+        let file_checksum = SSTable::file_checksum(&mut *file)?;
+
+        Ok(FileMeta {
+            file_number: file_no.0,
+            // Blocks are written from the start of the file, so they end where the
+            // index begins.
+            file_size: index_start
+                + index_size as u64
+                + std::mem::size_of::<SSTableFooter>() as u64,
+            smallest_key: first_key.encode_to_bytes(),
+            largest_key: last_key.encode_to_bytes(),
+            sub_level: 0,
+            earliest_expiry: None,
+            value_counts: ValueCounts::default(),
+            largest_seqno: range_tombstones
+                .iter()
+                .map(|tombstone| tombstone.seqno)
+                .max()
+                .unwrap_or(SeqNo(0)),
+            file_checksum,
+            range_tombstones,
+            bloom_filter: None,
+        })
+    }
+
+    /// Writes `entries`, which must be sorted by [`Key`], out to as many SSTables as needed
+    /// to keep each under `target_size`. `range_tombstones` are attached to the last file.
+    /// The files get bloom filters if `level`, where they're going, is configured to.
+    ///
+    /// Returns the metadata of every file written. The files are synced, but it's up to the
+    /// caller to add them to the manifest.
+    async fn write_sstables(
+        &mut self,
+        entries: impl Iterator<Item = anyhow::Result<(Key, Value)>>,
+        range_tombstones: Vec<RangeTombstone>,
+        level: Level,
+        target_size: u64,
+    ) -> anyhow::Result<Vec<FileMeta>> {
+        let mut entries = entries.peekable();
+        let mut files = Vec::new();
+
+        // The file currently being written, created lazily so that a split on the last
+        // entry doesn't leave an empty file behind.
+        let mut current_file = None;
+
+        let mut block_meta = Vec::new();
+        let mut current_block = bytes::BytesMut::with_capacity(self.config.block_buffer_capacity);
+        let mut sstable_size = 0u64;
+
+        // The offsets of the current block's restart points, and the number of entries
+        // written since the last one.
+        let mut restarts = Vec::new();
+        let mut since_restart = 0;
+
+        let mut first_key = None;
+        let mut last_key = None;
+        let mut earliest_expiry: Option<u64> = None;
+        let mut value_counts = ValueCounts::default();
+        let mut largest_seqno = SeqNo(0);
+
+        // The hashes of the current file's user keys, if it gets a bloom filter.
+        let mut key_hashes = self
+            .config
+            .bloom_filter_levels
+            .includes(level)
+            .then(Vec::new);
+
+        let mut yield_timer = YieldTimer::new(
+            Arc::clone(&self.config.clock),
+            self.config.flush_yield_interval,
+        );
+
+        let mut range_tombstones = Some(range_tombstones);
+
+        while let Some(entry) = entries.next() {
+            let (key, val) = entry?;
+
+            if yield_timer.should_yield() {
+                glommio::executor().yield_now().await;
+                yield_timer.reset();
+            }
+
+            let (file_no, file) = match &mut current_file {
+                Some(current) => current,
+                None => current_file.insert(self.create_sstable_file()?),
+            };
+
+            let new_user_key = last_key
+                .as_ref()
+                .is_none_or(|last: &Key| last.user_key() != key.user_key());
+
+            let at_restart = current_block.is_empty()
+                || (new_user_key
+                    && (since_restart >= self.config.block_restart_interval
+                        || (self.config.restart_at_every_user_key
+                            && current_block.len()
+                                - *restarts.last().expect("block start") as usize
+                                >= MIN_RESTART_SPACING)));
+
+            if new_user_key && let Some(key_hashes) = &mut key_hashes {
+                key_hashes.push(bloom::hash(key.user_key()));
+            }
+
+            if at_restart {
+                restarts.push(current_block.len() as u32);
+                since_restart = 0;
+            }
+
+            since_restart += 1;
+
+            // Keys at restart points are decoded without the key before them.
+            let prev = last_key.as_ref().filter(|_| !at_restart);
+
+            self.config
+                .key_encoding
+                .encode_key(&key, prev, &mut current_block);
+            val.encode_into(&mut current_block);
+            value_counts.record(&val);
+            largest_seqno = largest_seqno.max(key.seqno());
+
+            if let Value::Expiring { expires_at, .. } = val {
+                earliest_expiry = Some(earliest_expiry.map_or(expires_at, |e| e.min(expires_at)));
+            }
+
+            first_key.get_or_insert_with(|| key.clone());
+            last_key = Some(key);
+
+            if current_block.len() >= self.config.block_size {
+                finish_block(&mut current_block, &restarts);
+                restarts.clear();
+
+                let stored = compress_block(&current_block, self.config.sstable_compression);
+
+                block_meta.push(BlockMeta {
+                    last_key: last_key.clone().expect(
+                        "There should be at least one key in the block if we're writing it",
+                    ),
+                    offset: file.stream_position()?,
+                    size: stored.len() as u32,
+                    checksum: Some(crc32fast::hash(&stored)),
+                });
+
+                file.write_all(&stored)?;
+
+                sstable_size += stored.len() as u64;
 
                 current_block.clear();
 
                 if sstable_size
                     + index_block_size(&block_meta) as u64
                     + std::mem::size_of::<SSTableFooter>() as u64
-                    >= (BASE_LEVEL_SIZE as u64)
+                    >= target_size
                 {
-                    self.finalize_sstable(
+                    let file_no = *file_no;
+                    let mut file = current_file.take().expect("file is open").1;
+
+                    let tombstones = match entries.peek() {
+                        None => range_tombstones.take().unwrap_or_default(),
+                        Some(_) => Vec::new(),
+                    };
+
+                    let mut file_meta = self.finalize_sstable(
                         &mut file,
                         file_no,
-                        sstable_size,
                         first_key.as_ref().expect("smallest key"),
                         last_key.as_ref().expect("largest key"),
                         &block_meta,
+                        tombstones,
                     )?;
+                    file_meta.earliest_expiry = earliest_expiry.take();
+                    file_meta.value_counts = std::mem::take(&mut value_counts);
+                    file_meta.largest_seqno = file_meta.largest_seqno.max(largest_seqno);
+                    largest_seqno = SeqNo(0);
+                    file_meta.bloom_filter = key_hashes.as_mut().map(|key_hashes| {
+                        let filter = BloomFilter::from_hashes(key_hashes);
+                        key_hashes.clear();
+                        filter
+                    });
+
+                    files.push(file_meta);
 
                     block_meta.clear();
                     sstable_size = 0;
                     first_key = None;
                     last_key = None;
-
-                    // Don't allocate a new file if this is the last entry
-                    if idx + 1 < memtable_data.len() {
-                        file_no = self.alloc_file_number()?;
-                        let new_file_name = format_file_name(file_no, SSTABLE_FILE_EXT);
-                        file = std::fs::OpenOptions::new()
-                            .create(true)
-                            .write(true)
-                            .read(true)
-                            .open(&self.config.data_dir.join("sstables").join(new_file_name))
-                            .context("Failed to create new SSTable file")?;
-                    }
                 }
             }
         }
 
-        // If first_key is None, we finalized the SSTable on the last entry and skipped
-        // creating a second one.
-        if let Some(smallest_key) = &first_key {
-            block_meta.push(BlockMeta {
-                last_key: last_key
-                    .clone()
-                    .expect("There should be at least one key in the block if we're writing it"),
-                offset: file.stream_position()?,
-                size: current_block.len() as u32,
-            });
+        if let Some((file_no, mut file)) = current_file {
+            // The last block may be empty if the previous one was flushed on the final entry.
+            if !current_block.is_empty() {
+                finish_block(&mut current_block, &restarts);
 
-            file.write_all(&current_block)?;
+                let stored = compress_block(&current_block, self.config.sstable_compression);
+
+                block_meta.push(BlockMeta {
+                    last_key: last_key.clone().expect(
+                        "There should be at least one key in the block if we're writing it",
+                    ),
+                    offset: file.stream_position()?,
+                    size: stored.len() as u32,
+                    checksum: Some(crc32fast::hash(&stored)),
+                });
 
-            sstable_size += current_block.len() as u64;
+                file.write_all(&stored)?;
+            }
 
-            self.finalize_sstable(
+            let mut file_meta = self.finalize_sstable(
                 &mut file,
                 file_no,
-                sstable_size,
-                smallest_key,
+                first_key.as_ref().expect("smallest key"),
                 last_key.as_ref().expect("largest key"),
                 &block_meta,
+                range_tombstones.take().unwrap_or_default(),
             )?;
+            file_meta.earliest_expiry = earliest_expiry;
+            file_meta.value_counts = value_counts;
+            file_meta.largest_seqno = file_meta.largest_seqno.max(largest_seqno);
+            file_meta.bloom_filter = key_hashes.as_deref().map(BloomFilter::from_hashes);
+
+            files.push(file_meta);
+        } else if let Some(range_tombstones) = range_tombstones
+            && let Some(first) = range_tombstones.first()
+        {
+            // A file with no blocks, which only exists to carry the tombstones.
+            let key = Key::user_key_lower_bound(first.start.clone());
+            let (file_no, mut file) = self.create_sstable_file()?;
+
+            files.push(self.finalize_sstable(
+                &mut file,
+                file_no,
+                &key,
+                &key,
+                &[],
+                range_tombstones,
+            )?);
         }
 
-        Ok(())
+        Ok(files)
     }
 
-    pub async fn flush_memtable(
+    async fn flush_memtable_internal(
         &mut self,
-        frozen: &glommio::sync::RwLock<VecDeque<MemTable<Frozen>>>,
+        cf: ColumnFamilyId,
+        memtable: &MemTable<Frozen>,
     ) -> anyhow::Result<()> {
-        self.flush_memtable_internal(frozen).await?;
+        memtable.debug_verify_unchanged();
+
+        if let Some(key) = memtable.duplicate() {
+            let message = format!(
+                "Memtable held more than one write of key {:?} at seqno {} in column family \
+                 {cf}",
+                key.user_key(),
+                key.seqno().get()
+            );
+
+            if self.config.paranoid_checks {
+                anyhow::bail!(message);
+            }
+
+            eprintln!("{message}, flushing the last");
+        }
+
+        let files = self
+            .write_sstables(
+                memtable
+                    .data()
+                    .iter()
+                    .map(|(key, val)| Ok((key.clone(), val.clone()))),
+                memtable.range_tombstones().to_vec(),
+                Level(0),
+                BASE_LEVEL_SIZE as u64,
+            )
+            .await?;
+
+        let level = self.flush_level(cf, &files)?;
+        let sub_level = match level {
+            Level(0) => self.l0_sub_level_for(cf, &files)?,
+            _ => 0,
+        };
+
+        for mut file_meta in files {
+            file_meta.sub_level = sub_level;
+
+            if !self.config.bloom_filter_levels.includes(level) {
+                file_meta.bloom_filter = None;
+            }
+
+            self.append_record(ManifestRecord::CreateFile {
+                cf,
+                level,
+                file_meta,
+            })?;
+        }
+
+        self.sync()?;
 
         Ok(())
     }
 
-    pub async fn max_level(&self) -> Level {
-        self.active_manifest
-            .levels
-            .keys()
-            .max()
-            .cloned()
-            .unwrap_or_else(|| Level(0))
-    }
+    /// Picks the level for newly flushed `files`: L1 if they're within
+    /// [`Config::l1_flush_max_bytes`] and overlap nothing in L0 or L1, since then no older
+    /// version of their keys is above them and L1 stays one sorted run, and L0 otherwise.
+    fn flush_level(&self, cf: ColumnFamilyId, files: &[FileMeta]) -> anyhow::Result<Level> {
+        let Some(max_bytes) = self.config.l1_flush_max_bytes else {
+            return Ok(Level(0));
+        };
 
-    pub fn iter_level(&self, level: Level) -> anyhow::Result<impl Iterator<Item = FileMeta> + '_> {
-        let level_meta = self
-            .active_manifest
-            .levels
-            .get(&level)
-            .cloned()
-            .unwrap_or_else(|| LevelMeta {
-                level,
-                files: Default::default(),
-            });
+        if !matches!(self.config.compaction_strategy, CompactionStrategy::Leveled)
+            || files.iter().map(|file| file.file_size).sum::<u64>() > max_bytes
+        {
+            return Ok(Level(0));
+        }
 
-        Ok(level_meta.files.clone().into_values())
+        let levels = &self.column_family(cf)?.levels;
+
+        for existing in [Level(0), Level(1)]
+            .iter()
+            .filter_map(|level| levels.get(level))
+            .flat_map(|level_meta| level_meta.files.values())
+        {
+            for file in files {
+                if file.overlaps_file(existing)? {
+                    return Ok(Level(0));
+                }
+            }
+        }
+
+        Ok(Level(1))
     }
+
+    /// Picks the L0 sub-level for newly flushed `files`: one above the highest sub-level
+    /// holding a file they overlap, so reads reach the new files before older overlapping
+    /// ones.
+    ///
+    /// Files don't record their seqnos, so this relies on `files` being newer than every
+    /// L0 file they overlap. Flushes keep to that by refusing to flush a memtable ahead of
+    /// an older one it overlaps (see [`SSTableManager::flush_specific`]), ingested entries
+    /// are given seqnos newer than everything, and attached files can't overlap anything.
+    fn l0_sub_level_for(&self, cf: ColumnFamilyId, files: &[FileMeta]) -> anyhow::Result<u32> {
+        let Some(l0) = self.column_family(cf)?.levels.get(&Level(0)) else {
+            return Ok(0);
+        };
+
+        let mut sub_level = None;
+
+        for existing in l0.files.values() {
+            // Without sub-levels, every flush gets a sub-level of its own.
+            let overlaps = if self.config.l0_sub_levels {
+                files
+                    .iter()
+                    .map(|file| file.overlaps_file(existing))
+                    .collect::<anyhow::Result<Vec<_>>>()?
+                    .contains(&true)
+            } else {
+                true
+            };
+
+            if overlaps {
+                sub_level = sub_level.max(Some(existing.sub_level));
+            }
+        }
+
+        Ok(sub_level.map_or(0, |sub_level| sub_level + 1))
+    }
+
+    /// Writes the oldest frozen memtable out to L0 and marks its entries as committed.
+    ///
+    /// The memtable is left in `frozen`; the caller is responsible for removing it once
+    /// this returns successfully.
+    pub async fn flush_memtable(
+        &mut self,
+        cf: ColumnFamilyId,
+        frozen: &glommio::sync::RwLock<VecDeque<MemTable<Frozen>>>,
+    ) -> anyhow::Result<()> {
+        self.flush_specific(cf, frozen, 0).await
+    }
+
+    /// Writes the frozen memtable at `index` out to L0.
+    ///
+    /// The committed seqno only advances as far as every older entry has been flushed: if
+    /// an older memtable is still waiting in `frozen`, the flushed seqnos are held back
+    /// until that one is flushed too. As with [`SSTableManager::flush_memtable`], the
+    /// caller is responsible for removing the memtable from `frozen` afterwards.
+    ///
+    /// Fails if an older memtable in `frozen` overlaps the one at `index`. Reads search
+    /// the frozen memtables before any SSTable, and newer L0 files before the older ones
+    /// they overlap, so flushing the newer versions of a key first would hide them behind
+    /// the older ones.
+    pub async fn flush_specific(
+        &mut self,
+        cf: ColumnFamilyId,
+        frozen: &glommio::sync::RwLock<VecDeque<MemTable<Frozen>>>,
+        index: usize,
+    ) -> anyhow::Result<()> {
+        let memtable = {
+            let frozen = frozen.read().await.expect("lock closed");
+
+            let memtable = frozen
+                .get(index)
+                .with_context(|| format!("No frozen memtable at index {index} to flush"))?;
+
+            if let Some(older) = frozen
+                .range(..index)
+                .position(|older| older.overlaps(memtable))
+            {
+                anyhow::bail!(
+                    "Frozen memtable {index} overlaps the older frozen memtable {older}, which \
+                     has to be flushed first"
+                );
+            }
+
+            memtable.clone()
+        };
+
+        let mut retry = Retry::new(&self.config);
+
+        loop {
+            let first_file = self.active_manifest.next_file_number;
+
+            match self.flush_memtable_internal(cf, &memtable).await {
+                Ok(()) => break,
+                Err(e) => {
+                    self.remove_unused_files_from(first_file);
+
+                    if !retry.should_retry(&e, "Flush").await {
+                        return Err(e);
+                    }
+                }
+            }
+        }
+
+        let Some(flushed) = memtable.max_seqno() else {
+            return Ok(());
+        };
+
+        let flushed = self
+            .pending_commit
+            .get(&cf)
+            .map_or(flushed, |pending| flushed.max(*pending));
+
+        // Frozen memtables are ordered oldest first and never overlap in seqno, so the
+        // oldest unflushed one bounds what can be committed.
+        let oldest_unflushed = frozen
+            .read()
+            .await
+            .expect("lock closed")
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != index)
+            .find_map(|(_, table)| table.min_seqno());
+
+        let committed = match oldest_unflushed {
+            Some(oldest) if oldest <= flushed => {
+                self.pending_commit.insert(cf, flushed);
+
+                SeqNo(oldest.get() - 1)
+            }
+            _ => {
+                self.pending_commit.remove(&cf);
+
+                flushed
+            }
+        };
+
+        if committed > self.last_committed_sequence_number(cf)? {
+            self.append_record(ManifestRecord::SetLastSeqNo {
+                cf,
+                seqno: committed,
+            })?;
+            self.sync()?;
+        }
+
+        Ok(())
+    }
+
+    pub fn block_cache(&self) -> Option<&Arc<BlockCache>> {
+        self.block_cache.as_ref()
+    }
+
+    /// The [`FileMeta::file_checksum`] of `file_no`, if it's in the manifest.
+    fn file_checksum(&self, file_no: FileNo) -> Option<u64> {
+        self.active_manifest
+            .column_families
+            .values()
+            .flat_map(|cf_meta| cf_meta.levels.values())
+            .find_map(|level_meta| level_meta.files.get(&file_no))
+            .map(|file| file.file_checksum)
+    }
+
+    /// Returns the opened SSTable for `file_no`, opening and caching it if needed.
+    pub fn table(&self, file_no: FileNo) -> anyhow::Result<Rc<SSTable>> {
+        self.open_table(file_no, self.file_checksum(file_no))
+    }
+
+    /// Like [`Self::table`], but with the file's checksum given, for a file of a manifest
+    /// that isn't active yet. Without one the file is read uncached, since there'd be
+    /// nothing to tell its blocks apart from another file's with the same number.
+    fn open_table(&self, file_no: FileNo, checksum: Option<u64>) -> anyhow::Result<Rc<SSTable>> {
+        if let Some(table) = self.open_tables.borrow().get(&file_no) {
+            return Ok(Rc::clone(table));
+        }
+
+        let path = self
+            .config
+            .data_dir
+            .join("sstables")
+            .join(format_file_name(file_no, SSTABLE_FILE_EXT));
+
+        let mut table = match self.secondary {
+            Some(_) => SSTable::open_shared(path)?,
+            None => SSTable::open(path)?,
+        };
+
+        if let Some(cache) = &self.block_cache
+            && let Some(generation) = checksum
+        {
+            table = table.with_cache(file_no, generation, Arc::clone(cache));
+        }
+
+        let table = Rc::new(table);
+
+        self.open_tables
+            .borrow_mut()
+            .insert(file_no, Rc::clone(&table));
+
+        Ok(table)
+    }
+
+    /// Returns the files that may contain `user_key`, in the order they should be searched
+    /// (newest data first).
+    fn files_for_user_key(
+        &self,
+        cf: ColumnFamilyId,
+        user_key: &bytes::Bytes,
+    ) -> anyhow::Result<Vec<FileNo>> {
+        let mut files = Vec::new();
+
+        for level_meta in self.column_family(cf)?.levels.values() {
+            // Newer files are in higher sub-levels than the older files they overlap. Outside
+            // of L0 only size-tiered compaction uses more than one sub-level.
+            let mut sub_levels = BTreeMap::<u32, Vec<(Key, Key, &FileMeta)>>::new();
+
+            for file in level_meta.files.values() {
+                let (smallest, largest) = file.key_range()?;
+
+                sub_levels
+                    .entry(file.sub_level)
+                    .or_default()
+                    .push((smallest, largest, file));
+            }
+
+            for sub_level in sub_levels.values_mut().rev() {
+                // Files in a sub-level don't overlap, so sorted by smallest key they're
+                // sorted by largest key too. The only exception is that the versions of a
+                // key may be split across adjacent files written together.
+                sub_level.sort_by(|a, b| a.0.cmp(&b.0));
+
+                let first =
+                    sub_level.partition_point(|(_, largest, _)| largest.user_key() < user_key);
+
+                for (smallest, _, file) in &sub_level[first..] {
+                    if smallest.user_key() > user_key {
+                        break;
+                    }
+
+                    if let Some(filter) = &file.bloom_filter
+                        && !filter.may_contain(user_key)
+                    {
+                        continue;
+                    }
+
+                    files.push(FileNo(file.file_number));
+                }
+            }
+        }
+
+        Ok(files)
+    }
+
+    /// Finds the newest version of `user_key` with a seqno at or below `seqno` across all
+    /// levels of `cf`.
+    pub fn get(
+        &self,
+        cf: ColumnFamilyId,
+        user_key: &bytes::Bytes,
+        seqno: SeqNo,
+        options: BlockReadOptions<'_>,
+    ) -> anyhow::Result<Option<(Key, Value)>> {
+        for file_no in self.files_for_user_key(cf, user_key)? {
+            if let Some(entry) = self.table(file_no)?.get(user_key, seqno, options)? {
+                return Ok(Some(entry));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Whether any file in `cf` covers `user_key` by its key range, without reading any.
+    pub fn may_contain(&self, cf: ColumnFamilyId, user_key: &bytes::Bytes) -> anyhow::Result<bool> {
+        Ok(!self.files_for_user_key(cf, user_key)?.is_empty())
+    }
+
+    /// Iterates over the range tombstones of every file in every level of `cf`.
+    pub fn range_tombstones(
+        &self,
+        cf: ColumnFamilyId,
+    ) -> anyhow::Result<impl Iterator<Item = &RangeTombstone>> {
+        self.range_tombstones_in_levels(cf, Level(0)..=Level(u32::MAX))
+    }
+
+    /// Iterates over the range tombstones of every file in `levels` of `cf`.
+    pub fn range_tombstones_in_levels(
+        &self,
+        cf: ColumnFamilyId,
+        levels: RangeInclusive<Level>,
+    ) -> anyhow::Result<impl Iterator<Item = &RangeTombstone>> {
+        Ok(self
+            .column_family(cf)?
+            .levels
+            .range(levels)
+            .flat_map(|(_, level_meta)| level_meta.files.values())
+            .flat_map(|file| file.range_tombstones.iter()))
+    }
+
+    /// Returns every SSTable in `cf` whose key range overlaps `range`.
+    pub fn tables_in_range(
+        &self,
+        cf: ColumnFamilyId,
+        range: &(Bound<Key>, Bound<Key>),
+    ) -> anyhow::Result<Vec<Rc<SSTable>>> {
+        self.tables_in_levels(cf, range, Level(0)..=Level(u32::MAX))
+    }
+
+    /// Returns every SSTable in `levels` of `cf` whose key range overlaps `range`.
+    pub fn tables_in_levels(
+        &self,
+        cf: ColumnFamilyId,
+        range: &(Bound<Key>, Bound<Key>),
+        levels: RangeInclusive<Level>,
+    ) -> anyhow::Result<Vec<Rc<SSTable>>> {
+        let mut tables = Vec::new();
+
+        for (_, level_meta) in self.column_family(cf)?.levels.range(levels) {
+            for file in level_meta.files.values() {
+                if file.overlaps(range)? {
+                    tables.push(self.table(FileNo(file.file_number))?);
+                }
+            }
+        }
+
+        Ok(tables)
+    }
+
+    /// Returns every SSTable of `cf` holding an entry or range tombstone with a seqno at or
+    /// above `seqno`.
+    pub fn tables_since(
+        &self,
+        cf: ColumnFamilyId,
+        seqno: SeqNo,
+    ) -> anyhow::Result<Vec<Rc<SSTable>>> {
+        self.column_family(cf)?
+            .levels
+            .values()
+            .flat_map(|level_meta| level_meta.files.values())
+            .filter(|file| file.largest_seqno >= seqno)
+            .map(|file| self.table(FileNo(file.file_number)))
+            .collect()
+    }
+
+    /// Rewrites every SSTable in `cf` that was written with an older format version,
+    /// returning the number of files rewritten.
+    ///
+    /// Each file is replaced by a single manifest sync, so an interrupted upgrade leaves
+    /// every file either fully upgraded or untouched, and running it again picks up where
+    /// it left off.
+    pub async fn upgrade_format(&mut self, cf: ColumnFamilyId) -> anyhow::Result<usize> {
+        self.rewrite_files(cf, |table| table.version() < SSTABLE_FORMAT_VERSION)
+            .await
+    }
+
+    /// Rewrites every SSTable in `cf` whose blocks aren't compressed with
+    /// [`Config::sstable_compression`], or that was written with an older format version,
+    /// returning the number of files rewritten.
+    ///
+    /// Unlike compaction, each file is rewritten on its own, in place in its level and
+    /// sub-level, so nothing is merged and its key range doesn't change. Interrupting it is
+    /// safe in the same way as [`Self::upgrade_format`].
+    pub async fn recompress(&mut self, cf: ColumnFamilyId) -> anyhow::Result<usize> {
+        let compression = self.config.sstable_compression;
+
+        self.rewrite_files(cf, |table| {
+            table.compression() != compression || table.version() < SSTABLE_FORMAT_VERSION
+        })
+        .await
+    }
+
+    /// Rewrites each SSTable in `cf` that `needs_rewrite` picks into a single new file with
+    /// the current format and configuration, returning the number of files rewritten.
+    async fn rewrite_files(
+        &mut self,
+        cf: ColumnFamilyId,
+        needs_rewrite: impl Fn(&SSTable) -> bool,
+    ) -> anyhow::Result<usize> {
+        let files = self
+            .column_family(cf)?
+            .levels
+            .iter()
+            .flat_map(|(level, level_meta)| {
+                level_meta.files.values().map(|file| (*level, file.clone()))
+            })
+            .collect::<Vec<_>>();
+
+        let mut rewritten = 0;
+
+        for (level, old) in files {
+            let file_no = FileNo(old.file_number);
+            let table = self.table(file_no)?;
+
+            if !needs_rewrite(&table) {
+                continue;
+            }
+
+            let entries = table.range(
+                (Bound::Unbounded, Bound::Unbounded),
+                BlockReadOptions {
+                    fill_cache: false,
+                    ..Default::default()
+                },
+            );
+
+            // With no size limit, the old file's entries all go to one new file, which
+            // covers the same keys and so can take its place in its sub-level.
+            let new_files = self
+                .write_sstables(entries, old.range_tombstones.clone(), level, u64::MAX)
+                .await?;
+
+            drop(table);
+
+            for mut file_meta in new_files {
+                file_meta.sub_level = old.sub_level;
+
+                self.append_record(ManifestRecord::CreateFile {
+                    cf,
+                    level,
+                    file_meta,
+                })?;
+            }
+
+            self.append_record(ManifestRecord::DeleteFile {
+                cf,
+                level,
+                file_number: old.file_number,
+            })?;
+
+            self.sync()?;
+
+            self.remove_sstable_file(file_no)?;
+
+            rewritten += 1;
+        }
+
+        Ok(rewritten)
+    }
+
+    /// Drops everything in `cf` outside of the user keys `[start, end)`, or `start` onward if
+    /// `end` is `None`. Files entirely inside the range are kept as they are, files that
+    /// straddle one of its ends are rewritten with just the part inside it, and the rest are
+    /// deleted.
+    ///
+    /// Each file is replaced by a single manifest sync, like [`Self::upgrade_format`].
+    pub async fn retain_range(
+        &mut self,
+        cf: ColumnFamilyId,
+        start: &bytes::Bytes,
+        end: Option<&bytes::Bytes>,
+    ) -> anyhow::Result<()> {
+        let in_range =
+            |user_key: &bytes::Bytes| start <= user_key && end.is_none_or(|end| user_key < end);
+
+        let files = self
+            .column_family(cf)?
+            .levels
+            .iter()
+            .flat_map(|(level, level_meta)| {
+                level_meta.files.values().map(|file| (*level, file.clone()))
+            })
+            .collect::<Vec<_>>();
+
+        for (level, old) in files {
+            let (smallest, largest) = old.key_range()?;
+
+            let inside = in_range(smallest.user_key())
+                && in_range(largest.user_key())
+                && old.range_tombstones.iter().all(|t| {
+                    start <= &t.start
+                        && match (end, &t.end) {
+                            (None, _) => true,
+                            (Some(_), None) => false,
+                            (Some(end), Some(t_end)) => t_end <= end,
+                        }
+                });
+
+            if inside {
+                continue;
+            }
+
+            let tombstones = old
+                .range_tombstones
+                .iter()
+                .filter_map(|t| {
+                    let t_start = (&t.start).max(start).clone();
+                    let t_end = match (&t.end, end) {
+                        (Some(a), Some(b)) => Some(a.min(b).clone()),
+                        (Some(a), None) | (None, Some(a)) => Some(a.clone()),
+                        (None, None) => None,
+                    };
+
+                    t_end
+                        .as_ref()
+                        .is_none_or(|t_end| t_start < *t_end)
+                        .then_some(RangeTombstone {
+                            start: t_start,
+                            end: t_end,
+                            seqno: t.seqno,
+                        })
+                })
+                .collect::<Vec<_>>();
+
+            let file_no = FileNo(old.file_number);
+            let table = self.table(file_no)?;
+
+            let entries = table.range(
+                Key::range_by_user_bounds(&(
+                    Bound::Included(start.clone()),
+                    end.map_or(Bound::Unbounded, |end| Bound::Excluded(end.clone())),
+                )),
+                BlockReadOptions {
+                    fill_cache: false,
+                    ..Default::default()
+                },
+            );
+
+            let new_files = self
+                .write_sstables(
+                    entries,
+                    tombstones,
+                    level,
+                    calculate_sstable_size(&level) as u64,
+                )
+                .await?;
+
+            drop(table);
+
+            for mut file_meta in new_files {
+                // The rewritten files cover a subset of the old file's keys, so they can
+                // take its place in its sub-level.
+                file_meta.sub_level = old.sub_level;
+
+                self.append_record(ManifestRecord::CreateFile {
+                    cf,
+                    level,
+                    file_meta,
+                })?;
+            }
+
+            self.append_record(ManifestRecord::DeleteFile {
+                cf,
+                level,
+                file_number: old.file_number,
+            })?;
+
+            self.sync()?;
+
+            self.remove_sstable_file(file_no)?;
+        }
+
+        Ok(())
+    }
+
+    /// Drops every file in `cf` whose keys and range deletions all fall below the user key
+    /// `bound`, by recording their deletion in the manifest without reading them. Files that
+    /// reach `bound` are left alone. Returns the files dropped.
+    ///
+    /// Dropping a file can expose older versions of its keys in deeper files, so the caller
+    /// has to have deleted everything below `bound` first.
+    pub fn drop_files_before(
+        &mut self,
+        cf: ColumnFamilyId,
+        bound: &bytes::Bytes,
+    ) -> anyhow::Result<Vec<(Level, FileMeta)>> {
+        let mut dropped = Vec::new();
+
+        for (level, level_meta) in &self.column_family(cf)?.levels {
+            for file in level_meta.files.values() {
+                let (_, largest) = file.key_range()?;
+
+                let below = largest.user_key() < bound
+                    && file
+                        .range_tombstones
+                        .iter()
+                        .all(|t| t.end.as_ref().is_some_and(|end| end <= bound));
+
+                if below {
+                    dropped.push((*level, file.clone()));
+                }
+            }
+        }
+
+        if dropped.is_empty() {
+            return Ok(dropped);
+        }
+
+        for (level, file) in &dropped {
+            self.append_record(ManifestRecord::DeleteFile {
+                cf,
+                level: *level,
+                file_number: file.file_number,
+            })?;
+        }
+
+        self.sync()?;
+
+        for (_, file) in &dropped {
+            self.remove_sstable_file(FileNo(file.file_number))?;
+        }
+
+        Ok(dropped)
+    }
+
+    /// Writes `entries`, which must be sorted by [`Key`], straight into new SSTables in
+    /// `level`, bypassing the memtable and WAL. Returns the number of files written.
+    ///
+    /// Outside L0 the entries mustn't overlap anything already in `cf`. In L0 the files are
+    /// put in a sub-level above any they overlap, so entries newer than everything in `cf`
+    /// shadow its older versions of their keys.
+    ///
+    /// Every entry is durable once this returns, so `seqno` is recorded as committed.
+    pub async fn ingest(
+        &mut self,
+        cf: ColumnFamilyId,
+        entries: impl Iterator<Item = anyhow::Result<(Key, Value)>>,
+        level: Level,
+        seqno: SeqNo,
+    ) -> anyhow::Result<usize> {
+        let files = self
+            .write_sstables(
+                entries,
+                Vec::new(),
+                level,
+                calculate_sstable_size(&level) as u64,
+            )
+            .await?;
+        let written = files.len();
+
+        let files = if level == Level(0) {
+            let sub_level = self.l0_sub_level_for(cf, &files)?;
+
+            files
+                .into_iter()
+                .map(|file| FileMeta { sub_level, ..file })
+                .collect()
+        } else {
+            files
+        };
+
+        for file_meta in files {
+            self.append_record(ManifestRecord::CreateFile {
+                cf,
+                level,
+                file_meta,
+            })?;
+        }
+
+        if seqno > self.last_committed_sequence_number(cf)? {
+            self.append_record(ManifestRecord::SetLastSeqNo { cf, seqno })?;
+        }
+
+        self.sync()?;
+
+        Ok(written)
+    }
+
+    /// Adds the SSTables at `paths`, written by another database, to `level` of `cf`.
+    /// Each is read through in full to validate it and to rebuild its metadata, then
+    /// hard-linked into the sstables directory, or copied if it can't be. The files at
+    /// `paths` are left in place. Returns the highest seqno in the attached files.
+    ///
+    /// The files' seqnos can't be ordered against the versions already in `cf`, so they
+    /// mustn't overlap each other or anything in `cf`: any file at any level, or any range
+    /// tombstone. The caller has to have flushed the memtables for that to be checked
+    /// here. Range deletions in the source database aren't stored in its files, so the
+    /// attached files carry none.
+    pub fn attach(
+        &mut self,
+        cf: ColumnFamilyId,
+        paths: &[std::path::PathBuf],
+        level: Level,
+    ) -> anyhow::Result<SeqNo> {
+        let mut attached = Vec::with_capacity(paths.len());
+        let mut max_seqno = SeqNo(0);
+
+        for path in paths {
+            let file_meta = self.read_external_sstable(path, level)?;
+
+            max_seqno = max_seqno.max(file_meta.largest_seqno);
+            attached.push((path, file_meta));
+        }
+
+        for (i, (path, file)) in attached.iter().enumerate() {
+            for (other_path, other) in &attached[..i] {
+                if file.overlaps_file(other)? {
+                    anyhow::bail!(
+                        "Can't attach SSTables {} and {}, whose keys overlap",
+                        other_path.display(),
+                        path.display()
+                    );
+                }
+            }
+
+            let (smallest, largest) = file.key_range()?;
+
+            for level_meta in self.column_family(cf)?.levels.values() {
+                for existing in level_meta.files.values() {
+                    if file.overlaps_file(existing)? {
+                        anyhow::bail!(
+                            "Can't attach SSTable {}, whose keys overlap SSTable {}",
+                            path.display(),
+                            existing.file_number
+                        );
+                    }
+                }
+            }
+
+            let deleted = self.range_tombstones(cf)?.any(|t| {
+                t.start <= largest.user_key()
+                    && t.end.as_ref().is_none_or(|end| end > smallest.user_key())
+            });
+
+            if deleted {
+                anyhow::bail!(
+                    "Can't attach SSTable {}, whose keys overlap a range deletion",
+                    path.display()
+                );
+            }
+        }
+
+        if level == Level(0) {
+            let files = attached
+                .iter()
+                .map(|(_, file)| file.clone())
+                .collect::<Vec<_>>();
+            let sub_level = self.l0_sub_level_for(cf, &files)?;
+
+            for (_, file) in &mut attached {
+                file.sub_level = sub_level;
+            }
+        }
+
+        let sstables_dir = self.config.data_dir.join("sstables");
+
+        for (path, file) in &mut attached {
+            let file_no = self.alloc_file_number()?;
+            let target = sstables_dir.join(format_file_name(file_no, SSTABLE_FILE_EXT));
+
+            if std::fs::hard_link(path.as_path(), &target).is_err() {
+                std::fs::copy(path.as_path(), &target).with_context(|| {
+                    format!(
+                        "Failed to copy SSTable {} to {}",
+                        path.display(),
+                        target.display()
+                    )
+                })?;
+            }
+
+            std::fs::File::open(&target)
+                .and_then(|target| target.sync_all())
+                .with_context(|| format!("Failed to sync SSTable {}", target.display()))?;
+
+            file.file_number = file_no.0;
+
+            // The file was checksummed as it was validated, so a copy that differs from it
+            // is caught before it's added to the manifest.
+            verify_file_checksum(&target, file)?;
+        }
+
+        for (_, file_meta) in attached {
+            self.append_record(ManifestRecord::CreateFile {
+                cf,
+                level,
+                file_meta,
+            })?;
+        }
+
+        if max_seqno > self.last_committed_sequence_number(cf)? {
+            self.append_record(ManifestRecord::SetLastSeqNo {
+                cf,
+                seqno: max_seqno,
+            })?;
+        }
+
+        self.sync()?;
+
+        Ok(max_seqno)
+    }
+
+    /// Validates the SSTable at `path` by reading every block, and rebuilds the metadata
+    /// it would have been written with for `level`. Returns the metadata, with no file
+    /// number yet.
+    fn read_external_sstable(
+        &self,
+        path: &std::path::Path,
+        level: Level,
+    ) -> anyhow::Result<FileMeta> {
+        let table = SSTable::open(path.to_path_buf())?;
+
+        let mut first_key = None;
+        let mut last_key: Option<Key> = None;
+        let mut max_seqno = SeqNo(0);
+        let mut earliest_expiry: Option<u64> = None;
+        let mut value_counts = ValueCounts::default();
+        let mut key_hashes = self
+            .config
+            .bloom_filter_levels
+            .includes(level)
+            .then(Vec::new);
+
+        let entries = table.range(
+            (Bound::Unbounded, Bound::Unbounded),
+            BlockReadOptions {
+                fill_cache: false,
+                ..Default::default()
+            },
+        );
+
+        for entry in entries {
+            let (key, val) =
+                entry.with_context(|| format!("Failed to read SSTable {}", path.display()))?;
+
+            if let Some(last) = &last_key {
+                if key <= *last {
+                    anyhow::bail!("SSTable {} holds keys out of order", path.display());
+                }
+
+                if last.user_key() != key.user_key()
+                    && let Some(key_hashes) = &mut key_hashes
+                {
+                    key_hashes.push(bloom::hash(key.user_key()));
+                }
+            } else if let Some(key_hashes) = &mut key_hashes {
+                key_hashes.push(bloom::hash(key.user_key()));
+            }
+
+            value_counts.record(&val);
+
+            if let Value::Expiring { expires_at, .. } = val {
+                earliest_expiry = Some(earliest_expiry.map_or(expires_at, |e| e.min(expires_at)));
+            }
+
+            max_seqno = max_seqno.max(key.seqno());
+            first_key.get_or_insert_with(|| key.clone());
+            last_key = Some(key);
+        }
+
+        let (Some(first_key), Some(last_key)) = (first_key, last_key) else {
+            anyhow::bail!("SSTable {} holds no entries", path.display());
+        };
+
+        let reader = std::fs::File::open(path)
+            .with_context(|| format!("Failed to open SSTable {}", path.display()))?;
+        let file_size = reader.metadata()?.len();
+        let file_checksum = SSTable::file_checksum(std::io::BufReader::new(reader))
+            .with_context(|| format!("Failed to read SSTable {}", path.display()))?;
+
+        let file_meta = FileMeta {
+            file_number: 0,
+            file_size,
+            smallest_key: first_key.encode_to_bytes(),
+            largest_key: last_key.encode_to_bytes(),
+            sub_level: 0,
+            earliest_expiry,
+            value_counts,
+            largest_seqno: max_seqno,
+            file_checksum,
+            range_tombstones: Vec::new(),
+            bloom_filter: key_hashes.map(|key_hashes| BloomFilter::from_hashes(&key_hashes)),
+        };
+
+        Ok(file_meta)
+    }
+
+    /// The earliest expiry of every SSTable in `cf` that holds expiring values.
+    pub fn earliest_expiries(&self, cf: ColumnFamilyId) -> anyhow::Result<Vec<u64>> {
+        Ok(self
+            .column_family(cf)?
+            .levels
+            .values()
+            .flat_map(|level_meta| level_meta.files.values())
+            .filter_map(|file| file.earliest_expiry)
+            .collect())
+    }
+
+    /// Rewrites every SSTable in `cf` holding a value that has expired, returning the
+    /// number of files rewritten.
+    ///
+    /// Expired values become tombstones, which are dropped outright in the bottom level.
+    /// Files are rewritten one at a time, so their range tombstones are carried over as is
+    /// rather than applied, since they may cover keys in neighbouring files too.
+    pub async fn reclaim_expired(
+        &mut self,
+        cf: ColumnFamilyId,
+        oldest_snapshot: Option<SeqNo>,
+    ) -> anyhow::Result<usize> {
+        let now = self.config.clock.unix_millis();
+        let deepest = self.deepest_level(cf)?;
+
+        let files = self
+            .column_family(cf)?
+            .levels
+            .iter()
+            .flat_map(|(level, level_meta)| {
+                level_meta.files.values().map(|file| (*level, file.clone()))
+            })
+            .filter(|(_, file)| file.earliest_expiry.is_some_and(|expiry| expiry <= now))
+            .collect::<Vec<_>>();
+
+        let mut reclaimed = 0;
+
+        for (level, old) in files {
+            let file_no = FileNo(old.file_number);
+            let table = self.table(file_no)?;
+
+            // Older sub-levels may still hold versions an expired value hides.
+            let bottommost = level > Level(0)
+                && Some(level) == deepest
+                && self.column_family(cf)?.levels[&level]
+                    .files
+                    .values()
+                    .all(|file| file.sub_level >= old.sub_level);
+
+            let mut entries = CompactionIterator::new(
+                MergeIterator::new(vec![Box::new(table.range(
+                    (Bound::Unbounded, Bound::Unbounded),
+                    BlockReadOptions {
+                        fill_cache: false,
+                        ..Default::default()
+                    },
+                ))]),
+                level,
+                bottommost,
+                oldest_snapshot,
+                &[],
+                self.config.compaction_filter.clone(),
+                now,
+            );
+
+            let new_files = self
+                .write_sstables(
+                    &mut entries,
+                    old.range_tombstones.clone(),
+                    level,
+                    calculate_sstable_size(&level) as u64,
+                )
+                .await?;
+
+            let counts = entries.counts();
+
+            drop(entries);
+            drop(table);
+
+            self.verify_compaction_output(std::slice::from_ref(&old), &new_files, counts)?;
+
+            for mut file_meta in new_files {
+                file_meta.sub_level = old.sub_level;
+
+                self.append_record(ManifestRecord::CreateFile {
+                    cf,
+                    level,
+                    file_meta,
+                })?;
+            }
+
+            self.append_record(ManifestRecord::DeleteFile {
+                cf,
+                level,
+                file_number: old.file_number,
+            })?;
+
+            self.sync()?;
+
+            self.remove_sstable_file(file_no)?;
+
+            reclaimed += 1;
+        }
+
+        Ok(reclaimed)
+    }
+
+    /// Merges every file in `level` of `cf`, along with the files they overlap in the next
+    /// level, into new files in the next level.
+    ///
+    /// When the next level is a single run, the level is split into the smallest
+    /// independent merges: each pulls in only the next-level files that overlap one of its
+    /// inputs, and the input files those overlap in turn, so next-level files that fall
+    /// between the inputs aren't rewritten. A file that overlaps nothing is moved down by
+    /// relabelling its level in the manifest, without rewriting it. A moved file isn't run
+    /// through the compaction filter, and keeps versions a merge into the bottom level
+    /// would drop, so files with range tombstones are always merged.
+    ///
+    /// Versions that no reader can see are dropped: `oldest_snapshot` is the seqno of the
+    /// oldest live snapshot, if there is one. The inputs are only removed from the manifest
+    /// once the outputs are synced, in the same manifest sync that adds the outputs.
+    ///
+    /// A transient IO error is retried under [`Config::io_retry`], once the files written
+    /// by the failed attempt have been removed.
+    pub async fn compact_level(
+        &mut self,
+        cf: ColumnFamilyId,
+        level: Level,
+        oldest_snapshot: Option<SeqNo>,
+    ) -> anyhow::Result<CompactionResult> {
+        let mut retry = Retry::new(&self.config);
+
+        loop {
+            let first_file = self.active_manifest.next_file_number;
+
+            match self.compact_level_once(cf, level, oldest_snapshot).await {
+                Ok(result) => return Ok(result),
+                Err(e) => {
+                    self.remove_unused_files_from(first_file);
+
+                    if !retry.should_retry(&e, "Compaction").await {
+                        return Err(e);
+                    }
+                }
+            }
+        }
+    }
+
+    async fn compact_level_once(
+        &mut self,
+        cf: ColumnFamilyId,
+        level: Level,
+        oldest_snapshot: Option<SeqNo>,
+    ) -> anyhow::Result<CompactionResult> {
+        let output_level = Level(level.0 + 1);
+        let levels = &self.column_family(cf)?.levels;
+
+        let upper = levels
+            .get(&level)
+            .map(|level_meta| level_meta.files.values().cloned().collect::<Vec<_>>())
+            .unwrap_or_default();
+
+        if upper.is_empty() {
+            return Ok(CompactionResult::default());
+        }
+
+        let lower = levels
+            .get(&output_level)
+            .map(|level_meta| level_meta.files.values().cloned().collect::<Vec<_>>())
+            .unwrap_or_default();
+
+        let bottommost = self.deepest_level(cf)? <= Some(output_level);
+
+        // A level left with several runs by size-tiered compaction is merged whole, so that
+        // it's a single run again.
+        if lower.iter().any(|file| file.sub_level != 0) {
+            return self
+                .merge_into_level(cf, level, upper, lower, bottommost, oldest_snapshot)
+                .await;
+        }
+
+        // Groups of files whose user key ranges, including their range tombstones, chain
+        // together by overlapping. Sweeping the files in order of their smallest key, a
+        // group ends at the first file that starts past everything in it so far.
+        let mut extents = upper
+            .iter()
+            .map(|file| (true, file))
+            .chain(lower.iter().map(|file| (false, file)))
+            .map(|(is_upper, file)| {
+                let (start, end) = user_key_extent(file)?;
+
+                Ok((start, end, is_upper, file.file_number))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        extents.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut groups: Vec<(Vec<u64>, Vec<u64>)> = Vec::new();
+        let mut group_end: Option<Bound<bytes::Bytes>> = None;
+
+        for (start, end, is_upper, file_number) in extents {
+            let joins_group = match &group_end {
+                Some(Bound::Unbounded) => true,
+                Some(Bound::Included(group_end)) => start <= *group_end,
+                _ => false,
+            };
+
+            if !joins_group {
+                groups.push((Vec::new(), Vec::new()));
+            }
+
+            group_end = Some(match (group_end.filter(|_| joins_group), end) {
+                (Some(Bound::Unbounded), _) | (_, Bound::Unbounded) => Bound::Unbounded,
+                (Some(Bound::Included(a)), Bound::Included(b)) => Bound::Included(a.max(b)),
+                (_, end) => end,
+            });
+
+            let (group_upper, group_lower) = groups.last_mut().expect("a group was pushed");
+
+            if is_upper {
+                group_upper.push(file_number);
+            } else {
+                group_lower.push(file_number);
+            }
+        }
+
+        let mut result = CompactionResult::default();
+        let mut moved = Vec::new();
+
+        for (group_upper, group_lower) in groups {
+            if group_upper.is_empty() {
+                continue;
+            }
+
+            let group_upper = upper
+                .iter()
+                .filter(|file| group_upper.contains(&file.file_number))
+                .cloned()
+                .collect::<Vec<_>>();
+
+            if let [file] = group_upper.as_slice()
+                && group_lower.is_empty()
+                && file.range_tombstones.is_empty()
+            {
+                moved.push(file.clone());
+                continue;
+            }
+
+            let group_lower = lower
+                .iter()
+                .filter(|file| group_lower.contains(&file.file_number))
+                .cloned()
+                .collect::<Vec<_>>();
+
+            let merged = self
+                .merge_into_level(
+                    cf,
+                    level,
+                    group_upper,
+                    group_lower,
+                    bottommost,
+                    oldest_snapshot,
+                )
+                .await?;
+            result.add(merged);
+        }
+
+        if moved.is_empty() {
+            return Ok(result);
+        }
+
+        result.files_moved = moved.len();
+
+        for mut file_meta in moved {
+            self.append_record(ManifestRecord::DeleteFile {
+                cf,
+                level,
+                file_number: file_meta.file_number,
+            })?;
+
+            file_meta.sub_level = 0;
+
+            if !self.config.bloom_filter_levels.includes(output_level) {
+                file_meta.bloom_filter = None;
+            }
+
+            self.append_record(ManifestRecord::CreateFile {
+                cf,
+                level: output_level,
+                file_meta,
+            })?;
+        }
+
+        self.sync()?;
+
+        Ok(result)
+    }
+
+    /// Merges `upper`, from `level`, and `lower`, from the level below it, into new files
+    /// in the level below.
+    async fn merge_into_level(
+        &mut self,
+        cf: ColumnFamilyId,
+        level: Level,
+        upper: Vec<FileMeta>,
+        mut lower: Vec<FileMeta>,
+        bottommost: bool,
+        oldest_snapshot: Option<SeqNo>,
+    ) -> anyhow::Result<CompactionResult> {
+        let output_level = Level(level.0 + 1);
+
+        lower.sort_by_key(|file| std::cmp::Reverse(file.sub_level));
+
+        // Newest first, so the merge prefers newer files if two somehow hold the same key.
+        // Within L0 that means the newest files first.
+        let inputs = upper
+            .into_iter()
+            .rev()
+            .map(|file| (level, file))
+            .chain(lower.into_iter().map(|file| (output_level, file)))
+            .collect::<Vec<_>>();
+
+        self.merge_files(cf, inputs, output_level, 0, bottommost, oldest_snapshot)
+            .await
+    }
+
+    /// Merges every run of `level` of `cf` into a single new run in the next level, above
+    /// the runs already there. Used by [`CompactionStrategy::SizeTiered`], where each level
+    /// is a tier of overlapping runs told apart by their sub-level.
+    ///
+    /// [`CompactionStrategy::SizeTiered`]: crate::compaction::CompactionStrategy::SizeTiered
+    ///
+    /// Retried like [`SSTableManager::compact_level`].
+    pub async fn compact_tier(
+        &mut self,
+        cf: ColumnFamilyId,
+        level: Level,
+        oldest_snapshot: Option<SeqNo>,
+    ) -> anyhow::Result<CompactionResult> {
+        let mut retry = Retry::new(&self.config);
+
+        loop {
+            let first_file = self.active_manifest.next_file_number;
+
+            match self.compact_tier_once(cf, level, oldest_snapshot).await {
+                Ok(result) => return Ok(result),
+                Err(e) => {
+                    self.remove_unused_files_from(first_file);
+
+                    if !retry.should_retry(&e, "Compaction").await {
+                        return Err(e);
+                    }
+                }
+            }
+        }
+    }
+
+    async fn compact_tier_once(
+        &mut self,
+        cf: ColumnFamilyId,
+        level: Level,
+        oldest_snapshot: Option<SeqNo>,
+    ) -> anyhow::Result<CompactionResult> {
+        let output_level = Level(level.0 + 1);
+        let levels = &self.column_family(cf)?.levels;
+
+        let mut inputs = levels
+            .get(&level)
+            .map(|level_meta| {
+                level_meta
+                    .files
+                    .values()
+                    .map(|file| (level, file.clone()))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+
+        if inputs.is_empty() {
+            return Ok(CompactionResult::default());
+        }
+
+        // Newest runs first.
+        inputs.sort_by_key(|(_, file)| std::cmp::Reverse(file.sub_level));
+
+        // Everything in a tier is newer than everything in the tiers below it.
+        let sub_level = levels
+            .get(&output_level)
+            .and_then(|level_meta| level_meta.files.values().map(|file| file.sub_level).max())
+            .map_or(0, |sub_level| sub_level + 1);
+
+        let bottommost = self.deepest_level(cf)? <= Some(level);
+
+        self.merge_files(
+            cf,
+            inputs,
+            output_level,
+            sub_level,
+            bottommost,
+            oldest_snapshot,
+        )
+        .await
+    }
+
+    /// The number of files in `level` of `cf`.
+    pub fn level_file_count(&self, cf: ColumnFamilyId, level: Level) -> anyhow::Result<usize> {
+        Ok(self
+            .column_family(cf)?
+            .levels
+            .get(&level)
+            .map_or(0, |level_meta| level_meta.files.len()))
+    }
+
+    /// The number of runs in each level of `cf`: the number of sub-levels, since the files
+    /// within a sub-level don't overlap.
+    pub fn runs_per_level(&self, cf: ColumnFamilyId) -> anyhow::Result<BTreeMap<Level, usize>> {
+        Ok(self
+            .column_family(cf)?
+            .levels
+            .iter()
+            .map(|(level, level_meta)| {
+                let runs = level_meta
+                    .files
+                    .values()
+                    .map(|file| file.sub_level)
+                    .collect::<std::collections::BTreeSet<_>>()
+                    .len();
+
+                (*level, runs)
+            })
+            .collect())
+    }
+
+    /// Merges `inputs`, newest first, into new files in `sub_level` of `output_level`, and
+    /// swaps them for the inputs in a single manifest sync.
+    async fn merge_files(
+        &mut self,
+        cf: ColumnFamilyId,
+        inputs: Vec<(Level, FileMeta)>,
+        output_level: Level,
+        sub_level: u32,
+        bottommost: bool,
+        oldest_snapshot: Option<SeqNo>,
+    ) -> anyhow::Result<CompactionResult> {
+        let tables = inputs
+            .iter()
+            .map(|(_, file)| self.table(FileNo(file.file_number)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let range_tombstones = inputs
+            .iter()
+            .flat_map(|(_, file)| file.range_tombstones.iter().cloned())
+            .collect::<Vec<_>>();
+
+        let read_options = BlockReadOptions {
+            fill_cache: false,
+            ..Default::default()
+        };
+
+        let sources = tables
+            .iter()
+            .map(|table| -> Source<'_> {
+                Box::new(table.range((Bound::Unbounded, Bound::Unbounded), read_options))
+            })
+            .collect();
+
+        let mut entries = CompactionIterator::new(
+            MergeIterator::new(sources),
+            output_level,
+            bottommost,
+            oldest_snapshot,
+            &range_tombstones,
+            self.config.compaction_filter.clone(),
+            self.config.clock.unix_millis(),
+        );
+
+        let surviving_tombstones = entries.surviving_range_tombstones();
+
+        let outputs = self
+            .write_sstables(
+                &mut entries,
+                surviving_tombstones,
+                output_level,
+                calculate_sstable_size(&output_level) as u64,
+            )
+            .await?;
+
+        let counts = entries.counts();
+
+        drop(entries);
+        drop(tables);
+
+        let input_files = inputs
+            .iter()
+            .map(|(_, file)| file.clone())
+            .collect::<Vec<_>>();
+
+        self.verify_compaction_output(&input_files, &outputs, counts)?;
+
+        let result = CompactionResult::new(&input_files, &outputs, counts);
+
+        for mut file_meta in outputs {
+            file_meta.sub_level = sub_level;
+
+            self.append_record(ManifestRecord::CreateFile {
+                cf,
+                level: output_level,
+                file_meta,
+            })?;
+        }
+
+        for (level, file) in &inputs {
+            self.append_record(ManifestRecord::DeleteFile {
+                cf,
+                level: *level,
+                file_number: file.file_number,
+            })?;
+        }
+
+        self.sync()?;
+
+        for (_, file) in inputs {
+            self.remove_sstable_file(FileNo(file.file_number))?;
+        }
+
+        Ok(result)
+    }
+
+    /// Checks the `outputs` of a compaction of `inputs` as
+    /// [`Config::compaction_verification`] asks, if it does. If they fail, they're deleted
+    /// and this fails with [`CompactionVerificationFailed`], before anything is committed.
+    fn verify_compaction_output(
+        &mut self,
+        inputs: &[FileMeta],
+        outputs: &[FileMeta],
+        counts: CompactionCounts,
+    ) -> anyhow::Result<()> {
+        let Some(verification) = self.config.compaction_verification else {
+            return Ok(());
+        };
+
+        let sstables_dir = self.config.data_dir.join("sstables");
+
+        let check = || -> Result<(), String> {
+            let describe = |e: anyhow::Error| format!("{e:#}");
+
+            let read = inputs
+                .iter()
+                .map(|file| file.value_counts.total())
+                .sum::<u64>();
+
+            if counts.read != read {
+                return Err(format!(
+                    "Read {} entries from inputs that hold {read}",
+                    counts.read
+                ));
+            }
+
+            if counts.filtered as f64 > verification.max_filtered_fraction * read as f64 {
+                return Err(format!(
+                    "The compaction filter removed {} of {read} entries, more than the {}% \
+                     allowed",
+                    counts.filtered,
+                    verification.max_filtered_fraction * 100.0
+                ));
+            }
+
+            // Files that only carry range tombstones have no point keys to bound the output.
+            let mut input_range = None::<(Key, Key)>;
+
+            for file in inputs.iter().filter(|file| file.value_counts.total() > 0) {
+                let (smallest, largest) = file.key_range().map_err(describe)?;
+
+                input_range = Some(match input_range {
+                    Some((lo, hi)) => (lo.min(smallest), hi.max(largest)),
+                    None => (smallest, largest),
+                });
+            }
+
+            let mut written = 0;
+            let mut prev_largest = None::<Key>;
+
+            for file in outputs {
+                let path =
+                    sstables_dir.join(format_file_name(FileNo(file.file_number), SSTABLE_FILE_EXT));
+
+                verify_file_checksum(&path, file).map_err(describe)?;
+
+                let table = SSTable::open(path).map_err(describe)?;
+                let options = BlockReadOptions {
+                    verify_checksums: true,
+                    fill_cache: false,
+                    ..Default::default()
+                };
+
+                if file.value_counts.total() == 0 {
+                    if table
+                        .range((Bound::Unbounded, Bound::Unbounded), options)
+                        .next()
+                        .is_some()
+                    {
+                        return Err(format!(
+                            "Output file {} holds entries, but none were written to it",
+                            file.file_number
+                        ));
+                    }
+
+                    continue;
+                }
+
+                let (smallest, largest) = file.key_range().map_err(describe)?;
+
+                if prev_largest.as_ref().is_some_and(|prev| *prev >= smallest) {
+                    return Err(format!(
+                        "Output file {} overlaps the one before it",
+                        file.file_number
+                    ));
+                }
+
+                if input_range.as_ref().is_none_or(|(lo, hi)| {
+                    smallest.user_key() < lo.user_key() || largest.user_key() > hi.user_key()
+                }) {
+                    return Err(format!(
+                        "Output file {} holds keys outside of the inputs' range",
+                        file.file_number
+                    ));
+                }
+
+                let mut entries = 0;
+                let mut prev = None::<Key>;
+
+                for entry in table.range((Bound::Unbounded, Bound::Unbounded), options) {
+                    let (key, _) = entry.map_err(describe)?;
+
+                    let in_order = match &prev {
+                        Some(prev) => *prev < key,
+                        None => key == smallest,
+                    };
+
+                    if !in_order {
+                        return Err(format!(
+                            "Output file {} has a key out of order or out of its range",
+                            file.file_number
+                        ));
+                    }
+
+                    entries += 1;
+                    prev = Some(key);
+                }
+
+                if prev.as_ref() != Some(&largest) {
+                    return Err(format!(
+                        "Output file {} doesn't end at its largest key",
+                        file.file_number
+                    ));
+                }
+
+                if entries != file.value_counts.total() {
+                    return Err(format!(
+                        "Output file {} holds {entries} entries, but {} were written to it",
+                        file.file_number,
+                        file.value_counts.total()
+                    ));
+                }
+
+                written += entries;
+                prev_largest = Some(largest);
+            }
+
+            if written != counts.written {
+                return Err(format!(
+                    "The output holds {written} entries, but {} were written",
+                    counts.written
+                ));
+            }
+
+            Ok(())
+        };
+
+        let Err(reason) = check() else {
+            return Ok(());
+        };
+
+        for file in outputs {
+            self.remove_sstable_file(FileNo(file.file_number))?;
+        }
+
+        Err(CompactionVerificationFailed { reason }.into())
+    }
+
+    /// Recomputes the whole-file checksum of every SSTable, failing with a
+    /// [`FileChecksumMismatch`] for the first that doesn't match the manifest.
+    pub fn verify_file_checksums(&self) -> anyhow::Result<()> {
+        let sstables_dir = self.config.data_dir.join("sstables");
+
+        for cf_meta in self.active_manifest.column_families.values() {
+            for level_meta in cf_meta.levels.values() {
+                for file in level_meta.files.values() {
+                    verify_file_checksum(
+                        &sstables_dir
+                            .join(format_file_name(FileNo(file.file_number), SSTABLE_FILE_EXT)),
+                        file,
+                    )?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Verifies the checksums of up to `max_blocks` SSTable blocks, read from the files
+    /// rather than the block cache, picking up where the last call left off. Files are
+    /// visited in file number order, oldest first, since those are the least likely to
+    /// have been read lately.
+    ///
+    /// The position is saved to [`SCRUB_CURSOR_FILE_NAME`] after each call, except by a
+    /// secondary handle. It isn't synced, so a crash can only cost some progress.
+    pub async fn scrub(&self, max_blocks: usize) -> anyhow::Result<ScrubReport> {
+        let cursor_path = self.config.data_dir.join(SCRUB_CURSOR_FILE_NAME);
+
+        let mut cursor = match std::fs::read_to_string(&cursor_path) {
+            Ok(cursor) => ScrubCursor::decode(&cursor).unwrap_or_default(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => ScrubCursor::default(),
+            Err(e) => return Err(e).context("Failed to read scrub cursor"),
+        };
+
+        let mut files = self
+            .active_manifest
+            .column_families
+            .iter()
+            .flat_map(|(cf, cf_meta)| {
+                cf_meta.levels.iter().flat_map(move |(level, level_meta)| {
+                    level_meta
+                        .files
+                        .values()
+                        .map(move |file| (FileNo(file.file_number), *cf, *level))
+                })
+            })
+            .collect::<Vec<_>>();
+        files.sort();
+
+        let mut report = ScrubReport::default();
+        let mut yield_timer = YieldTimer::new(
+            Arc::clone(&self.config.clock),
+            self.config.flush_yield_interval,
+        );
+
+        // The file the cursor is in, or the next one if it's been compacted away since.
+        let mut next = files.partition_point(|(file_no, ..)| *file_no < cursor.file_number);
+
+        while report.blocks_verified < max_blocks {
+            let Some(&(file_no, cf, level)) = files.get(next) else {
+                report.pass_completed = true;
+                cursor = ScrubCursor::default();
+                break;
+            };
+
+            if file_no != cursor.file_number {
+                cursor = ScrubCursor {
+                    file_number: file_no,
+                    block: 0,
+                };
+            }
+
+            let table = match self.table(file_no) {
+                Ok(table) => table,
+                Err(e) => {
+                    report.corruptions.push(Corruption {
+                        cf,
+                        level,
+                        file_number: file_no,
+                        offset: None,
+                        error: format!("{e:#}"),
+                    });
+
+                    next += 1;
+                    continue;
+                }
+            };
+
+            while cursor.block < table.index().len() && report.blocks_verified < max_blocks {
+                if yield_timer.should_yield() {
+                    glommio::executor().yield_now().await;
+                    yield_timer.reset();
+                }
+
+                if let Err(e) = table.verify_stored_block(cursor.block) {
+                    report.corruptions.push(Corruption {
+                        cf,
+                        level,
+                        file_number: file_no,
+                        offset: Some(table.index()[cursor.block].offset),
+                        error: format!("{e:#}"),
+                    });
+                }
+
+                cursor.block += 1;
+                report.blocks_verified += 1;
+            }
+
+            if cursor.block >= table.index().len() {
+                next += 1;
+            }
+        }
+
+        if !self.is_secondary() {
+            let temp_path = cursor_path.with_extension("tmp");
+
+            std::fs::write(&temp_path, cursor.encode())
+                .and_then(|()| std::fs::rename(&temp_path, &cursor_path))
+                .context("Failed to save scrub cursor")?;
+        }
+
+        Ok(report)
+    }
+
+    /// Writes a copy of the current manifest, and links every SSTable it references, into
+    /// the `manifests` and `sstables` directories under `dir`.
+    ///
+    /// SSTables are immutable, so they're hard-linked where possible and only copied if
+    /// `dir` is on another filesystem.
+    pub fn checkpoint(&self, dir: &std::path::Path) -> anyhow::Result<()> {
+        self.incremental_checkpoint(dir, &BackupState::default())
+            .map(|_| ())
+    }
+
+    /// Brings the checkpoint in `dir`, which holds the SSTables in `since`, up to date.
+    /// Only the SSTables missing from it are linked or copied in, then a fresh manifest
+    /// replaces the old one, and the SSTables it no longer references are deleted.
+    pub fn incremental_checkpoint(
+        &self,
+        dir: &std::path::Path,
+        since: &BackupState,
+    ) -> anyhow::Result<BackupState> {
+        let sstables_dir = dir.join("sstables");
+        let manifests_dir = dir.join("manifests");
+
+        std::fs::create_dir_all(&sstables_dir)
+            .context("Failed to create checkpoint sstables directory")?;
+        std::fs::create_dir_all(&manifests_dir)
+            .context("Failed to create checkpoint manifests directory")?;
+
+        let mut state = BackupState::default();
+
+        for cf_meta in self.active_manifest.column_families.values() {
+            for level_meta in cf_meta.levels.values() {
+                for file in level_meta.files.values() {
+                    state.files.insert(file.file_number);
+
+                    if since.contains(file.file_number) {
+                        continue;
+                    }
+
+                    let name = format_file_name(FileNo(file.file_number), SSTABLE_FILE_EXT);
+                    let source = self.config.data_dir.join("sstables").join(&name);
+                    let target = sstables_dir.join(&name);
+
+                    if std::fs::hard_link(&source, &target).is_err() {
+                        std::fs::copy(&source, &target).with_context(|| {
+                            format!("Failed to copy SSTable {} to checkpoint", source.display())
+                        })?;
+
+                        verify_file_checksum(&target, file)?;
+                    }
+                }
+            }
+        }
+
+        let current_path = manifests_dir.join(CURRENT_FILE_NAME);
+        let temp_path = manifests_dir.join(format!("{CURRENT_FILE_NAME}.tmp"));
+
+        let old_name = match read_current(&current_path) {
+            Ok(name) => Some(name),
+            Err(e) if is_not_found(&e) => None,
+            Err(e) => return Err(e).context("Failed to read checkpoint CURRENT file"),
+        };
+
+        // Nothing may have been allocated since the last checkpoint, in which case the
+        // next number is the old manifest's, and it can't be overwritten in place.
+        let mut manifest = self.active_manifest.clone();
+        let manifest_name = loop {
+            let (manifest_no, _) = manifest.alloc_file_number();
+            let name = format_file_name(manifest_no, MANIFEST_FILE_EXT);
+
+            if old_name.as_ref() != Some(&name) {
+                break name;
+            }
+        };
+
+        let mut manifest_file = std::fs::OpenOptions::new()
+            .create_new(true)
+            .write(true)
+            .open(manifests_dir.join(&manifest_name))
+            .context("Failed to create checkpoint manifest")?;
+
+        write_manifest_snapshot(&mut manifest_file, manifest, &self.config)
+            .context("Failed to write checkpoint manifest")?;
+
+        manifest_file
+            .sync_all()
+            .context("Failed to sync checkpoint manifest")?;
+
+        // CURRENT is written last, and replaced by renaming over it, so an interrupted
+        // checkpoint is either not a database yet, or still the previous checkpoint.
+        let mut temp_file = std::fs::File::create(&temp_path)
+            .context("Failed to create temporary checkpoint CURRENT file")?;
+
+        temp_file
+            .write_all(current_contents(&manifest_name, self.config.current_checksum).as_bytes())
+            .context("Failed to write checkpoint CURRENT file")?;
+        temp_file
+            .sync_all()
+            .context("Failed to sync checkpoint CURRENT file")?;
+
+        std::fs::rename(&temp_path, &current_path)
+            .context("Failed to replace checkpoint CURRENT file")?;
+
+        if let Some(old_name) = old_name {
+            std::fs::remove_file(manifests_dir.join(&old_name))
+                .with_context(|| format!("Failed to remove old checkpoint manifest {old_name}"))?;
+        }
+
+        for &file_number in since.files.difference(&state.files) {
+            let path = sstables_dir.join(format_file_name(FileNo(file_number), SSTABLE_FILE_EXT));
+
+            match std::fs::remove_file(&path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    return Err(e).with_context(|| {
+                        format!(
+                            "Failed to remove SSTable {} from checkpoint",
+                            path.display()
+                        )
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        Ok(state)
+    }
+
+    /// Closes and deletes an SSTable that's no longer referenced by the manifest. If a
+    /// secondary handle still has it open, deleting it is deferred until a later sync.
+    fn remove_sstable_file(&mut self, file_no: FileNo) -> anyhow::Result<()> {
+        if let Some(table) = self.open_tables.borrow_mut().remove(&file_no) {
+            table.evict_cached_blocks();
+        }
+
+        self.deferred_removals.push(file_no);
+
+        self.retry_deferred_removals()
+    }
+
+    /// The deepest level of `cf` that holds any files, if any do.
+    pub fn deepest_level(&self, cf: ColumnFamilyId) -> anyhow::Result<Option<Level>> {
+        Ok(self
+            .column_family(cf)?
+            .levels
+            .iter()
+            .filter(|(_, level_meta)| !level_meta.files.is_empty())
+            .map(|(level, _)| *level)
+            .max())
+    }
+
+    /// Every file in `cf`, along with the level it's in.
+    pub fn files(&self, cf: ColumnFamilyId) -> anyhow::Result<Vec<(Level, FileMeta)>> {
+        Ok(self
+            .column_family(cf)?
+            .levels
+            .iter()
+            .flat_map(|(level, level_meta)| {
+                level_meta.files.values().map(|file| (*level, file.clone()))
+            })
+            .collect())
+    }
+
+    /// The entries in every file, by value type.
+    pub fn value_counts(&self) -> ValueCounts {
+        self.active_manifest
+            .column_families
+            .values()
+            .flat_map(|cf| cf.levels.values())
+            .flat_map(|level| level.files.values())
+            .fold(ValueCounts::default(), |mut counts, file| {
+                counts += file.value_counts;
+                counts
+            })
+    }
+
+    /// The total size of every live SSTable in every column family, per the manifest.
+    pub fn total_file_size(&self) -> u64 {
+        self.active_manifest
+            .column_families
+            .values()
+            .flat_map(|cf| cf.levels.values())
+            .flat_map(|level| level.files.values())
+            .map(|file| file.file_size)
+            .sum()
+    }
+
+    /// The total size of the SSTables in each level, across every column family.
+    pub fn level_sizes(&self) -> BTreeMap<Level, u64> {
+        let mut sizes = BTreeMap::new();
+
+        for (level, level_meta) in self
+            .active_manifest
+            .column_families
+            .values()
+            .flat_map(|cf| cf.levels.iter())
+        {
+            *sizes.entry(*level).or_default() += level_meta
+                .files
+                .values()
+                .map(|file| file.file_size)
+                .sum::<u64>();
+        }
+
+        sizes
+    }
+
+    /// Estimates the bytes of SSTable data that aren't overwritten or deleted, from the
+    /// manifest alone. Working up from the deepest level, a file only counts if its key
+    /// range overlaps none of the files counted so far, on the assumption that overlapping
+    /// files hold versions of the same keys, and then only in proportion to the share of
+    /// its entries that aren't tombstones.
+    pub fn live_size_estimate(&self) -> anyhow::Result<u64> {
+        let mut live = 0;
+
+        for cf in self.active_manifest.column_families.values() {
+            let mut counted = Vec::<&FileMeta>::new();
+
+            for level_meta in cf.levels.values().rev() {
+                for file in level_meta.files.values() {
+                    let mut overlaps = false;
+
+                    for other in &counted {
+                        if file.overlaps_file(other)? {
+                            overlaps = true;
+                            break;
+                        }
+                    }
+
+                    if overlaps {
+                        continue;
+                    }
+
+                    let counts = file.value_counts;
+
+                    if counts.total() > 0 {
+                        live += (file.file_size as u128
+                            * (counts.total() - counts.tombstones) as u128
+                            / counts.total() as u128) as u64;
+                    }
+
+                    counted.push(file);
+                }
+            }
+        }
+
+        Ok(live)
+    }
+
+    /// The total size of the files in the sstables directory that aren't in the manifest:
+    /// ones that compaction has replaced but that haven't been deleted yet, such as while a
+    /// secondary handle still reads them.
+    pub fn obsolete_file_size(&self) -> anyhow::Result<u64> {
+        let mut size = 0;
+
+        for entry in self
+            .config
+            .data_dir
+            .join("sstables")
+            .read_dir()
+            .context("Failed to read sstables dir")?
+        {
+            let entry = entry.context("Failed to read sstables dir entry")?;
+
+            if let Some(file_no) =
+                parse_file_name(&entry.file_name().to_string_lossy(), SSTABLE_FILE_EXT)
+                && self.file_checksum(file_no).is_none()
+            {
+                // Deleted since the directory was listed.
+                if let Ok(metadata) = entry.metadata() {
+                    size += metadata.len();
+                }
+            }
+        }
+
+        Ok(size)
+    }
+
+    /// The total size of every SSTable's bloom filter, all of which are held in memory.
+    pub fn total_bloom_filter_size(&self) -> u64 {
+        self.active_manifest
+            .column_families
+            .values()
+            .flat_map(|cf| cf.levels.values())
+            .flat_map(|level| level.files.values())
+            .filter_map(|file| file.bloom_filter.as_ref())
+            .map(|filter| filter.size() as u64)
+            .sum()
+    }
+
+    pub async fn max_level(&self, cf: ColumnFamilyId) -> anyhow::Result<Level> {
+        Ok(self
+            .column_family(cf)?
+            .levels
+            .keys()
+            .max()
+            .cloned()
+            .unwrap_or(Level(0)))
+    }
+
+    pub fn iter_level(
+        &self,
+        cf: ColumnFamilyId,
+        level: Level,
+    ) -> anyhow::Result<impl Iterator<Item = FileMeta> + '_> {
+        let level_meta = self
+            .column_family(cf)?
+            .levels
+            .get(&level)
+            .cloned()
+            .unwrap_or_else(|| LevelMeta {
+                level,
+                files: Default::default(),
+            });
+
+        Ok(level_meta.files.clone().into_values())
+    }
+}
+
+/// The smallest and largest user keys `file` covers, including its range tombstones,
+/// which can reach past its point keys. The end is unbounded if a range tombstone is.
+fn user_key_extent(file: &FileMeta) -> anyhow::Result<(bytes::Bytes, Bound<bytes::Bytes>)> {
+    let (smallest, largest) = file.key_range()?;
+
+    let start = file
+        .range_tombstones
+        .iter()
+        .map(|t| &t.start)
+        .chain(std::iter::once(smallest.user_key()))
+        .min()
+        .expect("at least one start")
+        .clone();
+
+    let end = if file.range_tombstones.iter().any(|t| t.end.is_none()) {
+        Bound::Unbounded
+    } else {
+        Bound::Included(
+            file.range_tombstones
+                .iter()
+                .filter_map(|t| t.end.as_ref())
+                .chain(std::iter::once(largest.user_key()))
+                .max()
+                .expect("at least one end")
+                .clone(),
+        )
+    };
+
+    Ok((start, end))
+}
+
+/// Checks the SSTable at `path` against the whole-file checksum in `file`.
+fn verify_file_checksum(path: &std::path::Path, file: &FileMeta) -> anyhow::Result<()> {
+    let reader = std::fs::File::open(path)
+        .with_context(|| format!("Failed to open SSTable {}", path.display()))?;
+
+    let actual = SSTable::file_checksum(std::io::BufReader::new(reader))
+        .with_context(|| format!("Failed to read SSTable {}", path.display()))?;
+
+    if actual != file.file_checksum {
+        return Err(FileChecksumMismatch {
+            file_number: FileNo(file.file_number),
+            expected: file.file_checksum,
+            actual,
+        }
+        .into());
+    }
+
+    Ok(())
 }
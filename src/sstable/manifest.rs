@@ -1,38 +1,168 @@
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, ops::Bound};
 
 use anyhow::Context;
 
 use crate::{
-    key::SeqNo,
-    sstable::{manager::FileNo, Level},
+    bloom::BloomFilter,
+    column_family::{ColumnFamilyId, DEFAULT_COLUMN_FAMILY_NAME},
+    compression::Compression,
+    config::Config,
+    framed::FramedError,
+    key::{Key, SeqNo},
+    sstable::{
+        manager::{FileNo, SSTABLE_FORMAT_VERSION},
+        Level,
+    },
+    stats::ValueCounts,
+    tombstone::RangeTombstone,
 };
 
+/// The name recorded for the only key order there is: user keys compared bytewise, then
+/// newest version first.
+///
+/// That's [`Key`]'s `Ord`, which everything that orders keys relies on directly: the
+/// memtables, block seeks, the merges flushes and compactions run through
+/// [`MergeIterator`](crate::iter::MergeIterator), and the `smallest_key`/`largest_key`
+/// recorded for every file. There's no comparator to configure, and
+/// [`DatabaseOptions::check`] refuses a database recorded with any other, so none of them
+/// can meet keys in another order.
+pub const BYTEWISE_COMPARATOR: &str = "mintdb.BytewiseComparator";
+/// The name recorded for the block checksum, CRC-32.
+pub const CRC32_CHECKSUM: &str = "crc32";
+
+/// The settings a database's files can only be read correctly with, recorded in the manifest
+/// when the database is created and checked against the [`Config`] on every open.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct DatabaseOptions {
+    /// How keys are ordered. See [`BYTEWISE_COMPARATOR`].
+    pub comparator: String,
+    pub wal_compression: Compression,
+    /// How SSTable blocks are checksummed. See [`CRC32_CHECKSUM`].
+    pub checksum: String,
+    /// The SSTable format version of the build that created the database.
+    pub format_version: u32,
+}
+
+impl DatabaseOptions {
+    pub fn from_config(config: &Config) -> Self {
+        DatabaseOptions {
+            comparator: BYTEWISE_COMPARATOR.to_owned(),
+            wal_compression: config.wal_compression,
+            checksum: CRC32_CHECKSUM.to_owned(),
+            format_version: SSTABLE_FORMAT_VERSION,
+        }
+    }
+
+    /// Fails with an [`OptionsMismatch`] if a database created with these options can't be
+    /// opened with `config` by this build.
+    pub fn check(&self, config: &Config) -> Result<(), OptionsMismatch> {
+        let mismatch = |option, stored: &dyn std::fmt::Debug, configured: &dyn std::fmt::Debug| {
+            Err(OptionsMismatch {
+                option,
+                stored: format!("{stored:?}"),
+                configured: format!("{configured:?}"),
+            })
+        };
+
+        if self.comparator != BYTEWISE_COMPARATOR {
+            return mismatch("comparator", &self.comparator, &BYTEWISE_COMPARATOR);
+        }
+
+        if self.checksum != CRC32_CHECKSUM {
+            return mismatch("checksum", &self.checksum, &CRC32_CHECKSUM);
+        }
+
+        if self.format_version > SSTABLE_FORMAT_VERSION {
+            return mismatch(
+                "format version",
+                &self.format_version,
+                &SSTABLE_FORMAT_VERSION,
+            );
+        }
+
+        if self.wal_compression != config.wal_compression {
+            return mismatch(
+                "WAL compression",
+                &self.wal_compression,
+                &config.wal_compression,
+            );
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for DatabaseOptions {
+    fn default() -> Self {
+        Self::from_config(&Config::new(std::path::PathBuf::new()))
+    }
+}
+
+/// Returned when opening a database with a [`Config`] its files are incompatible with, or
+/// with a build that doesn't support the options it was created with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OptionsMismatch {
+    pub option: &'static str,
+    /// The value recorded when the database was created.
+    pub stored: String,
+    /// The value the database was opened with.
+    pub configured: String,
+}
+
+impl std::fmt::Display for OptionsMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Database was created with {} {}, but is being opened with {}",
+            self.option, self.stored, self.configured
+        )
+    }
+}
+
+impl std::error::Error for OptionsMismatch {}
+
+/// Returned by [`Manifest::validate`] for a manifest that doesn't describe a database that
+/// could have been written, such as one edited by hand.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidManifest {
+    pub reason: String,
+}
+
+impl std::fmt::Display for InvalidManifest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid manifest: {}", self.reason)
+    }
+}
+
+impl std::error::Error for InvalidManifest {}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Manifest {
     pub next_file_number: FileNo,
-    pub last_committed_sequence_number: SeqNo,
 
-    pub levels: BTreeMap<Level, LevelMeta>,
+    pub column_families: BTreeMap<ColumnFamilyId, ColumnFamilyMeta>,
+
+    pub options: DatabaseOptions,
+}
+
+impl Default for Manifest {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Manifest {
     pub fn new() -> Self {
-        // There is always at least level 0.
-        //
-        // L0 is special in that is the target for flushing memtables and also the
-        // only level where files can overlap in key range.
-        let levels = BTreeMap::from_iter(std::iter::once((
-            Level(0),
-            LevelMeta {
-                level: Level(0),
-                files: BTreeMap::new(),
-            },
+        // There is always at least the default column family.
+        let column_families = BTreeMap::from_iter(std::iter::once((
+            ColumnFamilyId::DEFAULT,
+            ColumnFamilyMeta::new(DEFAULT_COLUMN_FAMILY_NAME.to_owned()),
         )));
 
         Manifest {
             next_file_number: FileNo(0),
-            last_committed_sequence_number: SeqNo::from(0u64),
-            levels,
+            column_families,
+            options: DatabaseOptions::default(),
         }
     }
 
@@ -43,12 +173,216 @@ impl Manifest {
         (id, ManifestRecord::AllocFileNumber(id))
     }
 
-    pub fn load_from_file(file: &std::fs::File) -> anyhow::Result<Self> {
-        let reader = std::io::BufReader::new(file);
+    /// The highest seqno flushed to an SSTable in any column family.
+    pub fn last_committed_sequence_number(&self) -> SeqNo {
+        self.column_families
+            .values()
+            .map(|cf_meta| cf_meta.last_committed_sequence_number)
+            .max()
+            .unwrap_or(SeqNo::from(0u64))
+    }
+
+    /// The id that will be given to the next column family created.
+    pub fn next_column_family_id(&self) -> ColumnFamilyId {
+        self.column_families
+            .keys()
+            .next_back()
+            .map_or(ColumnFamilyId::DEFAULT, |id| ColumnFamilyId(id.0 + 1))
+    }
+
+    fn column_family_mut(&mut self, cf: ColumnFamilyId) -> anyhow::Result<&mut ColumnFamilyMeta> {
+        self.column_families
+            .get_mut(&cf)
+            .with_context(|| format!("Unknown column family {cf}"))
+    }
+
+    /// Applies a single logged change to the manifest.
+    pub fn apply(&mut self, record: ManifestRecord) -> anyhow::Result<()> {
+        match record {
+            ManifestRecord::Snapshot(manifest) => {
+                *self = manifest;
+            }
+            ManifestRecord::CreateColumnFamily { id, name } => {
+                self.column_families
+                    .entry(id)
+                    .or_insert_with(|| ColumnFamilyMeta::new(name));
+            }
+            ManifestRecord::CreateFile {
+                cf,
+                level,
+                file_meta,
+            } => {
+                self.column_family_mut(cf)?
+                    .level_mut(level)
+                    .files
+                    .insert(FileNo(file_meta.file_number), file_meta);
+            }
+            ManifestRecord::DeleteFile {
+                cf,
+                level,
+                file_number,
+            } => {
+                self.column_family_mut(cf)?
+                    .level_mut(level)
+                    .files
+                    .remove(&FileNo(file_number));
+            }
+            ManifestRecord::SetLastSeqNo { cf, seqno } => {
+                let meta = self.column_family_mut(cf)?;
+
+                meta.last_committed_sequence_number =
+                    seqno.max(meta.last_committed_sequence_number);
+            }
+            ManifestRecord::AllocFileNumber(file_no) => {
+                self.next_file_number = self
+                    .next_file_number
+                    .max(FileNo(file_no.0.saturating_add(1)));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks the invariants every manifest the database writes holds: column family names
+    /// and file numbers are unique, every file number was allocated, every file is keyed by
+    /// its own number and level, and files in the same sub-level of a level don't overlap.
+    pub fn validate(&self) -> Result<(), InvalidManifest> {
+        let invalid = |reason: String| Err(InvalidManifest { reason });
+
+        let mut names = std::collections::HashSet::new();
+        let mut file_numbers = std::collections::HashSet::new();
+
+        for (cf, cf_meta) in &self.column_families {
+            if !names.insert(&cf_meta.name) {
+                return invalid(format!(
+                    "Column family name {:?} is used twice",
+                    cf_meta.name
+                ));
+            }
+
+            for (level, level_meta) in &cf_meta.levels {
+                if level_meta.level != *level {
+                    return invalid(format!(
+                        "Level {} of column family {cf} is recorded as level {}",
+                        level.0, level_meta.level.0
+                    ));
+                }
 
-        let logs = crate::framed::read_all_framed::<_, ManifestRecord>(reader)
-            .context("Failed to read manifest records")?;
+                let mut sub_levels = BTreeMap::<u32, Vec<(Key, Key, u64)>>::new();
 
+                for (file_no, file) in &level_meta.files {
+                    if file_no.0 != file.file_number {
+                        return invalid(format!(
+                            "File {} in level {} of column family {cf} is recorded as file {}",
+                            file_no.0, level.0, file.file_number
+                        ));
+                    }
+
+                    if !file_numbers.insert(file.file_number) {
+                        return invalid(format!("File {} is listed twice", file.file_number));
+                    }
+
+                    if *file_no >= self.next_file_number {
+                        return invalid(format!(
+                            "File {} hasn't been allocated, the next file number is {}",
+                            file.file_number, self.next_file_number.0
+                        ));
+                    }
+
+                    let (smallest, largest) = file.key_range().map_err(|e| InvalidManifest {
+                        reason: format!("File {} has an invalid key: {e}", file.file_number),
+                    })?;
+
+                    if smallest > largest {
+                        return invalid(format!(
+                            "File {} has a smallest key after its largest",
+                            file.file_number
+                        ));
+                    }
+
+                    sub_levels.entry(file.sub_level).or_default().push((
+                        smallest,
+                        largest,
+                        file.file_number,
+                    ));
+                }
+
+                for (sub_level, mut files) in sub_levels {
+                    files.sort_by(|a, b| a.0.cmp(&b.0));
+
+                    for pair in files.windows(2) {
+                        let [(_, prev_largest, prev), (next_smallest, _, next)] = pair else {
+                            unreachable!("windows of 2");
+                        };
+
+                        if prev_largest >= next_smallest {
+                            return invalid(format!(
+                                "Files {prev} and {next} overlap in sub-level {sub_level} of \
+                                 level {} of column family {cf}",
+                                level.0
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Replays a manifest file, returning the manifest along with the number of records
+    /// logged after its last snapshot.
+    pub fn load_from_file(file: &std::fs::File) -> anyhow::Result<(Self, usize)> {
+        Self::load(std::io::BufReader::new(file))
+    }
+
+    /// Like [`Manifest::load_from_file`], but replays the manifest's records from `reader`.
+    pub fn load(reader: impl std::io::Read) -> anyhow::Result<(Self, usize)> {
+        let logs = match crate::framed::read_all_checksummed::<_, ManifestRecord>(reader) {
+            Err(e @ FramedError::Legacy) => {
+                return Err(e).context(
+                    "Manifest was written by an older version of mintdb, whose manifests \
+                     have a different format",
+                );
+            }
+            logs => logs.context("Failed to read manifest records")?,
+        };
+
+        // Every manifest starts with a snapshot, so one without was cut short while it was
+        // being written, and replaying it would start from an empty database.
+        if !matches!(logs.first(), Some(ManifestRecord::Snapshot(_))) {
+            anyhow::bail!("Manifest doesn't start with a snapshot");
+        }
+
+        let mut manifest = Manifest::new();
+        let mut since_snapshot = 0;
+
+        for delta in logs {
+            since_snapshot = match delta {
+                ManifestRecord::Snapshot(_) => 0,
+                _ => since_snapshot + 1,
+            };
+
+            manifest.apply(delta)?;
+        }
+
+        Ok((manifest, since_snapshot))
+    }
+}
+
+/// The SSTables and flush progress of a single column family.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ColumnFamilyMeta {
+    pub name: String,
+
+    /// The highest seqno in this column family that has been flushed to an SSTable.
+    pub last_committed_sequence_number: SeqNo,
+
+    pub levels: BTreeMap<Level, LevelMeta>,
+}
+
+impl ColumnFamilyMeta {
+    pub fn new(name: String) -> Self {
         // There is always at least level 0.
         //
         // L0 is special in that is the target for flushing memtables and also the
@@ -61,49 +395,18 @@ impl Manifest {
             },
         )));
 
-        let mut manifest = Manifest {
-            next_file_number: FileNo(0),
+        ColumnFamilyMeta {
+            name,
             last_committed_sequence_number: SeqNo::from(0u64),
             levels,
-        };
-
-        for delta in logs {
-            match delta {
-                ManifestRecord::Snapshot(new_manifest) => {
-                    manifest = new_manifest;
-                }
-                ManifestRecord::CreateFile { level, file_meta } => {
-                    manifest
-                        .levels
-                        .entry(level)
-                        .or_insert_with(|| LevelMeta {
-                            level,
-                            files: BTreeMap::new(),
-                        })
-                        .files
-                        .insert(FileNo(file_meta.file_number), file_meta);
-                }
-                ManifestRecord::DeleteFile { level, file_number } => {
-                    manifest
-                        .levels
-                        .entry(level)
-                        .or_insert_with(|| LevelMeta {
-                            level,
-                            files: BTreeMap::new(),
-                        })
-                        .files
-                        .remove(&FileNo(file_number));
-                }
-                ManifestRecord::SetLastSeqNo(seq_no) => {
-                    manifest.last_committed_sequence_number = seq_no;
-                }
-                ManifestRecord::AllocFileNumber(file_no) => {
-                    manifest.next_file_number = manifest.next_file_number.max(file_no + 1);
-                }
-            }
         }
+    }
 
-        Ok(manifest)
+    fn level_mut(&mut self, level: Level) -> &mut LevelMeta {
+        self.levels.entry(level).or_insert_with(|| LevelMeta {
+            level,
+            files: BTreeMap::new(),
+        })
     }
 }
 
@@ -120,23 +423,112 @@ pub struct FileMeta {
 
     pub smallest_key: bytes::Bytes,
     pub largest_key: bytes::Bytes,
+
+    /// The sub-level the file belongs to. Files in the same sub-level never overlap, and
+    /// a file is always in a higher sub-level than the older files it overlaps. Outside of
+    /// L0, only size-tiered compaction puts files anywhere but sub-level 0.
+    pub sub_level: u32,
+
+    /// The earliest time any expiring value in the file expires, in milliseconds since the
+    /// Unix epoch, so that expired data can be reclaimed without reading every file.
+    pub earliest_expiry: Option<u64>,
+
+    /// The number of point entries in the file of each value type.
+    pub value_counts: ValueCounts,
+
+    /// The highest seqno of any entry or range tombstone in the file, so that a reader
+    /// after recent writes only, like [`Database::tail`](crate::Database::tail), can skip
+    /// the files that hold none.
+    pub largest_seqno: SeqNo,
+
+    /// An xxHash64 of the whole file, so a copy of it can be verified without decoding its
+    /// blocks. See [`SSTable::file_checksum`](crate::sstable::sstable::SSTable::file_checksum).
+    pub file_checksum: u64,
+
+    /// Range tombstones that were flushed along with this file. These aren't bounded by
+    /// `smallest_key`/`largest_key`, which only cover the file's point entries.
+    pub range_tombstones: Vec<RangeTombstone>,
+
+    /// A filter over the file's user keys, if its level gets one under
+    /// [`Config::bloom_filter_levels`](crate::config::Config::bloom_filter_levels).
+    pub bloom_filter: Option<BloomFilter>,
+}
+
+impl FileMeta {
+    /// Decodes the smallest and largest keys in the file.
+    pub fn key_range(&self) -> anyhow::Result<(Key, Key)> {
+        let smallest = Key::decode_from(&mut self.smallest_key.clone())
+            .context("Failed to decode smallest key")?;
+        let largest = Key::decode_from(&mut self.largest_key.clone())
+            .context("Failed to decode largest key")?;
+
+        Ok((smallest, largest))
+    }
+
+    pub fn may_contain_user_key(&self, user_key: &bytes::Bytes) -> anyhow::Result<bool> {
+        let (smallest, largest) = self.key_range()?;
+
+        Ok(smallest.user_key() <= user_key
+            && user_key <= largest.user_key()
+            && self
+                .bloom_filter
+                .as_ref()
+                .is_none_or(|filter| filter.may_contain(user_key)))
+    }
+
+    /// Whether any user key could be in both this file and `other`.
+    pub fn overlaps_file(&self, other: &FileMeta) -> anyhow::Result<bool> {
+        let (smallest, largest) = self.key_range()?;
+        let (other_smallest, other_largest) = other.key_range()?;
+
+        Ok(smallest.user_key() <= other_largest.user_key()
+            && other_smallest.user_key() <= largest.user_key())
+    }
+
+    pub fn overlaps(&self, range: &(Bound<Key>, Bound<Key>)) -> anyhow::Result<bool> {
+        let (smallest, largest) = self.key_range()?;
+
+        let after_start = match &range.0 {
+            Bound::Included(start) => largest >= *start,
+            Bound::Excluded(start) => largest > *start,
+            Bound::Unbounded => true,
+        };
+
+        let before_end = match &range.1 {
+            Bound::Included(end) => smallest <= *end,
+            Bound::Excluded(end) => smallest < *end,
+            Bound::Unbounded => true,
+        };
+
+        Ok(after_start && before_end)
+    }
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub enum ManifestRecord {
     Snapshot(Manifest),
+    /// Adds a new, empty column family.
+    CreateColumnFamily {
+        id: ColumnFamilyId,
+        name: String,
+    },
     /// Creates a new file in the manifest.
     CreateFile {
+        cf: ColumnFamilyId,
         level: Level,
         file_meta: FileMeta,
     },
     /// Deletes a file from the manifest.
     DeleteFile {
+        cf: ColumnFamilyId,
         level: Level,
         file_number: u64,
     },
-    /// Sets the last committed sequence number
-    SetLastSeqNo(SeqNo),
+    /// Sets the last committed sequence number of a column family
+    SetLastSeqNo {
+        cf: ColumnFamilyId,
+        seqno: SeqNo,
+    },
     /// Marks the allocation of a new file number.
     ///
     /// Set next_file_number to max(next_file_number, self.0).
@@ -1,5 +1,6 @@
 pub mod manager;
 pub mod manifest;
+#[allow(clippy::module_inception)]
 pub mod sstable;
 
 #[derive(
@@ -1,45 +1,389 @@
-use std::path::PathBuf;
+use std::{
+    hash::Hasher,
+    io::{Read, Seek, SeekFrom},
+    ops::Bound,
+    path::PathBuf,
+    sync::Arc,
+    time::Instant,
+};
 
-pub const BLOCK_SIZE: usize = 1024 * 16; // 16 KB
+use anyhow::Context;
+use bytes::{Buf, BufMut};
 
+use crate::{
+    cache::{BlockCache, BlockId},
+    clock::Clock,
+    compression::Compression,
+    key::{Key, SeqNo},
+    options::Timeout,
+    sstable::manager::{FileNo, SSTABLE_FORMAT_VERSION, SSTABLE_MAGIC},
+    stats::ReadStats,
+    value::Value,
+};
+
+/// The default [`Config::block_size`](crate::config::Config::block_size) (16KB).
+pub const BLOCK_SIZE: usize = 1024 * 16;
+
+/// Encoded size of [`SSTableFooter`].
+pub const FOOTER_SIZE: usize = std::mem::size_of::<SSTableFooter>();
+
+/// The first format version whose index records each block's checksum.
+pub const BLOCK_CHECKSUM_VERSION: u32 = 1;
+
+/// The first format version whose blocks end with their restart points.
+pub const RESTART_POINTS_VERSION: u32 = 2;
+
+/// The minimum number of bytes of entries between two restart points placed by
+/// [`Config::restart_at_every_user_key`](crate::config::Config::restart_at_every_user_key),
+/// which bounds their overhead when keys are small.
+pub const MIN_RESTART_SPACING: usize = 64;
+
+/// The first format version whose footer records the [`KeyEncoding`] of its blocks.
+pub const KEY_ENCODING_VERSION: u32 = 3;
+
+/// The first format version whose footer records the [`Compression`] of its blocks.
+pub const COMPRESSION_VERSION: u32 = 4;
+
+/// How keys are stored within SSTable blocks, configured with
+/// [`Config::key_encoding`](crate::config::Config::key_encoding) and recorded in each
+/// file's footer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[repr(u32)]
+pub enum KeyEncoding {
+    /// Every key is stored in full.
+    #[default]
+    Plain = 0,
+    /// For keys that end in a big-endian `u64`, like sequential ids or timestamps. A key
+    /// that differs from the previous key in its block only in that integer is stored as
+    /// the integer's and the seqno's differences from the previous key's. Other keys, and
+    /// keys at restart points, are stored in full.
+    DeltaU64 = 1,
+}
+
+/// Marks a key stored in full under [`KeyEncoding::DeltaU64`].
+const FULL_KEY: u8 = 0;
+/// Marks a key stored as deltas from the previous key under [`KeyEncoding::DeltaU64`].
+const DELTA_KEY: u8 = 1;
+
+/// The size of the integer suffix delta-encoded by [`KeyEncoding::DeltaU64`].
+const DELTA_INT_SIZE: usize = std::mem::size_of::<u64>();
+
+impl KeyEncoding {
+    pub fn from_u32(value: u32) -> Option<Self> {
+        match value {
+            x if x == KeyEncoding::Plain as u32 => Some(KeyEncoding::Plain),
+            x if x == KeyEncoding::DeltaU64 as u32 => Some(KeyEncoding::DeltaU64),
+            _ => None,
+        }
+    }
+
+    /// Encodes `key` into a block, relative to `prev`, the key before it in the block, or
+    /// `None` if `key` is at a restart point.
+    pub fn encode_key(&self, key: &Key, prev: Option<&Key>, buf: &mut bytes::BytesMut) {
+        if *self == KeyEncoding::Plain {
+            return key.encode_into(buf);
+        }
+
+        match prev.and_then(|prev| Some((prev, int_suffix(prev, key)?))) {
+            Some((prev, (prev_int, int))) if int >= prev_int => {
+                buf.put_u8(DELTA_KEY);
+                put_varint(buf, int - prev_int);
+                put_varint(
+                    buf,
+                    zigzag(key.seqno().get().wrapping_sub(prev.seqno().get())),
+                );
+            }
+            _ => {
+                buf.put_u8(FULL_KEY);
+                key.encode_into(buf);
+            }
+        }
+    }
+
+    /// Decodes a key written by [`KeyEncoding::encode_key`] with the same `prev`.
+    pub fn decode_key(&self, buf: &mut bytes::Bytes, prev: Option<&Key>) -> anyhow::Result<Key> {
+        if *self == KeyEncoding::Plain {
+            return Key::decode_from(buf);
+        }
+
+        match buf.try_get_u8()? {
+            FULL_KEY => Key::decode_from(buf),
+            DELTA_KEY => {
+                let Some(prev) = prev.filter(|prev| prev.user_key().len() >= DELTA_INT_SIZE) else {
+                    anyhow::bail!("Delta-encoded key has no previous key to decode against");
+                };
+
+                let user_key = prev.user_key();
+                let prefix_len = user_key.len() - DELTA_INT_SIZE;
+                let prev_int = (&user_key[prefix_len..]).get_u64();
+
+                let int = prev_int
+                    .checked_add(get_varint(buf)?)
+                    .context("Delta-encoded key overflows")?;
+                let seqno = prev.seqno().get().wrapping_add(unzigzag(get_varint(buf)?));
+
+                let mut key = bytes::BytesMut::with_capacity(user_key.len());
+                key.put_slice(&user_key[..prefix_len]);
+                key.put_u64(int);
+
+                Ok(Key::new(key.freeze(), SeqNo(seqno)))
+            }
+            tag => anyhow::bail!("Unknown key encoding tag {tag}"),
+        }
+    }
+}
+
+/// The integer suffixes of `prev` and `key`, if they differ in nothing else.
+fn int_suffix(prev: &Key, key: &Key) -> Option<(u64, u64)> {
+    let (prev, key) = (prev.user_key(), key.user_key());
+
+    if prev.len() != key.len() || key.len() < DELTA_INT_SIZE {
+        return None;
+    }
+
+    let prefix_len = key.len() - DELTA_INT_SIZE;
+
+    if prev[..prefix_len] != key[..prefix_len] {
+        return None;
+    }
+
+    Some((
+        (&prev[prefix_len..]).get_u64(),
+        (&key[prefix_len..]).get_u64(),
+    ))
+}
+
+fn put_varint(buf: &mut bytes::BytesMut, mut value: u64) {
+    while value >= 0x80 {
+        buf.put_u8(value as u8 | 0x80);
+        value >>= 7;
+    }
+
+    buf.put_u8(value as u8);
+}
+
+fn get_varint(buf: &mut bytes::Bytes) -> anyhow::Result<u64> {
+    let mut value = 0u64;
+
+    for shift in (0..64).step_by(7) {
+        let byte = buf.try_get_u8()?;
+        value |= u64::from(byte & 0x7f) << shift;
+
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+
+    anyhow::bail!("Varint is too long")
+}
+
+/// Maps a two's complement difference to an unsigned one, keeping small negative
+/// differences small.
+fn zigzag(value: u64) -> u64 {
+    (value << 1) ^ ((value as i64 >> 63) as u64)
+}
+
+fn unzigzag(value: u64) -> u64 {
+    (value >> 1) ^ (value & 1).wrapping_neg()
+}
+
+/// Appends `restarts`, the offsets of a block's restart points, to the block's entries.
+///
+/// A restart point is an entry a seek can start decoding from. Other than at the start of
+/// the block, restart points are only ever placed on the first version of a user key.
+pub fn finish_block(block: &mut bytes::BytesMut, restarts: &[u32]) {
+    for restart in restarts {
+        block.put_u32_le(*restart);
+    }
+
+    block.put_u32_le(restarts.len() as u32);
+}
+
+/// The bytes stored on disk for the finished block `block`: the block compressed with
+/// `compression`, or the block itself if it's [`Compression::None`]. Block checksums and
+/// sizes are of these bytes.
+pub fn compress_block(block: &[u8], compression: Compression) -> std::borrow::Cow<'_, [u8]> {
+    match compression {
+        Compression::None => std::borrow::Cow::Borrowed(block),
+        compression => std::borrow::Cow::Owned(compression.compress(block)),
+    }
+}
+
+/// A data block split into its entries and its restart points.
+struct Block {
+    entries: bytes::Bytes,
+    restarts: Vec<u32>,
+    key_encoding: KeyEncoding,
+}
+
+impl Block {
+    /// Returns the offset of the entry to start decoding from to find the first entry at
+    /// or after `target`.
+    fn seek(&self, target: &Key) -> anyhow::Result<usize> {
+        // Keys at restart points never depend on the key before them.
+        let key_at = |offset: u32| {
+            self.key_encoding
+                .decode_key(&mut self.entries.slice(offset as usize..), None)
+        };
+
+        // The first restart point at or after `target`.
+        let (mut lo, mut hi) = (0, self.restarts.len());
+
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+
+            if key_at(self.restarts[mid])? < *target {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+
+        // Past the start of the block, a restart point is the first version of its user
+        // key, so if it's `target`'s user key nothing before it can be at or after `target`.
+        if lo > 0
+            && lo < self.restarts.len()
+            && key_at(self.restarts[lo])?.user_key() == target.user_key()
+        {
+            return Ok(self.restarts[lo] as usize);
+        }
+
+        Ok(self.restarts[lo.saturating_sub(1)] as usize)
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct BlockMeta {
     pub(crate) last_key: crate::key::Key,
     pub(crate) offset: u64,
     pub(crate) size: u32,
+    /// CRC32 of the block's bytes as stored, or `None` in files from before
+    /// [`BLOCK_CHECKSUM_VERSION`], whose index didn't record one.
+    pub(crate) checksum: Option<u32>,
 }
 
+impl BlockMeta {
+    /// The last key in the block.
+    pub fn last_key(&self) -> &Key {
+        &self.last_key
+    }
+
+    /// The block's offset within the file.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// The block's size in bytes as stored, including its restart points. For a compressed
+    /// block, that's its compressed size.
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// CRC32 of the block's bytes as stored, if the file records one.
+    pub fn checksum(&self) -> Option<u32> {
+        self.checksum
+    }
+
+    pub fn encode_into(&self, buf: &mut bytes::BytesMut) {
+        self.last_key.encode_into(buf);
+        buf.put_u64_le(self.offset);
+        buf.put_u32_le(self.size);
+        buf.put_u32_le(
+            self.checksum
+                .expect("blocks are always written with a checksum"),
+        );
+    }
+
+    /// Decodes an index entry of a file written with format `version`.
+    pub fn decode_from(buf: &mut bytes::Bytes, version: u32) -> anyhow::Result<Self> {
+        let last_key = Key::decode_from(buf)?;
+        let offset = buf.try_get_u64_le()?;
+        let size = buf.try_get_u32_le()?;
+        let checksum = if version >= BLOCK_CHECKSUM_VERSION {
+            Some(buf.try_get_u32_le()?)
+        } else {
+            None
+        };
+
+        Ok(BlockMeta {
+            last_key,
+            offset,
+            size,
+            checksum,
+        })
+    }
+}
+
+#[derive(Debug)]
 #[repr(C)]
 pub struct SSTableFooter {
     pub(crate) index_offset: u64,
     pub(crate) index_size: u64,
-    pub(crate) _reserved1: u64,
-    pub(crate) _reserved2: u32,
+    /// The [`KeyEncoding`] of the file's blocks. Always 0 (plain) before
+    /// [`KEY_ENCODING_VERSION`].
+    pub(crate) key_encoding: u32,
+    /// The [`Compression`] of the file's blocks. Always 0 (none) before
+    /// [`COMPRESSION_VERSION`].
+    pub(crate) compression: u32,
+    /// The format version the file was written with.
+    pub(crate) version: u32,
     pub(crate) magic: u32,
 }
 
 impl SSTableFooter {
+    /// The offset of the index within the file.
+    pub fn index_offset(&self) -> u64 {
+        self.index_offset
+    }
+
+    /// The size of the index in bytes.
+    pub fn index_size(&self) -> u64 {
+        self.index_size
+    }
+
+    /// The format version the file was written with.
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// The encoding of the keys in the file's blocks.
+    pub fn key_encoding(&self) -> KeyEncoding {
+        KeyEncoding::from_u32(self.key_encoding).unwrap_or_default()
+    }
+
+    /// The compression of the file's blocks.
+    pub fn compression(&self) -> Compression {
+        u8::try_from(self.compression)
+            .ok()
+            .and_then(Compression::from_u8)
+            .unwrap_or_default()
+    }
+
     pub fn encode_into(&self, mut buf: impl bytes::BufMut) {
         buf.put_u64_le(self.index_offset);
         buf.put_u64_le(self.index_size);
-        buf.put_u64_le(self._reserved1);
-        buf.put_u32_le(self._reserved2);
+        buf.put_u32_le(self.key_encoding);
+        buf.put_u32_le(self.compression);
+        buf.put_u32_le(self.version);
         buf.put_u32_le(self.magic);
     }
 
-    pub fn decode_from(mut buf: impl bytes::Buf) -> Self {
-        let index_offset = buf.get_u64_le();
-        let index_size = buf.get_u64_le();
-        let _reserved1 = buf.get_u64_le();
-        let _reserved2 = buf.get_u32_le();
-        let magic = buf.get_u32_le();
+    pub fn decode_from(mut buf: impl bytes::Buf) -> anyhow::Result<Self> {
+        let index_offset = buf.try_get_u64_le()?;
+        let index_size = buf.try_get_u64_le()?;
+        let key_encoding = buf.try_get_u32_le()?;
+        let compression = buf.try_get_u32_le()?;
+        let version = buf.try_get_u32_le()?;
+        let magic = buf.try_get_u32_le()?;
 
-        SSTableFooter {
+        Ok(SSTableFooter {
             index_offset,
             index_size,
-            _reserved1,
-            _reserved2,
+            key_encoding,
+            compression,
+            version,
             magic,
-        }
+        })
     }
 }
 
@@ -48,15 +392,556 @@ pub fn index_block_size(entries: &[BlockMeta]) -> usize {
     // - last_key (variable size)
     // - offset (8 bytes)
     // - size (4 bytes)
+    // - checksum (4 bytes)
     let entries: usize = entries
         .iter()
-        .map(|e| e.last_key.encoded_len() + 8 + 4)
+        .map(|e| e.last_key.encoded_len() + 8 + 4 + 4)
         .sum();
 
     entries + 4 /* length (u32) */
 }
 
+/// How blocks should be read from an [`SSTable`].
+#[derive(Debug, Clone, Copy)]
+pub struct BlockReadOptions<'a> {
+    pub verify_checksums: bool,
+    pub fill_cache: bool,
+    /// Where to count the tables and blocks touched by the read, if anywhere.
+    pub stats: Option<&'a ReadStats>,
+    /// When, by the clock, to stop reading blocks and fail with [`Timeout`].
+    pub deadline: Option<(Instant, &'a dyn Clock)>,
+}
+
+impl Default for BlockReadOptions<'_> {
+    fn default() -> Self {
+        BlockReadOptions {
+            verify_checksums: true,
+            fill_cache: true,
+            stats: None,
+            deadline: None,
+        }
+    }
+}
+
+#[derive(Debug)]
 pub struct SSTable {
     path: PathBuf,
     mem: memmap2::Mmap,
+    index: Vec<BlockMeta>,
+    version: u32,
+    key_encoding: KeyEncoding,
+    compression: Compression,
+    /// The block cache and this table's file number and generation within it.
+    cache: Option<(FileNo, u64, Arc<BlockCache>)>,
+    /// The file, shared-locked for as long as the table is open, if it was opened with
+    /// [`SSTable::open_shared`].
+    _shared_lock: Option<std::fs::File>,
+}
+
+impl SSTable {
+    pub fn open(path: PathBuf) -> anyhow::Result<Self> {
+        let file = std::fs::File::open(&path)
+            .with_context(|| format!("Failed to open SSTable {}", path.display()))?;
+
+        Self::from_file(path, &file)
+    }
+
+    /// Opens the table like [`SSTable::open`], and holds a shared lock on its file until
+    /// it's dropped. The primary handle doesn't delete a file while it's locked, so this is
+    /// how secondary handles keep the files they read from.
+    pub fn open_shared(path: PathBuf) -> anyhow::Result<Self> {
+        let file = std::fs::File::open(&path)
+            .with_context(|| format!("Failed to open SSTable {}", path.display()))?;
+
+        file.lock_shared()
+            .with_context(|| format!("Failed to lock SSTable {}", path.display()))?;
+
+        let mut table = Self::from_file(path, &file)?;
+        table._shared_lock = Some(file);
+
+        Ok(table)
+    }
+
+    fn from_file(path: PathBuf, file: &std::fs::File) -> anyhow::Result<Self> {
+        // SAFETY: SSTables are immutable once written, and are only deleted after they've
+        // been removed from the manifest.
+        let mem = unsafe { memmap2::Mmap::map(file) }
+            .with_context(|| format!("Failed to mmap SSTable {}", path.display()))?;
+
+        let footer = Self::read_footer(std::io::Cursor::new(&mem[..]))
+            .with_context(|| format!("Failed to read SSTable {} footer", path.display()))?;
+        let index = Self::read_index(std::io::Cursor::new(&mem[..]))
+            .with_context(|| format!("Failed to read SSTable {} index", path.display()))?;
+
+        Ok(SSTable {
+            path,
+            mem,
+            index,
+            version: footer.version,
+            key_encoding: footer.key_encoding(),
+            compression: footer.compression(),
+            cache: None,
+            _shared_lock: None,
+        })
+    }
+
+    /// Computes the whole-file checksum recorded in
+    /// [`FileMeta::file_checksum`](crate::sstable::manifest::FileMeta::file_checksum): an
+    /// xxHash64 of every byte `reader` yields.
+    pub fn file_checksum(mut reader: impl Read) -> std::io::Result<u64> {
+        let mut hasher = twox_hash::XxHash64::with_seed(0);
+        let mut buf = vec![0; 64 * 1024];
+
+        loop {
+            match reader.read(&mut buf)? {
+                0 => return Ok(hasher.finish()),
+                n => hasher.write(&buf[..n]),
+            }
+        }
+    }
+
+    /// Reads and validates the footer of the SSTable in `reader`, which can be any seekable
+    /// reader rather than a file that can be mmapped.
+    pub fn read_footer(mut reader: impl Read + Seek) -> anyhow::Result<SSTableFooter> {
+        let len = reader.seek(SeekFrom::End(0))?;
+
+        if len < FOOTER_SIZE as u64 {
+            anyhow::bail!("SSTable is too small to contain a footer");
+        }
+
+        let mut buf = [0; FOOTER_SIZE];
+        reader.seek(SeekFrom::Start(len - FOOTER_SIZE as u64))?;
+        reader.read_exact(&mut buf)?;
+
+        let footer = SSTableFooter::decode_from(&buf[..])?;
+
+        if footer.magic != SSTABLE_MAGIC {
+            anyhow::bail!(
+                "SSTable has bad magic {:#x} (expected {:#x})",
+                footer.magic,
+                SSTABLE_MAGIC
+            );
+        }
+
+        if footer.version > SSTABLE_FORMAT_VERSION {
+            anyhow::bail!(
+                "SSTable has format version {}, but only versions up to {} are supported",
+                footer.version,
+                SSTABLE_FORMAT_VERSION
+            );
+        }
+
+        if KeyEncoding::from_u32(footer.key_encoding).is_none() {
+            anyhow::bail!("SSTable has unknown key encoding {}", footer.key_encoding);
+        }
+
+        if u8::try_from(footer.compression)
+            .ok()
+            .and_then(Compression::from_u8)
+            .is_none()
+        {
+            anyhow::bail!("SSTable has unknown compression {}", footer.compression);
+        }
+
+        let index_end = footer.index_offset.saturating_add(footer.index_size);
+
+        if index_end > len - FOOTER_SIZE as u64 {
+            anyhow::bail!("SSTable index is out of bounds");
+        }
+
+        Ok(footer)
+    }
+
+    /// Reads the footer and then the index of the SSTable in `reader`, returning the
+    /// metadata of every block. See [`SSTable::read_footer`].
+    pub fn read_index(mut reader: impl Read + Seek) -> anyhow::Result<Vec<BlockMeta>> {
+        let footer = Self::read_footer(&mut reader)?;
+
+        let mut index_buf = vec![0; footer.index_size as usize];
+        reader.seek(SeekFrom::Start(footer.index_offset))?;
+        reader.read_exact(&mut index_buf)?;
+
+        let mut index_buf = bytes::Bytes::from(index_buf);
+
+        let count = index_buf.try_get_u32_le()?;
+
+        (0..count)
+            .map(|_| BlockMeta::decode_from(&mut index_buf, footer.version))
+            .collect()
+    }
+
+    /// Caches blocks read from this table in `cache` under `file_no` and `generation` (see
+    /// [`BlockId::generation`]).
+    pub fn with_cache(mut self, file_no: FileNo, generation: u64, cache: Arc<BlockCache>) -> Self {
+        self.cache = Some((file_no, generation, cache));
+        self
+    }
+
+    /// Drops this table's blocks from its cache, if it has one.
+    pub fn evict_cached_blocks(&self) {
+        if let Some((file_no, generation, cache)) = &self.cache {
+            cache.remove_file(*file_no, *generation);
+        }
+    }
+
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    pub fn index(&self) -> &[BlockMeta] {
+        &self.index
+    }
+
+    /// The format version the table was written with.
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// The compression of the table's blocks.
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    /// Reads the block at `idx` in the index, going through the block cache if there is one.
+    /// Compressed blocks are returned, and cached, decompressed.
+    ///
+    /// The block's bounds come from the index alone, never from the configured block size,
+    /// which the file may not have been written with.
+    pub fn read_block(
+        &self,
+        idx: usize,
+        options: BlockReadOptions<'_>,
+    ) -> anyhow::Result<bytes::Bytes> {
+        if let Some((deadline, clock)) = options.deadline
+            && clock.now() >= deadline
+        {
+            return Err(Timeout.into());
+        }
+
+        let meta = &self.index[idx];
+
+        let cache_id = self.cache.as_ref().map(|(file_no, generation, cache)| {
+            (
+                BlockId {
+                    file_no: *file_no,
+                    generation: *generation,
+                    offset: meta.offset,
+                },
+                cache,
+            )
+        });
+
+        if let Some((id, cache)) = &cache_id
+            && let Some((block, verified)) = cache.get(*id)
+        {
+            if let Some(stats) = options.stats {
+                stats.record_block(true);
+            }
+
+            if options.verify_checksums && !verified {
+                // The cached block may have been decompressed, so the checksum is of what's
+                // on disk instead.
+                self.verify_block(meta, self.stored_block(meta)?)?;
+                cache.mark_verified(*id);
+            }
+
+            return Ok(block);
+        }
+
+        let stored = self.stored_block(meta)?;
+
+        if let Some(stats) = options.stats {
+            stats.record_block(false);
+        }
+
+        if options.verify_checksums {
+            self.verify_block(meta, stored)?;
+        }
+
+        let block = match self.compression {
+            Compression::None => bytes::Bytes::copy_from_slice(stored),
+            compression => {
+                bytes::Bytes::from(compression.decompress(stored).with_context(|| {
+                    format!(
+                        "Failed to decompress block at offset {} in SSTable {}",
+                        meta.offset,
+                        self.path.display()
+                    )
+                })?)
+            }
+        };
+
+        if options.fill_cache
+            && let Some((id, cache)) = cache_id
+        {
+            cache.insert(id, block.clone(), options.verify_checksums);
+        }
+
+        Ok(block)
+    }
+
+    /// Verifies the checksum of the block at `idx` in the index as it's stored in the file,
+    /// bypassing the block cache, which may hold a copy verified before the file was
+    /// damaged.
+    pub fn verify_stored_block(&self, idx: usize) -> anyhow::Result<()> {
+        let meta = &self.index[idx];
+
+        self.verify_block(meta, self.stored_block(meta)?)
+    }
+
+    /// The bytes of the block described by `meta` as they're stored in the file.
+    fn stored_block(&self, meta: &BlockMeta) -> anyhow::Result<&[u8]> {
+        let start = meta.offset as usize;
+
+        let Some(end) = start
+            .checked_add(meta.size as usize)
+            .filter(|end| *end <= self.mem.len())
+        else {
+            anyhow::bail!(
+                "Block at offset {} in SSTable {} is out of bounds",
+                meta.offset,
+                self.path.display()
+            );
+        };
+
+        Ok(&self.mem[start..end])
+    }
+
+    /// Checks `block` against the checksum in `meta`. Blocks of files from before
+    /// [`BLOCK_CHECKSUM_VERSION`] have none, so they pass unchecked.
+    fn verify_block(&self, meta: &BlockMeta, block: &[u8]) -> anyhow::Result<()> {
+        let Some(expected) = meta.checksum else {
+            return Ok(());
+        };
+
+        let checksum = crc32fast::hash(block);
+
+        if checksum != expected {
+            anyhow::bail!(
+                "Checksum mismatch for block at offset {} in SSTable {} (expected {:#x}, got {:#x})",
+                meta.offset,
+                self.path.display(),
+                expected,
+                checksum
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Splits a block read with [`SSTable::read_block`] into its entries and restart points.
+    fn split_block(&self, mut block: bytes::Bytes) -> anyhow::Result<Block> {
+        if self.version < RESTART_POINTS_VERSION {
+            return Ok(Block {
+                entries: block,
+                restarts: vec![0],
+                key_encoding: self.key_encoding,
+            });
+        }
+
+        let restarts_len = (&block[block.len().saturating_sub(4)..]).try_get_u32_le()? as usize;
+        let trailer_len = (restarts_len + 1) * 4;
+
+        if restarts_len == 0 || trailer_len > block.len() {
+            anyhow::bail!(
+                "Block in SSTable {} has an invalid restart point count {restarts_len}",
+                self.path.display()
+            );
+        }
+
+        let mut trailer = block.split_off(block.len() - trailer_len);
+        let restarts = (0..restarts_len)
+            .map(|_| trailer.try_get_u32_le())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if restarts
+            .iter()
+            .any(|restart| *restart as usize >= block.len())
+        {
+            anyhow::bail!(
+                "Block in SSTable {} has a restart point out of bounds",
+                self.path.display()
+            );
+        }
+
+        Ok(Block {
+            entries: block,
+            restarts,
+            key_encoding: self.key_encoding,
+        })
+    }
+
+    /// Returns the index of the first block that may contain `key`.
+    fn seek_block(&self, key: &Key) -> usize {
+        self.index.partition_point(|meta| meta.last_key < *key)
+    }
+
+    /// Finds the newest version of `user_key` with a seqno at or below `seqno`.
+    pub fn get(
+        &self,
+        user_key: &bytes::Bytes,
+        seqno: SeqNo,
+        options: BlockReadOptions<'_>,
+    ) -> anyhow::Result<Option<(Key, Value)>> {
+        if let Some(stats) = options.stats {
+            stats.record_sstable();
+        }
+
+        // Versions of a user key are ordered newest first, so the first entry at or after
+        // the start of this range is the newest visible version, if it's in the range.
+        let visible = Key::range_at_seqno(user_key.clone(), seqno);
+        let target = visible.start();
+
+        let block_idx = self.seek_block(target);
+
+        if block_idx >= self.index.len() {
+            return Ok(None);
+        }
+
+        let block = self.split_block(self.read_block(block_idx, options)?)?;
+        let mut entries = block.entries.slice(block.seek(target)?..);
+        let mut prev = None;
+
+        while entries.has_remaining() {
+            let key = self.key_encoding.decode_key(&mut entries, prev.as_ref())?;
+            let value = Value::decode_from(&mut entries)?;
+
+            if key < *target {
+                prev = Some(key);
+                continue;
+            }
+
+            if visible.contains(&key) {
+                return Ok(Some((key, value)));
+            }
+
+            break;
+        }
+
+        Ok(None)
+    }
+
+    /// Iterates over every entry within `range`, in [`Key`] order.
+    pub fn range<'a>(
+        &'a self,
+        range: (Bound<Key>, Bound<Key>),
+        options: BlockReadOptions<'a>,
+    ) -> SSTableIter<'a> {
+        if let Some(stats) = options.stats {
+            stats.record_sstable();
+        }
+
+        let block_idx = match &range.0 {
+            Bound::Included(key) | Bound::Excluded(key) => self.seek_block(key),
+            Bound::Unbounded => 0,
+        };
+
+        SSTableIter {
+            table: self,
+            block_idx,
+            block: bytes::Bytes::new(),
+            prev: None,
+            range,
+            options,
+            seeked: false,
+            done: false,
+        }
+    }
+}
+
+pub struct SSTableIter<'a> {
+    table: &'a SSTable,
+    /// Index of the next block to read.
+    block_idx: usize,
+    /// The unread remainder of the current block.
+    block: bytes::Bytes,
+    /// The last key decoded from the current block, which the next may be encoded against.
+    prev: Option<Key>,
+    range: (Bound<Key>, Bound<Key>),
+    options: BlockReadOptions<'a>,
+    /// Whether the first block has been read, which is the only one that needs seeking into.
+    seeked: bool,
+    done: bool,
+}
+
+impl SSTableIter<'_> {
+    fn next_entry(&mut self) -> anyhow::Result<Option<(Key, Value)>> {
+        loop {
+            if !self.block.has_remaining() {
+                if self.block_idx >= self.table.index.len() {
+                    return Ok(None);
+                }
+
+                let block = self
+                    .table
+                    .split_block(self.table.read_block(self.block_idx, self.options)?)?;
+
+                let start = match &self.range.0 {
+                    Bound::Included(key) | Bound::Excluded(key) if !self.seeked => {
+                        block.seek(key)?
+                    }
+                    _ => 0,
+                };
+
+                self.block = block.entries.slice(start..);
+                self.prev = None;
+                self.block_idx += 1;
+                self.seeked = true;
+
+                continue;
+            }
+
+            let key = self
+                .table
+                .key_encoding
+                .decode_key(&mut self.block, self.prev.as_ref())?;
+            let value = Value::decode_from(&mut self.block)?;
+
+            self.prev = Some(key.clone());
+
+            let after_start = match &self.range.0 {
+                Bound::Included(start) => key >= *start,
+                Bound::Excluded(start) => key > *start,
+                Bound::Unbounded => true,
+            };
+
+            if !after_start {
+                continue;
+            }
+
+            let before_end = match &self.range.1 {
+                Bound::Included(end) => key <= *end,
+                Bound::Excluded(end) => key < *end,
+                Bound::Unbounded => true,
+            };
+
+            if !before_end {
+                return Ok(None);
+            }
+
+            return Ok(Some((key, value)));
+        }
+    }
+}
+
+impl Iterator for SSTableIter<'_> {
+    type Item = anyhow::Result<(Key, Value)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        match self.next_entry() {
+            Ok(Some(entry)) => Some(Ok(entry)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
 }
@@ -0,0 +1,43 @@
+//! Notifications for writes held up by backpressure, configured with
+//! [`Config::on_write_stall`](crate::config::Config::on_write_stall).
+
+/// Why writes are being held up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WriteStallReason {
+    /// L0 of the column family being written to reached
+    /// [`Config::l0_stop_writes_trigger`](crate::config::Config::l0_stop_writes_trigger)
+    /// files, so the write waits for L0 to be compacted.
+    TooManyL0Files,
+    /// The column family being written to reached
+    /// [`Config::max_frozen_memtables`](crate::config::Config::max_frozen_memtables) frozen
+    /// memtables, so the write waits for them to be flushed.
+    TooManyMemtables,
+    /// The frozen memtables of every column family together held more than
+    /// [`Config::max_frozen_bytes`](crate::config::Config::max_frozen_bytes), so the write
+    /// waits for them to be flushed.
+    TooManyFrozenBytes,
+    /// The database is over
+    /// [`Config::max_total_bytes`](crate::config::Config::max_total_bytes), so writes other
+    /// than deletes fail with [`DiskBudgetExceeded`](crate::db::DiskBudgetExceeded) until
+    /// space is freed.
+    DiskBudget,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WriteStall {
+    /// A write has started waiting.
+    Started(WriteStallReason),
+    /// Writes are going through again.
+    ///
+    /// For [`WriteStallReason::DiskBudget`], that's only noticed by the next write that
+    /// gets through, rather than as soon as space is freed.
+    Ended(WriteStallReason),
+}
+
+/// A hook told when writes start and stop stalling, so an application can shed load or
+/// raise an alert rather than only seeing latency.
+///
+/// It's called from the write that stalls, so it should return quickly.
+pub trait WriteStallListener: std::fmt::Debug + Send + Sync {
+    fn on_write_stall(&self, stall: WriteStall);
+}
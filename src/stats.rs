@@ -0,0 +1,170 @@
+//! Per-operation and database-wide statistics.
+
+use std::{cell::Cell, collections::BTreeMap};
+
+use crate::{key::SeqNo, sstable::Level, value::Value};
+
+/// The on-disk work done by a single read, returned by
+/// [`Database::get_with_stats`](crate::Database::get_with_stats) and
+/// [`Database::scan_paginated_with_stats`](crate::Database::scan_paginated_with_stats).
+///
+/// A read that touches many SSTables for a single key is a sign of heavy L0 overlap or a
+/// tree that needs compacting.
+#[derive(Debug, Default)]
+pub struct ReadStats {
+    sstables: Cell<usize>,
+    blocks: Cell<usize>,
+    cache_hits: Cell<usize>,
+}
+
+impl ReadStats {
+    /// The number of SSTables consulted.
+    pub fn sstables(&self) -> usize {
+        self.sstables.get()
+    }
+
+    /// The number of data blocks read, including those served from the block cache.
+    pub fn blocks(&self) -> usize {
+        self.blocks.get()
+    }
+
+    /// The number of blocks that were served from the block cache.
+    pub fn cache_hits(&self) -> usize {
+        self.cache_hits.get()
+    }
+
+    pub(crate) fn record_sstable(&self) {
+        self.sstables.set(self.sstables.get() + 1);
+    }
+
+    pub(crate) fn record_block(&self, cached: bool) {
+        self.blocks.set(self.blocks.get() + 1);
+
+        if cached {
+            self.cache_hits.set(self.cache_hits.get() + 1);
+        }
+    }
+}
+
+/// The number of entries of each value type in one SSTable, recorded in its
+/// [`FileMeta`](crate::sstable::manifest::FileMeta), or summed across several.
+///
+/// A file that's mostly tombstones is taking up space for data that's already gone, and
+/// is worth compacting.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ValueCounts {
+    pub data: u64,
+    /// Values written with a TTL, whether or not they've expired yet.
+    pub expiring: u64,
+    /// Point tombstones. Range tombstones are kept separately, and aren't counted.
+    pub tombstones: u64,
+    /// Values of a type written by a newer version.
+    pub unsupported: u64,
+}
+
+impl ValueCounts {
+    pub(crate) fn record(&mut self, value: &Value) {
+        match value {
+            Value::Data(_) => self.data += 1,
+            Value::Expiring { .. } => self.expiring += 1,
+            Value::Tombstone => self.tombstones += 1,
+            Value::Unsupported { .. } => self.unsupported += 1,
+        }
+    }
+
+    /// The number of entries counted.
+    pub fn total(&self) -> u64 {
+        self.data + self.expiring + self.tombstones + self.unsupported
+    }
+
+    /// The fraction of the entries counted that are tombstones, or 0 if there are none.
+    pub fn tombstone_ratio(&self) -> f64 {
+        match self.total() {
+            0 => 0.0,
+            total => self.tombstones as f64 / total as f64,
+        }
+    }
+}
+
+impl std::ops::AddAssign for ValueCounts {
+    fn add_assign(&mut self, rhs: Self) {
+        self.data += rhs.data;
+        self.expiring += rhs.expiring;
+        self.tombstones += rhs.tombstones;
+        self.unsupported += rhs.unsupported;
+    }
+}
+
+/// Database-wide statistics, returned by [`Database::stats`](crate::Database::stats).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DbStats {
+    /// The total size of every SSTable, in bytes. Doesn't include the WAL or manifest.
+    pub total_bytes: u64,
+    /// The configured [`Config::max_total_bytes`](crate::config::Config::max_total_bytes).
+    pub max_total_bytes: Option<u64>,
+    /// The entries in every SSTable, by value type. Entries still in memtables aren't
+    /// counted until they're flushed.
+    pub value_counts: ValueCounts,
+    /// The memory held by SSTable bloom filters, in bytes. See
+    /// [`Config::bloom_filter_levels`](crate::config::Config::bloom_filter_levels).
+    pub bloom_filter_bytes: u64,
+    /// The memory held by the memtables and the block cache, in bytes.
+    pub memory_bytes: u64,
+    /// The part of [`memory_bytes`](Self::memory_bytes) held by frozen memtables waiting to
+    /// be flushed. See [`Config::max_frozen_bytes`](crate::config::Config::max_frozen_bytes).
+    pub frozen_memtable_bytes: u64,
+    /// The configured
+    /// [`Config::total_memory_budget`](crate::config::Config::total_memory_budget).
+    pub memory_budget: Option<u64>,
+    /// The seqno of the oldest live [`Snapshot`](crate::snapshot::Snapshot), if there are
+    /// any. Compaction keeps every version it can see, so one that stays far behind the
+    /// latest seqno is usually a leaked snapshot holding onto overwritten data.
+    pub oldest_snapshot: Option<SeqNo>,
+    /// The size the active memtables are frozen and flushed at, in bytes, as currently
+    /// picked by [`Config::memtable_size`](crate::config::Config::memtable_size).
+    pub memtable_flush_target: u64,
+}
+
+impl DbStats {
+    /// Whether the SSTables are over the configured budget.
+    pub fn over_budget(&self) -> bool {
+        self.max_total_bytes
+            .is_some_and(|max_total_bytes| self.total_bytes > max_total_bytes)
+    }
+}
+
+/// The database's footprint on disk, returned by
+/// [`Database::disk_usage`](crate::Database::disk_usage), with every size in bytes.
+///
+/// The physical sizes count every byte of every file, including versions that have been
+/// overwritten or deleted but not yet compacted away. [`live_bytes`](Self::live_bytes)
+/// estimates how much of that is current data, so the gap between it and
+/// [`total`](Self::total) is roughly what compacting everything would reclaim.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DiskUsage {
+    /// The SSTables in the manifest, by level, across every column family.
+    pub sstable_bytes_by_level: BTreeMap<Level, u64>,
+    /// SSTables that compaction has replaced but that haven't been deleted yet.
+    pub obsolete_sstable_bytes: u64,
+    /// The WAL, including space pre-allocated for appends. See
+    /// [`Config::wal_preallocate_chunk`](crate::config::Config::wal_preallocate_chunk).
+    pub wal_bytes: u64,
+    /// Every manifest kept, and the file pointing at the current one. See
+    /// [`Config::manifest_retention`](crate::config::Config::manifest_retention).
+    pub manifest_bytes: u64,
+    /// An estimate of the SSTable bytes holding data that hasn't been overwritten or
+    /// deleted, made from the manifest without reading any files.
+    pub live_bytes: u64,
+}
+
+impl DiskUsage {
+    /// The SSTables in the manifest, across every level.
+    pub fn sstable_bytes(&self) -> u64 {
+        self.sstable_bytes_by_level.values().sum()
+    }
+
+    /// Everything on disk.
+    pub fn total(&self) -> u64 {
+        self.sstable_bytes() + self.obsolete_sstable_bytes + self.wal_bytes + self.manifest_bytes
+    }
+}
@@ -0,0 +1,63 @@
+//! Tailing the log of committed writes, for replication and change data capture.
+
+use std::{
+    collections::VecDeque,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::{
+    channel::Receiver, column_family::ColumnFamilyId, key::Key, value::Value, wal::WalRecord,
+};
+
+/// The number of live writes a [`Tail`] buffers for a consumer that isn't keeping up. A
+/// consumer that falls further behind than this is cut off, and its stream ends.
+pub const TAIL_BUFFER_CAPACITY: usize = 1024;
+
+/// A stream of committed writes in seqno order, returned by
+/// [`Database::tail`](crate::Database::tail).
+///
+/// Yields the writes that were already committed when it was created, then each new write
+/// as it's committed. It ends if the database is dropped, or if the consumer falls more
+/// than [`TAIL_BUFFER_CAPACITY`] writes behind, in which case a new tail can be started
+/// from the seqno after the last record received.
+pub struct Tail {
+    backlog: VecDeque<WalRecord>,
+    live: Receiver<WalRecord>,
+}
+
+impl Tail {
+    pub(crate) fn new(backlog: Vec<WalRecord>, live: Receiver<WalRecord>) -> Self {
+        Tail {
+            backlog: backlog.into(),
+            live,
+        }
+    }
+}
+
+impl futures_lite::Stream for Tail {
+    type Item = WalRecord;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<WalRecord>> {
+        match self.backlog.pop_front() {
+            Some(record) => Poll::Ready(Some(record)),
+            None => self.live.poll_recv(cx),
+        }
+    }
+}
+
+/// The record that would have written `value` at `key`, for entries read back from an
+/// SSTable or memtable. `None` for values of a type this version can't read.
+pub(crate) fn entry_record(cf: ColumnFamilyId, key: Key, value: Value) -> Option<WalRecord> {
+    match value {
+        Value::Data(val) => Some(WalRecord::Put { cf, key, val }),
+        Value::Expiring { data, expires_at } => Some(WalRecord::PutExpiring {
+            cf,
+            key,
+            val: data,
+            expires_at,
+        }),
+        Value::Tombstone => Some(WalRecord::Delete { cf, key }),
+        Value::Unsupported { .. } => None,
+    }
+}
@@ -0,0 +1,37 @@
+//! Range tombstones, which delete every version of every key in a range older than
+//! the tombstone itself.
+
+use crate::key::{Key, SeqNo};
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct RangeTombstone {
+    /// Inclusive start of the deleted range.
+    pub start: bytes::Bytes,
+    /// Exclusive end of the deleted range, or `None` if it extends to the end of the keyspace.
+    pub end: Option<bytes::Bytes>,
+    pub seqno: SeqNo,
+}
+
+impl RangeTombstone {
+    pub fn covers(&self, user_key: &bytes::Bytes) -> bool {
+        self.start <= user_key && self.end.as_ref().is_none_or(|end| user_key < end)
+    }
+
+    /// Returns true if this tombstone is visible at `read_seqno` and hides `key`.
+    pub fn deletes(&self, key: &Key, read_seqno: SeqNo) -> bool {
+        self.seqno <= read_seqno && key.seqno() < self.seqno && self.covers(key.user_key())
+    }
+}
+
+/// Returns the seqno of the newest tombstone visible at `read_seqno` that covers `user_key`.
+pub fn max_covering_seqno<'a>(
+    tombstones: impl IntoIterator<Item = &'a RangeTombstone>,
+    user_key: &bytes::Bytes,
+    read_seqno: SeqNo,
+) -> Option<SeqNo> {
+    tombstones
+        .into_iter()
+        .filter(|t| t.seqno <= read_seqno && t.covers(user_key))
+        .map(|t| t.seqno)
+        .max()
+}
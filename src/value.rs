@@ -5,6 +5,7 @@ use bytes::{Buf, BufMut};
 pub enum ValueType {
     Data = 0,
     Tombstone = 1,
+    Expiring = 2,
 }
 
 impl ValueType {
@@ -12,6 +13,7 @@ impl ValueType {
         match value {
             x if x == ValueType::Data as u8 => Some(ValueType::Data),
             x if x == ValueType::Tombstone as u8 => Some(ValueType::Tombstone),
+            x if x == ValueType::Expiring as u8 => Some(ValueType::Expiring),
             _ => None,
         }
     }
@@ -21,47 +23,124 @@ impl ValueType {
 pub enum Value {
     Data(bytes::Bytes),
     Tombstone,
+    /// Data that reads as deleted once the wall clock passes `expires_at`, in milliseconds
+    /// since the Unix epoch.
+    Expiring {
+        data: bytes::Bytes,
+        expires_at: u64,
+    },
+    /// A value of a type this version doesn't know, written by a newer version. It can't
+    /// be read, but compaction carries it over unchanged rather than losing it.
+    Unsupported {
+        tag: u8,
+        payload: bytes::Bytes,
+    },
 }
 
 impl Value {
-    pub fn value_type(&self) -> ValueType {
+    /// The value's type, or `None` if it's [`Value::Unsupported`].
+    pub fn value_type(&self) -> Option<ValueType> {
         match self {
-            Value::Data(_) => ValueType::Data,
-            Value::Tombstone => ValueType::Tombstone,
+            Value::Data(_) => Some(ValueType::Data),
+            Value::Tombstone => Some(ValueType::Tombstone),
+            Value::Expiring { .. } => Some(ValueType::Expiring),
+            Value::Unsupported { .. } => None,
         }
     }
 
-    pub fn encode_into(&self, buf: &mut bytes::BytesMut) {
-        buf.put_u8(self.value_type() as u8);
+    /// The value's data, or `None` for a tombstone or an unsupported value. Expired data is
+    /// still returned.
+    pub fn data(&self) -> Option<&bytes::Bytes> {
+        match self {
+            Value::Data(data) | Value::Expiring { data, .. } => Some(data),
+            Value::Tombstone | Value::Unsupported { .. } => None,
+        }
+    }
+
+    /// Replaces the value's data, keeping its expiry.
+    pub fn with_data(self, data: bytes::Bytes) -> Self {
+        match self {
+            Value::Expiring { expires_at, .. } => Value::Expiring { data, expires_at },
+            _ => Value::Data(data),
+        }
+    }
+
+    /// Whether the value has expired as of `now`, in milliseconds since the Unix epoch.
+    pub fn is_expired(&self, now: u64) -> bool {
+        matches!(self, Value::Expiring { expires_at, .. } if *expires_at <= now)
+    }
 
+    /// The data a read as of `now` sees, or `None` if the value is a tombstone or has
+    /// expired.
+    pub fn live_data(self, now: u64) -> Option<bytes::Bytes> {
+        match self {
+            Value::Data(data) => Some(data),
+            Value::Expiring { data, expires_at } if now < expires_at => Some(data),
+            _ => None,
+        }
+    }
+
+    /// Fails if the value is [`Value::Unsupported`], which can't be read.
+    pub fn ensure_supported(&self) -> anyhow::Result<()> {
+        match self {
+            Value::Unsupported { tag, .. } => anyhow::bail!(
+                "Unsupported value type {tag}, which was probably written by a newer version"
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    /// Every value but a tombstone is encoded as its tag, the length of its payload, and
+    /// the payload, so that values of types added later can be skipped over.
+    pub fn encode_into(&self, buf: &mut bytes::BytesMut) {
         match self {
             Value::Data(data) => {
+                buf.put_u8(ValueType::Data as u8);
                 buf.put_u32_le(data.len() as u32);
                 buf.put_slice(data);
             }
-            Value::Tombstone => {}
+            Value::Tombstone => buf.put_u8(ValueType::Tombstone as u8),
+            Value::Expiring { data, expires_at } => {
+                buf.put_u8(ValueType::Expiring as u8);
+                buf.put_u32_le((data.len() + std::mem::size_of::<u64>()) as u32);
+                buf.put_u64_le(*expires_at);
+                buf.put_slice(data);
+            }
+            Value::Unsupported { tag, payload } => {
+                buf.put_u8(*tag);
+                buf.put_u32_le(payload.len() as u32);
+                buf.put_slice(payload);
+            }
         }
     }
 
     pub fn decode_from(buf: &mut bytes::Bytes) -> anyhow::Result<Self> {
-        let value_type = ValueType::from_u8(buf.try_get_u8()?)
-            .ok_or_else(|| anyhow::anyhow!("Invalid value type"))?;
+        let tag = buf.try_get_u8()?;
+
+        if tag == ValueType::Tombstone as u8 {
+            return Ok(Value::Tombstone);
+        }
+
+        let len = buf.try_get_u32_le()? as usize;
 
-        match value_type {
-            ValueType::Data => {
-                let len = buf.try_get_u32_le()?;
+        if buf.remaining() < len {
+            anyhow::bail!("Buffer underflow while decoding value of type {tag}");
+        }
 
-                if buf.remaining() < len as usize {
-                    return Err(anyhow::anyhow!(
-                        "Buffer underflow while decoding Value::Data"
-                    ));
-                }
+        let mut payload = buf.copy_to_bytes(len);
 
-                let data = buf.copy_to_bytes(len as usize);
+        match ValueType::from_u8(tag) {
+            Some(ValueType::Data) => Ok(Value::Data(payload)),
+            Some(ValueType::Expiring) => {
+                let expires_at = payload.try_get_u64_le()?;
 
-                Ok(Value::Data(data))
+                Ok(Value::Expiring {
+                    data: payload,
+                    expires_at,
+                })
             }
-            ValueType::Tombstone => Ok(Value::Tombstone),
+            Some(ValueType::Tombstone) => unreachable!("handled above"),
+            None => Ok(Value::Unsupported { tag, payload }),
         }
     }
 }
@@ -1,34 +1,222 @@
 use std::{
+    collections::BTreeMap,
     io::{Seek, Write},
+    os::{fd::AsRawFd, unix::fs::FileExt},
     path::PathBuf,
 };
 
 use anyhow::Context;
 use bytes::Bytes;
 
-use crate::key::Key;
+use crate::{
+    column_family::ColumnFamilyId,
+    compression::Compression,
+    config::Config,
+    framed::{FrameReader, FramedError},
+    key::{Key, SeqNo},
+    recovery::{OpenReport, RecoveryAction},
+};
 
 const WAL_MAX_SIZE: u64 = 1024 * 64 /* 64KB */;
 
+/// The most writes held back under [`WritePolicy::WriteBehind`] before the write that
+/// reaches it logs them all.
+pub const WRITE_BEHIND_BATCH_SIZE: usize = 1024;
+
+/// The order a write goes to the WAL and its memtable in, which decides what a crash can
+/// lose. Only matters with [`Config::wal_enabled`] set.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WritePolicy {
+    /// Each write is appended to the WAL before it's applied to its memtable, and with
+    /// [`WriteOptions::sync`](crate::options::WriteOptions::sync) set it's fsynced before
+    /// the write returns. A crash loses no synced write. Unsynced writes are in the OS page
+    /// cache, so they survive the process crashing but not the machine, along with every
+    /// later write.
+    #[default]
+    WriteAhead,
+    /// Each write is applied to its memtable, and acknowledged, before it's logged. Writes
+    /// wait in memory until [`Database::sync_wal`](crate::Database::sync_wal) appends and
+    /// fsyncs them as one batch, as do the write that makes [`WRITE_BEHIND_BATCH_SIZE`] of
+    /// them, a flush, and closing or dropping the database. A write with
+    /// [`WriteOptions::sync`] set is logged as under [`WritePolicy::WriteAhead`] instead,
+    /// along with the writes waiting before it, and fsynced before it's applied. It's set by
+    /// default, so only writes made with it unset are held back.
+    ///
+    /// Nothing logs the batch in the background: the write that fills it pays for the append
+    /// and fsync, and nothing bounds how long a write waits, so call `sync_wal` on a timer
+    /// to bound that. If logging a full batch fails, the write that filled it still succeeds,
+    /// having already been applied; the batch keeps waiting and the next `sync_wal` fails.
+    ///
+    /// A crash of the process or the machine loses every write since the last of those,
+    /// even ones that were acknowledged and read back. Writes are logged in the order they
+    /// were made, so what's lost is always the most recent writes: the database recovers to
+    /// the state it was in at some earlier write, with batches still all or nothing.
+    ///
+    /// [`WriteOptions::sync`]: crate::options::WriteOptions::sync
+    WriteBehind,
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub enum WalRecord {
-    Put { key: Key, val: Bytes },
-    Delete { key: Key },
+    Put {
+        cf: ColumnFamilyId,
+        key: Key,
+        val: Bytes,
+    },
+    Delete {
+        cf: ColumnFamilyId,
+        key: Key,
+    },
+    /// Deletes `[key.user_key(), end)`, or everything from `key.user_key()` onward if `end`
+    /// is `None`.
+    DeleteRange {
+        cf: ColumnFamilyId,
+        key: Key,
+        end: Option<Bytes>,
+    },
+    /// Records written by a single [`WriteBatch`](crate::batch::WriteBatch). Logging them as
+    /// one record makes the batch atomic: a torn write loses the whole batch.
+    Batch(Vec<WalRecord>),
+    /// A put that expires at `expires_at`, in milliseconds since the Unix epoch.
+    ///
+    /// Declared last so that adding it didn't renumber the variants of existing logs.
+    PutExpiring {
+        cf: ColumnFamilyId,
+        key: Key,
+        val: Bytes,
+        expires_at: u64,
+    },
+    /// The records of a batch prepared under `txn` by
+    /// [`Database::prepare_batch`](crate::Database::prepare_batch), which only take effect
+    /// once a [`WalRecord::CommitPrepared`] for the same `txn` follows. Their keys hold a
+    /// placeholder seqno until then.
+    Prepare {
+        txn: u64,
+        records: Vec<WalRecord>,
+    },
+    /// Commits the batch prepared under `txn`, its records taking seqnos from `seqno` on.
+    CommitPrepared {
+        txn: u64,
+        seqno: SeqNo,
+    },
+    /// Drops the batch prepared under `txn` without applying it.
+    AbortPrepared {
+        txn: u64,
+    },
 }
 
 impl WalRecord {
-    pub fn key(&self) -> &Key {
+    /// The column family this record applies to, or `None` for a batch or a prepared
+    /// batch's record.
+    pub fn cf(&self) -> Option<ColumnFamilyId> {
+        match self {
+            WalRecord::Put { cf, .. }
+            | WalRecord::PutExpiring { cf, .. }
+            | WalRecord::Delete { cf, .. }
+            | WalRecord::DeleteRange { cf, .. } => Some(*cf),
+            WalRecord::Batch(_)
+            | WalRecord::Prepare { .. }
+            | WalRecord::CommitPrepared { .. }
+            | WalRecord::AbortPrepared { .. } => None,
+        }
+    }
+
+    /// The key written by this record, or `None` for a batch or a prepared batch's record.
+    pub fn key(&self) -> Option<&Key> {
+        match self {
+            WalRecord::Put { key, .. }
+            | WalRecord::PutExpiring { key, .. }
+            | WalRecord::Delete { key, .. }
+            | WalRecord::DeleteRange { key, .. } => Some(key),
+            WalRecord::Batch(_)
+            | WalRecord::Prepare { .. }
+            | WalRecord::CommitPrepared { .. }
+            | WalRecord::AbortPrepared { .. } => None,
+        }
+    }
+
+    /// The seqno of this record, or of the first record of a batch.
+    pub fn seqno(&self) -> Option<SeqNo> {
         match self {
-            WalRecord::Put { key, .. } => key,
-            WalRecord::Delete { key } => key,
+            WalRecord::Batch(records) => records.first().and_then(WalRecord::seqno),
+            record => record.key().map(Key::seqno),
+        }
+    }
+
+    /// Flattens batches, returning the individual records in the order they were written.
+    pub fn into_records(self) -> Vec<WalRecord> {
+        match self {
+            WalRecord::Batch(records) => records
+                .into_iter()
+                .flat_map(WalRecord::into_records)
+                .collect(),
+            record => vec![record],
+        }
+    }
+
+    /// This record with its key's seqno replaced by `seqno`.
+    pub(crate) fn with_seqno(self, seqno: SeqNo) -> WalRecord {
+        let rekey = |key: Key| key.with_seqno(seqno);
+
+        match self {
+            WalRecord::Put { cf, key, val } => WalRecord::Put {
+                cf,
+                key: rekey(key),
+                val,
+            },
+            WalRecord::PutExpiring {
+                cf,
+                key,
+                val,
+                expires_at,
+            } => WalRecord::PutExpiring {
+                cf,
+                key: rekey(key),
+                val,
+                expires_at,
+            },
+            WalRecord::Delete { cf, key } => WalRecord::Delete {
+                cf,
+                key: rekey(key),
+            },
+            WalRecord::DeleteRange { cf, key, end } => WalRecord::DeleteRange {
+                cf,
+                key: rekey(key),
+                end,
+            },
+            record => record,
         }
     }
 }
 
+/// The records of each batch prepared but not yet committed or aborted, by transaction id.
+pub type PreparedBatches = BTreeMap<u64, Vec<WalRecord>>;
+
+/// The records of a prepared batch committed from `seqno` on, as one batch.
+pub(crate) fn commit_prepared(records: Vec<WalRecord>, seqno: SeqNo) -> WalRecord {
+    let seqno = seqno.get();
+
+    WalRecord::Batch(
+        records
+            .into_iter()
+            .enumerate()
+            .map(|(i, record)| record.with_seqno(SeqNo::from(seqno + i as u64)))
+            .collect(),
+    )
+}
+
 pub struct Wal {
     file: std::fs::File,
     /// The size of the WAL file *NOT* including trailing zeros from pre-allocation.
     size: u64,
+    /// The physical length of the WAL file, including trailing zeros from pre-allocation.
+    capacity: u64,
+    /// The number of bytes to grow the file by when an append doesn't fit in `capacity`.
+    /// Zero disables pre-allocation.
+    preallocate_chunk: u64,
+    /// Compression applied to records whose serialized size is at least `compression_threshold`.
+    compression: Compression,
+    compression_threshold: usize,
     /// The number of records in the WAL.
     len: usize,
 }
@@ -39,6 +227,12 @@ impl Drop for Wal {
             eprintln!("Failed to flush WAL on drop: {:?}", e);
         }
 
+        if self.capacity > self.size
+            && let Err(e) = self.file.set_len(self.size)
+        {
+            eprintln!("Failed to truncate pre-allocated WAL tail on drop: {:?}", e);
+        }
+
         if let Err(e) = self.file.unlock() {
             eprintln!("Failed to unlock WAL file on drop: {:?}", e);
         }
@@ -46,61 +240,204 @@ impl Drop for Wal {
 }
 
 impl Wal {
-    pub fn open(path: PathBuf) -> anyhow::Result<Self> {
+    /// Opens the log at `path`, creating it if it doesn't exist. Records that were only
+    /// partly written when the log was last open are cut off, and reported in `report`.
+    pub fn open(path: PathBuf, config: &Config, report: &mut OpenReport) -> anyhow::Result<Self> {
+        // Records are written at `size` rather than appended, since the physical end of the
+        // file may be pre-allocated zeros.
         let file = std::fs::OpenOptions::new()
             .create(true)
-            .append(true)
+            .truncate(false)
+            .write(true)
             .read(true)
             .open(&path)
             .context("Failed to open WAL file")?;
 
-        file.lock().context("Failed to lock WAL file")?;
+        crate::lock::lock_for_open(&file, &path, config).context("Failed to lock WAL file")?;
 
-        let (size, len) = Self::read_stats(&file)?;
+        let (size, len) = Self::read_stats(&file, config.paranoid_checks)?;
 
-        Ok(Wal { file, len, size })
+        // Left alone, the next append would only overwrite the start of the torn records,
+        // and the rest would be read back as if it followed it. Records after a corrupt one
+        // are cut off with it, so the log recovers to the last write before the corruption.
+        if let Some((bytes, records)) = Self::torn_tail(&file, size)? {
+            eprintln!(
+                "WAL ends in {records} partly written records ({bytes} bytes), truncating it"
+            );
+
+            file.set_len(size)
+                .context("Failed to truncate torn WAL tail")?;
+            file.sync_all().context("Failed to sync truncated WAL")?;
+
+            report
+                .actions
+                .push(RecoveryAction::WalTailTruncated { bytes, records });
+        }
+
+        let capacity = file
+            .metadata()
+            .context("Failed to read WAL metadata")?
+            .len();
+
+        Ok(Wal {
+            file,
+            len,
+            size,
+            capacity,
+            preallocate_chunk: config.wal_preallocate_chunk,
+            compression: config.wal_compression,
+            compression_threshold: config.wal_compression_threshold,
+        })
     }
 
-    pub fn should_compact(&self) -> bool {
-        self.size > WAL_MAX_SIZE
+    /// Whether the log has grown enough that the memtables should be flushed so it can be
+    /// cleared. It's allowed at least the `memtable_size` they're frozen at, so that a
+    /// larger target isn't cut short by the log.
+    pub fn should_compact(&self, memtable_size: usize) -> bool {
+        self.size > WAL_MAX_SIZE.max(memtable_size as u64)
     }
 
-    fn read_stats(mut file: &std::fs::File) -> anyhow::Result<(u64, usize)> {
+    /// The size of the log up to the end of its last readable record, and the number of
+    /// records up to there. Reading stops at a record that fails its checksum, unless
+    /// `paranoid` is set, in which case it's an error.
+    fn read_stats(file: &std::fs::File, paranoid: bool) -> anyhow::Result<(u64, usize)> {
         let mut reader = std::io::BufReader::new(file);
 
         reader
             .seek(std::io::SeekFrom::Start(0))
             .context("seek to start")?;
 
+        let mut reader = FrameReader::checksummed_only(reader);
         let mut len = 0;
+        let mut offset = 0;
 
         loop {
-            match crate::framed::read_framed::<_, WalRecord>(&mut reader) {
+            match reader.read::<WalRecord>() {
                 Ok(_) => {
                     len += 1;
+                    offset = reader
+                        .get_mut()
+                        .stream_position()
+                        .context("Failed to get WAL size")?;
+                }
+                Err(FramedError::UnexpectedEnd) => break,
+                // Cutting it off as corrupt would lose every write in it.
+                Err(e @ FramedError::Legacy) => {
+                    return Err(e).context(
+                        "WAL was written by an older version of mintdb, whose records have \
+                         a different format. Open the database with that version and flush \
+                         it before upgrading",
+                    );
                 }
-                Err(e) => match e {
-                    postcard::Error::DeserializeUnexpectedEnd => {
-                        break;
+                Err(e @ (FramedError::ChecksumMismatch { .. } | FramedError::MissingChecksum)) => {
+                    if paranoid {
+                        return Err(e).with_context(|| {
+                            format!("WAL record {len} at offset {offset} is corrupt")
+                        });
                     }
-                    e => panic!("{e}"),
-                },
+
+                    eprintln!("WAL record {len} at offset {offset} is corrupt: {e}");
+                    break;
+                }
+                Err(e) => return Err(e).context("Failed to read WAL record"),
             };
         }
 
-        let offset = file.stream_position().context("Failed to get WAL size")?;
-
         Ok((offset, len))
     }
 
-    pub fn append(&mut self, record: WalRecord) -> anyhow::Result<()> {
-        let written = crate::framed::write_framed(&mut self.file, &record)
-            .context("Failed to serialize WAL record")?;
+    /// The length of whatever follows the last complete record, which ends at `size`, up to
+    /// the pre-allocated zeros after it, and the number of records it holds the start of.
+    /// `None` if nothing does.
+    fn torn_tail(file: &std::fs::File, size: u64) -> anyhow::Result<Option<(u64, usize)>> {
+        let end = file
+            .metadata()
+            .context("Failed to read WAL metadata")?
+            .len();
+
+        let mut tail = vec![0; end.saturating_sub(size) as usize];
+
+        file.read_exact_at(&mut tail, size)
+            .context("Failed to read WAL tail")?;
+
+        let Some(last) = tail.iter().rposition(|byte| *byte != 0) else {
+            return Ok(None);
+        };
+
+        let tail = &tail[..=last];
+
+        // A record whose length didn't make it to disk still counts.
+        Ok(Some((
+            tail.len() as u64,
+            crate::framed::count_frames(tail).max(1),
+        )))
+    }
+
+    /// Ensures the file has room for `additional` bytes past the logical end, growing it
+    /// by whole pre-allocation chunks if it doesn't.
+    fn reserve(&mut self, additional: u64) -> anyhow::Result<()> {
+        let required = self.size + additional;
+
+        if self.preallocate_chunk == 0 || required <= self.capacity {
+            return Ok(());
+        }
+
+        let new_capacity = required.next_multiple_of(self.preallocate_chunk);
+
+        // SAFETY: the file descriptor is owned by `self.file` and valid for the duration
+        // of the call.
+        let res = unsafe {
+            libc::fallocate(
+                self.file.as_raw_fd(),
+                0,
+                self.capacity as libc::off_t,
+                (new_capacity - self.capacity) as libc::off_t,
+            )
+        };
+
+        if res != 0 {
+            let err = std::io::Error::last_os_error();
+
+            if err.raw_os_error() != Some(libc::EOPNOTSUPP) {
+                return Err(err).context("Failed to pre-allocate WAL file");
+            }
+
+            // The filesystem can't pre-allocate, so just extend the file. This won't
+            // reserve blocks, but it keeps the logical/physical size bookkeeping uniform.
+            self.file
+                .set_len(new_capacity)
+                .context("Failed to extend WAL file")?;
+        }
+
+        self.capacity = new_capacity;
+
+        Ok(())
+    }
+
+    /// Appends `record` to the log, fsyncing it before returning if `sync` is set.
+    pub fn append(&mut self, record: WalRecord, sync: bool) -> anyhow::Result<()> {
+        let mut buf = Vec::new();
+        let written = crate::framed::write_framed_compressed(
+            &mut buf,
+            &record,
+            self.compression,
+            self.compression_threshold,
+        )
+        .context("Failed to serialize WAL record")?;
+
+        self.reserve(written as u64)?;
+
+        self.file
+            .write_all_at(&buf, self.size)
+            .context("Failed to write WAL record")?;
 
         self.size += written as u64;
+        self.capacity = self.capacity.max(self.size);
         self.len += 1;
 
-        self.flush()?;
+        if sync {
+            self.flush()?;
+        }
 
         Ok(())
     }
@@ -109,20 +446,101 @@ impl Wal {
         self.len
     }
 
-    pub fn replay(&mut self) -> anyhow::Result<Vec<WalRecord>> {
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The logical size of the WAL, excluding pre-allocated space.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// The physical length of the WAL file, including pre-allocated space.
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    /// The records in the log, in the order they were written. Prepared batches are
+    /// resolved: a committed one is returned as a [`WalRecord::Batch`] where it was
+    /// committed, and one that was aborted, or is still waiting, is left out.
+    pub fn replay(&self) -> anyhow::Result<Vec<WalRecord>> {
+        Ok(self.replay_with_prepared()?.0)
+    }
+
+    /// Like [`Wal::replay`], also returning the records of each batch that was prepared but
+    /// neither committed nor aborted, by transaction id.
+    pub fn replay_with_prepared(&self) -> anyhow::Result<(Vec<WalRecord>, PreparedBatches)> {
         let mut reader = std::io::BufReader::new(&self.file);
 
         reader
             .seek(std::io::SeekFrom::Start(0))
             .context("seek to start")?;
 
-        Ok(crate::framed::read_all_framed::<_, WalRecord>(&mut reader)
-            .context("Failed to read WAL records")?)
+        let logged = crate::framed::read_all_checksummed::<_, WalRecord>(&mut reader)
+            .context("Failed to read WAL records")?;
+
+        let mut records = Vec::with_capacity(logged.len());
+        let mut prepared = BTreeMap::new();
+
+        for record in logged {
+            match record {
+                WalRecord::Prepare { txn, records } => {
+                    prepared.insert(txn, records);
+                }
+                WalRecord::CommitPrepared { txn, seqno } => {
+                    let Some(batch) = prepared.remove(&txn) else {
+                        anyhow::bail!("WAL commits transaction {txn}, which was never prepared");
+                    };
+
+                    records.push(commit_prepared(batch, seqno));
+                }
+                WalRecord::AbortPrepared { txn } => {
+                    prepared.remove(&txn);
+                }
+                record => records.push(record),
+            }
+        }
+
+        Ok((records, prepared))
+    }
+
+    /// Writes the log's records, excluding pre-allocated space, to a new file at `path`,
+    /// followed by `unlogged`, the writes waiting to be appended under
+    /// [`WritePolicy::WriteBehind`].
+    pub fn copy_to(&self, path: &std::path::Path, unlogged: &[WalRecord]) -> anyhow::Result<()> {
+        let mut buf = vec![0; self.size as usize];
+
+        self.file
+            .read_exact_at(&mut buf, 0)
+            .context("Failed to read WAL for copy")?;
+
+        for record in unlogged {
+            crate::framed::write_framed_compressed(
+                &mut buf,
+                record,
+                self.compression,
+                self.compression_threshold,
+            )
+            .context("Failed to serialize WAL record")?;
+        }
+
+        let mut file = std::fs::OpenOptions::new()
+            .create_new(true)
+            .write(true)
+            .open(path)
+            .context("Failed to create WAL copy")?;
+
+        file.write_all(&buf).context("Failed to write WAL copy")?;
+        file.sync_all().context("Failed to sync WAL copy")?;
+
+        Ok(())
     }
 
     pub fn flush(&mut self) -> anyhow::Result<()> {
         self.file.flush().context("Failed to flush WAL")?;
-        self.file.sync_all().context("Failed to sync WAL")?;
+        // Pre-allocation means appends usually don't change the file length, so only
+        // the data needs to be synced.
+        self.file.sync_data().context("Failed to sync WAL")?;
 
         Ok(())
     }
@@ -134,6 +552,7 @@ impl Wal {
 
         self.len = 0;
         self.size = 0;
+        self.capacity = 0;
 
         Ok(())
     }
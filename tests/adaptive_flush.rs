@@ -0,0 +1,56 @@
+mod common;
+
+use std::{sync::Arc, time::Duration};
+
+use common::run;
+use mintdb::{clock::ManualClock, memtable::MemtableSize, Database};
+
+const MIN: usize = 64 * 1024;
+const MAX: usize = 1024 * 1024;
+
+#[test]
+fn flush_target_shrinks_in_a_burst_within_its_bounds() {
+    run(|mut config| async move {
+        let clock = Arc::new(ManualClock::new());
+        config.clock = clock.clone();
+        config.memtable_size = MemtableSize::Adaptive { min: MIN, max: MAX };
+
+        let mut db = Database::open(config)?;
+        let target = |db: &Database| db.stats().memtable_flush_target as usize;
+        let value = bytes::Bytes::from("x".repeat(1_000));
+        let mut i = 0;
+
+        // Steady writes, ten a second for two minutes.
+        for _ in 0..1_200 {
+            db.put(format!("key{i:06}"), value.clone()).await?;
+            clock.advance(Duration::from_millis(100));
+            i += 1;
+        }
+        let steady = target(&db);
+        assert!(steady > MAX * 3 / 4 && steady <= MAX, "{steady}");
+
+        // A burst of a hundred times the rate shrinks it, as far as the minimum.
+        for _ in 0..1_000 {
+            db.put(format!("key{i:06}"), value.clone()).await?;
+            clock.advance(Duration::from_millis(1));
+            i += 1;
+
+            assert!((MIN..=MAX).contains(&target(&db)));
+        }
+        let burst = target(&db);
+        assert!(burst < steady / 4, "{burst} vs {steady}");
+        assert!(burst >= MIN);
+
+        // Back to the steady rate, it grows back.
+        for _ in 0..600 {
+            db.put(format!("key{i:06}"), value.clone()).await?;
+            clock.advance(Duration::from_millis(100));
+            i += 1;
+
+            assert!((MIN..=MAX).contains(&target(&db)));
+        }
+        assert!(target(&db) > MAX / 2, "{}", target(&db));
+
+        Ok(())
+    });
+}
@@ -0,0 +1,80 @@
+mod common;
+
+use common::{b, corrupt, run, sstable_path};
+use mintdb::{config::Config, sstable::Level, Database};
+
+/// Builds a database in `dir` holding `prefix{i}` for each `i` in `keys`, one file per
+/// hundred keys, and returns the paths of its SSTables.
+async fn build(
+    dir: &std::path::Path,
+    prefix: &str,
+    keys: std::ops::Range<usize>,
+) -> anyhow::Result<Vec<std::path::PathBuf>> {
+    let mut db = Database::open(Config::new(dir))?;
+
+    for (n, i) in keys.enumerate() {
+        db.put(format!("{prefix}{i:04}"), format!("{prefix} {i}"))
+            .await?;
+
+        if n % 100 == 99 {
+            db.flush().await?;
+        }
+    }
+    db.flush().await?;
+
+    let files = db
+        .live_files(&db.default_cf())?
+        .into_iter()
+        .map(|(_, file)| sstable_path(dir, file.file_number))
+        .collect();
+    db.close().await?;
+
+    Ok(files)
+}
+
+#[test]
+fn attached_sstables_become_readable() {
+    run(|config| async move {
+        let source = tempfile::tempdir()?;
+        let files = build(source.path(), "x", 0..300).await?;
+        assert_eq!(files.len(), 3);
+
+        let mut db = Database::open(config.clone())?;
+        db.put("a", "local").await?;
+
+        assert_eq!(db.attach_sstables(&files, Level(1)).await?, 3);
+        assert_eq!(db.get(&b("x0000")).await?, Some(b("x 0")));
+        assert_eq!(db.get(&b("x0299")).await?, Some(b("x 299")));
+        assert_eq!(db.get(&b("a")).await?, Some(b("local")));
+        assert_eq!(db.count(..).await?, 301);
+
+        // The originals are left where they were.
+        assert!(files.iter().all(|file| file.exists()));
+
+        // Files overlapping what's there already are refused.
+        assert!(db.attach_sstables(&files[..1], Level(1)).await.is_err());
+
+        // So are ones that fail validation.
+        let other = tempfile::tempdir()?;
+        let corrupted = build(other.path(), "y", 0..100).await?;
+        assert!(corrupt(&corrupted[0], b"y 5")?);
+        assert!(db.attach_sstables(&corrupted, Level(1)).await.is_err());
+        assert_eq!(db.get(&b("y0000")).await?, None);
+
+        // Nothing is attached while a snapshot could see it appear.
+        let more_dir = tempfile::tempdir()?;
+        let more = build(more_dir.path(), "z", 0..10).await?;
+        let snapshot = db.snapshot();
+        assert!(db.attach_sstables(&more, Level(1)).await.is_err());
+        drop(snapshot);
+        assert_eq!(db.attach_sstables(&more, Level(1)).await?, 1);
+
+        db.close().await?;
+
+        let db = Database::open(config)?;
+        assert_eq!(db.get(&b("x0150")).await?, Some(b("x 150")));
+        assert_eq!(db.count(..).await?, 311);
+
+        Ok(())
+    });
+}
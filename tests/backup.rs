@@ -0,0 +1,177 @@
+mod common;
+
+use common::{b, run};
+use mintdb::{batch::WriteBatch, config::Config, sstable::Level, Database};
+
+fn all_entries(
+    db: &Database,
+    cf: &mintdb::column_family::ColumnFamily,
+) -> anyhow::Result<Vec<(bytes::Bytes, bytes::Bytes)>> {
+    db.scan_cf(cf, ..).collect()
+}
+
+#[test]
+fn restore_reproduces_an_export_exactly() {
+    run(|config| async move {
+        let mut db = Database::open(config)?;
+        let default = db.default_cf();
+        let other = db.create_cf("other")?;
+
+        for chunk in 0..4u32 {
+            let mut batch = WriteBatch::new();
+
+            for i in chunk * 5_000..(chunk + 1) * 5_000 {
+                let value = i.wrapping_mul(2_654_435_761).to_le_bytes().repeat(4);
+                batch.put(&default, format!("key{i:06}"), value);
+            }
+
+            db.write(batch).await?;
+            db.flush().await?;
+        }
+
+        let mut batch = WriteBatch::new();
+        for i in (0..20_000).step_by(3) {
+            batch.delete(&default, format!("key{i:06}"));
+        }
+        db.write(batch).await?;
+        for i in 0..100 {
+            db.put_cf(&other, format!("other{i}"), vec![0, 1, 2, 255])
+                .await?;
+        }
+
+        let mut export = Vec::new();
+        let entries = db.export(&mut export).await?;
+        assert_eq!(entries, 20_000 - 6_667 + 100);
+
+        let dir = tempfile::tempdir()?;
+        let restored = Database::restore(&export[..], Config::new(dir.path())).await?;
+        let restored_other = restored.cf("other").expect("column family was restored");
+
+        assert_eq!(
+            all_entries(&restored, &restored.default_cf())?,
+            all_entries(&db, &default)?
+        );
+        assert_eq!(
+            all_entries(&restored, &restored_other)?,
+            all_entries(&db, &other)?
+        );
+
+        // Loaded straight into L1, in files that don't overlap.
+        let mut files = restored.live_files(&restored.default_cf())?;
+        assert!(!files.is_empty());
+        assert!(files.iter().all(|(level, _)| *level == Level(1)));
+
+        files.sort_by(|a, b| a.1.smallest_key.cmp(&b.1.smallest_key));
+        for pair in files.windows(2) {
+            let (_, prev_largest) = pair[0].1.key_range()?;
+            let (next_smallest, _) = pair[1].1.key_range()?;
+            assert!(prev_largest.user_key() < next_smallest.user_key());
+        }
+
+        Ok(())
+    });
+}
+
+/// The file numbers of the SSTables in `dir`, with their inode numbers.
+fn sstables_in(dir: &std::path::Path) -> anyhow::Result<std::collections::BTreeMap<u64, u64>> {
+    use std::os::unix::fs::MetadataExt;
+
+    let mut files = std::collections::BTreeMap::new();
+
+    for entry in std::fs::read_dir(dir.join("sstables"))? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+
+        if let Some(file_no) = name.strip_suffix(".sstable") {
+            files.insert(file_no.parse()?, entry.metadata()?.ino());
+        }
+    }
+
+    Ok(files)
+}
+
+fn live_file_numbers(db: &Database) -> anyhow::Result<Vec<u64>> {
+    let mut files = db
+        .live_files(&db.default_cf())?
+        .into_iter()
+        .map(|(_, file)| file.file_number)
+        .collect::<Vec<u64>>();
+    files.sort();
+
+    Ok(files)
+}
+
+#[test]
+fn incremental_checkpoint_copies_only_new_files() {
+    run(|config| async move {
+        let backup = tempfile::tempdir()?;
+        let mut db = Database::open(config)?;
+
+        for round in 0..3 {
+            for i in 0..100 {
+                db.put(format!("key{round}{i:03}"), format!("{round}"))
+                    .await?;
+            }
+            db.flush().await?;
+        }
+
+        let full = db.incremental_checkpoint(backup.path(), &Default::default())?;
+        assert_eq!(full.files().collect::<Vec<_>>(), live_file_numbers(&db)?);
+        let before = sstables_in(backup.path())?;
+        assert_eq!(
+            before.keys().copied().collect::<Vec<_>>(),
+            live_file_numbers(&db)?
+        );
+
+        for i in 0..100 {
+            db.put(format!("key3{i:03}"), "3").await?;
+        }
+        db.flush().await?;
+
+        let incremental = db.incremental_checkpoint(backup.path(), &full)?;
+        let after = sstables_in(backup.path())?;
+        assert_eq!(
+            after.keys().copied().collect::<Vec<_>>(),
+            live_file_numbers(&db)?
+        );
+
+        // The files the full checkpoint took are left as they were, and only the flushed
+        // one is new.
+        let new = after
+            .iter()
+            .filter(|(file_no, _)| !full.contains(**file_no))
+            .collect::<Vec<_>>();
+        assert_eq!(new.len(), 1);
+        for (file_no, ino) in &before {
+            assert_eq!(after.get(file_no), Some(ino), "file {file_no} was replaced");
+        }
+
+        // Compaction replaces the file holding the overwritten key; the next checkpoint
+        // drops it.
+        db.put("key0000", "overwritten").await?;
+        db.flush().await?;
+        db.compact().await?;
+
+        let compacted = db.incremental_checkpoint(backup.path(), &incremental)?;
+        let files = sstables_in(backup.path())?;
+        assert_eq!(
+            files.keys().copied().collect::<Vec<_>>(),
+            live_file_numbers(&db)?
+        );
+        assert!(incremental
+            .files()
+            .any(|file_no| !files.contains_key(&file_no)));
+        assert_eq!(
+            compacted.files().collect::<Vec<_>>(),
+            live_file_numbers(&db)?
+        );
+        drop(db);
+
+        let restored = Database::open(Config::new(backup.path()))?;
+        assert_eq!(restored.count(..).await?, 400);
+        assert_eq!(restored.get(&b("key0000")).await?, Some(b("overwritten")));
+        assert_eq!(restored.get(&b("key3099")).await?, Some(b("3")));
+
+        Ok(())
+    });
+}
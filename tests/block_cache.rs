@@ -0,0 +1,63 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::{b, run};
+use mintdb::{cache::BlockCache, config::Config, options::ReadOptions, Database};
+
+#[test]
+fn a_shared_cache_serves_another_handles_first_read() {
+    run(|mut config| async move {
+        let cache = Arc::new(BlockCache::new(1 << 20));
+        config.block_cache = Some(cache.clone());
+
+        let mut db = Database::open(config.clone())?;
+        db.put("key", "first").await?;
+        db.flush().await?;
+
+        let cf = db.default_cf();
+        let (value, stats) = db
+            .get_with_stats(&cf, &b("key"), &ReadOptions::default())
+            .await?;
+        assert_eq!(value, Some(b("first")));
+        assert_eq!((stats.blocks(), stats.cache_hits()), (1, 0));
+        db.close().await?;
+        assert!(cache.size() > 0);
+
+        // A fresh handle on the same cache finds the block already there...
+        let db = Database::open(config.clone())?;
+        let (value, stats) = db
+            .get_with_stats(&cf, &b("key"), &ReadOptions::default())
+            .await?;
+        assert_eq!(value, Some(b("first")));
+        assert_eq!((stats.blocks(), stats.cache_hits()), (1, 1));
+        db.close().await?;
+
+        // ...one with a cache of its own doesn't.
+        let mut uncached = config.clone();
+        uncached.block_cache = None;
+        let db = Database::open(uncached)?;
+        let (_, stats) = db
+            .get_with_stats(&cf, &b("key"), &ReadOptions::default())
+            .await?;
+        assert_eq!((stats.blocks(), stats.cache_hits()), (1, 0));
+        db.close().await?;
+
+        // Another database sharing the cache has a file of the same number, but none of
+        // the first one's blocks are served for it.
+        let other_dir = tempfile::tempdir()?;
+        let mut other = Config::new(other_dir.path());
+        other.block_cache = Some(cache);
+
+        let mut db = Database::open(other)?;
+        db.put("key", "second").await?;
+        db.flush().await?;
+        let (value, stats) = db
+            .get_with_stats(&cf, &b("key"), &ReadOptions::default())
+            .await?;
+        assert_eq!(value, Some(b("second")));
+        assert_eq!((stats.blocks(), stats.cache_hits()), (1, 0));
+
+        Ok(())
+    });
+}
@@ -0,0 +1,49 @@
+mod common;
+
+use common::{b, run};
+use mintdb::{bloom::BloomFilterLevels, sstable::Level, Database};
+
+#[test]
+fn only_included_levels_get_filters() {
+    run(|mut config| async move {
+        config.bloom_filter_levels = BloomFilterLevels::UpTo(Level(0));
+
+        let mut db = Database::open(config)?;
+        let cf = db.default_cf();
+
+        // One file overlapping the next, to be merged, and one apart from both, to be moved.
+        for keys in [0..50, 25..75, 100..150] {
+            for i in keys {
+                db.put(format!("key{i:03}"), "v").await?;
+            }
+            db.flush().await?;
+        }
+
+        let files = db.live_files(&cf)?;
+        assert_eq!(files.len(), 3);
+        assert!(files
+            .iter()
+            .all(|(level, file)| *level == Level(0) && file.bloom_filter.is_some()));
+        assert!(db.stats().bloom_filter_bytes > 0);
+
+        let result = db.compact_level(Level(0)).await?;
+        assert_eq!((result.input_files, result.files_moved), (2, 1));
+
+        let files = db.live_files(&cf)?;
+        assert!(files
+            .iter()
+            .all(|(level, file)| *level == Level(1) && file.bloom_filter.is_none()));
+        assert_eq!(db.stats().bloom_filter_bytes, 0);
+
+        db.put("key200", "v").await?;
+        db.flush().await?;
+        let (level, file) = db.live_files(&cf)?.remove(0);
+        assert_eq!(level, Level(0));
+        assert!(file.bloom_filter.is_some());
+
+        assert_eq!(db.get(&b("key030")).await?, Some(b("v")));
+        assert_eq!(db.get(&b("key090")).await?, None);
+
+        Ok(())
+    });
+}
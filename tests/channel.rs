@@ -0,0 +1,102 @@
+mod common;
+
+use std::{cell::Cell, rc::Rc};
+
+use common::run;
+use futures_lite::future::poll_once;
+use mintdb::channel::{channel, SendError, TrySendError};
+
+#[test]
+fn values_are_received_in_the_order_they_were_sent() {
+    run(|_| async move {
+        let (tx, mut rx) = channel(4);
+        let other = tx.clone();
+
+        let mut sent = Vec::new();
+        for i in 0..100 {
+            let sender = if i % 3 == 0 { &other } else { &tx };
+            assert_eq!(sender.send(i).await, Ok(()));
+            sent.push(i);
+
+            if i % 4 == 3 {
+                while let Some(Some(i)) = poll_once(rx.recv()).await {
+                    assert_eq!(i, sent.remove(0));
+                }
+            }
+        }
+
+        drop((tx, other));
+        while let Some(i) = rx.recv().await {
+            assert_eq!(i, sent.remove(0));
+        }
+        assert!(sent.is_empty());
+
+        Ok(())
+    });
+}
+
+#[test]
+fn a_full_channel_holds_senders_back_until_there_is_room() {
+    run(|_| async move {
+        let (tx, mut rx) = channel(2);
+
+        assert_eq!(tx.try_send(1), Ok(()));
+        assert_eq!(tx.try_send(2), Ok(()));
+        assert_eq!(tx.try_send(3), Err(TrySendError::Full(3)));
+
+        let sent = Rc::new(Cell::new(0));
+        let sender = glommio::spawn_local({
+            let sent = sent.clone();
+            async move {
+                for i in [3, 4] {
+                    tx.send(i).await?;
+                    sent.set(i);
+                }
+                Ok::<_, SendError<_>>(())
+            }
+        });
+
+        glommio::executor().yield_now().await;
+        assert_eq!(sent.get(), 0);
+
+        assert_eq!(rx.recv().await, Some(1));
+        glommio::executor().yield_now().await;
+        assert_eq!(sent.get(), 3);
+
+        assert_eq!(rx.recv().await, Some(2));
+        assert_eq!(rx.recv().await, Some(3));
+        assert_eq!(rx.recv().await, Some(4));
+
+        assert_eq!(sender.await, Ok(()));
+        assert_eq!(rx.recv().await, None);
+
+        Ok(())
+    });
+}
+
+#[test]
+fn closing_fails_waiting_senders_but_keeps_sent_values() {
+    run(|_| async move {
+        let (tx, mut rx) = channel(1);
+
+        assert_eq!(tx.try_send(1), Ok(()));
+
+        let sender = glommio::spawn_local(async move {
+            let blocked = tx.send(2).await;
+            (tx, blocked)
+        });
+        glommio::executor().yield_now().await;
+
+        rx.close();
+
+        let (tx, blocked) = sender.await;
+        assert_eq!(blocked, Err(SendError(2)));
+        assert!(tx.is_closed());
+        assert_eq!(tx.try_send(3), Err(TrySendError::Closed(3)));
+
+        assert_eq!(rx.recv().await, Some(1));
+        assert_eq!(rx.recv().await, None);
+
+        Ok(())
+    });
+}
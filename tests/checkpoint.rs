@@ -0,0 +1,86 @@
+mod common;
+
+use common::{b, run};
+use mintdb::{
+    batch::WriteBatch, config::Config, options::WriteOptions, wal::WritePolicy, Database,
+};
+
+const UNSYNCED: WriteOptions = WriteOptions {
+    sync: false,
+    delete_if_exists: false,
+    idempotency_key: None,
+};
+
+/// Writes round `round` of `key{i}` for every `i` in `keys`, in batches of two so a
+/// checkpoint can be checked for a torn batch.
+async fn write_round(
+    db: &mut Database,
+    round: usize,
+    keys: std::ops::Range<usize>,
+) -> anyhow::Result<()> {
+    let cf = db.default_cf();
+
+    for i in keys.step_by(2) {
+        let mut batch = WriteBatch::new();
+        batch
+            .put(&cf, format!("key{i:04}"), format!("{round}"))
+            .put(&cf, format!("key{:04}", i + 1), format!("{round}"));
+
+        db.write_opt(batch, &UNSYNCED).await?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn checkpoint_taken_mid_writes_is_a_consistent_point_in_time() {
+    run(|mut config| async move {
+        // Writes that haven't reached the WAL yet have to come along too.
+        config.write_policy = WritePolicy::WriteBehind;
+
+        let mut db = Database::open(config.clone())?;
+        let cf = db.default_cf();
+
+        // Flushed, frozen, and still in the active memtable.
+        write_round(&mut db, 0, 0..400).await?;
+        db.flush().await?;
+        write_round(&mut db, 1, 0..200).await?;
+        db.freeze_memtable(&cf).await?;
+        write_round(&mut db, 2, 100..150).await?;
+
+        let dir = tempfile::tempdir()?;
+        let checkpoint = dir.path().join("checkpoint");
+        db.create_checkpoint(&checkpoint)?;
+
+        // Writes carry on, through flushes and compactions, without the checkpoint moving.
+        write_round(&mut db, 3, 0..400).await?;
+        db.delete("key0002").await?;
+        db.flush().await?;
+        db.compact().await?;
+        write_round(&mut db, 4, 300..500).await?;
+
+        let expected = |i: usize| match i {
+            100..150 => "2",
+            0..200 => "1",
+            _ => "0",
+        };
+
+        // Opened as a database of its own, and only read.
+        let copy = Database::open(Config::new(&checkpoint))?;
+        let entries = copy.scan(..).collect::<anyhow::Result<Vec<_>>>()?;
+        assert_eq!(
+            entries,
+            (0..400)
+                .map(|i| (b(&format!("key{i:04}")), b(expected(i))))
+                .collect::<Vec<_>>()
+        );
+        drop(copy);
+
+        // The live database is unaffected by the copy being opened.
+        assert_eq!(db.get(&b("key0002")).await?, None);
+        assert_eq!(db.get(&b("key0450")).await?, Some(b("4")));
+        assert_eq!(db.count(..).await?, 499);
+
+        Ok(())
+    });
+}
@@ -0,0 +1,88 @@
+mod common;
+
+use common::{b, copy_dir, corrupt, run, sstable_path};
+use mintdb::{
+    config::Config,
+    options::ReadOptions,
+    sstable::{
+        manager::{FileChecksumMismatch, FileNo},
+        sstable::SSTable,
+    },
+    Database,
+};
+
+fn verifying(verify: bool) -> ReadOptions {
+    ReadOptions {
+        verify_checksums: Some(verify),
+        ..ReadOptions::default()
+    }
+}
+
+#[test]
+fn unverified_reads_return_corrupt_blocks() {
+    run(|config| async move {
+        let mut db = Database::open(config.clone())?;
+        let cf = db.default_cf();
+
+        for i in 0..10 {
+            db.put(format!("key{i}"), format!("value-{i}")).await?;
+        }
+        db.flush().await?;
+
+        let files = db.live_files(&cf)?;
+        db.close().await?;
+
+        assert!(corrupt(
+            &sstable_path(&config.data_dir, files[0].1.file_number),
+            b"value-3"
+        )?);
+
+        let db = Database::open(config)?;
+
+        // Without verification the flipped byte goes unnoticed: that's the tradeoff.
+        let value = db.get_opt(&b("key3"), &verifying(false)).await?;
+        assert!(value.is_some_and(|value| value != b("value-3")));
+
+        assert!(db.get_opt(&b("key3"), &verifying(true)).await.is_err());
+
+        Ok(())
+    });
+}
+
+#[test]
+fn copied_files_verify_until_corrupted() {
+    run(|config| async move {
+        let mut db = Database::open(config.clone())?;
+
+        for i in 0..100 {
+            db.put(format!("key{i:03}"), format!("value {i}")).await?;
+        }
+        db.flush().await?;
+
+        let (_, file) = db.live_files(&db.default_cf())?.remove(0);
+        db.verify_file_checksums()?;
+        db.close().await?;
+
+        let dir = tempfile::tempdir()?;
+        copy_dir(&config.data_dir, dir.path())?;
+
+        let copy = sstable_path(dir.path(), file.file_number);
+        let checksum = SSTable::file_checksum(std::fs::File::open(&copy)?)?;
+        assert_eq!(checksum, file.file_checksum);
+        Database::open(Config::new(dir.path()))?.verify_file_checksums()?;
+
+        assert!(corrupt(&copy, b"value 50")?);
+
+        let e = Database::open(Config::new(dir.path()))?
+            .verify_file_checksums()
+            .unwrap_err();
+        let Some(mismatch) = e.downcast_ref::<FileChecksumMismatch>() else {
+            panic!("{e:#}");
+        };
+        assert_eq!(mismatch.file_number, FileNo(file.file_number));
+        assert_eq!(mismatch.expected, file.file_checksum);
+        assert_ne!(mismatch.actual, file.file_checksum);
+
+        Ok(())
+    });
+}
@@ -0,0 +1,30 @@
+use std::{sync::Arc, time::Duration};
+
+use mintdb::clock::{ManualClock, YieldTimer};
+
+#[test]
+fn yields_on_elapsed_time_rather_than_a_count() {
+    let clock = Arc::new(ManualClock::new());
+    let mut timer = YieldTimer::new(clock.clone(), Duration::from_millis(5));
+
+    // However much work is done, there's no yield until the interval has passed.
+    for _ in 0..100_000 {
+        assert!(!timer.should_yield());
+    }
+
+    clock.advance(Duration::from_millis(4));
+    assert!(!timer.should_yield());
+
+    clock.advance(Duration::from_millis(1));
+    assert!(timer.should_yield());
+
+    // Time spent suspended doesn't count against the next interval.
+    timer.reset();
+    assert!(!timer.should_yield());
+
+    // A single slow step is enough.
+    clock.advance(Duration::from_millis(50));
+    assert!(timer.should_yield());
+    timer.reset();
+    assert!(!timer.should_yield());
+}
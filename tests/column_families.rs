@@ -0,0 +1,151 @@
+mod common;
+
+use std::collections::BTreeMap;
+
+use bytes::Bytes;
+use common::{b, copy_dir, current_manifest, run};
+use mintdb::{
+    batch::WriteBatch,
+    config::Config,
+    framed::FramedError,
+    key::{Key, SeqNo},
+    Database,
+};
+
+/// A WAL record as the first release wrote it, before column families.
+#[derive(serde::Serialize)]
+enum BaselineWalRecord {
+    Put { key: Key, val: Bytes },
+}
+
+/// A manifest record as the first release wrote it.
+#[derive(serde::Serialize)]
+enum BaselineManifestRecord {
+    Snapshot {
+        next_file_number: u64,
+        last_committed_sequence_number: u64,
+        levels: BTreeMap<u32, ()>,
+    },
+}
+
+/// Frames `record` as the first release did: a length prefix with no checksum.
+fn baseline_frame(record: &impl serde::Serialize) -> anyhow::Result<Vec<u8>> {
+    let payload = postcard::to_stdvec(record)?;
+    let mut frame = (payload.len() as u32).to_le_bytes().to_vec();
+    frame.extend(payload);
+
+    Ok(frame)
+}
+
+fn is_legacy(e: &anyhow::Error) -> bool {
+    e.chain()
+        .any(|e| matches!(e.downcast_ref(), Some(FramedError::Legacy)))
+}
+
+#[test]
+fn batch_across_column_families_is_isolated_and_atomic() {
+    run(|config| async move {
+        let mut db = Database::open(config.clone())?;
+        let default = db.default_cf();
+        let other = db.create_cf("other")?;
+
+        db.put("before", "1").await?;
+
+        let mut batch = WriteBatch::new();
+        batch
+            .put(&default, "shared", "in default")
+            .put(&other, "shared", "in other")
+            .put(&other, "only-other", "x");
+        db.write(batch).await?;
+
+        assert_eq!(db.get(&b("shared")).await?, Some(b("in default")));
+        assert_eq!(db.get_cf(&other, &b("shared")).await?, Some(b("in other")));
+        assert_eq!(db.get(&b("only-other")).await?, None);
+        assert_eq!(db.get_cf(&other, &b("before")).await?, None);
+
+        // The whole batch is recovered from the WAL...
+        let dir = tempfile::tempdir()?;
+        copy_dir(&config.data_dir, dir.path())?;
+
+        let crashed = Database::open(Config::new(dir.path()))?;
+        let crashed_other = crashed.cf("other").expect("column family was created");
+        assert_eq!(crashed.get(&b("shared")).await?, Some(b("in default")));
+        assert_eq!(
+            crashed.get_cf(&crashed_other, &b("shared")).await?,
+            Some(b("in other"))
+        );
+        assert_eq!(crashed.get(&b("only-other")).await?, None);
+        drop(crashed);
+
+        // ...or none of it, if its record was torn.
+        let dir = tempfile::tempdir()?;
+        copy_dir(&config.data_dir, dir.path())?;
+
+        let wal = dir.path().join("wal.log");
+        let torn = std::fs::read(&wal)?;
+        let end = torn
+            .iter()
+            .rposition(|byte| *byte != 0)
+            .expect("WAL has records");
+        std::fs::write(&wal, &torn[..end - 2])?;
+
+        let crashed = Database::open(Config::new(dir.path()))?;
+        let crashed_other = crashed.cf("other").expect("column family was created");
+        assert_eq!(crashed.get(&b("before")).await?, Some(b("1")));
+        assert_eq!(crashed.get(&b("shared")).await?, None);
+        assert_eq!(crashed.get_cf(&crashed_other, &b("shared")).await?, None);
+        assert_eq!(
+            crashed.get_cf(&crashed_other, &b("only-other")).await?,
+            None
+        );
+
+        Ok(())
+    });
+}
+
+#[test]
+fn baseline_wal_fails_open_without_being_truncated() {
+    run(|config| async move {
+        Database::open(config.clone())?.close().await?;
+
+        let mut wal = Vec::new();
+        for (i, key) in ["a", "b"].into_iter().enumerate() {
+            wal.extend(baseline_frame(&BaselineWalRecord::Put {
+                key: Key::new(b(key), SeqNo::from(i as u64 + 1)),
+                val: b("v"),
+            })?);
+        }
+
+        let path = config.data_dir.join("wal.log");
+        std::fs::write(&path, &wal)?;
+
+        let e = Database::open(config).err().expect("open should fail");
+        assert!(is_legacy(&e), "{e:#}");
+        assert!(format!("{e:#}").contains("older version"), "{e:#}");
+
+        assert_eq!(std::fs::read(&path)?, wal);
+
+        Ok(())
+    });
+}
+
+#[test]
+fn baseline_manifest_fails_open() {
+    run(|config| async move {
+        Database::open(config.clone())?.close().await?;
+
+        std::fs::write(
+            current_manifest(&config.data_dir)?,
+            baseline_frame(&BaselineManifestRecord::Snapshot {
+                next_file_number: 1,
+                last_committed_sequence_number: 0,
+                levels: BTreeMap::new(),
+            })?,
+        )?;
+
+        let e = Database::open(config).err().expect("open should fail");
+        assert!(is_legacy(&e), "{e:#}");
+
+        Ok(())
+    });
+}
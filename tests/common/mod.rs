@@ -0,0 +1,84 @@
+// Each test binary uses its own share of these helpers.
+#![allow(dead_code)]
+
+use std::future::Future;
+
+use mintdb::config::Config;
+
+/// Runs `test` on a glommio executor, given a config for a fresh data directory that's
+/// removed once the test is done.
+pub fn run<F, Fut>(test: F)
+where
+    F: FnOnce(Config) -> Fut + Send + 'static,
+    Fut: Future<Output = anyhow::Result<()>>,
+{
+    glommio::LocalExecutorBuilder::default()
+        .spawn(|| async move {
+            let dir = tempfile::tempdir()?;
+            test(Config::new(dir.path())).await
+        })
+        .expect("Failed to spawn executor")
+        .join()
+        .expect("Executor panicked")
+        .expect("Test failed");
+}
+
+/// Shorthand for a key or value.
+pub fn b(s: &str) -> bytes::Bytes {
+    bytes::Bytes::copy_from_slice(s.as_bytes())
+}
+
+/// Copies the files of the database open in `from` into `to`, as a crash of the process
+/// would leave them: with everything written to them, whether or not it was fsynced.
+pub fn copy_dir(from: &std::path::Path, to: &std::path::Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(to)?;
+
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            std::fs::copy(entry.path(), target)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The path of the SSTable numbered `file_number` in the database in `data_dir`.
+pub fn sstable_path(data_dir: &std::path::Path, file_number: u64) -> std::path::PathBuf {
+    use mintdb::sstable::manager::{format_file_name, FileNo, SSTABLE_FILE_EXT};
+
+    data_dir
+        .join("sstables")
+        .join(format_file_name(FileNo(file_number), SSTABLE_FILE_EXT))
+}
+
+/// Flips the bits of the first byte of the first occurrence of `needle` in the file at
+/// `path`, returning whether it was found.
+pub fn corrupt(path: &std::path::Path, needle: &[u8]) -> std::io::Result<bool> {
+    let mut data = std::fs::read(path)?;
+
+    let Some(at) = data
+        .windows(needle.len())
+        .position(|window| window == needle)
+    else {
+        return Ok(false);
+    };
+
+    data[at] ^= 0xff;
+    std::fs::write(path, data)?;
+
+    Ok(true)
+}
+
+/// The path of the manifest CURRENT points at in the database in `data_dir`.
+pub fn current_manifest(data_dir: &std::path::Path) -> std::io::Result<std::path::PathBuf> {
+    let manifests = data_dir.join("manifests");
+    let current = std::fs::read_to_string(manifests.join("CURRENT"))?;
+    let name = current.split(' ').next().expect("CURRENT names a manifest");
+
+    Ok(manifests.join(name))
+}
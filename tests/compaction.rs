@@ -0,0 +1,385 @@
+mod common;
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
+
+use common::{b, run};
+use mintdb::{
+    compaction::{
+        CompactionFilter, CompactionStrategy, CompactionVerification, CompactionVerificationFailed,
+        FilterDecision,
+    },
+    sstable::Level,
+    Database,
+};
+
+/// Removes every key under `tmp/`.
+#[derive(Debug)]
+struct DropTemporary;
+
+impl CompactionFilter for DropTemporary {
+    fn filter(&self, _: Level, key: &bytes::Bytes, _: &bytes::Bytes) -> FilterDecision {
+        match key.starts_with(b"tmp/") {
+            true => FilterDecision::Remove,
+            false => FilterDecision::Keep,
+        }
+    }
+}
+
+#[test]
+fn compaction_filter_only_applies_once_compacted() {
+    run(|mut config| async move {
+        config.compaction_filter = Some(Arc::new(DropTemporary));
+
+        let mut db = Database::open(config)?;
+
+        // Two overlapping files, so compaction merges them rather than moving them down
+        // as they are, which wouldn't run them through the filter.
+        for half in 0..2 {
+            for i in (half..20).step_by(2) {
+                db.put(format!("tmp/{i:02}"), "scratch").await?;
+                db.put(format!("keep/{i:02}"), "data").await?;
+            }
+            db.flush().await?;
+        }
+
+        assert_eq!(db.get(&b("tmp/07")).await?, Some(b("scratch")));
+        assert_eq!(db.count(..).await?, 40);
+
+        db.compact().await?;
+
+        assert_eq!(db.get(&b("tmp/07")).await?, None);
+        assert_eq!(db.get(&b("keep/07")).await?, Some(b("data")));
+
+        let keys = db
+            .scan(..)
+            .map(|entry| Ok(entry?.0))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        assert_eq!(
+            keys,
+            (0..20)
+                .map(|i| b(&format!("keep/{i:02}")))
+                .collect::<Vec<_>>()
+        );
+
+        Ok(())
+    });
+}
+
+#[test]
+fn compact_level_merges_l0_into_l1() {
+    run(|config| async move {
+        let mut db = Database::open(config)?;
+        let cf = db.default_cf();
+
+        // Three overlapping files, each overwriting or deleting some of the last's keys.
+        for round in 0..3 {
+            for i in (round..30).step_by(3 - round) {
+                db.put(format!("key{i:02}"), format!("round {round}"))
+                    .await?;
+            }
+            db.delete(format!("key{:02}", round * 10)).await?;
+            db.flush().await?;
+        }
+
+        let levels = |db: &Database| -> anyhow::Result<Vec<Level>> {
+            Ok(db
+                .live_files(&cf)?
+                .into_iter()
+                .map(|(level, _)| level)
+                .collect())
+        };
+        assert_eq!(levels(&db)?, vec![Level(0); 3]);
+
+        let expected = db.scan(..).collect::<anyhow::Result<Vec<_>>>()?;
+
+        let result = db.compact_level(Level(0)).await?;
+        assert_eq!(result.input_files, 3);
+
+        let after = levels(&db)?;
+        assert!(!after.is_empty());
+        assert!(after.iter().all(|level| *level == Level(1)), "{after:?}");
+
+        assert_eq!(db.scan(..).collect::<anyhow::Result<Vec<_>>>()?, expected);
+        assert_eq!(db.get(&b("key29")).await?, Some(b("round 2")));
+        assert_eq!(db.get(&b("key20")).await?, None);
+
+        Ok(())
+    });
+}
+
+/// The number of runs in each non-empty level of the default column family: sorted runs
+/// are told apart by their sub-level.
+fn runs_per_level(db: &Database) -> anyhow::Result<BTreeMap<u32, usize>> {
+    let mut runs = BTreeMap::<u32, BTreeSet<u32>>::new();
+
+    for (level, file) in db.live_files(&db.default_cf())? {
+        runs.entry(level.0).or_default().insert(file.sub_level);
+    }
+
+    Ok(runs
+        .into_iter()
+        .map(|(level, subs)| (level, subs.len()))
+        .collect())
+}
+
+/// Overwrites the same keys in each of `flushes` flushes, compacting after each.
+async fn write_workload(
+    db: &mut Database,
+    flushes: usize,
+    mut after: impl FnMut(&Database, usize) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    for flush in 1..=flushes {
+        for i in 0..50 {
+            db.put(format!("key{i:02}"), format!("flush {flush}"))
+                .await?;
+        }
+        db.flush().await?;
+        db.compact().await?;
+
+        after(db, flush)?;
+    }
+
+    for i in 0..50 {
+        assert_eq!(
+            db.get(&b(&format!("key{i:02}"))).await?,
+            Some(b(&format!("flush {flushes}")))
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn leveled_compaction_keeps_a_single_run_below_l0() {
+    run(|config| async move {
+        let mut db = Database::open(config)?;
+
+        write_workload(&mut db, 10, |db, _| {
+            assert_eq!(runs_per_level(db)?, BTreeMap::from([(1, 1)]));
+            Ok(())
+        })
+        .await
+    });
+}
+
+#[test]
+fn size_tiered_compaction_merges_full_tiers_into_the_next() {
+    run(|mut config| async move {
+        config.compaction_strategy = CompactionStrategy::SizeTiered { min_runs: 4 };
+
+        let mut db = Database::open(config)?;
+
+        // Each level collects up to three runs before its fourth merges them into one run
+        // in the next, so the runs per level count the flushes in base 4.
+        write_workload(&mut db, 20, |db, flushes| {
+            let expected = (0..3)
+                .map(|level| (level, flushes / 4usize.pow(level) % 4))
+                .filter(|(_, runs)| *runs > 0)
+                .collect::<BTreeMap<_, _>>();
+            assert_eq!(runs_per_level(db)?, expected, "after {flushes} flushes");
+            Ok(())
+        })
+        .await
+    });
+}
+
+/// The file numbers in each level of the default column family.
+fn files_by_level(db: &Database) -> anyhow::Result<BTreeMap<u32, BTreeSet<u64>>> {
+    let mut levels = BTreeMap::<u32, BTreeSet<u64>>::new();
+
+    for (level, file) in db.live_files(&db.default_cf())? {
+        levels.entry(level.0).or_default().insert(file.file_number);
+    }
+
+    Ok(levels)
+}
+
+#[test]
+fn disjoint_files_move_down_and_merges_only_pull_in_overlaps() {
+    run(|config| async move {
+        let mut db = Database::open(config.clone())?;
+
+        for prefix in ["a", "b", "c", "d"] {
+            for i in 0..20 {
+                db.put(format!("{prefix}{i:02}"), "v1").await?;
+            }
+            db.flush().await?;
+        }
+
+        let l0 = files_by_level(&db)?.remove(&0).expect("flushed to L0");
+        let on_disk = std::fs::read_dir(config.data_dir.join("sstables"))?.count();
+
+        // None of the files overlap, so each is moved as it is.
+        let result = db.compact_level(Level(0)).await?;
+        assert_eq!(result.files_moved, 4);
+        assert_eq!((result.input_files, result.output_files), (0, 0));
+        assert_eq!(files_by_level(&db)?, BTreeMap::from([(1, l0.clone())]));
+        assert_eq!(
+            std::fs::read_dir(config.data_dir.join("sstables"))?.count(),
+            on_disk
+        );
+
+        // A file overlapping only the `b` file is merged with just that one.
+        db.put("b05", "v2").await?;
+        db.put("b06", "v2").await?;
+        db.flush().await?;
+
+        let result = db.compact_level(Level(0)).await?;
+        assert_eq!((result.input_files, result.files_moved), (2, 0));
+        assert_eq!(result.output_files, 1);
+
+        let l1 = &files_by_level(&db)?[&1];
+        assert_eq!(l1.len(), 4);
+        assert_eq!(l1.intersection(&l0).count(), 3);
+
+        assert_eq!(db.get(&b("b05")).await?, Some(b("v2")));
+        assert_eq!(db.get(&b("b07")).await?, Some(b("v1")));
+        assert_eq!(db.count(..).await?, 80);
+
+        Ok(())
+    });
+}
+
+#[test]
+fn failed_verification_aborts_and_keeps_the_inputs() {
+    run(|mut config| async move {
+        config.compaction_filter = Some(Arc::new(DropTemporary));
+        config.compaction_verification = Some(CompactionVerification {
+            max_filtered_fraction: 0.5,
+        });
+
+        let mut db = Database::open(config.clone())?;
+
+        // Three in four entries are temporary, more than verification lets the filter drop.
+        for half in 0..2 {
+            for i in (half..40).step_by(2) {
+                let key = match i % 4 {
+                    0 => format!("keep/{i:02}"),
+                    _ => format!("tmp/{i:02}"),
+                };
+                db.put(key, "v").await?;
+            }
+            db.flush().await?;
+        }
+
+        let before = files_by_level(&db)?;
+        let sstables = || -> anyhow::Result<BTreeSet<_>> {
+            std::fs::read_dir(config.data_dir.join("sstables"))?
+                .map(|entry| Ok(entry?.file_name()))
+                .collect()
+        };
+        let on_disk = sstables()?;
+
+        let e = db
+            .compact_level(Level(0))
+            .await
+            .expect_err("too much filtered");
+        let Some(failed) = e.downcast_ref::<CompactionVerificationFailed>() else {
+            panic!("{e:#}");
+        };
+        assert!(failed.reason.contains("filter"), "{failed}");
+
+        // The outputs are gone, and the inputs still live.
+        assert_eq!(files_by_level(&db)?, before);
+        assert_eq!(sstables()?, on_disk);
+        assert_eq!(db.count(..).await?, 40);
+        assert_eq!(db.get(&b("tmp/01")).await?, Some(b("v")));
+        drop(db);
+
+        let db = Database::open(config)?;
+        assert_eq!(files_by_level(&db)?, before);
+        assert_eq!(db.count(..).await?, 40);
+
+        Ok(())
+    });
+}
+
+#[test]
+fn finalize_bulk_load_leaves_no_l0_and_disjoint_levels() {
+    run(|config| async move {
+        let mut db = Database::open(config)?;
+
+        // Each round overwrites an interleaved slice of the last one's keys.
+        for round in 0..4 {
+            let pairs = (0..2_000)
+                .rev()
+                .filter(|i| i % (round + 1) == 0)
+                .map(|i| (b(&format!("key{i:05}")), b(&format!("round {round}"))));
+            db.ingest_unsorted(pairs).await?;
+        }
+        db.put("key99999", "put").await?;
+        assert!(files_by_level(&db)?
+            .get(&0)
+            .is_some_and(|files| files.len() > 1));
+
+        db.finalize_bulk_load().await?;
+
+        let files = db.live_files(&db.default_cf())?;
+        assert!(files.iter().all(|(level, _)| level.0 > 0));
+
+        for (i, (level, file)) in files.iter().enumerate() {
+            for (other_level, other) in &files[i + 1..] {
+                assert!(level != other_level || !file.overlaps_file(other)?);
+            }
+        }
+
+        for i in (0..2_000).step_by(11) {
+            let round = (0..4).rev().find(|round| i % (round + 1) == 0).unwrap();
+            assert_eq!(
+                db.get(&b(&format!("key{i:05}"))).await?,
+                Some(b(&format!("round {round}")))
+            );
+        }
+        assert_eq!(db.get(&b("key99999")).await?, Some(b("put")));
+        assert_eq!(db.count(..).await?, 2_001);
+
+        Ok(())
+    });
+}
+
+#[test]
+fn compaction_result_reports_the_reclaimed_tombstones() {
+    run(|config| async move {
+        let mut db = Database::open(config)?;
+
+        for i in 0..1_000 {
+            db.put(
+                format!("key{i:04}"),
+                format!("value {i} {}", "x".repeat(100)),
+            )
+            .await?;
+        }
+        db.flush().await?;
+
+        for i in (0..1_000).filter(|i| i % 10 < 3) {
+            db.delete(format!("key{i:04}")).await?;
+        }
+        db.flush().await?;
+        let l0_files = files_by_level(&db)?[&0].len();
+        assert!(l0_files >= 2);
+
+        // Nothing is below L1, so the tombstones have nothing left to hide.
+        let result = db.compact_level(Level(0)).await?;
+        assert_eq!(result.input_files, l0_files);
+        assert_eq!(result.files_moved, 0);
+        assert_eq!(result.entries_read, 1_300);
+        assert_eq!(result.tombstones_dropped, 300);
+        assert_eq!(result.entries_written, 700);
+        assert_eq!((result.expired_dropped, result.entries_filtered), (0, 0));
+        assert!(result.output_files >= 1);
+        assert!(
+            result.output_bytes < result.input_bytes,
+            "{} bytes written from {}",
+            result.output_bytes,
+            result.input_bytes
+        );
+
+        assert_eq!(db.count(..).await?, 700);
+        assert_eq!(db.get(&b("key0001")).await?, None);
+
+        Ok(())
+    });
+}
@@ -0,0 +1,59 @@
+mod common;
+
+use common::{b, run};
+use mintdb::{compression::Compression, Database};
+
+#[test]
+fn lz4_round_trips_highly_compressible_data() {
+    let data = vec![0; 1024 * 1024];
+    let compressed = Compression::Lz4.compress(&data);
+
+    assert_eq!(Compression::Lz4.decompress(&compressed).unwrap(), data);
+}
+
+#[test]
+fn lz4_rejects_a_corrupt_size_before_allocating() {
+    let mut compressed = Compression::Lz4.compress(b"some data to compress");
+    compressed[..4].copy_from_slice(&u32::MAX.to_le_bytes());
+
+    assert!(Compression::Lz4.decompress(&compressed).is_err());
+}
+
+#[test]
+fn recompress_shrinks_files_in_place() {
+    run(|config| async move {
+        let mut db = Database::open(config.clone())?;
+        let cf = db.default_cf();
+
+        for i in 0..1000 {
+            db.put(format!("key{i:04}"), "a very compressible value ".repeat(8))
+                .await?;
+        }
+        db.flush().await?;
+
+        let before = db.live_files(&cf)?;
+        db.close().await?;
+
+        let mut config = config;
+        config.sstable_compression = Compression::Lz4;
+
+        let mut db = Database::open(config)?;
+        assert_eq!(db.recompress().await?, before.len());
+
+        let after = db.live_files(&cf)?;
+        assert_eq!(after.len(), before.len());
+
+        for ((level, old), (new_level, new)) in before.iter().zip(&after) {
+            assert_eq!(level, new_level);
+            assert_eq!(old.sub_level, new.sub_level);
+            assert_eq!(old.smallest_key, new.smallest_key);
+            assert_eq!(old.largest_key, new.largest_key);
+            assert!(new.file_size < old.file_size);
+        }
+
+        let value = db.get(&b("key0500")).await?;
+        assert_eq!(value, Some(b(&"a very compressible value ".repeat(8))));
+
+        Ok(())
+    });
+}
@@ -0,0 +1,48 @@
+mod common;
+
+use std::{sync::Arc, time::Duration};
+
+use common::{b, run};
+use mintdb::{clock::ManualClock, Database};
+
+#[test]
+fn counts_live_keys_rather_than_versions() {
+    run(|mut config| async move {
+        let clock = Arc::new(ManualClock::new());
+        config.clock = clock.clone();
+
+        let mut db = Database::open(config)?;
+
+        for i in 0..100 {
+            db.put(format!("key{i:03}"), "v1").await?;
+        }
+        db.flush().await?;
+
+        // Overwrites in the memtable and in a second file shadow the first versions.
+        for i in 0..50 {
+            db.put(format!("key{i:03}"), "v2").await?;
+        }
+        db.flush().await?;
+        for i in 0..25 {
+            db.put(format!("key{i:03}"), "v3").await?;
+        }
+
+        for i in (0..100).step_by(10) {
+            db.delete(format!("key{i:03}")).await?;
+        }
+        db.delete_range("key090", Some(b("key095"))).await?;
+        db.put_with_ttl("key099", "v", Duration::from_secs(10))
+            .await?;
+
+        // 10 point deletes, and 4 more from the range (key090 was already gone).
+        assert_eq!(db.count(..).await?, 100 - 10 - 4);
+        assert_eq!(db.count(b("key000")..b("key050")).await?, 50 - 5);
+        assert_eq!(db.count(b("key095")..).await?, 5);
+
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(db.count(b("key095")..).await?, 4);
+        assert_eq!(db.count(b("key100")..).await?, 0);
+
+        Ok(())
+    });
+}
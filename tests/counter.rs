@@ -0,0 +1,60 @@
+mod common;
+
+use common::{b, run};
+use mintdb::{
+    counter::{self, CounterOverflow, CounterOverflowed, NotACounter},
+    Database,
+};
+
+#[test]
+fn increments_sum_across_flushes() {
+    run(|config| async move {
+        let mut db = Database::open(config.clone())?;
+        let mut sums = [0i64; 3];
+
+        // Interleaved increments of a few counters, flushed partway through so that some
+        // read their previous total from an SSTable and some from the memtable.
+        for i in 0..300i64 {
+            let which = (i % 3) as usize;
+            let delta = if i % 7 == 0 { -i } else { i };
+            sums[which] += delta;
+
+            let total = db.increment(format!("counter{which}"), delta).await?;
+            assert_eq!(total, sums[which]);
+
+            if i % 50 == 49 {
+                db.flush().await?;
+            }
+        }
+
+        for (which, sum) in sums.iter().enumerate() {
+            let key = b(&format!("counter{which}"));
+            assert_eq!(db.get(&key).await?, Some(counter::encode(*sum)));
+        }
+        db.close().await?;
+
+        let mut db = Database::open(config)?;
+        assert_eq!(db.increment("counter0", 0).await?, sums[0]);
+
+        Ok(())
+    });
+}
+
+#[test]
+fn increments_fail_on_other_values_and_overflow() {
+    run(|mut config| async move {
+        config.counter_overflow = CounterOverflow::Error;
+        let mut db = Database::open(config)?;
+
+        db.put("text", "not a counter").await?;
+        let e = db.increment("text", 1).await.expect_err("not a counter");
+        assert!(e.downcast_ref::<NotACounter>().is_some(), "{e:#}");
+
+        db.increment("big", i64::MAX).await?;
+        let e = db.increment("big", 1).await.expect_err("overflows");
+        assert!(e.downcast_ref::<CounterOverflowed>().is_some(), "{e:#}");
+        assert_eq!(db.get(&b("big")).await?, Some(counter::encode(i64::MAX)));
+
+        Ok(())
+    });
+}
@@ -0,0 +1,199 @@
+mod common;
+
+use common::{b, run, sstable_path};
+use mintdb::{options::WriteOptions, Database};
+
+#[test]
+fn drop_prefix_removes_only_that_prefix() {
+    run(|config| async move {
+        let mut db = Database::open(config.clone())?;
+
+        for i in 0..200 {
+            db.put(format!("a/{i:03}"), "a").await?;
+            db.put(format!("b/{i:03}"), "b").await?;
+
+            // Flushed and unflushed keys alike.
+            if i == 100 {
+                db.flush().await?;
+            }
+        }
+        // Sorts right after the prefix, without starting with it.
+        db.put("a0", "neighbour").await?;
+
+        db.drop_prefix("a/").await?;
+
+        let check = |db: Database| async move {
+            let keys = db
+                .scan(..)
+                .map(|entry| Ok(entry?.0))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+
+            let expected = std::iter::once(b("a0"))
+                .chain((0..200).map(|i| b(&format!("b/{i:03}"))))
+                .collect::<Vec<_>>();
+            assert_eq!(keys, expected);
+            assert_eq!(db.get(&b("a/150")).await?, None);
+
+            anyhow::Ok(db)
+        };
+
+        let mut db = check(db).await?;
+        db.compact().await?;
+        let db = check(db).await?;
+        db.close().await?;
+
+        check(Database::open(config)?).await?;
+
+        Ok(())
+    });
+}
+
+/// Whether any file in the database's directory holds `needle`.
+fn on_disk(data_dir: &std::path::Path, needle: &[u8]) -> anyhow::Result<bool> {
+    for entry in std::fs::read_dir(data_dir)? {
+        let entry = entry?;
+
+        let found = match entry.file_type()?.is_dir() {
+            true => on_disk(&entry.path(), needle)?,
+            false => std::fs::read(entry.path())?
+                .windows(needle.len())
+                .any(|window| window == needle),
+        };
+
+        if found {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+#[test]
+fn guarantee_deleted_removes_the_bytes_from_disk() {
+    run(|config| async move {
+        let mut db = Database::open(config.clone())?;
+
+        // Spread over several levels and the memtable.
+        for round in 0..3 {
+            for i in 0..50 {
+                db.put(format!("key{i:02}"), format!("secret-{round}-{i:02}"))
+                    .await?;
+            }
+
+            db.flush().await?;
+            if round == 0 {
+                db.compact().await?;
+            }
+        }
+        db.put("key20", "secret-3-20").await?;
+
+        assert!(on_disk(&config.data_dir, b"secret-0-25")?);
+
+        db.guarantee_deleted("key20", Some(b("key30"))).await?;
+
+        for round in 0..4 {
+            for i in 20..30 {
+                let value = format!("secret-{round}-{i:02}");
+                assert!(!on_disk(&config.data_dir, value.as_bytes())?, "{value}");
+            }
+        }
+
+        // Everything outside the range is still there.
+        assert!(on_disk(&config.data_dir, b"secret-2-19")?);
+        assert_eq!(db.get(&b("key19")).await?, Some(b("secret-2-19")));
+        assert_eq!(db.get(&b("key30")).await?, Some(b("secret-2-30")));
+        assert_eq!(db.get(&b("key25")).await?, None);
+
+        Ok(())
+    });
+}
+
+#[test]
+fn delete_if_exists_only_writes_a_tombstone_for_present_keys() {
+    run(|mut config| async move {
+        config.wal_preallocate_chunk = 0;
+
+        let mut db = Database::open(config.clone())?;
+        let wal_len = || std::fs::metadata(config.data_dir.join("wal.log")).map(|m| m.len());
+
+        let options = WriteOptions {
+            delete_if_exists: true,
+            ..WriteOptions::default()
+        };
+
+        db.put("flushed", "v").await?;
+        db.flush().await?;
+        db.put("unflushed", "v").await?;
+
+        let (len, seqno) = (wal_len()?, db.last_seqno());
+        db.delete_opt("absent", &options).await?;
+        assert_eq!(wal_len()?, len);
+        assert_eq!(db.last_seqno(), seqno);
+
+        for key in ["flushed", "unflushed"] {
+            let (len, seqno) = (wal_len()?, db.last_seqno());
+            db.delete_opt(key, &options).await?;
+            assert!(wal_len()? > len);
+            assert!(db.last_seqno() > seqno);
+            assert_eq!(db.get(&b(key)).await?, None);
+        }
+
+        // Already deleted, so there's nothing to write.
+        let len = wal_len()?;
+        db.delete_opt("flushed", &options).await?;
+        assert_eq!(wal_len()?, len);
+
+        Ok(())
+    });
+}
+
+#[test]
+fn drop_files_before_drops_only_files_wholly_below_the_bound() {
+    run(|config| async move {
+        let mut db = Database::open(config.clone())?;
+        let cf = db.default_cf();
+
+        // Five files of time-ordered keys, a hundred each.
+        for file in 0..5 {
+            for i in file * 100..(file + 1) * 100 {
+                db.put(format!("t{i:04}"), "v").await?;
+            }
+            db.flush().await?;
+        }
+        let files = db
+            .live_files(&cf)?
+            .into_iter()
+            .map(|(_, file)| file.file_number)
+            .collect::<Vec<_>>();
+        assert_eq!(files.len(), 5);
+
+        // A snapshot could still read the files.
+        let snapshot = db.snapshot();
+        assert!(db.drop_files_before("t0250").await.is_err());
+        drop(snapshot);
+
+        assert_eq!(db.drop_files_before("t0250").await?, 2);
+
+        let mut remaining = db
+            .live_files(&cf)?
+            .into_iter()
+            .map(|(_, file)| file.file_number)
+            .collect::<Vec<_>>();
+        remaining.sort();
+        let mut expected = files[2..].to_vec();
+        expected.sort();
+        assert_eq!(remaining, expected);
+
+        for file_no in &files[..2] {
+            assert!(!sstable_path(&config.data_dir, *file_no).exists());
+        }
+
+        // The file reaching the bound keeps the keys past it.
+        assert_eq!(db.get(&b("t0000")).await?, None);
+        assert_eq!(db.get(&b("t0249")).await?, None);
+        assert_eq!(db.get(&b("t0250")).await?, Some(b("v")));
+        assert_eq!(db.count(..).await?, 250);
+
+        Ok(())
+    });
+}
@@ -0,0 +1,46 @@
+mod common;
+
+use common::{b, run};
+use mintdb::{batch::WriteBatch, db::DiskBudgetExceeded, Database};
+
+#[test]
+fn writes_are_rejected_over_budget_until_compaction_reclaims_space() {
+    run(|mut config| async move {
+        config.max_total_bytes = Some(16 * 1024);
+
+        let mut db = Database::open(config)?;
+        let cf = db.default_cf();
+
+        let mut batch = WriteBatch::new();
+        for i in 0..400 {
+            batch.put(&cf, format!("key{i:03}"), "x".repeat(100));
+        }
+        db.write(batch).await?;
+        db.flush().await?;
+
+        // Compacting after the flush can't reclaim space from live data.
+        let stats = db.stats();
+        assert!(stats.total_bytes > 16 * 1024, "{}", stats.total_bytes);
+        assert!(stats.over_budget());
+
+        let e = db.put("one more", "value").await.unwrap_err();
+        let Some(budget) = e.downcast_ref::<DiskBudgetExceeded>() else {
+            panic!("{e:#}");
+        };
+        assert_eq!(budget.max_total_bytes, 16 * 1024);
+        assert_eq!(db.get(&b("one more")).await?, None);
+
+        // Deletes are let through, since they're how space is freed...
+        db.delete_range("key000", Some(b("key390"))).await?;
+        db.flush().await?;
+        db.compact().await?;
+
+        // ...and once compaction has dropped what they deleted, writes resume.
+        assert!(!db.stats().over_budget());
+        db.put("one more", "value").await?;
+        assert_eq!(db.get(&b("one more")).await?, Some(b("value")));
+        assert_eq!(db.get(&b("key399")).await?, Some(b(&"x".repeat(100))));
+
+        Ok(())
+    });
+}
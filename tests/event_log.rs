@@ -0,0 +1,52 @@
+mod common;
+
+use common::{b, run};
+use mintdb::Database;
+
+#[test]
+fn events_read_back_in_order_from_any_offset() {
+    run(|config| async move {
+        let mut db = Database::open(config.clone())?;
+        db.put("unrelated", "v").await?;
+
+        let mut log = db.event_log("events").await?;
+        for i in 0..100u64 {
+            assert_eq!(log.append(format!("event {i}")).await?, i);
+        }
+        assert_eq!(log.next_offset(), 100);
+
+        // All of them from the middle on, in order, with none missing.
+        let events = log.read_from(40, usize::MAX).await?;
+        let expected = (40..100)
+            .map(|i| (i, b(&format!("event {i}"))))
+            .collect::<Vec<_>>();
+        assert_eq!(events, expected);
+
+        // And the same a page at a time.
+        let mut paged = Vec::new();
+        let mut offset = 40;
+        loop {
+            let page = log.read_from(offset, 7).await?;
+            let Some((last, _)) = page.last() else {
+                break;
+            };
+            offset = last + 1;
+            paged.extend(page);
+        }
+        assert_eq!(paged, expected);
+        assert_eq!(log.read_from(100, 10).await?, []);
+
+        // Offsets carry on past truncated events, and across a reopen.
+        log.truncate_before(50).await?;
+        assert_eq!(log.read_from(0, 1).await?, [(50, b("event 50"))]);
+        db.close().await?;
+
+        let mut db = Database::open(config)?;
+        let mut log = db.event_log("events").await?;
+        assert_eq!(log.append("event 100").await?, 100);
+        assert_eq!(log.read_from(0, usize::MAX).await?.len(), 51);
+        assert_eq!(db.get(&b("unrelated")).await?, Some(b("v")));
+
+        Ok(())
+    });
+}
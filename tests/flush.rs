@@ -0,0 +1,203 @@
+mod common;
+
+use std::{sync::Arc, time::Duration};
+
+use common::{b, run, sstable_path};
+use mintdb::{
+    clock::{Clock, ManualClock},
+    retry::RetryPolicy,
+    sstable::Level,
+    Database,
+};
+
+#[test]
+fn out_of_order_flush_holds_back_committed_seqno() {
+    run(|config| async move {
+        let mut db = Database::open(config)?;
+        let cf = db.default_cf();
+
+        db.put("a", "1").await?;
+        db.freeze_memtable(&cf).await?;
+        db.put("b", "2").await?;
+        db.freeze_memtable(&cf).await?;
+
+        let committed = db.last_committed_seqno(&cf)?;
+
+        // The second memtable is flushed, but the first still holds older seqnos.
+        db.flush_frozen(&cf, 1).await?;
+        assert_eq!(db.last_committed_seqno(&cf)?, committed);
+
+        db.flush_frozen(&cf, 0).await?;
+        assert_eq!(db.last_committed_seqno(&cf)?, db.last_seqno());
+
+        assert_eq!(db.get(&b("a")).await?, Some(b("1")));
+        assert_eq!(db.get(&b("b")).await?, Some(b("2")));
+
+        Ok(())
+    });
+}
+
+#[test]
+fn out_of_order_flush_of_overlapping_memtable_is_rejected() {
+    run(|config| async move {
+        let mut db = Database::open(config)?;
+        let cf = db.default_cf();
+
+        db.put("k", "old").await?;
+        db.freeze_memtable(&cf).await?;
+        db.put("k", "new").await?;
+        db.freeze_memtable(&cf).await?;
+
+        assert!(db.flush_frozen(&cf, 1).await.is_err());
+        assert_eq!(db.get(&b("k")).await?, Some(b("new")));
+
+        db.flush_frozen(&cf, 0).await?;
+        db.flush_frozen(&cf, 0).await?;
+        assert_eq!(db.get(&b("k")).await?, Some(b("new")));
+        assert_eq!(db.last_committed_seqno(&cf)?, db.last_seqno());
+
+        Ok(())
+    });
+}
+
+#[test]
+fn memtable_is_flushed_once_it_gets_too_old() {
+    run(|mut config| async move {
+        let clock = Arc::new(ManualClock::new());
+        config.clock = clock.clone();
+        config.max_memtable_age = Some(Duration::from_secs(10));
+
+        let mut db = Database::open(config)?;
+        let cf = db.default_cf();
+        let files = |db: &Database| anyhow::Ok(db.live_files(&cf)?.len());
+
+        // The age is counted from the oldest write in the memtable, not the latest.
+        db.put("a", "1").await?;
+        clock.advance(Duration::from_secs(6));
+        db.put("b", "2").await?;
+        clock.advance(Duration::from_secs(3));
+        assert!(!db.flush_aged_memtables().await?);
+        assert_eq!(files(&db)?, 0);
+
+        clock.advance(Duration::from_secs(1));
+        assert!(db.flush_aged_memtables().await?);
+        assert_eq!(files(&db)?, 1);
+
+        // Nothing to flush, however long it's been.
+        clock.advance(Duration::from_secs(60));
+        assert!(!db.flush_aged_memtables().await?);
+
+        // A write past the limit flushes without being asked.
+        db.put("c", "3").await?;
+        clock.advance(Duration::from_secs(10));
+        db.put("d", "4").await?;
+        assert_eq!(files(&db)?, 2);
+
+        for (key, val) in [("a", "1"), ("b", "2"), ("c", "3"), ("d", "4")] {
+            assert_eq!(db.get(&b(key)).await?, Some(b(val)));
+        }
+
+        Ok(())
+    });
+}
+
+#[test]
+fn small_disjoint_flushes_go_straight_to_l1() {
+    run(|mut config| async move {
+        config.l1_flush_max_bytes = Some(16 * 1024);
+
+        let mut db = Database::open(config)?;
+        let cf = db.default_cf();
+
+        let levels = |db: &Database| -> anyhow::Result<Vec<Level>> {
+            let mut levels = db
+                .live_files(&cf)?
+                .into_iter()
+                .map(|(level, file)| (file.file_number, level))
+                .collect::<Vec<_>>();
+            levels.sort();
+
+            Ok(levels.into_iter().map(|(_, level)| level).collect())
+        };
+
+        for prefix in ["b", "d", "a"] {
+            for i in 0..10 {
+                db.put(format!("{prefix}{i}"), "v").await?;
+            }
+            db.flush().await?;
+        }
+        assert_eq!(levels(&db)?, [Level(1); 3]);
+
+        // Overlapping a file in L1, so it could hide a newer version there.
+        db.put("b5", "newer").await?;
+        db.flush().await?;
+        assert_eq!(levels(&db)?.last(), Some(&Level(0)));
+
+        // Disjoint from L1, but overlapping that L0 file.
+        db.put("c", "v").await?;
+        db.put("b9", "newest").await?;
+        db.flush().await?;
+        assert_eq!(levels(&db)?.last(), Some(&Level(0)));
+
+        // Too large, however disjoint.
+        for i in 0..100 {
+            db.put(format!("e{i:02}"), "x".repeat(500)).await?;
+        }
+        db.flush().await?;
+        assert_eq!(levels(&db)?.last(), Some(&Level(0)));
+
+        assert_eq!(db.get(&b("a3")).await?, Some(b("v")));
+        assert_eq!(db.get(&b("b5")).await?, Some(b("newer")));
+        assert_eq!(db.get(&b("b9")).await?, Some(b("newest")));
+        assert_eq!(db.get(&b("d0")).await?, Some(b("v")));
+
+        Ok(())
+    });
+}
+
+#[test]
+fn flush_retries_past_a_transient_write_error() {
+    run(|mut config| async move {
+        let clock = Arc::new(ManualClock::new());
+        config.clock = clock.clone();
+        config.io_retry = RetryPolicy {
+            max_retries: 2,
+            initial_backoff: Duration::from_secs(1),
+            ..Default::default()
+        };
+
+        let mut db = Database::open(config.clone())?;
+        let cf = db.default_cf();
+
+        db.put("a", "1").await?;
+        db.flush().await?;
+        let (_, first) = db.live_files(&cf)?.remove(0);
+
+        // The next SSTable is written to a device that's always full, as a full disk would
+        // fail it.
+        let next = sstable_path(&config.data_dir, first.file_number + 1);
+        std::os::unix::fs::symlink("/dev/full", &next)?;
+
+        db.put("b", "2").await?;
+        let started = clock.now();
+        db.flush().await?;
+
+        // It backed off once through the clock, then wrote the next file number instead.
+        assert_eq!(clock.now() - started, Duration::from_secs(1));
+        assert!(!next.exists());
+
+        let files = db.live_files(&cf)?;
+        assert_eq!(files.len(), 2);
+        assert!(files
+            .iter()
+            .any(|(_, file)| file.file_number > first.file_number + 1));
+
+        db.close().await?;
+
+        let db = Database::open(config)?;
+        assert_eq!(db.get(&b("a")).await?, Some(b("1")));
+        assert_eq!(db.get(&b("b")).await?, Some(b("2")));
+
+        Ok(())
+    });
+}
@@ -0,0 +1,73 @@
+mod common;
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use common::{b, run};
+use mintdb::{config::Config, Database};
+
+/// Counts the allocations that had to grow in place or move, which is what a buffer
+/// that starts too small costs.
+struct CountingAlloc;
+
+static REALLOCS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        REALLOCS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static ALLOC: CountingAlloc = CountingAlloc;
+
+/// Flushes the same entries under `config`, returning the reallocations the flush made.
+async fn flush_reallocs(config: Config) -> anyhow::Result<usize> {
+    let mut db = Database::open(config)?;
+
+    for i in 0..2_000 {
+        db.put(format!("key{i:05}"), "x".repeat(50)).await?;
+    }
+
+    let before = REALLOCS.load(Ordering::Relaxed);
+    db.flush().await?;
+    let reallocs = REALLOCS.load(Ordering::Relaxed) - before;
+
+    assert_eq!(db.get(&b("key01234")).await?, Some(b(&"x".repeat(50))));
+    db.close().await?;
+
+    Ok(reallocs)
+}
+
+#[test]
+fn flush_honours_the_block_buffer_capacity() {
+    run(|config| async move {
+        let mut small = Config::new(config.data_dir.join("small"));
+        small.block_size = 4096;
+        small.block_buffer_capacity = 0;
+
+        let mut sized = Config::new(config.data_dir.join("sized"));
+        sized.block_size = 4096;
+        sized.block_buffer_capacity = 2 * 4096;
+
+        let small = flush_reallocs(small).await?;
+        let sized = flush_reallocs(sized).await?;
+
+        // A buffer with room for a whole block never grows; an empty one doubles its way
+        // up to the block size.
+        assert!(sized < small, "{sized} vs {small} reallocations");
+
+        Ok(())
+    });
+}
@@ -0,0 +1,81 @@
+mod common;
+
+use common::{b, run};
+use mintdb::{
+    framed::{read_all_framed, read_framed, write_framed, FramedError},
+    Database,
+};
+
+/// The flag in a frame's length prefix saying a checksum follows it.
+const CHECKSUM_FLAG: u8 = 1 << 6;
+
+fn frames(records: &[&str]) -> Vec<u8> {
+    let mut buf = Vec::new();
+
+    for record in records {
+        write_framed(&mut buf, &record.to_string()).unwrap();
+    }
+
+    buf
+}
+
+#[test]
+fn flipped_payload_byte_fails_checksum() {
+    let mut buf = frames(&["hello, world"]);
+    let last = buf.len() - 1;
+    buf[last] ^= 1;
+
+    let result = read_framed::<_, String>(&buf[..]);
+    assert!(matches!(result, Err(FramedError::ChecksumMismatch { .. })));
+}
+
+#[test]
+fn frames_without_checksums_are_still_read() {
+    let payload = postcard::to_stdvec(&"written before checksums".to_string()).unwrap();
+    let mut buf = (payload.len() as u32).to_le_bytes().to_vec();
+    buf.extend(&payload);
+
+    let records = read_all_framed::<_, String>(&buf[..]).unwrap();
+    assert_eq!(records, ["written before checksums"]);
+}
+
+#[test]
+fn cleared_checksum_flag_after_checksummed_frames_is_corruption() {
+    let mut buf = frames(&["first", "second"]);
+    let second = 8 + postcard::to_stdvec(&"first".to_string()).unwrap().len();
+    buf[second + 3] &= !CHECKSUM_FLAG;
+
+    let result = read_all_framed::<_, String>(&buf[..]);
+    assert!(matches!(result, Err(FramedError::MissingChecksum)));
+}
+
+#[test]
+fn wal_recovery_stops_at_a_cleared_checksum_flag() {
+    run(|config| async move {
+        let mut db = Database::open(config.clone())?;
+
+        for i in 0..3 {
+            db.put(format!("k{i}"), "v").await?;
+        }
+
+        drop(db);
+
+        // Clear the flag of the second record.
+        let path = config.data_dir.join("wal.log");
+        let mut wal = std::fs::read(&path)?;
+        let first = u32::from_le_bytes(wal[..4].try_into()?) & !(0b11 << 30);
+        wal[first as usize + 8 + 3] &= !CHECKSUM_FLAG;
+        std::fs::write(&path, wal)?;
+
+        let mut paranoid = config.clone();
+        paranoid.paranoid_checks = true;
+        assert!(Database::open(paranoid).is_err());
+
+        let db = Database::open(config)?;
+        assert_eq!(db.get(&b("k0")).await?, Some(b("v")));
+        assert_eq!(db.get(&b("k1")).await?, None);
+        assert_eq!(db.get(&b("k2")).await?, None);
+
+        Ok(())
+    });
+}
@@ -0,0 +1,85 @@
+mod common;
+
+use std::{sync::Arc, time::Duration};
+
+use common::{b, run};
+use mintdb::{clock::ManualClock, options::WriteOptions, Database};
+
+fn token(token: &str) -> WriteOptions {
+    WriteOptions {
+        idempotency_key: Some(b(token)),
+        ..WriteOptions::default()
+    }
+}
+
+/// Writes `val` under `key` with the idempotency key `t`, returning whether it was applied.
+async fn put(db: &mut Database, t: &str, key: &str, val: &str) -> anyhow::Result<bool> {
+    let seqno = db.last_seqno();
+    db.put_opt(key.to_string(), val.to_string(), &token(t))
+        .await?;
+
+    Ok(db.last_seqno() != seqno)
+}
+
+#[test]
+fn a_retried_write_is_applied_once() {
+    run(|config| async move {
+        let mut db = Database::open(config)?;
+
+        assert!(put(&mut db, "t1", "k", "first").await?);
+        db.put("k", "second").await?;
+
+        // The retry is acknowledged without undoing the write that came after it.
+        assert!(!put(&mut db, "t1", "k", "first").await?);
+        assert_eq!(db.get(&b("k")).await?, Some(b("second")));
+
+        // Other tokens aren't affected.
+        assert!(put(&mut db, "t2", "k", "third").await?);
+        assert_eq!(db.get(&b("k")).await?, Some(b("third")));
+
+        Ok(())
+    });
+}
+
+#[test]
+fn tokens_are_forgotten_after_the_window() {
+    run(|mut config| async move {
+        let clock = Arc::new(ManualClock::new());
+        config.clock = clock.clone();
+        config.idempotency_window = Duration::from_secs(60);
+
+        let mut db = Database::open(config)?;
+
+        assert!(put(&mut db, "t", "k", "v").await?);
+
+        clock.advance(Duration::from_secs(59));
+        assert!(!put(&mut db, "t", "k", "v").await?);
+
+        clock.advance(Duration::from_secs(1));
+        assert!(put(&mut db, "t", "k", "v").await?);
+
+        Ok(())
+    });
+}
+
+#[test]
+fn the_oldest_tokens_are_forgotten_past_capacity() {
+    run(|mut config| async move {
+        config.idempotency_capacity = 2;
+
+        let mut db = Database::open(config)?;
+
+        for t in ["t1", "t2", "t3"] {
+            assert!(put(&mut db, t, "k", t).await?);
+        }
+
+        assert!(!put(&mut db, "t3", "k", "t3").await?);
+        assert!(!put(&mut db, "t2", "k", "t2").await?);
+        assert!(put(&mut db, "t1", "k", "t1").await?);
+
+        // Remembering `t1` again pushed out `t2`, the oldest left.
+        assert!(put(&mut db, "t2", "k", "t2").await?);
+
+        Ok(())
+    });
+}
@@ -0,0 +1,63 @@
+mod common;
+
+use common::{b, run};
+use mintdb::Database;
+
+/// The names of the files in the current directory, which an in-memory database's empty
+/// data directory resolves to.
+fn cwd_entries() -> anyhow::Result<Vec<std::ffi::OsString>> {
+    let mut entries = std::fs::read_dir(".")?
+        .map(|entry| Ok(entry?.file_name()))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    entries.sort();
+
+    Ok(entries)
+}
+
+#[test]
+fn runs_the_standard_operations_without_touching_disk() {
+    run(|_| async move {
+        let before = cwd_entries()?;
+
+        let mut db = Database::open_in_memory();
+        assert!(db.is_in_memory());
+
+        for i in 0..100 {
+            db.put(format!("key{i:03}"), format!("val{i}")).await?;
+        }
+
+        db.put("key000", "overwritten").await?;
+        db.delete("key001").await?;
+        db.delete_range("key050", Some(b("key060"))).await?;
+
+        let cf = db.create_cf("other")?;
+        db.put_cf(&cf, "key000", "elsewhere").await?;
+
+        db.flush().await?;
+        db.compact().await?;
+
+        assert_eq!(db.get(&b("key000")).await?, Some(b("overwritten")));
+        assert_eq!(db.get(&b("key001")).await?, None);
+        assert_eq!(db.get(&b("key055")).await?, None);
+        assert_eq!(db.get(&b("key099")).await?, Some(b("val99")));
+        assert_eq!(db.get_cf(&cf, &b("key000")).await?, Some(b("elsewhere")));
+
+        let keys = db
+            .scan(b("key040")..b("key070"))
+            .map(|entry| Ok(entry?.0))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let expected = (40..50)
+            .chain(60..70)
+            .map(|i| b(&format!("key{i:03}")))
+            .collect::<Vec<_>>();
+        assert_eq!(keys, expected);
+
+        assert_eq!(db.count(..).await?, 100 - 1 - 10);
+
+        db.close().await?;
+
+        assert_eq!(cwd_entries()?, before);
+
+        Ok(())
+    });
+}
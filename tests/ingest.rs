@@ -0,0 +1,49 @@
+mod common;
+
+use common::{b, run};
+use mintdb::Database;
+
+const KEYS: u32 = 20_000;
+
+#[test]
+fn unsorted_ingest_over_the_memory_budget_reads_back_sorted() {
+    run(|mut config| async move {
+        // Enough spilled runs that they're merged in more than one pass.
+        config.ingest_memory_budget = 4 * 1024;
+
+        let mut db = Database::open(config.clone())?;
+        db.put("key00000", "overwritten").await?;
+
+        // Every key once, in a scrambled order, then every tenth again with a new value.
+        let scrambled = (0..KEYS).map(|i| i * 7_919 % KEYS);
+        let updated = (0..KEYS).step_by(10).rev();
+        let pairs = scrambled
+            .map(|i| (i, format!("value {i}")))
+            .chain(updated.map(|i| (i, format!("updated {i}"))))
+            .map(|(i, value)| (b(&format!("key{i:05}")), b(&value)));
+
+        assert_eq!(db.ingest_unsorted(pairs).await?, KEYS as usize);
+        assert!(!config.data_dir.join("ingest").exists());
+
+        let entries = db.scan(..).collect::<anyhow::Result<Vec<_>>>()?;
+        let expected = (0..KEYS)
+            .map(|i| {
+                let value = match i % 10 {
+                    0 => format!("updated {i}"),
+                    _ => format!("value {i}"),
+                };
+
+                (b(&format!("key{i:05}")), b(&value))
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(entries, expected);
+
+        db.close().await?;
+
+        let db = Database::open(config)?;
+        assert_eq!(db.get(&b("key12345")).await?, Some(b("value 12345")));
+        assert_eq!(db.count(..).await?, u64::from(KEYS));
+
+        Ok(())
+    });
+}
@@ -0,0 +1,176 @@
+use bytes::Bytes;
+use mintdb::{
+    iter::{MergeIterator, NewestVersion, Source},
+    key::{Key, SeqNo},
+    value::Value,
+};
+
+fn entry(user_key: &str, seqno: u64, value: Option<&str>) -> anyhow::Result<(Key, Value)> {
+    let value = match value {
+        Some(data) => Value::Data(Bytes::copy_from_slice(data.as_bytes())),
+        None => Value::Tombstone,
+    };
+
+    Ok((
+        Key::new(
+            Bytes::copy_from_slice(user_key.as_bytes()),
+            SeqNo::from(seqno),
+        ),
+        value,
+    ))
+}
+
+/// The user keys, seqnos and data of `entries`, for comparing against what's expected.
+fn collect(
+    entries: impl Iterator<Item = anyhow::Result<(Key, Value)>>,
+) -> anyhow::Result<Vec<(String, u64, String)>> {
+    entries
+        .map(|entry| {
+            let (key, value) = entry?;
+            let data = value.data().expect("tombstones are skipped");
+
+            Ok((
+                String::from_utf8(key.user_key().to_vec())?,
+                u64::from(key.seqno()),
+                String::from_utf8(data.to_vec())?,
+            ))
+        })
+        .collect()
+}
+
+#[test]
+fn newest_version_keeps_the_newest_live_value_per_user_key() -> anyhow::Result<()> {
+    // Sorted by key, so each user key's versions run newest first.
+    let entries = vec![
+        entry("a", 9, Some("a9")),
+        entry("a", 4, None),
+        entry("a", 1, Some("a1")),
+        // Deleted, with an older live version underneath.
+        entry("b", 8, None),
+        entry("b", 3, Some("b3")),
+        // Deleted and rewritten.
+        entry("c", 7, Some("c7")),
+        entry("c", 6, None),
+        entry("c", 2, Some("c2")),
+        entry("d", 5, None),
+        entry("e", 10, Some("e10")),
+    ];
+
+    let expected = [("a", 9, "a9"), ("c", 7, "c7"), ("e", 10, "e10")]
+        .map(|(key, seqno, data)| (key.to_owned(), seqno, data.to_owned()));
+    assert_eq!(collect(NewestVersion::new(entries.into_iter()))?, expected);
+
+    Ok(())
+}
+
+#[test]
+fn newest_version_stops_at_an_error() -> anyhow::Result<()> {
+    let entries = vec![
+        entry("a", 2, Some("a2")),
+        Err(anyhow::anyhow!("failed read")),
+        entry("b", 1, Some("b1")),
+    ];
+
+    let mut newest = NewestVersion::new(entries.into_iter());
+    assert!(newest.next().is_some_and(|entry| entry.is_ok()));
+    assert!(newest.next().is_some_and(|entry| entry.is_err()));
+    assert!(newest.next().is_none());
+
+    Ok(())
+}
+
+/// Three overlapping sources, oldest to newest, as successive flushes of the same keys
+/// would be.
+fn overlapping_sources() -> Vec<Source<'static>> {
+    [
+        vec![
+            entry("a", 1, Some("a1")),
+            entry("b", 2, Some("b2")),
+            entry("c", 3, Some("c3")),
+            entry("e", 4, Some("e4")),
+        ],
+        vec![
+            entry("a", 5, None),
+            entry("c", 6, Some("c6")),
+            entry("d", 7, Some("d7")),
+            entry("e", 8, None),
+        ],
+        vec![
+            entry("a", 9, Some("a9")),
+            entry("b", 10, None),
+            entry("d", 11, None),
+            entry("f", 12, Some("f12")),
+        ],
+    ]
+    .into_iter()
+    .map(|entries| Box::new(entries.into_iter()) as Source<'static>)
+    .collect()
+}
+
+#[test]
+fn merge_of_overlapping_sources_yields_every_version_in_key_order() -> anyhow::Result<()> {
+    // Listed in any order, the sources still merge into Key order.
+    let mut sources = overlapping_sources();
+    sources.rotate_left(1);
+
+    let merged = MergeIterator::new(sources)
+        .map(|entry| Ok(entry?.0))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    assert!(merged.windows(2).all(|pair| pair[0] < pair[1]));
+
+    let versions = merged
+        .iter()
+        .map(|key| (key.user_key().clone(), u64::from(key.seqno())))
+        .collect::<Vec<_>>();
+    let expected = [
+        ("a", 9),
+        ("a", 5),
+        ("a", 1),
+        ("b", 10),
+        ("b", 2),
+        ("c", 6),
+        ("c", 3),
+        ("d", 11),
+        ("d", 7),
+        ("e", 8),
+        ("e", 4),
+        ("f", 12),
+    ]
+    .map(|(key, seqno)| (Bytes::from_static(key.as_bytes()), seqno));
+    assert_eq!(versions, expected);
+
+    Ok(())
+}
+
+#[test]
+fn merge_narrowed_to_the_newest_version_drops_tombstoned_keys() -> anyhow::Result<()> {
+    let live = collect(NewestVersion::new(
+        MergeIterator::new(overlapping_sources()),
+    ))?;
+
+    let expected = [("a", 9, "a9"), ("c", 6, "c6"), ("f", 12, "f12")]
+        .map(|(key, seqno, data)| (key.to_owned(), seqno, data.to_owned()));
+    assert_eq!(live, expected);
+
+    Ok(())
+}
+
+#[test]
+fn merge_stops_at_an_error_from_any_source() {
+    let sources: Vec<Source<'static>> = vec![
+        Box::new(vec![entry("a", 1, Some("a1")), entry("c", 3, Some("c3"))].into_iter()),
+        Box::new(
+            vec![
+                entry("b", 2, Some("b2")),
+                Err(anyhow::anyhow!("failed read")),
+            ]
+            .into_iter(),
+        ),
+    ];
+    let merged = MergeIterator::new(sources).collect::<Vec<_>>();
+
+    // Not even `b` comes out: its source failed as it was refilled, which ends the merge.
+    assert_eq!(merged.len(), 2);
+    assert!(merged[0].is_ok());
+    assert!(merged[1].is_err());
+}
@@ -0,0 +1,90 @@
+mod common;
+
+use std::{collections::BTreeSet, ops::Bound};
+
+use bytes::Bytes;
+use common::{b, run};
+use mintdb::{
+    key::{Key, SeqNo},
+    Database,
+};
+
+const SEQNOS: [u64; 5] = [0, 1, 7, 1 << 40, u64::MAX];
+
+/// Every version in [`SEQNOS`] of each of `user_keys`.
+fn versions(user_keys: &[&str]) -> BTreeSet<Key> {
+    user_keys
+        .iter()
+        .flat_map(|user_key| SEQNOS.map(|seqno| Key::new(b(user_key), SeqNo(seqno))))
+        .collect()
+}
+
+/// The user keys of the versions in `keys` that fall in the `Key` bounds translated from
+/// `range`, with each version counted.
+fn in_range(keys: &BTreeSet<Key>, range: (Bound<Bytes>, Bound<Bytes>)) -> Vec<String> {
+    keys.range(Key::range_by_user_bounds(&range))
+        .map(|key| String::from_utf8_lossy(key.user_key()).into_owned())
+        .collect()
+}
+
+fn repeat(user_key: &str) -> Vec<String> {
+    vec![user_key.to_owned(); SEQNOS.len()]
+}
+
+#[test]
+fn user_key_bounds_take_in_every_version_or_none() {
+    let keys = versions(&["a", "b", "c", "d"]);
+
+    for key in &keys {
+        assert!(Key::range_by_user_key(key.user_key().clone()).contains(key));
+        assert!(Key::user_key_lower_bound(key.user_key().clone()) <= *key);
+        assert!(Key::user_key_upper_bound(key.user_key().clone()) >= *key);
+    }
+
+    let included = in_range(&keys, (Bound::Included(b("b")), Bound::Included(b("c"))));
+    assert_eq!(included, [repeat("b"), repeat("c")].concat());
+
+    let excluded = in_range(&keys, (Bound::Excluded(b("b")), Bound::Excluded(b("d"))));
+    assert_eq!(excluded, repeat("c"));
+
+    let unbounded = in_range(&keys, (Bound::Unbounded, Bound::Excluded(b("b"))));
+    assert_eq!(unbounded, repeat("a"));
+
+    assert!(in_range(&keys, (Bound::Excluded(b("b")), Bound::Excluded(b("c")))).is_empty());
+}
+
+#[test]
+fn scans_return_the_newest_version_of_keys_at_their_bounds() {
+    run(|config| async move {
+        let mut db = Database::open(config)?;
+
+        // Older versions of every key in an SSTable, newer ones of the endpoints in the
+        // memtable.
+        for key in ["a", "b", "c", "d", "e"] {
+            db.put(key, "old").await?;
+        }
+        db.flush().await?;
+        db.put("b", "new").await?;
+        db.put("d", "new").await?;
+
+        let scan = |range: (Bound<Bytes>, Bound<Bytes>)| {
+            db.scan(range).collect::<anyhow::Result<Vec<_>>>()
+        };
+
+        assert_eq!(
+            scan((Bound::Included(b("b")), Bound::Included(b("d"))))?,
+            [(b("b"), b("new")), (b("c"), b("old")), (b("d"), b("new"))]
+        );
+        assert_eq!(
+            scan((Bound::Excluded(b("b")), Bound::Excluded(b("d"))))?,
+            [(b("c"), b("old"))]
+        );
+        assert_eq!(
+            scan((Bound::Excluded(b("a")), Bound::Included(b("b"))))?,
+            [(b("b"), b("new"))]
+        );
+        assert_eq!(db.count(b("b")..=b("d")).await?, 3);
+
+        Ok(())
+    });
+}
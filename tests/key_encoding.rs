@@ -0,0 +1,73 @@
+mod common;
+
+use bytes::Bytes;
+use common::run;
+use mintdb::{batch::WriteBatch, config::Config, sstable::sstable::KeyEncoding, Database};
+
+const KEYS: u64 = 1_000_000;
+
+fn key(i: u64) -> Bytes {
+    Bytes::copy_from_slice(&(i * 10).to_be_bytes())
+}
+
+/// Writes `KEYS` sequential keys under `encoding` and flushes them, returning the
+/// database and the total size of its SSTables.
+async fn write_sequential(
+    mut config: Config,
+    encoding: KeyEncoding,
+) -> anyhow::Result<(Database, u64)> {
+    config.key_encoding = encoding;
+    config.wal_enabled = false;
+
+    let mut db = Database::open(config)?;
+    let cf = db.default_cf();
+
+    for chunk in 0..KEYS / 10_000 {
+        let mut batch = WriteBatch::new();
+        for i in chunk * 10_000..(chunk + 1) * 10_000 {
+            batch.put(&cf, key(i), "v");
+        }
+        db.write(batch).await?;
+    }
+    db.flush().await?;
+
+    let size = db
+        .live_files(&cf)?
+        .iter()
+        .map(|(_, file)| file.file_size)
+        .sum();
+
+    Ok((db, size))
+}
+
+#[test]
+fn delta_encoding_shrinks_sequential_keys_and_reads_them_back() {
+    run(|config| async move {
+        let plain_dir = tempfile::tempdir()?;
+        let (_, plain) =
+            write_sequential(Config::new(plain_dir.path()), KeyEncoding::Plain).await?;
+
+        let (db, delta) = write_sequential(config, KeyEncoding::DeltaU64).await?;
+        assert!(delta * 2 < plain, "{delta} vs {plain} bytes");
+
+        for i in [0, 1, 4_999, 500_000, KEYS - 1] {
+            assert_eq!(db.get(&key(i)).await?, Some(Bytes::from("v")));
+        }
+        // Between and past the written keys.
+        assert_eq!(
+            db.get(&Bytes::copy_from_slice(&15u64.to_be_bytes()))
+                .await?,
+            None
+        );
+        assert_eq!(db.get(&key(KEYS)).await?, None);
+
+        let mut expected = 0;
+        for entry in db.scan(key(123_456)..key(133_456)) {
+            assert_eq!(entry?.0, key(123_456 + expected));
+            expected += 1;
+        }
+        assert_eq!(expected, 10_000);
+
+        Ok(())
+    });
+}
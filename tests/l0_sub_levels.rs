@@ -0,0 +1,78 @@
+mod common;
+
+use std::collections::BTreeSet;
+
+use common::{b, run};
+use mintdb::{options::ReadOptions, sstable::Level, Database};
+
+#[test]
+fn lookup_checks_at_most_one_file_per_sub_level() {
+    run(|mut config| async move {
+        config.l0_sub_levels = true;
+
+        let mut db = Database::open(config)?;
+        let cf = db.default_cf();
+
+        // Two runs of overlapping flushes, one over the `k` keys and one over the `z` keys,
+        // which don't overlap each other, so each sub-level ends up with one file of each.
+        for i in 0..5 {
+            db.put("k0", format!("{i}")).await?;
+            db.put(format!("k{}", i + 1), format!("{i}")).await?;
+            db.put("k9", format!("{i}")).await?;
+            db.flush().await?;
+
+            db.put(format!("z{i}"), format!("{i}")).await?;
+            db.put("z9", format!("{i}")).await?;
+            db.flush().await?;
+        }
+
+        let files = db.live_files(&cf)?;
+        assert_eq!(files.len(), 10);
+        assert!(files.iter().all(|(level, _)| *level == Level(0)));
+
+        let sub_levels = files
+            .iter()
+            .map(|(_, file)| file.sub_level)
+            .collect::<BTreeSet<_>>();
+        assert_eq!(sub_levels.len(), 5);
+
+        for (key, expected) in [("k0", "4"), ("k1", "0"), ("k5", "4"), ("z2", "2")] {
+            let (value, stats) = db
+                .get_with_stats(&cf, &b(key), &ReadOptions::default())
+                .await?;
+
+            assert_eq!(value, Some(b(expected)), "{key}");
+            assert!(stats.sstables() <= sub_levels.len(), "{key}");
+        }
+
+        Ok(())
+    });
+}
+
+#[test]
+fn out_of_order_flush_keeps_newer_data_above() {
+    run(|mut config| async move {
+        config.l0_sub_levels = true;
+
+        let mut db = Database::open(config)?;
+        let cf = db.default_cf();
+
+        db.put("a", "1").await?;
+        db.flush().await?;
+
+        db.put("a", "2").await?;
+        db.freeze_memtable(&cf).await?;
+        db.put("b", "1").await?;
+        db.freeze_memtable(&cf).await?;
+
+        // The newer memtable doesn't overlap the older, so it can go first; the older is
+        // still put above the file it overlaps.
+        db.flush_frozen(&cf, 1).await?;
+        db.flush_frozen(&cf, 0).await?;
+
+        assert_eq!(db.get(&b("a")).await?, Some(b("2")));
+        assert_eq!(db.get(&b("b")).await?, Some(b("1")));
+
+        Ok(())
+    });
+}
@@ -0,0 +1,86 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::run;
+use mintdb::{
+    db::AlreadyOpen,
+    lock::{LockStrategy, StaleLock, LOCK_FILE_NAME},
+    recovery::RecoveryAction,
+    Database,
+};
+
+#[test]
+fn second_open_fails_promptly_once_its_lock_timeout_passes() {
+    run(|mut config| async move {
+        config.open_lock_timeout = Some(Duration::from_millis(100));
+
+        let db = Database::open(config.clone())?;
+
+        let started = Instant::now();
+        let e = Database::open(config.clone())
+            .err()
+            .expect("the database is already open");
+        let waited = started.elapsed();
+
+        assert!(e.downcast_ref::<AlreadyOpen>().is_some(), "{e:#}");
+        assert!(waited >= Duration::from_millis(100), "{waited:?}");
+        assert!(waited < Duration::from_secs(2), "{waited:?}");
+
+        db.close().await?;
+        Database::open(config)?;
+
+        Ok(())
+    });
+}
+
+#[test]
+fn stale_lockfile_is_detected_and_reclaimed() {
+    run(|mut config| async move {
+        config.lock_strategy = LockStrategy::Lockfile { break_stale: false };
+        config.open_lock_timeout = Some(Duration::from_millis(100));
+        let path = config.data_dir.join(LOCK_FILE_NAME);
+
+        let db = Database::open(config.clone())?;
+        let owner = std::fs::read_to_string(&path)?;
+        let (pid, host) = owner
+            .split_once(' ')
+            .expect("lockfile holds a PID and host");
+        assert_eq!(pid.parse::<u32>()?, std::process::id());
+        db.close().await?;
+        assert!(!path.exists());
+
+        // As a process that exited without closing the database would leave it.
+        let mut child = std::process::Command::new("true").spawn()?;
+        let dead = child.id();
+        child.wait()?;
+        std::fs::write(&path, format!("{dead} {host}"))?;
+
+        let e = Database::open(config.clone())
+            .err()
+            .expect("the lockfile is stale");
+        let stale = e
+            .downcast_ref::<StaleLock>()
+            .expect("open fails on a stale lock");
+        assert_eq!(stale.pid, dead);
+        assert!(path.exists());
+
+        config.lock_strategy = LockStrategy::Lockfile { break_stale: true };
+        let (db, report) = Database::open_with_report(config.clone())?;
+        assert_eq!(
+            report.actions,
+            [RecoveryAction::StaleLockBroken { pid: dead }]
+        );
+        assert_eq!(std::fs::read_to_string(&path)?, owner);
+
+        // A lockfile held by a live process is never broken.
+        let e = Database::open(config.clone())
+            .err()
+            .expect("the database is already open");
+        assert!(e.downcast_ref::<AlreadyOpen>().is_some(), "{e:#}");
+
+        db.close().await?;
+
+        Ok(())
+    });
+}
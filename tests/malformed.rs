@@ -0,0 +1,138 @@
+//! The inputs the fuzz targets under `fuzz/` found panics with, which now fail to decode
+//! instead.
+
+use std::ops::Bound;
+
+use mintdb::{
+    bloom::BloomFilter,
+    config::Config,
+    framed::{read_framed, write_framed, FramedError},
+    key::SeqNo,
+    recovery::OpenReport,
+    sstable::{
+        manager::FileNo,
+        manifest::{Manifest, ManifestRecord},
+        sstable::{BlockReadOptions, SSTable, SSTableFooter},
+    },
+    wal::{Wal, WalRecord},
+};
+
+/// The regression input `name`, without the first byte the `framed` target picks a reader
+/// with.
+fn framed_input(name: &str) -> anyhow::Result<Vec<u8>> {
+    let path = format!(
+        "{}/fuzz/regressions/framed/{name}",
+        env!("CARGO_MANIFEST_DIR")
+    );
+
+    Ok(std::fs::read(path)?.split_off(1))
+}
+
+fn sstable_input(name: &str) -> anyhow::Result<Vec<u8>> {
+    let path = format!(
+        "{}/fuzz/regressions/sstable/{name}",
+        env!("CARGO_MANIFEST_DIR")
+    );
+
+    Ok(std::fs::read(path)?)
+}
+
+#[test]
+fn short_footer_is_an_error() -> anyhow::Result<()> {
+    let data = sstable_input("short-footer")?;
+
+    assert!(SSTableFooter::decode_from(&data[..]).is_err());
+    assert!(SSTable::read_footer(std::io::Cursor::new(&data)).is_err());
+
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("short.sstable");
+    std::fs::write(&path, &data)?;
+    assert!(SSTable::open(path).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn block_past_the_end_of_the_file_is_an_error() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("overflow.sstable");
+    std::fs::write(&path, sstable_input("block-offset-overflow")?)?;
+
+    let table = SSTable::open(path)?;
+    assert!(!table.index().is_empty());
+
+    let e = table
+        .read_block(0, BlockReadOptions::default())
+        .expect_err("block is out of bounds");
+    assert!(format!("{e:#}").contains("out of bounds"), "{e:#}");
+
+    let mut entries = table.range(
+        (Bound::Unbounded, Bound::Unbounded),
+        BlockReadOptions::default(),
+    );
+    assert!(entries.next().is_some_and(|entry| entry.is_err()));
+
+    let last_key = table.index()[0].last_key().user_key().clone();
+    assert!(table
+        .get(&last_key, SeqNo(u64::MAX), BlockReadOptions::default())
+        .is_err());
+
+    Ok(())
+}
+
+#[test]
+fn manifest_allocating_the_last_file_number_loads_without_overflowing() -> anyhow::Result<()> {
+    // Found before frames had checksums, which logs without are now refused for.
+    let data = framed_input("manifest-file-number-overflow")?;
+    assert!(Manifest::load(&data[..]).is_err());
+
+    let mut data = Vec::new();
+    write_framed(&mut data, &ManifestRecord::Snapshot(Manifest::new()))?;
+    write_framed(
+        &mut data,
+        &ManifestRecord::AllocFileNumber(FileNo(u64::MAX)),
+    )?;
+
+    // The next file number saturates rather than wrapping past zero.
+    let (manifest, _) = Manifest::load(&data[..])?;
+    assert_eq!(manifest.next_file_number, FileNo(u64::MAX));
+
+    Ok(())
+}
+
+#[test]
+fn frame_with_a_huge_length_is_cut_short() -> anyhow::Result<()> {
+    let data = framed_input("wal-bad-record")?;
+    assert!(read_framed::<_, WalRecord>(&data[..]).is_err());
+
+    // The largest length there is, with its checksum flag set, and nowhere near that much
+    // data after it.
+    let mut data = (u32::MAX >> 2 | 1 << 30).to_le_bytes().to_vec();
+    data.extend([0xff; 16]);
+    assert!(matches!(
+        read_framed::<_, WalRecord>(&data[..]),
+        Err(FramedError::UnexpectedEnd)
+    ));
+
+    // Which in a WAL is a torn tail.
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("wal.log");
+    std::fs::write(&path, &data)?;
+
+    let wal = Wal::open(path, &Config::new(dir.path()), &mut OpenReport::default())?;
+    assert!(wal.replay()?.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn empty_bloom_filter_rules_nothing_out() -> anyhow::Result<()> {
+    let data = framed_input("empty-bloom-filter")?;
+    let mut reader = &data[..];
+
+    let filter = read_framed::<_, BloomFilter>(&mut reader)?;
+    assert!(filter.may_contain(reader));
+    assert!(filter.may_contain(b"anything else"));
+
+    Ok(())
+}
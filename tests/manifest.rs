@@ -0,0 +1,394 @@
+mod common;
+
+use common::{b, copy_dir, current_manifest, run};
+use mintdb::{
+    column_family::ColumnFamilyId,
+    compression::Compression,
+    config::Config,
+    framed::read_all_checksummed,
+    sstable::{
+        manager::{manifest_files, read_manifest, write_manifest, FileNo},
+        manifest::{InvalidManifest, LevelMeta, Manifest, ManifestRecord, OptionsMismatch},
+        Level,
+    },
+    Database,
+};
+
+#[test]
+fn manifest_is_snapshotted_every_interval() {
+    run(|mut config| async move {
+        config.manifest_snapshot_interval = 8;
+
+        let mut db = Database::open(config.clone())?;
+        let first = current_manifest(&config.data_dir)?;
+
+        for i in 0..20 {
+            db.put(format!("key{i:02}"), "v").await?;
+            db.flush().await?;
+
+            // Never more than an interval of records past the snapshot it starts with.
+            let records: Vec<ManifestRecord> =
+                read_all_checksummed(std::fs::File::open(current_manifest(&config.data_dir)?)?)?;
+            assert!(matches!(records[0], ManifestRecord::Snapshot(_)));
+            assert!(records.len() <= 8, "{} records", records.len());
+        }
+
+        assert_ne!(current_manifest(&config.data_dir)?, first);
+        db.close().await?;
+
+        // Opening replays the snapshot and what came after it.
+        let (_, since_snapshot) =
+            Manifest::load_from_file(&std::fs::File::open(current_manifest(&config.data_dir)?)?)?;
+        assert!(since_snapshot < 8);
+
+        let db = Database::open(config)?;
+        for i in 0..20 {
+            assert_eq!(db.get(&b(&format!("key{i:02}"))).await?, Some(b("v")));
+        }
+
+        Ok(())
+    });
+}
+
+#[test]
+fn opening_with_another_wal_compression_is_a_mismatch() {
+    run(|mut config| async move {
+        config.wal_compression = Compression::Lz4;
+
+        let mut db = Database::open(config.clone())?;
+        db.put("k", "v").await?;
+        db.close().await?;
+
+        config.wal_compression = Compression::None;
+        let e = Database::open(config.clone())
+            .err()
+            .expect("open should fail");
+        let Some(mismatch) = e.chain().find_map(|e| e.downcast_ref::<OptionsMismatch>()) else {
+            panic!("{e:#}");
+        };
+        assert_eq!(mismatch.option, "WAL compression");
+        assert_eq!(mismatch.stored, "Lz4");
+        assert_eq!(mismatch.configured, "None");
+        assert!(format!("{e:#}").contains("created with WAL compression Lz4"));
+
+        // The database is untouched, and opens with the setting it was created with.
+        config.wal_compression = Compression::Lz4;
+        let db = Database::open(config)?;
+        assert_eq!(db.get(&b("k")).await?, Some(b("v")));
+
+        Ok(())
+    });
+}
+
+#[test]
+fn open_picks_a_newer_manifest_than_current_names() {
+    run(|mut config| async move {
+        config.manifest_snapshot_interval = 4;
+        config.manifest_retention = 2;
+
+        let mut db = Database::open(config.clone())?;
+        let current = config.data_dir.join("manifests").join("CURRENT");
+
+        db.put("old", "v").await?;
+        db.flush().await?;
+        let older = current_manifest(&config.data_dir)?;
+        let older_current = std::fs::read(&current)?;
+
+        // Enough flushes to rotate to a new manifest, which CURRENT then names.
+        let mut i = 0;
+        while current_manifest(&config.data_dir)? == older {
+            db.put(format!("new{i}"), "v").await?;
+            db.flush().await?;
+            i += 1;
+        }
+        let newer = current_manifest(&config.data_dir)?;
+        db.close().await?;
+
+        // As a crash between writing the new manifest and renaming CURRENT over would
+        // leave it.
+        assert!(older.exists());
+        std::fs::write(&current, older_current)?;
+        assert_eq!(current_manifest(&config.data_dir)?, older);
+
+        let db = Database::open(config.clone())?;
+        assert_eq!(current_manifest(&config.data_dir)?, newer);
+        assert_eq!(db.get(&b("old")).await?, Some(b("v")));
+        for i in 0..i {
+            assert_eq!(db.get(&b(&format!("new{i}"))).await?, Some(b("v")));
+        }
+
+        Ok(())
+    });
+}
+
+#[test]
+fn manifest_round_trips_through_json() {
+    run(|config| async move {
+        let mut db = Database::open(config.clone())?;
+        let other = db.create_cf("other")?;
+
+        for i in 0..3 {
+            db.put(format!("key{i}"), "v").await?;
+            db.put_cf(&other, format!("key{i}"), "w").await?;
+            db.flush().await?;
+        }
+        db.compact().await?;
+        db.put("key9", "v").await?;
+        db.flush().await?;
+        let expected = db.scan(..).collect::<anyhow::Result<Vec<_>>>()?;
+        db.close().await?;
+
+        let exported = serde_json::to_string_pretty(&read_manifest(&config.data_dir)?)?;
+        write_manifest(&config, serde_json::from_str(&exported)?)?;
+
+        let imported = read_manifest(&config.data_dir)?;
+        let original: Manifest = serde_json::from_str(&exported)?;
+        assert_eq!(
+            serde_json::to_value(&imported.column_families)?,
+            serde_json::to_value(&original.column_families)?
+        );
+        assert_eq!(imported.options, original.options);
+        assert!(imported.next_file_number > original.next_file_number);
+
+        let db = Database::open(config)?;
+        assert_eq!(db.scan(..).collect::<anyhow::Result<Vec<_>>>()?, expected);
+        let other = db.cf("other").expect("column family was kept");
+        assert_eq!(db.get_cf(&other, &b("key2")).await?, Some(b("w")));
+
+        Ok(())
+    });
+}
+
+#[test]
+fn importing_an_inconsistent_manifest_fails() {
+    run(|config| async move {
+        let mut db = Database::open(config.clone())?;
+
+        for keys in [["a", "c"], ["b", "b"]] {
+            for key in keys {
+                db.put(key, "v").await?;
+            }
+            db.flush().await?;
+        }
+        db.close().await?;
+
+        let before = std::fs::read(current_manifest(&config.data_dir)?)?;
+
+        // The L0 files' key ranges overlap, so they can't share a sub-level of L1.
+        let mut manifest = read_manifest(&config.data_dir)?;
+        let levels = &mut manifest
+            .column_families
+            .get_mut(&ColumnFamilyId::DEFAULT)
+            .expect("default column family")
+            .levels;
+        let mut files = levels.remove(&Level(0)).expect("L0").files;
+        for file in files.values_mut() {
+            file.sub_level = 0;
+        }
+        levels.insert(
+            Level(1),
+            LevelMeta {
+                level: Level(1),
+                files,
+            },
+        );
+
+        let e = write_manifest(&config, manifest).expect_err("manifest is inconsistent");
+        assert!(e.downcast_ref::<InvalidManifest>().is_some(), "{e:#}");
+        assert_eq!(std::fs::read(current_manifest(&config.data_dir)?)?, before);
+
+        let db = Database::open(config)?;
+        assert_eq!(db.get(&b("b")).await?, Some(b("v")));
+
+        Ok(())
+    });
+}
+
+#[test]
+fn open_at_manifest_sees_the_database_as_of_a_rotated_manifest() {
+    run(|mut config| async move {
+        config.manifest_snapshot_interval = 4;
+        config.manifest_retention = 2;
+
+        let mut db = Database::open(config.clone())?;
+
+        db.put("key", "old").await?;
+        db.put("gone", "v").await?;
+        db.flush().await?;
+        let [older] = manifest_files(&config.data_dir)?[..] else {
+            panic!("one manifest before rotating");
+        };
+
+        let mut i = 0;
+        while manifest_files(&config.data_dir)?.len() == 1 {
+            db.put(format!("new{i}"), "v").await?;
+            db.flush().await?;
+            i += 1;
+        }
+        db.put("key", "new").await?;
+        db.delete("gone").await?;
+        db.flush().await?;
+
+        assert_eq!(manifest_files(&config.data_dir)?[0], older);
+
+        // Opened alongside the primary, pinned to the state before it rotated.
+        let mut pinned = Database::open_at_manifest(config.clone(), older)?;
+        assert!(pinned.is_secondary());
+        assert_eq!(pinned.get(&b("key")).await?, Some(b("old")));
+        assert_eq!(pinned.get(&b("gone")).await?, Some(b("v")));
+        assert_eq!(pinned.get(&b(&format!("new{}", i - 1))).await?, None);
+
+        assert!(pinned.put("key", "x").await.is_err());
+        assert!(pinned.refresh().is_err());
+
+        assert_eq!(db.get(&b("key")).await?, Some(b("new")));
+        assert_eq!(db.get(&b("gone")).await?, None);
+
+        Ok(())
+    });
+}
+
+/// Flushes writes until the manifest rotates to a new file, returning its number.
+#[test]
+fn open_rebuilds_a_corrupt_current_from_the_newest_manifest() {
+    run(|mut config| async move {
+        config.manifest_snapshot_interval = 4;
+        config.manifest_retention = 2;
+
+        let mut db = Database::open(config.clone())?;
+        let data_dir = config.data_dir.clone();
+        let current = data_dir.join("manifests").join("CURRENT");
+
+        rotate(&mut db, &data_dir).await?;
+        rotate(&mut db, &data_dir).await?;
+        db.put("last", "v").await?;
+        db.flush().await?;
+        let named = current_manifest(&data_dir)?;
+        let files = manifest_files(&data_dir)?;
+        assert_eq!(files.len(), 3);
+        db.close().await?;
+
+        let valid = std::fs::read_to_string(&current)?;
+        let (name, crc) = valid.split_once(' ').expect("CURRENT has a checksum");
+        let flipped = if crc.starts_with('0') { "1" } else { "0" };
+
+        // A name that fails its checksum, a torn name, and garbage.
+        for contents in [
+            format!("{name} {flipped}{}", &crc[1..]),
+            name[..name.len() / 2].to_owned(),
+            "\0\0\0\0".to_owned(),
+        ] {
+            std::fs::write(&current, &contents)?;
+
+            let db = Database::open(config.clone())?;
+            assert_eq!(db.get(&b("key")).await?, Some(b("v")));
+            assert_eq!(db.get(&b("last")).await?, Some(b("v")));
+            db.close().await?;
+
+            // Rebuilt to name the newest manifest, keeping the retained ones.
+            assert_eq!(current_manifest(&data_dir)?, named);
+            assert_eq!(std::fs::read_to_string(&current)?, valid);
+            assert_eq!(manifest_files(&data_dir)?, files);
+        }
+
+        Ok(())
+    });
+}
+
+async fn rotate(db: &mut Database, data_dir: &std::path::Path) -> anyhow::Result<FileNo> {
+    let current = current_manifest(data_dir)?;
+
+    while current_manifest(data_dir)? == current {
+        db.put("key", "v").await?;
+        db.flush().await?;
+    }
+
+    Ok(*manifest_files(data_dir)?
+        .last()
+        .expect("CURRENT names a manifest"))
+}
+
+#[test]
+fn rotation_keeps_the_retained_manifests_and_any_open_one() {
+    run(|mut config| async move {
+        config.manifest_snapshot_interval = 4;
+        config.manifest_retention = 2;
+
+        let mut db = Database::open(config.clone())?;
+        let data_dir = config.data_dir.clone();
+
+        let mut rotated = manifest_files(&data_dir)?;
+        for _ in 0..4 {
+            rotated.push(rotate(&mut db, &data_dir).await?);
+            assert_eq!(
+                manifest_files(&data_dir)?,
+                rotated[rotated.len().saturating_sub(3)..],
+            );
+        }
+
+        // A handle open at the oldest kept manifest holds it past the window...
+        let oldest = manifest_files(&data_dir)?[0];
+        let pinned = Database::open_at_manifest(config.clone(), oldest)?;
+
+        let newest = rotate(&mut db, &data_dir).await?;
+        let files = manifest_files(&data_dir)?;
+        assert_eq!(files.len(), 4);
+        assert!(files.contains(&oldest) && files.contains(&newest));
+
+        // ...until it's closed, and a later rotation deletes it.
+        drop(pinned);
+        rotate(&mut db, &data_dir).await?;
+        let files = manifest_files(&data_dir)?;
+        assert_eq!(files.len(), 3);
+        assert!(!files.contains(&oldest));
+
+        Ok(())
+    });
+}
+
+#[test]
+fn compressed_snapshots_are_smaller_and_load_the_same() {
+    run(|mut config| async move {
+        let mut db = Database::open(config.clone())?;
+
+        for i in 0..150 {
+            db.put(format!("key{i:04}"), "v").await?;
+            db.flush().await?;
+        }
+        let expected = db.scan(..).collect::<anyhow::Result<Vec<_>>>()?;
+        db.close().await?;
+
+        let manifest = read_manifest(&config.data_dir)?;
+        let compressed_dir = tempfile::tempdir()?;
+        copy_dir(&config.data_dir, compressed_dir.path())?;
+
+        // Each manifest written holds nothing but the snapshot.
+        config.sstable_compression = Compression::None;
+        write_manifest(&config, manifest.clone())?;
+        let plain = std::fs::metadata(current_manifest(&config.data_dir)?)?.len();
+
+        let mut compressed_config = Config::new(compressed_dir.path());
+        compressed_config.sstable_compression = Compression::Lz4;
+        write_manifest(&compressed_config, manifest)?;
+        let compressed = std::fs::metadata(current_manifest(compressed_dir.path())?)?.len();
+
+        assert!(compressed * 3 < plain * 2, "{compressed} vs {plain} bytes");
+
+        let db = Database::open(compressed_config.clone())?;
+        assert_eq!(db.scan(..).collect::<anyhow::Result<Vec<_>>>()?, expected);
+        drop(db);
+
+        // Rotation writes its snapshot compressed too.
+        compressed_config.manifest_snapshot_interval = 4;
+        let mut db = Database::open(compressed_config.clone())?;
+        rotate(&mut db, compressed_dir.path()).await?;
+        let rotated = std::fs::read(current_manifest(compressed_dir.path())?)?;
+        assert_ne!(rotated[3] & 0x80, 0, "snapshot frame isn't compressed");
+        drop(db);
+
+        let db = Database::open(compressed_config)?;
+        assert_eq!(db.get(&b("key0149")).await?, Some(b("v")));
+
+        Ok(())
+    });
+}
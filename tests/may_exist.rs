@@ -0,0 +1,39 @@
+mod common;
+
+use common::{b, run};
+use mintdb::Database;
+
+#[test]
+fn may_exist_rules_out_keys_outside_every_file() {
+    run(|config| async move {
+        let mut db = Database::open(config)?;
+
+        for i in 100..200 {
+            db.put(format!("k{i}"), "v").await?;
+        }
+        db.flush().await?;
+
+        for i in 300..400 {
+            db.put(format!("k{i}"), "v").await?;
+        }
+        db.flush().await?;
+
+        db.put("memtable", "v").await?;
+        db.put("deleted", "v").await?;
+        db.delete("deleted").await?;
+
+        for i in (100..200).chain(300..400) {
+            assert!(db.may_exist(&b(&format!("k{i}"))).await?, "k{i}");
+        }
+        assert!(db.may_exist(&b("memtable")).await?);
+
+        // Before, between, and after the files' key ranges.
+        for key in ["a", "k000", "k250", "k999", "z"] {
+            assert!(!db.may_exist(&b(key)).await?, "{key}");
+            assert_eq!(db.get(&b(key)).await?, None);
+        }
+        assert!(!db.may_exist(&b("deleted")).await?);
+
+        Ok(())
+    });
+}
@@ -0,0 +1,57 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::{b, run};
+use mintdb::{cache::BlockCache, memtable::MemtableSize, Database};
+
+const BUDGET: usize = 256 * 1024;
+
+#[test]
+fn block_cache_shrinks_as_memtables_grow() {
+    run(|mut config| async move {
+        let cache = Arc::new(BlockCache::new(BUDGET));
+        config.block_cache = Some(cache.clone());
+        config.total_memory_budget = Some(BUDGET);
+        // Only the budget flushes the memtable early.
+        config.memtable_size = MemtableSize::Fixed(4 * BUDGET);
+
+        let mut db = Database::open(config)?;
+
+        for i in 0..2_000 {
+            db.put(format!("key{i:04}"), "x".repeat(100)).await?;
+        }
+        db.flush().await?;
+
+        // Reading everything back fills the cache to about the whole budget.
+        for i in 0..2_000 {
+            db.get(&b(&format!("key{i:04}"))).await?;
+        }
+        let full = cache.size();
+        assert!(full > BUDGET * 3 / 4, "{full} bytes cached");
+
+        // Under half the budget, so the memtable isn't flushed, but the cache gives up what
+        // it takes.
+        for i in 0..600 {
+            db.put(format!("new{i:04}"), "x".repeat(100)).await?;
+        }
+        let stats = db.stats();
+        assert_eq!(stats.memory_budget, Some(BUDGET as u64));
+        assert!(stats.memory_bytes <= BUDGET as u64, "{stats:?}");
+        assert!(
+            cache.size() < full - 60_000,
+            "{} bytes cached",
+            cache.size()
+        );
+        assert!(cache.effective_capacity() < BUDGET);
+
+        // Past half the budget, the memtable is flushed and the cache gets its room back.
+        for i in 600..2_000 {
+            db.put(format!("new{i:04}"), "x".repeat(100)).await?;
+        }
+        assert!(db.stats().memory_bytes <= BUDGET as u64);
+        assert!(cache.effective_capacity() > BUDGET / 2);
+
+        Ok(())
+    });
+}
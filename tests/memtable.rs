@@ -0,0 +1,126 @@
+mod common;
+
+use bytes::Bytes;
+use common::{b, run};
+use mintdb::{
+    key::{Key, SeqNo},
+    memtable::MemTable,
+    tombstone::RangeTombstone,
+    Database,
+};
+
+#[test]
+fn frozen_memtables_verify_unchanged() -> anyhow::Result<()> {
+    let mut table = MemTable::new();
+
+    for seqno in 1..100 {
+        let key = Key::new(
+            Bytes::from(format!("key{}", seqno % 30)),
+            SeqNo::from(seqno),
+        );
+
+        if seqno % 7 == 0 {
+            table.delete(key);
+        } else {
+            table.put(key, Bytes::from(format!("value {seqno}")));
+        }
+    }
+    table.delete_range(RangeTombstone {
+        start: b("key1"),
+        end: Some(b("key2")),
+        seqno: SeqNo::from(100),
+    });
+
+    let frozen = table.freeze();
+    frozen.debug_verify_unchanged();
+
+    // A clone, as a flush takes, carries the digest along with the contents.
+    frozen.clone().debug_verify_unchanged();
+
+    // The active table starts over empty, and freezes again just as well.
+    assert!(table.freeze().data().is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn frozen_memtables_report_a_duplicate_write() -> anyhow::Result<()> {
+    let mut table = MemTable::new();
+    let key = Key::new(b("key"), SeqNo::from(2));
+
+    table.put(Key::new(b("key"), SeqNo::from(1)), b("older"));
+    table.put(key.clone(), b("first"));
+    table.put(Key::new(b("other"), SeqNo::from(3)), b("v"));
+    let frozen = table.freeze();
+    assert_eq!(frozen.duplicate(), None);
+
+    table.put(key.clone(), b("first"));
+    table.delete(key.clone());
+    let frozen = table.freeze();
+    assert_eq!(frozen.duplicate(), Some(&key));
+    assert!(frozen.data()[&key].data().is_none());
+
+    // Freezing starts the next table over.
+    assert_eq!(table.freeze().duplicate(), None);
+
+    Ok(())
+}
+
+#[test]
+fn incremental_size_matches_the_recomputed_size() -> anyhow::Result<()> {
+    let mut table = MemTable::new();
+
+    for seqno in 1..2_000u64 {
+        let key = Key::new(
+            Bytes::from(format!("key{}", seqno % 97)),
+            SeqNo::from(seqno % 500 + 1),
+        );
+        let value = Bytes::from("v".repeat((seqno % 13) as usize));
+
+        // Seqnos repeat, so some of these replace an entry with a longer or shorter one.
+        match seqno % 5 {
+            0 => table.delete(key),
+            1 => table.put_expiring(key, value, seqno),
+            2 if seqno % 3 == 0 => table.delete_range(RangeTombstone {
+                start: key.user_key().clone(),
+                end: (seqno % 2 == 0).then(|| b("key9")),
+                seqno: key.seqno(),
+            }),
+            _ => table.put(key, value),
+        }
+
+        assert_eq!(table.size(), table.recompute_size());
+    }
+
+    let size = table.size();
+    let frozen = table.freeze();
+    assert_eq!(frozen.size(), size);
+    assert_eq!(frozen.recompute_size(), size);
+    assert_eq!(table.size(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn flush_passes_the_check() {
+    run(|mut config| async move {
+        // Which recounts the memtable as it's frozen, too.
+        config.paranoid_checks = true;
+
+        let mut db = Database::open(config)?;
+
+        for i in 0..1_000 {
+            db.put(format!("key{i:04}"), format!("value {i}")).await?;
+        }
+        db.delete("key0005").await?;
+        db.delete_range("key0100", Some(b("key0200"))).await?;
+        db.flush().await?;
+
+        assert_eq!(db.get(&b("key0004")).await?, Some(b("value 4")));
+        assert_eq!(db.get(&b("key0005")).await?, None);
+        assert_eq!(db.get(&b("key0150")).await?, None);
+        assert_eq!(db.count(..).await?, 1_000 - 1 - 100);
+
+        Ok(())
+    });
+}
@@ -0,0 +1,39 @@
+use futures_lite::future::{block_on, poll_once};
+use mintdb::oneshot::{channel, RecvError};
+
+#[test]
+fn send_is_received() {
+    let (tx, rx) = channel();
+
+    assert_eq!(tx.send(7), Ok(()));
+    assert_eq!(block_on(rx), Ok(7));
+}
+
+#[test]
+fn dropping_the_sender_is_not_a_cancellation() {
+    let (tx, mut rx) = channel::<()>();
+
+    assert!(block_on(poll_once(&mut rx)).is_none());
+
+    drop(tx);
+    assert_eq!(block_on(rx), Err(RecvError::Dropped));
+}
+
+#[test]
+fn cancel_is_distinct_from_a_dropped_sender() {
+    let (tx, mut rx) = channel::<()>();
+
+    assert!(block_on(poll_once(&mut rx)).is_none());
+
+    tx.cancel();
+    assert_eq!(block_on(rx), Err(RecvError::Canceled));
+}
+
+#[test]
+fn send_fails_once_the_receiver_is_gone() {
+    let (tx, rx) = channel();
+
+    drop(rx);
+    assert!(tx.is_closed());
+    assert_eq!(tx.send(7), Err(7));
+}
@@ -0,0 +1,137 @@
+mod common;
+
+use common::{b, run};
+use futures_lite::future::poll_once;
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use mintdb::{
+    bloom::BloomFilterLevels,
+    clock::{Clock, ManualClock},
+    options::{ReadOptions, Timeout, WriteOptions},
+    Database,
+};
+
+#[test]
+fn non_caching_reads_leave_the_block_cache_alone() {
+    run(|config| async move {
+        let mut db = Database::open(config)?;
+        let cf = db.default_cf();
+
+        db.put("k", "v").await?;
+        db.flush().await?;
+
+        let uncached = ReadOptions {
+            fill_cache: false,
+            ..ReadOptions::default()
+        };
+
+        for _ in 0..2 {
+            let (value, stats) = db.get_with_stats(&cf, &b("k"), &uncached).await?;
+            assert_eq!(value, Some(b("v")));
+            assert_eq!((stats.blocks(), stats.cache_hits()), (1, 0));
+        }
+
+        let default = ReadOptions::default();
+        let (_, stats) = db.get_with_stats(&cf, &b("k"), &default).await?;
+        assert_eq!(stats.cache_hits(), 0);
+        let (_, stats) = db.get_with_stats(&cf, &b("k"), &default).await?;
+        assert_eq!(stats.cache_hits(), 1);
+
+        Ok(())
+    });
+}
+
+#[test]
+fn non_syncing_writes_wait_for_the_next_sync() {
+    run(|config| async move {
+        let mut db = Database::open(config)?;
+
+        let unsynced = WriteOptions {
+            sync: false,
+            ..WriteOptions::default()
+        };
+
+        db.put_opt("a", "1", &unsynced).await?;
+        assert_eq!(db.get(&b("a")).await?, Some(b("1")));
+
+        let mut durable = Box::pin(db.wait_durable(db.last_seqno()));
+        assert!(poll_once(durable.as_mut()).await.is_none());
+
+        // A synced write fsyncs everything logged before it too.
+        db.put("b", "2").await?;
+        assert!(matches!(poll_once(durable).await, Some(Ok(()))));
+
+        Ok(())
+    });
+}
+
+/// A clock that moves forward 10ms each time it's read, as if every SSTable block read
+/// between checks of a deadline were slow.
+#[derive(Debug, Default)]
+struct SlowStorage {
+    clock: ManualClock,
+    reads: AtomicUsize,
+}
+
+impl Clock for SlowStorage {
+    fn now(&self) -> Instant {
+        self.reads.fetch_add(1, Ordering::Relaxed);
+        self.clock.advance(Duration::from_millis(10));
+        self.clock.now()
+    }
+
+    fn wall_time(&self) -> SystemTime {
+        self.clock.wall_time()
+    }
+}
+
+#[test]
+fn reads_past_their_deadline_time_out() {
+    run(|mut config| async move {
+        let clock = Arc::new(SlowStorage::default());
+        config.clock = clock.clone();
+        config.l0_sub_levels = false;
+        config.bloom_filter_levels = BloomFilterLevels::None;
+
+        let mut db = Database::open(config)?;
+
+        // Only the oldest of several overlapping files has `m`, so finding it reads a
+        // block from each of them.
+        db.put("m", "oldest").await?;
+        for _ in 0..6 {
+            db.put("a", "v").await?;
+            db.put("z", "v").await?;
+            db.flush().await?;
+        }
+        db.put("in memory", "v").await?;
+
+        let tight = || ReadOptions {
+            deadline: Some(clock.now() + Duration::from_millis(25)),
+            ..ReadOptions::default()
+        };
+
+        let reads = clock.reads.load(Ordering::Relaxed);
+        let e = db.get_opt(&b("m"), &tight()).await.unwrap_err();
+        assert!(e.downcast_ref::<Timeout>().is_some(), "{e:#}");
+
+        // It gave up after a few blocks rather than reading all of them.
+        assert!(clock.reads.load(Ordering::Relaxed) - reads < 6);
+
+        // Reads that don't need a block never time out.
+        assert_eq!(db.get_opt(&b("in memory"), &tight()).await?, Some(b("v")));
+
+        let relaxed = ReadOptions {
+            deadline: Some(clock.now() + Duration::from_secs(1)),
+            ..ReadOptions::default()
+        };
+        assert_eq!(db.get_opt(&b("m"), &relaxed).await?, Some(b("oldest")));
+
+        Ok(())
+    });
+}
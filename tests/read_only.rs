@@ -0,0 +1,37 @@
+mod common;
+
+use std::os::unix::fs::PermissionsExt;
+
+use common::run;
+use mintdb::{config::Config, db::ReadOnlyDataDir, Database};
+
+#[test]
+fn unwritable_data_dir_is_a_typed_error() {
+    run(|config| async move {
+        let parent = config.data_dir.parent().expect("data dir has a parent");
+        std::fs::set_permissions(parent, std::fs::Permissions::from_mode(0o555))?;
+
+        // Permissions don't stop root, but nobody can create directories in sysfs.
+        let data_dir = match std::fs::File::create(parent.join("probe")) {
+            Err(_) => config.data_dir.clone(),
+            Ok(_) if cfg!(target_os = "linux") => "/sys/mintdb".into(),
+            Ok(_) => {
+                eprintln!("skipping: can't make a directory unwritable");
+                return Ok(());
+            }
+        };
+
+        let e = Database::open(Config::new(&data_dir))
+            .err()
+            .expect("open should fail");
+        let Some(read_only) = e.downcast_ref::<ReadOnlyDataDir>() else {
+            panic!("{e:#}");
+        };
+        assert_eq!(read_only.path, data_dir);
+        assert!(!data_dir.exists());
+
+        std::fs::set_permissions(parent, std::fs::Permissions::from_mode(0o755))?;
+
+        Ok(())
+    });
+}
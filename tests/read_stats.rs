@@ -0,0 +1,45 @@
+mod common;
+
+use common::{b, run};
+use mintdb::{bloom::BloomFilterLevels, options::ReadOptions, sstable::Level, Database};
+
+#[test]
+fn lookup_reports_every_overlapping_l0_file() {
+    run(|mut config| async move {
+        // Every flush its own sub-level, and no filters to skip files with.
+        config.l0_sub_levels = false;
+        config.bloom_filter_levels = BloomFilterLevels::None;
+
+        let mut db = Database::open(config)?;
+        let cf = db.default_cf();
+
+        db.put("m", "oldest").await?;
+
+        for i in 0..6 {
+            db.put("a", format!("{i}")).await?;
+            db.put("z", format!("{i}")).await?;
+            db.flush().await?;
+        }
+
+        let files = db.live_files(&cf)?;
+        assert_eq!(files.len(), 6);
+        assert!(files.iter().all(|(level, _)| *level == Level(0)));
+
+        // Only the oldest file has it, and every newer one overlaps it, so all six are
+        // checked, newest first, one block each.
+        let (value, stats) = db
+            .get_with_stats(&cf, &b("m"), &ReadOptions::default())
+            .await?;
+        assert_eq!(value, Some(b("oldest")));
+        assert_eq!((stats.sstables(), stats.blocks()), (6, 6));
+
+        // The newest file has this one.
+        let (value, stats) = db
+            .get_with_stats(&cf, &b("a"), &ReadOptions::default())
+            .await?;
+        assert_eq!(value, Some(b("5")));
+        assert_eq!((stats.sstables(), stats.blocks()), (1, 1));
+
+        Ok(())
+    });
+}
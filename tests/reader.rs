@@ -0,0 +1,51 @@
+mod common;
+
+use common::{b, run};
+use mintdb::{options::ReadOptions, Database};
+
+#[test]
+fn readers_keep_their_own_options_over_shared_data() {
+    run(|config| async move {
+        let mut db = Database::open(config)?;
+        let cf = db.default_cf();
+
+        // In separate files, so that they're in separate blocks.
+        db.put("a", "1").await?;
+        db.flush().await?;
+        db.put("b", "2").await?;
+        db.flush().await?;
+
+        let uncached_options = ReadOptions {
+            fill_cache: false,
+            ..ReadOptions::default()
+        };
+        let cached = db.reader();
+        let uncached = db.reader().with_options(uncached_options.clone());
+        assert!(cached.options().fill_cache);
+        assert!(!uncached.options().fill_cache);
+
+        // Whether the block holding `key` is cached, without caching it.
+        let is_cached = |key: &'static str| {
+            let (db, cf, options) = (&db, &cf, &uncached_options);
+            async move {
+                let (value, stats) = db.get_with_stats(cf, &b(key), options).await?;
+                assert!(value.is_some());
+                anyhow::Ok(stats.cache_hits() > 0)
+            }
+        };
+
+        assert_eq!(uncached.get(&b("a")).await?, Some(b("1")));
+        assert!(!is_cached("a").await?);
+
+        assert_eq!(cached.get(&b("b")).await?, Some(b("2")));
+        assert!(is_cached("b").await?);
+        assert!(!is_cached("a").await?);
+
+        // Both see the same data, and one reader's cache fills serve the other.
+        assert_eq!(cached.get(&b("a")).await?, Some(b("1")));
+        assert_eq!(uncached.get(&b("b")).await?, Some(b("2")));
+        assert!(is_cached("a").await?);
+
+        Ok(())
+    });
+}
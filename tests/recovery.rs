@@ -0,0 +1,146 @@
+mod common;
+
+use common::{b, copy_dir, run, sstable_path};
+use mintdb::{
+    column_family::ColumnFamilyId,
+    config::Config,
+    options::WriteOptions,
+    recovery::RecoveryAction,
+    sstable::manager::{FileNo, MissingSstable},
+    wal::WritePolicy,
+    Database,
+};
+
+#[test]
+fn open_reports_which_sstable_is_missing() {
+    run(|config| async move {
+        let mut db = Database::open(config.clone())?;
+        let cf = db.default_cf();
+
+        for batch in 0..3 {
+            db.put(format!("key{batch}"), "v").await?;
+            db.flush().await?;
+        }
+
+        let files = db.live_files(&cf)?;
+        assert_eq!(files.len(), 3);
+        db.close().await?;
+
+        let (level, file) = &files[1];
+        std::fs::remove_file(sstable_path(&config.data_dir, file.file_number))?;
+
+        let missing = MissingSstable {
+            cf: ColumnFamilyId::DEFAULT,
+            level: *level,
+            file_number: FileNo(file.file_number),
+        };
+
+        let e = Database::open(config.clone())
+            .err()
+            .expect("open should fail");
+        assert_eq!(e.downcast_ref::<MissingSstable>(), Some(&missing));
+
+        // Repairing drops just that file, and its data.
+        let mut repair = config;
+        repair.repair_missing_sstables = true;
+
+        let (db, report) = Database::open_with_report(repair)?;
+        assert!(report
+            .actions
+            .contains(&RecoveryAction::MissingSstablesDropped {
+                files: vec![missing],
+            }));
+        assert_eq!(db.live_files(&cf)?.len(), 2);
+        assert_eq!(db.get(&b("key0")).await?, Some(b("v")));
+        assert_eq!(db.get(&b("key2")).await?, Some(b("v")));
+
+        Ok(())
+    });
+}
+
+#[test]
+fn close_leaves_nothing_to_replay() {
+    run(|mut config| async move {
+        config.write_policy = WritePolicy::WriteBehind;
+
+        let mut db = Database::open(config.clone())?;
+        let unsynced = WriteOptions {
+            sync: false,
+            ..WriteOptions::default()
+        };
+
+        db.put("flushed", "v").await?;
+        db.flush().await?;
+        db.put("frozen", "v").await?;
+        db.freeze_memtable(&db.default_cf()).await?;
+        db.put("active", "v").await?;
+        db.put_opt("unsynced", "v", &unsynced).await?;
+
+        db.close().await?;
+
+        // The lock was let go of, and everything is in SSTables.
+        let (db, report) = Database::open_with_report(config)?;
+        assert!(
+            !report
+                .actions
+                .iter()
+                .any(|action| matches!(action, RecoveryAction::WalReplayed { .. })),
+            "{report:?}"
+        );
+
+        for key in ["flushed", "frozen", "active", "unsynced"] {
+            assert_eq!(db.get(&b(key)).await?, Some(b("v")), "{key}");
+        }
+
+        Ok(())
+    });
+}
+
+#[test]
+fn open_reports_a_torn_wal_tail() {
+    run(|mut config| async move {
+        config.wal_preallocate_chunk = 0;
+
+        let mut db = Database::open(config.clone())?;
+        let wal = config.data_dir.join("wal.log");
+
+        db.put("a", "1").await?;
+        db.put("b", "2").await?;
+        let before_last = std::fs::metadata(&wal)?.len();
+        db.put("c", "3").await?;
+        let whole = std::fs::metadata(&wal)?.len();
+
+        // A crash partway through writing the last record.
+        let dir = tempfile::tempdir()?;
+        copy_dir(&config.data_dir, dir.path())?;
+        let torn = before_last + (whole - before_last) / 2;
+        std::fs::OpenOptions::new()
+            .write(true)
+            .open(dir.path().join("wal.log"))?
+            .set_len(torn)?;
+
+        let (crashed, report) = Database::open_with_report(Config::new(dir.path()))?;
+        assert!(
+            report.actions.contains(&RecoveryAction::WalTailTruncated {
+                bytes: torn - before_last,
+                records: 1,
+            }),
+            "{report:?}"
+        );
+        assert_eq!(crashed.get(&b("b")).await?, Some(b("2")));
+        assert_eq!(crashed.get(&b("c")).await?, None);
+        drop(crashed);
+
+        // Once cut off, the tail is gone for good.
+        let (_, report) = Database::open_with_report(Config::new(dir.path()))?;
+        assert!(
+            !report
+                .actions
+                .iter()
+                .any(|action| matches!(action, RecoveryAction::WalTailTruncated { .. })),
+            "{report:?}"
+        );
+
+        Ok(())
+    });
+}
@@ -0,0 +1,129 @@
+mod common;
+
+use common::{b, run};
+use mintdb::{
+    column_family::ColumnFamilyId,
+    config::Config,
+    key::{Key, SeqNo},
+    recovery::OpenReport,
+    wal::{Wal, WalRecord},
+    Database,
+};
+
+fn put(key: &str, seqno: SeqNo, val: &str) -> WalRecord {
+    WalRecord::Put {
+        cf: ColumnFamilyId::DEFAULT,
+        key: Key::new(b(key), seqno),
+        val: b(val),
+    }
+}
+
+/// Appends `records` to the WAL of the closed database in `config`'s data directory.
+fn append(config: &Config, records: impl IntoIterator<Item = WalRecord>) -> anyhow::Result<()> {
+    let path = config.data_dir.join("wal.log");
+    let mut wal = Wal::open(path, config, &mut OpenReport::default())?;
+
+    for record in records {
+        wal.append(record, true)?;
+    }
+
+    Ok(())
+}
+
+/// Flushes a write of `a`, closes the database, and returns the seqno it was committed at.
+async fn flushed(config: &Config) -> anyhow::Result<SeqNo> {
+    let mut db = Database::open(config.clone())?;
+
+    db.put("a", "flushed").await?;
+    db.flush().await?;
+
+    let committed = db.last_committed_seqno(&db.default_cf())?;
+    assert_eq!(committed, db.last_seqno());
+    db.close().await?;
+
+    Ok(committed)
+}
+
+#[test]
+fn replay_skips_records_at_the_last_committed_seqno() {
+    run(|mut config| async move {
+        config.paranoid_checks = true;
+
+        let committed = flushed(&config).await?;
+
+        // A record at the committed seqno is already in an SSTable, however it reads.
+        append(
+            &config,
+            [
+                put("a", committed, "stale"),
+                put("c", SeqNo::from(committed.get() + 1), "replayed"),
+            ],
+        )?;
+
+        let db = Database::open(config)?;
+        assert_eq!(db.get(&b("a")).await?, Some(b("flushed")));
+        assert_eq!(db.get(&b("c")).await?, Some(b("replayed")));
+
+        Ok(())
+    });
+}
+
+#[test]
+fn paranoid_checks_report_a_replay_gap() {
+    run(|config| async move {
+        let committed = flushed(&config).await?;
+        let seqno = SeqNo::from(committed.get() + 1);
+
+        // The second record replaces the first in the memtable, leaving a gap.
+        append(
+            &config,
+            [put("b", seqno, "first"), put("b", seqno, "second")],
+        )?;
+
+        let mut paranoid = config.clone();
+        paranoid.paranoid_checks = true;
+
+        let e = Database::open(paranoid).err().expect("open should fail");
+        let message = format!("{e:#}");
+        assert!(message.contains(r#"b"b""#), "{message}");
+        assert!(
+            message.contains(&format!("seqno {}", seqno.get())),
+            "{message}"
+        );
+
+        let db = Database::open(config)?;
+        assert_eq!(db.get(&b("b")).await?, Some(b("second")));
+
+        Ok(())
+    });
+}
+
+#[test]
+fn a_duplicate_write_flushes_the_last_of_them() {
+    run(|config| async move {
+        let committed = flushed(&config).await?;
+        let seqno = SeqNo::from(committed.get() + 1);
+
+        append(
+            &config,
+            [
+                put("b", seqno, "first"),
+                put("b", seqno, "second"),
+                put("c", SeqNo::from(seqno.get() + 1), "after"),
+            ],
+        )?;
+
+        // Without paranoid checks nothing tracks the replayed versions, so the memtable
+        // keeps the last write, and the flush notices it held two.
+        let mut db = Database::open(config.clone())?;
+        db.flush().await?;
+        db.close().await?;
+
+        let db = Database::open(config)?;
+        assert_eq!(db.get(&b("b")).await?, Some(b("second")));
+        assert_eq!(db.get(&b("c")).await?, Some(b("after")));
+        assert_eq!(db.count(..).await?, 3);
+
+        Ok(())
+    });
+}
@@ -0,0 +1,202 @@
+mod common;
+
+use common::{b, run};
+use mintdb::{scan::SCAN_PAGE_SIZE, sstable::Level, Database};
+
+fn key(i: usize) -> bytes::Bytes {
+    b(&format!("key{i:05}"))
+}
+
+#[test]
+fn scan_merges_memtable_and_sstables() {
+    run(|config| async move {
+        let mut db = Database::open(config)?;
+
+        for i in 0..100 {
+            db.put(key(i), format!("old{i}")).await?;
+        }
+        db.flush().await?;
+
+        // Overwrites and deletes of flushed keys, and new keys, left in the memtable.
+        for i in (0..100).step_by(10) {
+            db.put(key(i), format!("new{i}")).await?;
+        }
+        for i in (5..100).step_by(10) {
+            db.delete(key(i)).await?;
+        }
+        for i in 100..150 {
+            db.put(key(i), format!("new{i}")).await?;
+        }
+
+        let expected = |i: usize| {
+            let value = match i {
+                100.. => format!("new{i}"),
+                _ if i.is_multiple_of(10) => format!("new{i}"),
+                _ => format!("old{i}"),
+            };
+
+            (key(i), b(&value))
+        };
+        let live = |i: &usize| i % 10 != 5 || *i >= 100;
+
+        let all = db.scan(..).collect::<anyhow::Result<Vec<_>>>()?;
+        assert_eq!(all, (0..150).filter(live).map(expected).collect::<Vec<_>>());
+
+        let inclusive = db
+            .scan(key(50)..=key(120))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        assert_eq!(
+            inclusive,
+            (50..=120).filter(live).map(expected).collect::<Vec<_>>()
+        );
+
+        let exclusive = db
+            .scan(key(50)..key(120))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        assert_eq!(
+            exclusive,
+            (50..120).filter(live).map(expected).collect::<Vec<_>>()
+        );
+
+        Ok(())
+    });
+}
+
+#[test]
+fn scan_reads_more_than_one_page() {
+    run(|config| async move {
+        let mut db = Database::open(config)?;
+        let count = SCAN_PAGE_SIZE * 2 + 10;
+
+        for i in 0..count {
+            db.put(key(i), "v").await?;
+        }
+        db.flush().await?;
+
+        let keys = db
+            .scan(..)
+            .map(|pair| pair.map(|(key, _)| key))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        assert_eq!(keys, (0..count).map(key).collect::<Vec<_>>());
+
+        // A scan can stop at any pair.
+        assert_eq!(
+            db.scan(key(3)..).next().transpose()?,
+            Some((key(3), b("v")))
+        );
+
+        Ok(())
+    });
+}
+
+#[test]
+fn paginated_scan_concatenates_to_a_full_scan() {
+    run(|config| async move {
+        let mut db = Database::open(config)?;
+
+        for i in 0..95 {
+            db.put(key(i), format!("v{i}")).await?;
+        }
+        db.flush().await?;
+        for i in (0..95).step_by(7) {
+            db.delete(key(i)).await?;
+        }
+
+        let mut pages = Vec::new();
+        let mut after = None;
+
+        loop {
+            let (page, next) = db.scan_paginated(..key(90), 10, after).await?;
+            assert!(page.len() <= 10);
+            pages.extend(page);
+
+            match next {
+                Some(next) => after = Some(next),
+                None => break,
+            }
+        }
+
+        let full = db.scan(..key(90)).collect::<anyhow::Result<Vec<_>>>()?;
+        assert_eq!(pages, full);
+
+        Ok(())
+    });
+}
+
+#[test]
+fn scan_levels_only_reads_the_levels_asked_for() {
+    run(|config| async move {
+        let mut db = Database::open(config)?;
+
+        db.put("in-l1", "1").await?;
+        db.flush().await?;
+        db.compact_level(Level(0)).await?;
+
+        db.put("in-l0", "0").await?;
+        db.flush().await?;
+        db.put("in-memtable", "m").await?;
+
+        let l0 = db.scan_levels(.., Level(0)..=Level(0)).await?;
+        assert_eq!(l0, [(b("in-l0"), b("0"))]);
+
+        let l1 = db.scan_levels(.., Level(1)..=Level(1)).await?;
+        assert_eq!(l1, [(b("in-l1"), b("1"))]);
+
+        let full = db
+            .scan(..)
+            .map(|entry| Ok(entry?.0))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        assert_eq!(full, [b("in-l0"), b("in-l1"), b("in-memtable")]);
+
+        Ok(())
+    });
+}
+
+#[test]
+fn scan_filter_returns_matching_live_pairs() {
+    run(|config| async move {
+        let mut db = Database::open(config)?;
+
+        for i in 0..60usize {
+            let value = if i.is_multiple_of(3) {
+                "match"
+            } else {
+                "other"
+            };
+            db.put(format!("key{i:02}"), format!("{value}-{i}")).await?;
+
+            if i == 30 {
+                db.flush().await?;
+            }
+        }
+
+        // Deleted keys, some of whose older versions match.
+        for i in (0..60).step_by(5) {
+            db.delete(format!("key{i:02}")).await?;
+        }
+        db.delete_range("key50", None).await?;
+
+        let seen = std::cell::RefCell::new(Vec::new());
+        let pairs = db
+            .scan_filter(b("key10")..b("key55"), |key, value| {
+                seen.borrow_mut().push(key.clone());
+                value.starts_with(b"match")
+            })
+            .await?;
+
+        let live = |i: &usize| !i.is_multiple_of(5) && *i < 50;
+        let expected = (10..55)
+            .filter(|i| live(i) && i.is_multiple_of(3))
+            .map(|i| (b(&format!("key{i:02}")), b(&format!("match-{i}"))))
+            .collect::<Vec<_>>();
+        assert_eq!(pairs, expected);
+
+        let passed = (10..55)
+            .filter(live)
+            .map(|i| b(&format!("key{i:02}")))
+            .collect::<Vec<_>>();
+        assert_eq!(seen.into_inner(), passed);
+
+        Ok(())
+    });
+}
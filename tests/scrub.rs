@@ -0,0 +1,90 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use common::{run, sstable_path};
+use mintdb::{
+    scrub::{Corruption, CorruptionListener},
+    sstable::{manager::FileNo, sstable::SSTable, Level},
+    Database,
+};
+
+/// Keeps every corruption it's told about.
+#[derive(Debug, Default)]
+struct Recorder(Mutex<Vec<Corruption>>);
+
+impl CorruptionListener for Recorder {
+    fn on_corruption(&self, corruption: &Corruption) {
+        self.0.lock().unwrap().push(corruption.clone());
+    }
+}
+
+#[test]
+fn scrub_reports_a_corrupt_block_by_file_and_offset() {
+    run(|mut config| async move {
+        let recorder = Arc::new(Recorder::default());
+        config.on_corruption = Some(recorder.clone());
+        config.block_size = 1024;
+
+        let mut db = Database::open(config.clone())?;
+        for i in 0..1_000 {
+            db.put(format!("key{i:04}"), format!("value {i:04}"))
+                .await?;
+        }
+        db.flush().await?;
+
+        let (level, file) = db.live_files(&db.default_cf())?.remove(0);
+        db.close().await?;
+
+        let path = sstable_path(&config.data_dir, file.file_number);
+        let blocks = SSTable::open(path.clone())?
+            .index()
+            .iter()
+            .map(|block| (block.offset(), block.size()))
+            .collect::<Vec<_>>();
+        assert!(blocks.len() > 10);
+
+        // Plant the damage in a block in the middle of the file.
+        let at = std::fs::read(&path)?
+            .windows(10)
+            .position(|window| window == b"value 0500")
+            .expect("value is in the file") as u64;
+        let (offset, _) = blocks
+            .iter()
+            .copied()
+            .find(|(offset, size)| (*offset..*offset + *size as u64).contains(&at))
+            .expect("value is in a block");
+        assert!(common::corrupt(&path, b"value 0500")?);
+
+        // A few blocks at a time, reopening in between, so the scrub has to pick up where
+        // it left off.
+        let mut verified = 0;
+        let mut corruptions = Vec::new();
+
+        loop {
+            let db = Database::open(config.clone())?;
+            let report = db.scrub(3).await?;
+            db.close().await?;
+
+            verified += report.blocks_verified;
+            corruptions.extend(report.corruptions);
+
+            if report.pass_completed {
+                break;
+            }
+        }
+
+        assert_eq!(verified, blocks.len());
+        assert_eq!(corruptions.len(), 1);
+
+        let corruption = &corruptions[0];
+        assert_eq!(corruption.file_number, FileNo(file.file_number));
+        assert_eq!(corruption.level, level);
+        assert_eq!(corruption.offset, Some(offset));
+        assert_eq!(level, Level(0));
+
+        assert_eq!(*recorder.0.lock().unwrap(), corruptions);
+
+        Ok(())
+    });
+}
@@ -0,0 +1,49 @@
+mod common;
+
+use common::{b, run};
+use mintdb::{db::ReadOnlyHandle, Database};
+
+#[test]
+fn secondary_sees_flushed_data_after_refresh() {
+    run(|config| async move {
+        let mut primary = Database::open(config.clone())?;
+
+        primary.put("a", "1").await?;
+        primary.flush().await?;
+
+        let mut secondary = Database::open_secondary(config.clone())?;
+        assert!(secondary.is_secondary());
+        assert_eq!(secondary.get(&b("a")).await?, Some(b("1")));
+
+        let e = secondary.put("x", "y").await.expect_err("writes fail");
+        assert!(e.downcast_ref::<ReadOnlyHandle>().is_some(), "{e:#}");
+
+        // Unflushed writes aren't visible, and flushed ones only after a refresh.
+        primary.put("b", "2").await?;
+        assert!(!secondary.refresh()?);
+        assert_eq!(secondary.get(&b("b")).await?, None);
+
+        primary.flush().await?;
+        assert_eq!(secondary.get(&b("b")).await?, None);
+        assert!(secondary.refresh()?);
+        assert_eq!(secondary.get(&b("b")).await?, Some(b("2")));
+
+        // Compaction replaces the files the secondary reads, which are kept until it has
+        // refreshed past them.
+        primary.put("a", "3").await?;
+        primary.flush().await?;
+        primary.compact().await?;
+        assert_eq!(secondary.get(&b("a")).await?, Some(b("1")));
+        assert_eq!(secondary.get(&b("b")).await?, Some(b("2")));
+
+        assert!(secondary.refresh()?);
+        assert_eq!(secondary.get(&b("a")).await?, Some(b("3")));
+        assert_eq!(secondary.get(&b("b")).await?, Some(b("2")));
+        assert_eq!(
+            secondary.scan(..).collect::<anyhow::Result<Vec<_>>>()?,
+            primary.scan(..).collect::<anyhow::Result<Vec<_>>>()?
+        );
+
+        Ok(())
+    });
+}
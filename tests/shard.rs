@@ -0,0 +1,198 @@
+mod common;
+
+use common::{b, copy_dir};
+use mintdb::{
+    batch::WriteBatch,
+    config::Config,
+    shard::{shard_dir, shard_for_key, write_sharded, CommitLog},
+    Database,
+};
+
+const SHARDS: usize = 2;
+
+#[test]
+fn keys_route_to_shards_with_their_own_directories() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let mut config = Config::new(dir.path());
+    config.shards = SHARDS;
+
+    let keys = (0..200).map(|i| format!("key{i:03}")).collect::<Vec<_>>();
+
+    // Routing depends only on the key.
+    let routes = keys
+        .iter()
+        .map(|key| shard_for_key(key.as_bytes(), SHARDS))
+        .collect::<Vec<_>>();
+    for (key, shard) in keys.iter().zip(&routes) {
+        assert_eq!(shard_for_key(key.as_bytes(), SHARDS), *shard);
+    }
+
+    // Each shard's executor writes the keys it owns to its own database.
+    let executors = (0..SHARDS)
+        .map(|shard| {
+            let config = config.for_shard(shard);
+            let owned = keys
+                .iter()
+                .zip(&routes)
+                .filter(|(_, owner)| **owner == shard)
+                .map(|(key, _)| key.clone())
+                .collect::<Vec<_>>();
+            assert!(!owned.is_empty(), "shard {shard} owns no keys");
+
+            glommio::LocalExecutorBuilder::default()
+                .spawn(move || async move {
+                    let mut db = Database::open(config)?;
+
+                    for key in owned {
+                        db.put(key, format!("shard {shard}")).await?;
+                    }
+
+                    db.close().await
+                })
+                .expect("Failed to spawn executor")
+        })
+        .collect::<Vec<_>>();
+
+    for executor in executors {
+        executor.join().expect("executor panicked")?;
+    }
+
+    glommio::LocalExecutorBuilder::default()
+        .spawn(move || async move {
+            for shard in 0..SHARDS {
+                let shard_config = config.for_shard(shard);
+                assert_eq!(shard_config.data_dir, shard_dir(dir.path(), shard));
+                assert!(shard_config.data_dir.join("manifests").is_dir());
+
+                let db = Database::open(shard_config)?;
+
+                for (key, owner) in keys.iter().zip(&routes) {
+                    let expected = (*owner == shard).then(|| b(&format!("shard {shard}")));
+                    assert_eq!(db.get(&b(key)).await?, expected, "{key}");
+                }
+            }
+
+            anyhow::Ok(())
+        })
+        .expect("Failed to spawn executor")
+        .join()
+        .expect("executor panicked")
+}
+
+/// A key owned by `shard`, distinct for each `n`.
+fn key_in(shard: usize, n: usize) -> String {
+    (0..)
+        .map(|i| format!("key{i:04}"))
+        .filter(|key| shard_for_key(key.as_bytes(), SHARDS) == shard)
+        .nth(n)
+        .expect("every shard owns keys")
+}
+
+#[test]
+fn shards_recover_their_own_wals_and_cross_shard_batches_atomically() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let crashed = tempfile::tempdir()?;
+    let crashed_dir = crashed.path().to_owned();
+
+    let mut config = Config::new(dir.path());
+    config.shards = SHARDS;
+    let shard_configs = (0..SHARDS)
+        .map(|shard| config.for_shard(shard))
+        .collect::<Vec<_>>();
+
+    glommio::LocalExecutorBuilder::default()
+        .spawn(move || async move {
+            let mut log = CommitLog::open(&config.data_dir)?;
+            let mut shards = shard_configs
+                .into_iter()
+                .map(Database::open)
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            let cf = shards[0].default_cf();
+
+            // A write to each shard's own keys, and a batch across both that completes.
+            for shard in 0..SHARDS {
+                let mut batch = WriteBatch::new();
+                batch.put(&cf, key_in(shard, 0), format!("own {shard}"));
+                write_sharded(&mut log, &mut shards, batch).await?;
+            }
+
+            let mut batch = WriteBatch::new();
+            for shard in 0..SHARDS {
+                batch.put(&cf, key_in(shard, 1), "completed");
+            }
+            write_sharded(&mut log, &mut shards, batch).await?;
+
+            // One committed in the log, but only applied in shard 0 before the crash...
+            let committed = log.begin();
+            for (shard, db) in shards.iter_mut().enumerate() {
+                let mut part = WriteBatch::new();
+                part.put(&cf, key_in(shard, 2), "committed");
+                db.prepare_batch(committed, part)?;
+            }
+            log.record_commit(committed)?;
+            shards[0].commit_prepared(committed).await?;
+
+            // ...and one prepared everywhere, but never committed.
+            let abandoned = log.begin();
+            for (shard, db) in shards.iter_mut().enumerate() {
+                let mut part = WriteBatch::new();
+                part.put(&cf, key_in(shard, 3), "abandoned");
+                db.prepare_batch(abandoned, part)?;
+            }
+
+            copy_dir(&config.data_dir, &crashed_dir)?;
+
+            anyhow::Ok(())
+        })
+        .expect("Failed to spawn executor")
+        .join()
+        .expect("executor panicked")?;
+
+    let mut config = Config::new(crashed.path());
+    config.shards = SHARDS;
+
+    // Each shard replays its own WAL on its own executor, at the same time as the others.
+    let executors = (0..SHARDS)
+        .map(|shard| {
+            let config = config.clone();
+
+            glommio::LocalExecutorBuilder::default()
+                .spawn(move || async move {
+                    let log = CommitLog::open(&config.data_dir)?;
+                    let mut db = Database::open(config.for_shard(shard))?;
+
+                    let resolved = log.resolve(&mut db).await?;
+                    let expected = if shard == 0 { (0, 1) } else { (1, 1) };
+                    assert_eq!(resolved, expected, "shard {shard}");
+                    assert_eq!(db.prepared_batches(), []);
+
+                    let expected = [
+                        Some(format!("own {shard}")),
+                        Some("completed".to_owned()),
+                        Some("committed".to_owned()),
+                        None,
+                    ];
+                    for (n, expected) in expected.into_iter().enumerate() {
+                        let key = key_in(shard, n);
+                        let expected = expected.as_deref().map(b);
+                        assert_eq!(db.get(&b(&key)).await?, expected, "{key}");
+                    }
+
+                    // None of the other shard's keys were written here.
+                    for n in 0..4 {
+                        let key = key_in(1 - shard, n);
+                        assert_eq!(db.get(&b(&key)).await?, None, "{key}");
+                    }
+
+                    db.close().await
+                })
+                .expect("Failed to spawn executor")
+        })
+        .collect::<Vec<_>>();
+
+    for executor in executors {
+        executor.join().expect("executor panicked")?;
+    }
+
+    Ok(())
+}
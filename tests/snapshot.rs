@@ -0,0 +1,53 @@
+mod common;
+
+use common::{b, run};
+use mintdb::{options::ReadOptions, Database};
+
+#[test]
+fn released_snapshots_stop_holding_old_versions() {
+    run(|config| async move {
+        let mut db = Database::open(config)?;
+
+        for i in 0..10 {
+            db.put(format!("key{i}"), "old").await?;
+        }
+        db.flush().await?;
+
+        let snapshot = db.snapshot();
+        assert_eq!(db.stats().oldest_snapshot, Some(snapshot.seqno()));
+
+        for i in 0..5 {
+            db.put(format!("key{i}"), "new").await?;
+        }
+        db.delete("key9").await?;
+        db.flush().await?;
+        db.compact().await?;
+
+        // The snapshot still sees the versions compaction would otherwise have dropped.
+        let counts = db.stats().value_counts;
+        assert_eq!((counts.data, counts.tombstones), (15, 1));
+
+        let at_snapshot = ReadOptions {
+            snapshot: Some(snapshot.clone()),
+            ..ReadOptions::default()
+        };
+        assert_eq!(db.get_opt(&b("key0"), &at_snapshot).await?, Some(b("old")));
+        assert_eq!(db.get_opt(&b("key9"), &at_snapshot).await?, Some(b("old")));
+        drop(at_snapshot);
+
+        db.release_snapshot(snapshot);
+        assert_eq!(db.stats().oldest_snapshot, None);
+
+        // The next compaction over the same keys drops them.
+        db.put("key5", "new").await?;
+        db.flush().await?;
+        db.compact().await?;
+
+        let counts = db.stats().value_counts;
+        assert_eq!((counts.data, counts.tombstones), (9, 0));
+        assert_eq!(db.get(&b("key0")).await?, Some(b("new")));
+        assert_eq!(db.get(&b("key9")).await?, None);
+
+        Ok(())
+    });
+}
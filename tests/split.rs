@@ -0,0 +1,62 @@
+mod common;
+
+use common::{b, run};
+use mintdb::{config::Config, Database};
+
+fn entries(db: &Database) -> anyhow::Result<Vec<(bytes::Bytes, bytes::Bytes)>> {
+    db.scan(..).collect()
+}
+
+#[test]
+fn split_halves_hold_exactly_their_share_of_the_keyspace() {
+    run(|config| async move {
+        let mut db = Database::open(config)?;
+        let cf = db.default_cf();
+
+        // Files straddling the split, overwrites and deletes across them, and unflushed
+        // writes.
+        for round in 0..3 {
+            for i in (round..200).step_by(3) {
+                db.put(format!("key{i:03}"), format!("round {round}"))
+                    .await?;
+            }
+            db.delete(format!("key{:03}", 90 + round)).await?;
+            db.flush().await?;
+        }
+        db.put("key050", "unflushed").await?;
+        db.put("key150", "unflushed").await?;
+
+        let before = entries(&db)?;
+        assert!(!db.live_files(&cf)?.is_empty());
+
+        let (left_dir, right_dir) = (tempfile::tempdir()?, tempfile::tempdir()?);
+        let (left, right) = db
+            .split_at(
+                "key100",
+                Config::new(left_dir.path()),
+                Config::new(right_dir.path()),
+            )
+            .await?;
+
+        // No file on either side holds a key from the other.
+        for (_, file) in left.live_files(&left.default_cf())? {
+            assert!(file.key_range()?.1.user_key() < &b("key100"));
+        }
+        for (_, file) in right.live_files(&right.default_cf())? {
+            assert!(file.key_range()?.0.user_key() >= &b("key100"));
+        }
+
+        let (left, right) = (entries(&left)?, entries(&right)?);
+        assert!(left.iter().all(|(key, _)| key < &b("key100")));
+        assert!(right.iter().all(|(key, _)| key >= &b("key100")));
+
+        let (expected_left, expected_right): (Vec<_>, Vec<_>) =
+            before.into_iter().partition(|(key, _)| key < &b("key100"));
+        assert_eq!(left, expected_left);
+        assert_eq!(right, expected_right);
+        assert!(left.contains(&(b("key050"), b("unflushed"))));
+        assert!(right.contains(&(b("key150"), b("unflushed"))));
+
+        Ok(())
+    });
+}
@@ -0,0 +1,216 @@
+mod common;
+
+use bytes::{Buf, Bytes};
+use common::{b, run, sstable_path};
+use mintdb::{
+    compression::Compression,
+    key::Key,
+    sstable::sstable::{BlockReadOptions, KeyEncoding, SSTable},
+    value::Value,
+    Database,
+};
+
+/// A decoded block: each entry's key and offset, and the offsets of its restart points.
+struct DecodedBlock {
+    entries: Vec<(u32, Key)>,
+    restarts: Vec<u32>,
+}
+
+fn decode_blocks(table: &SSTable, encoding: KeyEncoding) -> anyhow::Result<Vec<DecodedBlock>> {
+    (0..table.index().len())
+        .map(|idx| {
+            let block = table.read_block(idx, BlockReadOptions::default())?;
+
+            let count = (&block[block.len() - 4..]).get_u32_le() as usize;
+            let start = block.len() - (count + 1) * 4;
+            let mut trailer = block.slice(start..);
+            let restarts = (0..count).map(|_| trailer.get_u32_le()).collect();
+
+            let mut buf = block.slice(..start);
+            let mut entries = Vec::<(u32, Key)>::new();
+
+            while buf.has_remaining() {
+                let offset = (start - buf.len()) as u32;
+                let key = encoding.decode_key(&mut buf, entries.last().map(|(_, key)| key))?;
+                Value::decode_from(&mut buf)?;
+                entries.push((offset, key));
+            }
+
+            Ok(DecodedBlock { entries, restarts })
+        })
+        .collect()
+}
+
+#[test]
+fn seeks_land_on_the_first_version_of_a_user_key() {
+    run(|mut config| async move {
+        config.restart_at_every_user_key = true;
+        config.block_restart_interval = 1_000;
+
+        let mut db = Database::open(config.clone())?;
+
+        for i in 0..20 {
+            db.put(format!("a{i:02}"), "x".repeat(100)).await?;
+        }
+
+        // Snapshots keep every version of `many` through the flush.
+        let mut snapshots = Vec::new();
+        for i in 0..30 {
+            db.put("many", format!("version {i:02} {}", "x".repeat(100)))
+                .await?;
+            snapshots.push(db.snapshot());
+        }
+
+        db.put("z", "last").await?;
+        db.flush().await?;
+
+        let files = db.live_files(&db.default_cf())?;
+        assert_eq!(files.len(), 1);
+        let path = sstable_path(&config.data_dir, files[0].1.file_number);
+        let table = SSTable::open(path)?;
+        let blocks = decode_blocks(&table, config.key_encoding)?;
+
+        for block in &blocks {
+            for &restart in &block.restarts {
+                let position = block
+                    .entries
+                    .iter()
+                    .position(|(offset, _)| *offset == restart)
+                    .expect("restart points are at entries");
+
+                // Only the restart point at the start of a block may fall among a user
+                // key's versions.
+                if position > 0 {
+                    let (_, prev) = &block.entries[position - 1];
+                    assert_ne!(block.entries[position].1.user_key(), prev.user_key());
+                }
+            }
+        }
+
+        let versions = blocks
+            .iter()
+            .flat_map(|block| block.entries.iter().map(move |entry| (block, entry)))
+            .filter(|(_, (_, key))| key.user_key() == "many")
+            .collect::<Vec<_>>();
+        assert_eq!(versions.len(), 30);
+
+        // The newest version of `many` is a restart point, and decodes without the key
+        // before it.
+        let (block, (offset, first)) = versions[0];
+        assert!(block.restarts.contains(offset));
+
+        let idx = blocks
+            .iter()
+            .position(|other| std::ptr::eq(other, block))
+            .expect("block is in the table");
+        let mut entries = table
+            .read_block(idx, BlockReadOptions::default())?
+            .slice(*offset as usize..);
+        assert_eq!(config.key_encoding.decode_key(&mut entries, None)?, *first);
+
+        assert_eq!(
+            db.get(&b("many")).await?,
+            Some(Bytes::from(format!("version 29 {}", "x".repeat(100))))
+        );
+        drop(snapshots);
+
+        Ok(())
+    });
+}
+
+#[test]
+fn files_written_with_another_block_size_read_back_under_the_default() {
+    run(|mut config| async move {
+        let default_block_size = config.block_size;
+
+        for block_size in [default_block_size * 8, 512] {
+            let dir = tempfile::tempdir()?;
+            config.data_dir = dir.path().to_owned();
+            config.block_size = block_size;
+
+            let mut db = Database::open(config.clone())?;
+            for i in 0..2_000 {
+                db.put(
+                    format!("key{i:04}"),
+                    format!("value {i} {}", "x".repeat(50)),
+                )
+                .await?;
+            }
+            db.flush().await?;
+            db.close().await?;
+
+            config.block_size = default_block_size;
+            let db = Database::open(config.clone())?;
+
+            for i in (0..2_000).step_by(7) {
+                assert_eq!(
+                    db.get(&b(&format!("key{i:04}"))).await?,
+                    Some(Bytes::from(format!("value {i} {}", "x".repeat(50))))
+                );
+            }
+            assert_eq!(db.get(&b("key2000")).await?, None);
+            assert_eq!(db.count(..).await?, 2_000);
+
+            let keys = db
+                .scan(b("key0990")..b("key1010"))
+                .map(|entry| Ok(entry?.0))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            let expected = (990..1010)
+                .map(|i| b(&format!("key{i:04}")))
+                .collect::<Vec<_>>();
+            assert_eq!(keys, expected);
+
+            let (_, file) = db.live_files(&db.default_cf())?.remove(0);
+            let table = SSTable::open(sstable_path(dir.path(), file.file_number))?;
+            // The blocks really are sized for the writer's config, not the reader's.
+            let oversized = table
+                .index()
+                .iter()
+                .any(|block| block.size() as usize > default_block_size);
+            assert_eq!(oversized, block_size > default_block_size);
+        }
+
+        Ok(())
+    });
+}
+
+#[test]
+fn footer_and_index_parse_the_same_from_any_reader() {
+    run(|mut config| async move {
+        config.block_size = 1024;
+        config.sstable_compression = Compression::Lz4;
+
+        let mut db = Database::open(config.clone())?;
+
+        for i in 0..1_000 {
+            db.put(format!("key{i:04}"), format!("value {i}")).await?;
+        }
+        db.flush().await?;
+
+        let (_, file) = db.live_files(&db.default_cf())?.remove(0);
+        let path = sstable_path(&config.data_dir, file.file_number);
+        let table = SSTable::open(path.clone())?;
+        assert!(table.index().len() > 10);
+
+        let bytes = std::fs::read(&path)?;
+
+        let footer = SSTable::read_footer(std::io::Cursor::new(&bytes))?;
+        assert_eq!(footer.version(), table.version());
+        assert_eq!(footer.compression(), table.compression());
+        assert_eq!(footer.compression(), Compression::Lz4);
+        assert_eq!(footer.key_encoding(), config.key_encoding);
+        assert!(footer.index_offset() + footer.index_size() < bytes.len() as u64);
+
+        let index = SSTable::read_index(std::io::Cursor::new(&bytes))?;
+        assert_eq!(index.len(), table.index().len());
+
+        for (streamed, mapped) in index.iter().zip(table.index()) {
+            assert_eq!(streamed.last_key(), mapped.last_key());
+            assert_eq!(streamed.offset(), mapped.offset());
+            assert_eq!(streamed.size(), mapped.size());
+            assert_eq!(streamed.checksum(), mapped.checksum());
+        }
+
+        Ok(())
+    });
+}
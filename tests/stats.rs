@@ -0,0 +1,119 @@
+mod common;
+
+use std::time::Duration;
+
+use common::run;
+use mintdb::{sstable::Level, stats::ValueCounts, Database};
+
+#[test]
+fn value_counts_are_kept_per_file_and_in_aggregate() {
+    run(|mut config| async move {
+        config.l0_sub_levels = false;
+
+        let mut db = Database::open(config)?;
+        let cf = db.default_cf();
+
+        // Counted only once flushed.
+        for i in 0..10 {
+            db.put(format!("data{i}"), "v").await?;
+        }
+        for i in 0..3 {
+            db.put_with_ttl(format!("ttl{i}"), "v", Duration::from_secs(3600))
+                .await?;
+        }
+        for i in 0..4 {
+            db.delete(format!("data{i}")).await?;
+        }
+        assert_eq!(db.stats().value_counts, ValueCounts::default());
+
+        db.flush().await?;
+
+        let first = ValueCounts {
+            data: 10,
+            expiring: 3,
+            tombstones: 4,
+            unsupported: 0,
+        };
+        let files = db.live_files(&cf)?;
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].1.value_counts, first);
+        assert_eq!(db.stats().value_counts, first);
+        assert!((first.tombstone_ratio() - 4.0 / 17.0).abs() < 1e-9);
+
+        for i in 0..5 {
+            db.delete(format!("missing{i}")).await?;
+        }
+        db.put("data0", "again").await?;
+        db.flush().await?;
+
+        let second = ValueCounts {
+            data: 1,
+            expiring: 0,
+            tombstones: 5,
+            unsupported: 0,
+        };
+        let mut counts = db
+            .live_files(&cf)?
+            .into_iter()
+            .map(|(_, file)| file.value_counts)
+            .collect::<Vec<_>>();
+        counts.sort_by_key(|counts| counts.total());
+        assert_eq!(counts, [second, first]);
+
+        let mut total = first;
+        total += second;
+        assert_eq!(db.stats().value_counts, total);
+
+        Ok(())
+    });
+}
+
+#[test]
+fn disk_footprint_converges_on_the_live_estimate_once_compacted() {
+    run(|config| async move {
+        let mut db = Database::open(config)?;
+
+        // Three versions of every key, each flushed to its own file.
+        for round in 0..3 {
+            for i in 0..300 {
+                db.put(
+                    format!("key{i:03}"),
+                    format!("round {round} {}", "x".repeat(100)),
+                )
+                .await?;
+            }
+            db.flush().await?;
+        }
+
+        let before = db.disk_usage()?;
+        assert!(before.manifest_bytes > 0);
+        assert!(before.total() > before.sstable_bytes());
+        assert!(
+            before.sstable_bytes() > 2 * before.live_bytes,
+            "{} bytes on disk, {} live",
+            before.sstable_bytes(),
+            before.live_bytes
+        );
+
+        db.compact().await?;
+
+        let after = db.disk_usage()?;
+        assert_eq!(after.obsolete_sstable_bytes, 0);
+        assert_eq!(after.live_bytes, after.sstable_bytes());
+        assert!(after.sstable_bytes() < before.sstable_bytes() / 2);
+        assert_eq!(
+            after
+                .sstable_bytes_by_level
+                .get(&Level(0))
+                .copied()
+                .unwrap_or(0),
+            0
+        );
+
+        // Roughly the one version of each key the estimate counted before.
+        let drift = after.live_bytes.abs_diff(before.live_bytes);
+        assert!(drift * 10 < after.live_bytes, "{before:?} vs {after:?}");
+
+        Ok(())
+    });
+}
@@ -0,0 +1,72 @@
+mod common;
+
+use common::{b, run};
+use futures_lite::{future::poll_once, StreamExt};
+use mintdb::{key::SeqNo, wal::WalRecord, Database};
+
+/// The key and value a put record writes, and its seqno.
+fn put(record: WalRecord) -> (u64, bytes::Bytes, bytes::Bytes) {
+    let WalRecord::Put { key, val, .. } = record else {
+        panic!("expected a put, got {record:?}");
+    };
+
+    (key.seqno().get(), key.user_key().clone(), val)
+}
+
+#[test]
+fn tail_streams_every_write_from_an_old_seqno_in_order() {
+    run(|config| async move {
+        let mut db = Database::open(config)?;
+
+        // Several flushed files, the oldest entirely before the seqno tailed from, then
+        // writes left in the memtable.
+        let mut seqnos = Vec::new();
+        for i in 0..100 {
+            db.put(format!("key{i:03}"), format!("value {i}")).await?;
+            seqnos.push(db.last_seqno());
+
+            if i % 20 == 19 {
+                db.flush().await?;
+            }
+        }
+        db.put("unflushed", "v").await?;
+        seqnos.push(db.last_seqno());
+
+        let from = seqnos[30];
+        assert!(db
+            .live_files(&db.default_cf())?
+            .iter()
+            .any(|(_, file)| file.largest_seqno < from));
+        let mut tail = db.tail(from).await?;
+
+        // Writes made after the tail started follow the backlog.
+        for i in 100..110 {
+            db.put(format!("key{i:03}"), format!("value {i}")).await?;
+            seqnos.push(db.last_seqno());
+        }
+
+        let mut received = Vec::new();
+        while let Some(Some(record)) = poll_once(tail.next()).await {
+            received.push(put(record));
+        }
+
+        let expected = (30..100)
+            .map(|i| (b(&format!("key{i:03}")), b(&format!("value {i}"))))
+            .chain([(b("unflushed"), b("v"))])
+            .chain((100..110).map(|i| (b(&format!("key{i:03}")), b(&format!("value {i}")))))
+            .zip(&seqnos[30..])
+            .map(|((key, val), seqno)| (seqno.get(), key, val))
+            .collect::<Vec<_>>();
+        assert_eq!(received, expected);
+
+        // Tailing from past the last write only gets new ones.
+        let mut tail = db.tail(SeqNo::from(db.last_seqno().get() + 1)).await?;
+        assert!(poll_once(tail.next()).await.is_none());
+
+        db.put("later", "v").await?;
+        let (_, key, _) = put(tail.next().await.expect("tail is open"));
+        assert_eq!(key, b("later"));
+
+        Ok(())
+    });
+}
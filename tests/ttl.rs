@@ -0,0 +1,65 @@
+mod common;
+
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use common::{b, run};
+use mintdb::{
+    clock::{Clock, ManualClock},
+    Database,
+};
+
+#[test]
+fn staggered_ttls_expire_on_time_and_are_reclaimed() {
+    run(|mut config| async move {
+        let clock = Arc::new(ManualClock::new());
+        config.clock = clock.clone();
+
+        let mut db = Database::open(config)?;
+        // Expiry times are kept to the millisecond.
+        let started = clock.unix_millis();
+
+        for (key, secs) in [("a", 10), ("b", 20), ("c", 30)] {
+            db.put_with_ttl(key, "v", Duration::from_secs(secs)).await?;
+        }
+        db.put("forever", "v").await?;
+        db.flush().await?;
+
+        let live = |db: &Database| {
+            db.scan(..)
+                .map(|entry| Ok(entry?.0))
+                .collect::<anyhow::Result<Vec<_>>>()
+        };
+
+        assert_eq!(live(&db)?, [b("a"), b("b"), b("c"), b("forever")]);
+        assert_eq!(db.stats().value_counts.expiring, 3);
+
+        for (key, expired, remaining) in [("a", 10, 2), ("b", 20, 1), ("c", 30, 0)] {
+            let expires_at = started + expired * 1000;
+            assert_eq!(
+                db.next_expiry(),
+                Some(SystemTime::UNIX_EPOCH + Duration::from_millis(expires_at)),
+                "{key}"
+            );
+
+            // Still there up to the moment it expires.
+            clock.advance(Duration::from_millis(expires_at - clock.unix_millis() - 1));
+            assert!(!db.reclaim_expired().await?, "{key}");
+            assert_eq!(db.get(&b(key)).await?, Some(b("v")), "{key}");
+
+            clock.advance(Duration::from_millis(1));
+            assert_eq!(db.get(&b(key)).await?, None, "{key}");
+
+            // Hidden straight away, and reclaimed once the wakeup for it runs.
+            assert!(db.reclaim_expired().await?, "{key}");
+            assert_eq!(db.stats().value_counts.expiring, remaining, "{key}");
+        }
+
+        assert_eq!(live(&db)?, [b("forever")]);
+        assert_eq!(db.next_expiry(), None);
+
+        Ok(())
+    });
+}
@@ -0,0 +1,109 @@
+mod common;
+
+use bytes::{Buf, BufMut};
+use common::{b, run};
+use mintdb::{
+    sstable::{
+        manager::{
+            format_file_name, FileNo, SSTABLE_FILE_EXT, SSTABLE_FORMAT_VERSION, SSTABLE_MAGIC,
+        },
+        sstable::SSTable,
+    },
+    Database,
+};
+
+/// Rewrites the SSTable at `path` as version 0 wrote it: no restart points at the end of
+/// its blocks, no checksums in its index, and a footer with no version.
+fn downgrade_to_version_0(path: &std::path::Path) -> anyhow::Result<()> {
+    let data = std::fs::read(path)?;
+    let index = SSTable::read_index(std::io::Cursor::new(&data))?;
+
+    let mut file = bytes::BytesMut::new();
+    let mut entries = Vec::new();
+
+    for meta in &index {
+        let block = &data[meta.offset() as usize..][..meta.size() as usize];
+        let restarts = (&block[block.len() - 4..]).get_u32_le() as usize;
+        let block = &block[..block.len() - (restarts + 1) * 4];
+
+        entries.push((
+            meta.last_key().clone(),
+            file.len() as u64,
+            block.len() as u32,
+        ));
+        file.put_slice(block);
+    }
+
+    let index_offset = file.len() as u64;
+    file.put_u32_le(entries.len() as u32);
+
+    for (last_key, offset, size) in &entries {
+        last_key.encode_into(&mut file);
+        file.put_u64_le(*offset);
+        file.put_u32_le(*size);
+    }
+
+    let index_size = file.len() as u64 - index_offset;
+    file.put_u64_le(index_offset);
+    file.put_u64_le(index_size);
+    // Key encoding, compression and version, all reserved at 0 in version 0.
+    file.put_u32_le(0);
+    file.put_u32_le(0);
+    file.put_u32_le(0);
+    file.put_u32_le(SSTABLE_MAGIC);
+
+    std::fs::write(path, file)?;
+
+    Ok(())
+}
+
+#[test]
+fn upgrade_rewrites_version_0_files() {
+    run(|config| async move {
+        let mut db = Database::open(config.clone())?;
+        let cf = db.default_cf();
+
+        for i in 0..100 {
+            db.put(format!("key{i:03}"), format!("value{i}")).await?;
+        }
+        db.flush().await?;
+
+        db.close().await?;
+
+        let sstables = config.data_dir.join("sstables");
+        let mut downgraded = 0;
+
+        for entry in std::fs::read_dir(&sstables)? {
+            let path = entry?.path();
+
+            if path.extension().is_some_and(|ext| ext == SSTABLE_FILE_EXT) {
+                downgrade_to_version_0(&path)?;
+                downgraded += 1;
+            }
+        }
+
+        assert!(downgraded > 0);
+
+        let mut db = Database::open(config)?;
+
+        // Version 0 files are readable as they are, without checksums to verify.
+        assert_eq!(db.get(&b("key042")).await?, Some(b("value42")));
+
+        db.upgrade_format().await?;
+
+        for (_, file) in db.live_files(&cf)? {
+            let path = sstables.join(format_file_name(FileNo(file.file_number), SSTABLE_FILE_EXT));
+            let table = SSTable::open(path)?;
+            assert_eq!(table.version(), SSTABLE_FORMAT_VERSION);
+        }
+
+        for i in 0..100 {
+            assert_eq!(
+                db.get(&b(&format!("key{i:03}"))).await?,
+                Some(b(&format!("value{i}")))
+            );
+        }
+
+        Ok(())
+    });
+}
@@ -0,0 +1,45 @@
+use bytes::{BufMut, Bytes, BytesMut};
+use mintdb::Value;
+
+#[test]
+fn unknown_value_type_is_skipped_and_carried_over() -> anyhow::Result<()> {
+    // A value of a type from a newer version, followed by one this version knows.
+    let mut encoded = BytesMut::new();
+    encoded.put_u8(42);
+    encoded.put_u32_le(3);
+    encoded.put_slice(b"new");
+    Value::Data(Bytes::from_static(b"after")).encode_into(&mut encoded);
+
+    let mut buf = encoded.clone().freeze();
+    let unknown = Value::decode_from(&mut buf)?;
+
+    assert!(matches!(
+        &unknown,
+        Value::Unsupported { tag: 42, payload } if payload == "new"
+    ));
+    assert!(unknown.value_type().is_none());
+    assert!(unknown.data().is_none());
+    assert!(unknown.ensure_supported().is_err());
+
+    // Decoding carries on past it.
+    assert!(matches!(
+        Value::decode_from(&mut buf)?,
+        Value::Data(data) if data == "after"
+    ));
+
+    // And it's written back exactly as it was read.
+    let mut reencoded = BytesMut::new();
+    unknown.encode_into(&mut reencoded);
+    assert_eq!(reencoded[..], encoded[..reencoded.len()]);
+
+    Ok(())
+}
+
+#[test]
+fn truncated_value_fails_to_decode() {
+    let mut encoded = BytesMut::new();
+    Value::Data(Bytes::from_static(b"payload")).encode_into(&mut encoded);
+    encoded.truncate(encoded.len() - 1);
+
+    assert!(Value::decode_from(&mut encoded.freeze()).is_err());
+}
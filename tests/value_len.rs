@@ -0,0 +1,47 @@
+mod common;
+
+use common::{b, run};
+use mintdb::Database;
+
+#[test]
+fn value_len_matches_get_across_a_flush() {
+    run(|config| async move {
+        let mut db = Database::open(config)?;
+
+        db.put("flushed", "x".repeat(10_000)).await?;
+        db.put("overwritten", "old").await?;
+        db.put("deleted", "v").await?;
+        db.put("empty", "").await?;
+        db.flush().await?;
+
+        db.put("overwritten", "newer value").await?;
+        db.delete("deleted").await?;
+        db.put("unflushed", "y".repeat(100)).await?;
+
+        let keys = [
+            "flushed",
+            "overwritten",
+            "deleted",
+            "empty",
+            "unflushed",
+            "absent",
+        ];
+        let expected = [Some(10_000), Some(11), None, Some(0), Some(100), None];
+
+        for _ in 0..2 {
+            for (key, expected) in keys.iter().zip(expected) {
+                let key = b(key);
+                assert_eq!(db.value_len(&key).await?, expected);
+                assert_eq!(
+                    db.value_len(&key).await?,
+                    db.get(&key).await?.map(|v| v.len())
+                );
+            }
+
+            // The same again with everything read from SSTables.
+            db.flush().await?;
+        }
+
+        Ok(())
+    });
+}
@@ -0,0 +1,63 @@
+mod common;
+
+use common::run;
+use futures_lite::future::poll_once;
+use mintdb::{options::WriteOptions, wal::WritePolicy, Database};
+
+const UNSYNCED: WriteOptions = WriteOptions {
+    sync: false,
+    delete_if_exists: false,
+    idempotency_key: None,
+};
+
+#[test]
+fn resolves_once_buffered_writes_are_synced() {
+    run(|mut config| async move {
+        config.write_policy = WritePolicy::WriteBehind;
+
+        let mut db = Database::open(config)?;
+
+        db.put_opt("k", "v", &UNSYNCED).await?;
+
+        let mut durable = Box::pin(db.wait_durable(db.last_seqno()));
+        assert!(poll_once(durable.as_mut()).await.is_none());
+
+        db.sync_wal()?;
+        assert!(matches!(poll_once(durable).await, Some(Ok(()))));
+
+        Ok(())
+    });
+}
+
+#[test]
+fn resolves_on_flush_without_a_wal() {
+    run(|mut config| async move {
+        config.wal_enabled = false;
+
+        let mut db = Database::open(config)?;
+
+        db.put("k", "v").await?;
+
+        // Nothing is durable until it's in an SSTable, synced write or not.
+        let mut durable = Box::pin(db.wait_durable(db.last_seqno()));
+        assert!(poll_once(durable.as_mut()).await.is_none());
+
+        db.flush().await?;
+        assert!(matches!(poll_once(durable).await, Some(Ok(()))));
+
+        Ok(())
+    });
+}
+
+#[test]
+fn fails_for_in_memory_databases() {
+    run(|_| async move {
+        let mut db = Database::open_in_memory();
+
+        db.put("k", "v").await?;
+
+        assert!(db.wait_durable(db.last_seqno()).await.is_err());
+
+        Ok(())
+    });
+}
@@ -0,0 +1,103 @@
+use bytes::Bytes;
+use mintdb::{
+    column_family::ColumnFamilyId,
+    compression::Compression,
+    config::Config,
+    key::{Key, SeqNo},
+    recovery::OpenReport,
+    wal::{Wal, WalRecord},
+};
+
+fn put(seqno: u64, val: &[u8]) -> WalRecord {
+    WalRecord::Put {
+        cf: ColumnFamilyId::DEFAULT,
+        key: Key::new(Bytes::from(format!("key{seqno:05}")), SeqNo::from(seqno)),
+        val: Bytes::copy_from_slice(val),
+    }
+}
+
+#[test]
+fn preallocation_grows_the_file_in_chunks() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("wal.log");
+
+    let mut config = Config::new(dir.path());
+    config.wal_preallocate_chunk = 4096;
+
+    let mut wal = Wal::open(path.clone(), &config, &mut OpenReport::default())?;
+    let mut chunks_grown = 0;
+
+    for seqno in 0..200 {
+        let capacity = wal.capacity();
+        wal.append(put(seqno, &[7; 100]), false)?;
+
+        if wal.capacity() != capacity {
+            chunks_grown += 1;
+        }
+
+        assert!(wal.capacity().is_multiple_of(4096));
+        assert!(wal.size() <= wal.capacity());
+        assert_eq!(std::fs::metadata(&path)?.len(), wal.capacity());
+    }
+
+    // Each record is over 100 bytes, so 200 of them take several chunks, but far fewer
+    // than one per record.
+    assert!(wal.size() > 200 * 100);
+    assert!((2..10).contains(&chunks_grown));
+
+    let size = wal.size();
+    drop(wal);
+
+    // The pre-allocated tail is cut off once the log is let go of.
+    assert_eq!(std::fs::metadata(&path)?.len(), size);
+
+    let wal = Wal::open(path, &config, &mut OpenReport::default())?;
+    assert_eq!(wal.size(), size);
+    assert_eq!(wal.len(), 200);
+
+    Ok(())
+}
+
+/// Appends `records` to a fresh log at `path` under `config`, returning the size of the
+/// file once it's let go of.
+fn write_log(
+    path: &std::path::Path,
+    config: &Config,
+    records: &[WalRecord],
+) -> anyhow::Result<u64> {
+    let mut wal = Wal::open(path.to_path_buf(), config, &mut OpenReport::default())?;
+
+    for record in records {
+        wal.append(record.clone(), false)?;
+    }
+
+    drop(wal);
+
+    Ok(std::fs::metadata(path)?.len())
+}
+
+#[test]
+fn compressed_records_are_smaller_and_replay_exactly() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+
+    let records = (0..50)
+        .map(|seqno| put(seqno, "compressible ".repeat(50).as_bytes()))
+        .collect::<Vec<_>>();
+
+    let mut config = Config::new(dir.path());
+    config.wal_preallocate_chunk = 0;
+    config.wal_compression_threshold = 64;
+
+    let plain = write_log(&dir.path().join("plain.log"), &config, &records)?;
+
+    config.wal_compression = Compression::Lz4;
+    let path = dir.path().join("lz4.log");
+    let compressed = write_log(&path, &config, &records)?;
+
+    assert!(compressed * 4 < plain, "{compressed} vs {plain} bytes");
+
+    let replayed = Wal::open(path, &config, &mut OpenReport::default())?.replay()?;
+    assert_eq!(format!("{replayed:?}"), format!("{records:?}"));
+
+    Ok(())
+}
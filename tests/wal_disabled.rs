@@ -0,0 +1,35 @@
+mod common;
+
+use common::{b, run};
+use mintdb::Database;
+
+#[test]
+fn only_flushed_writes_survive_without_a_wal() {
+    run(|mut config| async move {
+        config.wal_enabled = false;
+
+        let mut db = Database::open(config.clone())?;
+
+        db.put("flushed", "v").await?;
+        db.put("deleted", "v").await?;
+        db.flush().await?;
+
+        db.delete("deleted").await?;
+        db.put("unflushed", "v").await?;
+        assert_eq!(db.get(&b("unflushed")).await?, Some(b("v")));
+
+        // Dropping without closing is a crash, as far as a database without a WAL goes.
+        drop(db);
+
+        assert!(!config.data_dir.join("wal.log").exists());
+
+        let db = Database::open(config.clone())?;
+        assert_eq!(db.get(&b("flushed")).await?, Some(b("v")));
+        assert_eq!(db.get(&b("deleted")).await?, Some(b("v")));
+        assert_eq!(db.get(&b("unflushed")).await?, None);
+
+        assert!(!config.data_dir.join("wal.log").exists());
+
+        Ok(())
+    });
+}
@@ -0,0 +1,120 @@
+mod common;
+
+use common::{b, copy_dir, run};
+use futures_lite::future::poll_once;
+use mintdb::{
+    config::Config, options::WriteOptions, recovery::RecoveryAction, wal::WritePolicy, Database,
+};
+
+const UNSYNCED: WriteOptions = WriteOptions {
+    sync: false,
+    delete_if_exists: false,
+    idempotency_key: None,
+};
+
+/// Opens a copy of the database in `config`'s data directory, as a crash would leave it.
+fn reopen_after_crash(config: &Config) -> anyhow::Result<(tempfile::TempDir, Database)> {
+    let dir = tempfile::tempdir()?;
+    copy_dir(&config.data_dir, dir.path())?;
+
+    let db = Database::open(Config::new(dir.path()))?;
+
+    Ok((dir, db))
+}
+
+#[test]
+fn write_ahead_is_durable_when_acknowledged() {
+    run(|config| async move {
+        let mut db = Database::open(config.clone())?;
+
+        db.put("k", "v").await?;
+
+        let durable = poll_once(db.wait_durable(db.last_seqno())).await;
+        assert!(matches!(durable, Some(Ok(()))));
+
+        let (_dir, crashed) = reopen_after_crash(&config)?;
+        assert_eq!(crashed.get(&b("k")).await?, Some(b("v")));
+
+        Ok(())
+    });
+}
+
+#[test]
+fn write_behind_acknowledges_first_and_persists_later() {
+    run(|mut config| async move {
+        config.write_policy = WritePolicy::WriteBehind;
+
+        let mut db = Database::open(config.clone())?;
+
+        db.put_opt("k", "v", &UNSYNCED).await?;
+        assert_eq!(db.get(&b("k")).await?, Some(b("v")));
+
+        // Acknowledged and readable, but not logged yet.
+        let durable = db.wait_durable(db.last_seqno());
+        assert!(poll_once(db.wait_durable(db.last_seqno())).await.is_none());
+
+        let (_dir, crashed) = reopen_after_crash(&config)?;
+        assert_eq!(crashed.get(&b("k")).await?, None);
+
+        db.sync_wal()?;
+        durable.await?;
+
+        let (_dir, crashed) = reopen_after_crash(&config)?;
+        assert_eq!(crashed.get(&b("k")).await?, Some(b("v")));
+
+        Ok(())
+    });
+}
+
+#[test]
+fn write_behind_synced_write_is_durable_when_acknowledged() {
+    run(|mut config| async move {
+        config.write_policy = WritePolicy::WriteBehind;
+
+        let mut db = Database::open(config.clone())?;
+
+        db.put_opt("a", "1", &UNSYNCED).await?;
+        db.put("b", "2").await?;
+
+        // The synced write is logged along with the one held back before it.
+        let durable = poll_once(db.wait_durable(db.last_seqno())).await;
+        assert!(matches!(durable, Some(Ok(()))));
+
+        let (_dir, crashed) = reopen_after_crash(&config)?;
+        assert_eq!(crashed.get(&b("a")).await?, Some(b("1")));
+        assert_eq!(crashed.get(&b("b")).await?, Some(b("2")));
+
+        Ok(())
+    });
+}
+
+#[test]
+fn sync_wal_makes_buffered_writes_survive_a_crash_without_a_flush() {
+    run(|mut config| async move {
+        config.write_policy = WritePolicy::WriteBehind;
+
+        let mut db = Database::open(config.clone())?;
+        let cf = db.default_cf();
+
+        for i in 0..10 {
+            db.put_opt(format!("k{i}"), "v", &UNSYNCED).await?;
+        }
+
+        db.sync_wal()?;
+
+        let dir = tempfile::tempdir()?;
+        copy_dir(&config.data_dir, dir.path())?;
+
+        let (crashed, report) = Database::open_with_report(Config::new(dir.path()))?;
+        assert!(crashed.live_files(&cf)?.is_empty());
+        assert!(report
+            .actions
+            .contains(&RecoveryAction::WalReplayed { records: 10 }));
+
+        for i in 0..10 {
+            assert_eq!(crashed.get(&b(&format!("k{i}"))).await?, Some(b("v")));
+        }
+
+        Ok(())
+    });
+}
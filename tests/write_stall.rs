@@ -0,0 +1,140 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use common::{b, run};
+use mintdb::{
+    stall::{WriteStall, WriteStallListener, WriteStallReason},
+    Database,
+};
+
+/// Keeps every stall it's told about.
+#[derive(Debug, Default)]
+struct Recorder(Mutex<Vec<WriteStall>>);
+
+impl Recorder {
+    fn take(&self) -> Vec<WriteStall> {
+        std::mem::take(&mut self.0.lock().unwrap())
+    }
+}
+
+impl WriteStallListener for Recorder {
+    fn on_write_stall(&self, stall: WriteStall) {
+        self.0.lock().unwrap().push(stall);
+    }
+}
+
+#[test]
+fn stalls_on_frozen_memtables_until_they_are_flushed() {
+    run(|mut config| async move {
+        let recorder = Arc::new(Recorder::default());
+        config.on_write_stall = Some(recorder.clone());
+        config.max_frozen_memtables = Some(2);
+
+        let mut db = Database::open(config)?;
+        let cf = db.default_cf();
+
+        // Freezing without flushing stands in for a flusher that's fallen behind.
+        db.put("a", "1").await?;
+        db.freeze_memtable(&cf).await?;
+        db.put("b", "2").await?;
+        db.freeze_memtable(&cf).await?;
+        assert_eq!(recorder.take(), []);
+        assert!(db.stats().frozen_memtable_bytes > 0);
+
+        db.put("c", "3").await?;
+        assert_eq!(
+            recorder.take(),
+            [
+                WriteStall::Started(WriteStallReason::TooManyMemtables),
+                WriteStall::Ended(WriteStallReason::TooManyMemtables),
+            ]
+        );
+        assert_eq!(db.stats().frozen_memtable_bytes, 0);
+
+        db.put("d", "4").await?;
+        assert_eq!(recorder.take(), []);
+
+        for (key, val) in [("a", "1"), ("b", "2"), ("c", "3"), ("d", "4")] {
+            assert_eq!(db.get(&b(key)).await?, Some(b(val)));
+        }
+
+        Ok(())
+    });
+}
+
+#[test]
+fn stalls_on_frozen_bytes_however_many_memtables_hold_them() {
+    run(|mut config| async move {
+        const LIMIT: usize = 16 * 1024;
+
+        let recorder = Arc::new(Recorder::default());
+        config.on_write_stall = Some(recorder.clone());
+        config.max_frozen_bytes = Some(LIMIT);
+
+        let mut db = Database::open(config)?;
+        let cf = db.default_cf();
+
+        // Plenty of small memtables stay under the limit between them.
+        for i in 0..10 {
+            db.put(format!("small{i}"), "v").await?;
+            db.freeze_memtable(&cf).await?;
+        }
+        db.put("next", "v").await?;
+        assert_eq!(recorder.take(), []);
+        assert!(db.stats().frozen_memtable_bytes < LIMIT as u64);
+
+        // One large one goes over it.
+        db.put("large", "x".repeat(2 * LIMIT)).await?;
+        db.freeze_memtable(&cf).await?;
+        assert!(db.stats().frozen_memtable_bytes > LIMIT as u64);
+        assert_eq!(recorder.take(), []);
+
+        db.put("after", "v").await?;
+        assert_eq!(
+            recorder.take(),
+            [
+                WriteStall::Started(WriteStallReason::TooManyFrozenBytes),
+                WriteStall::Ended(WriteStallReason::TooManyFrozenBytes),
+            ]
+        );
+        assert_eq!(db.stats().frozen_memtable_bytes, 0);
+
+        assert_eq!(db.get(&b("small3")).await?, Some(b("v")));
+        assert_eq!(db.get(&b("large")).await?.map(|v| v.len()), Some(2 * LIMIT));
+        assert_eq!(db.get(&b("after")).await?, Some(b("v")));
+
+        Ok(())
+    });
+}
+
+#[test]
+fn stalls_on_l0_files_until_they_are_compacted() {
+    run(|mut config| async move {
+        let recorder = Arc::new(Recorder::default());
+        config.on_write_stall = Some(recorder.clone());
+        config.l0_stop_writes_trigger = Some(2);
+
+        let mut db = Database::open(config)?;
+
+        for key in ["a", "b"] {
+            db.put(key, "v").await?;
+            db.flush().await?;
+        }
+        assert_eq!(recorder.take(), []);
+
+        db.put("c", "v").await?;
+        assert_eq!(
+            recorder.take(),
+            [
+                WriteStall::Started(WriteStallReason::TooManyL0Files),
+                WriteStall::Ended(WriteStallReason::TooManyL0Files),
+            ]
+        );
+
+        db.put("d", "v").await?;
+        assert_eq!(recorder.take(), []);
+
+        Ok(())
+    });
+}